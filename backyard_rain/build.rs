@@ -21,6 +21,19 @@ fn main() {
         .unwrap()
         .write_all(include_bytes!("memory.x"))
         .unwrap();
+
+    // `memory.x` ends FLASH before the settings sectors at the end of the
+    // card, so firmware which would overwrite them fails to link. Sizes match
+    // `audio::FLASH_SIZE` and the two erase sectors used by `settings`.
+    let flash_size = if env::var_os("CARGO_FEATURE_AUDIO_16MB").is_some() {
+        "16M"
+    } else {
+        "2M"
+    };
+    File::create(out.join("flash_size.x"))
+        .unwrap()
+        .write_all(format!("__flash_size = {flash_size};\n__settings_size = 8K;\n").as_bytes())
+        .unwrap();
     println!("cargo:rustc-link-search={}", out.display());
 
     // By default, Cargo will re-run a build script whenever
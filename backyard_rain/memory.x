@@ -1,6 +1,10 @@
+/* Card and settings sizes, written by build.rs for the audio feature */
+INCLUDE flash_size.x
+
 MEMORY {
     BOOT2 : ORIGIN = 0x10000000, LENGTH = 0x100
-    FLASH : ORIGIN = 0x10000100, LENGTH = 16M - 0x100
+    /* Excludes the settings sectors at the end, see settings.rs */
+    FLASH : ORIGIN = 0x10000100, LENGTH = __flash_size - 0x100 - __settings_size
 
     /* Pick one of the two options for RAM layout     */
 
@@ -8,8 +8,12 @@ use embassy_executor::Executor;
 use embassy_rp::bind_interrupts;
 use embassy_rp::clocks;
 use embassy_rp::gpio::{self};
+#[cfg(feature = "pwm_audio_clock")]
+use embassy_rp::interrupt::{self, InterruptExt};
 // use embassy_rp::interrupt;
 use embassy_rp::multicore::{spawn_core1, Stack};
+#[cfg(any(feature = "dma_dac", feature = "pwm_audio_clock"))]
+use embassy_rp::pac;
 use embassy_rp::peripherals;
 use embassy_rp::pwm;
 use embassy_rp::pwm::SetDutyCycle;
@@ -17,19 +21,80 @@ use embassy_rp::spi;
 use embassy_rp::{adc, Peripheral};
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::channel::Channel;
+#[cfg(feature = "pwm_audio_clock")]
+use embassy_sync::waitqueue::AtomicWaker;
 use embassy_sync::watch::Watch;
 use embassy_time::{Duration, Instant, Ticker, Timer};
 
 use audio_codec_algorithms::decode_adpcm_ima_ms;
 use gpio::{Level, Output};
-use portable_atomic::{AtomicU32, Ordering};
+use portable_atomic::{AtomicBool, AtomicU32, Ordering};
 use static_cell::StaticCell;
 use {defmt_rtt as _, panic_probe as _};
 
+use wscomp::beep::Beep;
+use wscomp::center::CenterTracker;
+use wscomp::clock::{period_for_tempo, tempo_for_interval, PulseClock, TapTempo};
+use wscomp::comparator::{Comparator, Crossing};
+#[cfg(feature = "reduced_resolution")]
+use wscomp::dac::reduce_resolution;
+use wscomp::dac::{apply_trim, ZERO_VOLT_CODE};
+#[cfg(not(any(feature = "benchmark", feature = "dma_dac")))]
+use wscomp::dac::{delay_cycles, DacBus, Mcp4822};
+#[cfg(feature = "dma_dac")]
+use wscomp::dac::{pacing_fraction, DacChannel, DacCommand, DmaRing};
+use wscomp::epoch::{Epoch, Stamped};
+use wscomp::fade::{fade_between, fade_level, FadeCurve};
+use wscomp::fixed::{self, Fixed};
+#[cfg(debug_assertions)]
+use wscomp::headroom::HeadroomMonitor;
+use wscomp::indicator::{center_peak, PeakCurve, PeakMeter};
+use wscomp::inputs::{ComputerInputs, InputHardware, MuxPin, MuxReadings, ScanTiming};
+use wscomp::limiter::Limiter;
+use wscomp::math::{rms_level, Rms};
+use wscomp::mixer::{apply_gain, crossfade3, full_storm, level_match_gains, morph4, solo, Layer};
+use wscomp::modulation::{apply_cv, CvTarget};
+#[cfg(feature = "reduced_resolution")]
+use wscomp::noise::Noise;
+use wscomp::noise::{RainNoise, SmoothNoise};
+use wscomp::oneshot::{EdgeTrigger, OneShot};
+use wscomp::pickup::{Pickup, PickupState};
+use wscomp::probe::{ProbeConfig, ProbePolarity};
+use wscomp::processor::{Chain, Processor};
+use wscomp::quantizer::Quantizer;
+use wscomp::ramp::Ramp;
+use wscomp::resample::{speed_for_rate, Resampler, UNITY_RATIO};
+use wscomp::retry::{Retry, RetryDecision};
+use wscomp::routing::{Destination, PulseSource, Routing, Signals};
+use wscomp::sequence::{SequenceStage, StormSequence};
+use wscomp::slew::{AsymmetricSlew, ChannelSmoothing, Slew};
+#[cfg(feature = "pwm_audio_clock")]
+use wscomp::stats::PwmWrap;
+use wscomp::stats::{rate_per_second, LoopStats, RateDrift};
+#[cfg(not(any(feature = "dma_dac", feature = "pwm_audio_clock")))]
+use wscomp::stats::{ticker_millihz, ticker_period};
+use wscomp::stereo::{widen, Decorrelator};
+use wscomp::storage::SaveThrottle;
+use wscomp::stream::{
+    all_failed, refill_candidate, start_position, SampleQueue, StreamHealth, TailLoop,
+};
+use wscomp::switch::{SwitchCalibration, SwitchPosition};
+use wscomp::temperature::die_temperature_millicelsius;
+use wscomp::timeout::FirstValueTimeout;
+#[cfg(feature = "dma_dac")]
+use wscomp::timeout::StallTimeout;
+use wscomp::tone::{knob_volume, Muffle};
+use wscomp::wav::{adpcm_block_header, adpcm_blocks, adpcm_format, AdpcmFormat};
 use wscomp::{JackSample, Sample, SampleUpdate, U12_MAX};
 
 use mutually_exclusive_features::none_or_one_of;
 none_or_one_of!("audio_sine", "audio_micro", "audio_2mb", "audio_16mb");
+none_or_one_of!("benchmark", "dma_dac", "pwm_audio_clock");
+
+mod settings;
+use settings::{Settings, SettingsFlash, SettingsStore, INPUT_CHANNELS};
+#[cfg(feature = "usb_inputs")]
+mod usb_inputs;
 
 // This is a port of the Backyard Rain Soundscape app from Playdate to the
 // Music Thing Modular Workshop System Computer via Rust & Embassy.
@@ -38,7 +103,38 @@ none_or_one_of!("audio_sine", "audio_micro", "audio_2mb", "audio_16mb");
 // outputs seem to be numbers from 0..4095 (12 bit), inverted from the thing they represent.
 
 static AUDIO_FREQ_COUNTER: AtomicU32 = AtomicU32::new(0);
+/// Longest time between samples from sample_write_loop() in the last second,
+/// in timer ticks (µs). With `dma_dac` the DMA paces the DAC instead, and this
+/// is the longest wait between topping up its ring.
 static AUDIO_MAX_TICKS: AtomicU32 = AtomicU32::new(0);
+/// Times sample_write_loop() restarted the DMA feeding the DAC after it
+/// stalled, with `dma_dac`
+static DAC_TIMEOUTS: AtomicU32 = AtomicU32::new(0);
+/// Mux reads which hadn't settled and were read again, see [`ScanTiming`]
+static MUX_RESETTLES: AtomicU32 = AtomicU32::new(0);
+/// Wraps of the audio clock's PWM slice, counted by its interrupt
+#[cfg(feature = "pwm_audio_clock")]
+static AUDIO_CLOCK_TICKS: AtomicU32 = AtomicU32::new(0);
+/// Wakes sample_write_loop() from the audio clock's interrupt
+#[cfg(feature = "pwm_audio_clock")]
+static AUDIO_CLOCK_WAKER: AtomicWaker = AtomicWaker::new();
+/// Times sample_write_loop() found no sample ready from mixer_loop(), or with
+/// `dma_dac`, times the DMA ran out of samples to send
+static AUDIO_UNDERRUNS: AtomicU32 = AtomicU32::new(0);
+/// Set by periodic_stats() while there are faults, mixer_loop() beeps
+static FAULT_BEEP: AtomicBool = AtomicBool::new(false);
+/// Clock output period, in fixed point samples, set by logic_loop()
+static CLOCK_PERIOD: AtomicU32 = AtomicU32::new(0);
+/// Count of rain event threshold crossings, from logic_loop()
+static RAIN_EVENTS: AtomicU32 = AtomicU32::new(0);
+/// Count of thunder triggers, from pulse_input_loop() and logic_loop()
+static THUNDER_TRIGGERS: AtomicU32 = AtomicU32::new(0);
+/// Set by logic_loop() while the rain is above [`RAIN_EVENT_THRESHOLD`]
+static RAIN_HEAVY: AtomicBool = AtomicBool::new(false);
+/// Set by input_loop() when the ADC could not be brought up, shown on the LEDs
+static ADC_FAULT: AtomicBool = AtomicBool::new(false);
+/// Set by settings_loop() while output offsets are being calibrated
+static CALIBRATING: AtomicBool = AtomicBool::new(false);
 
 bind_interrupts!(struct Irqs {
     ADC_IRQ_FIFO => adc::InterruptHandler;
@@ -63,24 +159,55 @@ static MUX_INPUT: Watch<CriticalSectionRawMutex, MuxState, 2> = Watch::new();
 /// Sample::ZERO = 100% medium rain
 /// Sample::MIN = 100% light rain
 /// ```
-static INTENSITY: Watch<CriticalSectionRawMutex, Sample, 2> = Watch::new();
+///
+/// Stamped with the [`MODE_EPOCH`] it was computed in.
+static INTENSITY: Watch<CriticalSectionRawMutex, Stamped<Sample>, 2> = Watch::new();
+
+/// Advanced by logic_loop() each time the Z switch changes mode, so values
+/// computed before the change can be ignored
+static MODE_EPOCH: AtomicU32 = AtomicU32::new(0);
+
+fn mode_epoch() -> Epoch {
+    Epoch::from_raw(MODE_EPOCH.load(Ordering::Relaxed))
+}
 
 /// Slow LFO for modulating intensity
 static LFO: Watch<CriticalSectionRawMutex, Sample, 2> = Watch::new();
+/// RMS level of the rain mix, from mixer_loop() for the level LED
+static OUTPUT_LEVEL: Watch<CriticalSectionRawMutex, Sample, 2> = Watch::new();
 static AUDIO_INPUT: Watch<CriticalSectionRawMutex, AudioState, 2> = Watch::new();
+/// Current [`Settings`], loaded from flash and updated by settings_loop().
+static SETTINGS: Watch<CriticalSectionRawMutex, Settings, 2> = Watch::new();
+/// Main knob intensity and lock state for settings_loop() to save, from
+/// logic_loop() with [`RESUME_ENABLED`]
+static RESUME_STATE: Watch<CriticalSectionRawMutex, (Sample, bool), 1> = Watch::new();
 static AUDIO_OUT_SAMPLES: Channel<CriticalSectionRawMutex, DACSamplePair, 1024> = Channel::new();
 
 /// The state of the three position Z switch
-#[derive(Clone, Format)]
+#[derive(Clone, Copy, PartialEq, Format)]
 enum ZSwitch {
     On,
     Off,
     Momentary,
 }
 
-impl ZSwitch {
-    fn default() -> ZSwitch {
-        ZSwitch::Off
+impl From<SwitchPosition> for ZSwitch {
+    fn from(position: SwitchPosition) -> Self {
+        match position {
+            SwitchPosition::Off => ZSwitch::Off,
+            SwitchPosition::On => ZSwitch::On,
+            SwitchPosition::Momentary => ZSwitch::Momentary,
+        }
+    }
+}
+
+impl From<ZSwitch> for SwitchPosition {
+    fn from(zswitch: ZSwitch) -> Self {
+        match zswitch {
+            ZSwitch::Off => SwitchPosition::Off,
+            ZSwitch::On => SwitchPosition::On,
+            ZSwitch::Momentary => SwitchPosition::Momentary,
+        }
     }
 }
 
@@ -91,31 +218,41 @@ struct MuxState {
     x_knob: Sample,
     y_knob: Sample,
     zswitch: ZSwitch,
+    /// Raw ADC level the Z switch was decoded from, for calibrating it
+    zswitch_level: u16,
     cv1: JackSample,
     cv2: JackSample,
     sequence_counter: usize,
+    /// RP2040 die temperature in thousandths of a °C, once it has been read
+    die_temperature: Option<i32>,
 }
 
 impl MuxState {
     fn default() -> Self {
+        let readings = MuxReadings::new(PROBE);
         MuxState {
-            main_knob: Sample::new(Sample::CENTER, false),
-            x_knob: Sample::new(Sample::CENTER, false),
-            y_knob: Sample::new(Sample::CENTER, false),
-            zswitch: ZSwitch::default(),
-            // CV inputs are not inverted according to docs.  0V reads ~ 2030
-            // NOTE: I get inverted data, and ~2060 as 0v
-            cv1: JackSample::new(
-                Sample::new(Sample::CENTER, true),
-                Sample::new(Sample::CENTER, true),
-            ),
-            cv2: JackSample::new(
-                Sample::new(Sample::CENTER, true),
-                Sample::new(Sample::CENTER, true),
-            ),
+            main_knob: readings.main_knob,
+            x_knob: readings.x_knob,
+            y_knob: readings.y_knob,
+            zswitch: readings.zswitch.into(),
+            zswitch_level: readings.zswitch_level,
+            cv1: readings.cv1,
+            cv2: readings.cv2,
             sequence_counter: 0,
+            die_temperature: None,
         }
     }
+
+    /// Copy in the latest scan
+    fn set_readings(&mut self, readings: &MuxReadings) {
+        self.main_knob = readings.main_knob;
+        self.x_knob = readings.x_knob;
+        self.y_knob = readings.y_knob;
+        self.zswitch = readings.zswitch.into();
+        self.zswitch_level = readings.zswitch_level;
+        self.cv1 = readings.cv1.clone();
+        self.cv2 = readings.cv2.clone();
+    }
 }
 
 /// State of audio inputs collected via direct ADC read.
@@ -171,7 +308,14 @@ fn main() -> ! {
             let executor1 = EXECUTOR1.init(Executor::new());
             executor1.run(|spawner| {
                 unwrap!(spawner.spawn(sample_write_loop(
-                    p.SPI0, p.PIN_18, p.PIN_19, p.DMA_CH0, p.PIN_21, p.PIN_8, p.PIN_9,
+                    p.SPI0,
+                    p.PIN_18,
+                    p.PIN_19,
+                    p.DMA_CH0,
+                    p.PIN_21,
+                    p.PIN_8,
+                    p.PIN_9,
+                    p.PWM_SLICE0,
                 )))
             })
         },
@@ -181,11 +325,21 @@ fn main() -> ! {
     let executor = EXECUTOR_DEFAULT.init(Executor::new());
     executor.run(|spawner| {
         unwrap!(spawner.spawn(input_loop(
-            p.PIN_4, p.PIN_24, p.PIN_25, p.ADC, p.PIN_28, p.PIN_29, p.PIN_27, p.PIN_26,
+            p.PIN_4,
+            p.PIN_24,
+            p.PIN_25,
+            p.ADC,
+            p.ADC_TEMP_SENSOR,
+            p.PIN_28,
+            p.PIN_29,
+            p.PIN_27,
+            p.PIN_26,
         )));
         unwrap!(spawner.spawn(periodic_stats()));
+        unwrap!(spawner.spawn(settings_loop(p.FLASH)));
         unwrap!(spawner.spawn(mixer_loop()));
         unwrap!(spawner.spawn(logic_loop()));
+        unwrap!(spawner.spawn(pulse_input_loop(p.PIN_2)));
         unwrap!(spawner.spawn(update_pwm_loop(
             p.PWM_SLICE5,
             p.PIN_10,
@@ -200,6 +354,8 @@ fn main() -> ! {
             p.PIN_23,
             p.PIN_22,
         )));
+        #[cfg(feature = "usb_inputs")]
+        unwrap!(spawner.spawn(usb_inputs::usb_inputs_loop(p.USB)));
     })
 }
 
@@ -228,15 +384,126 @@ impl TriangleWave11 {
     }
 }
 
+/// Rate of logic_loop(), which reads inputs and updates intensity
+const LOGIC_RATE_HZ: u32 = 480;
+/// How close the main knob must get to the locked intensity to take over
+const PICKUP_THRESHOLD: i32 = 32;
+/// Main knob values this close to noon snap to medium rain, 0 for none
+const MAIN_KNOB_DEADZONE: i32 = 0;
+/// How much CV 1 moves intensity from the main knob's setting,
+/// [`Sample::MAX`] is the knob's full range, 0 ignores CV 1 and negative
+/// values invert it
+const CV1_DEPTH: i32 = Sample::MAX;
+
+/// Step intensity between the [`RAIN_STATES`], so the knob clicks between
+/// rain characters rather than blending continuously
+const RAIN_STATES_ENABLED: bool = false;
+/// Light, light-medium, medium, medium-heavy and heavy, applied before the
+/// intensity curve
+const RAIN_STATES: [i32; 5] = [Sample::MIN, -1024, 0, 1024, Sample::MAX];
+/// How far past half way between two rain states intensity has to go to
+/// change state, so a knob resting near the boundary doesn't flip between them
+const RAIN_STATE_HYSTERESIS: i32 = 96;
+
+/// Resume at the intensity and knob lock from before power off, rather than
+/// at medium rain
+///
+/// The intensity is saved to flash during play, which pauses audio for tens
+/// of milliseconds each time, so saves are limited by the RESUME_* settings
+/// below.
+const RESUME_ENABLED: bool = false;
+/// Change in the main knob's intensity worth saving
+const RESUME_THRESHOLD: i32 = 64;
+/// Logic loop ticks the main knob has to rest before it's saved (2 seconds)
+const RESUME_SETTLE_TICKS: u32 = 2 * LOGIC_RATE_HZ;
+/// Least logic loop ticks between saves (5 minutes), about a year of
+/// continuous play per 100,000 erases of each settings sector
+const RESUME_INTERVAL_TICKS: u32 = 300 * LOGIC_RATE_HZ;
+
+/// Clock output tempo at light, medium and heavy rain, in thousandths of a BPM
+const CLOCK_TEMPO_RANGE: (u32, u32, u32) = (60_000, 120_000, 180_000);
+/// Length of each clock output pulse, in samples (10ms)
+const CLOCK_PULSE_SAMPLES: u32 = SAMPLE_RATE_HZ / 100;
+
+/// Audio output sample rate, the clock output and everything else counted in
+/// samples is timed from it. Recordings at other rates are resampled to keep
+/// their pitch.
+const SAMPLE_RATE_HZ: u32 = 48_000;
+/// Period of sample_write_loop()'s ticker, as close to `SAMPLE_RATE_HZ` as
+/// whole timer ticks get
+#[cfg(not(any(feature = "dma_dac", feature = "pwm_audio_clock")))]
+const AUDIO_TICKER_PERIOD: u32 = ticker_period(SAMPLE_RATE_HZ, embassy_time::TICK_HZ as u32);
+
+/// Z presses roll a storm in: intensity sweeps from light to heavy, holds,
+/// then eases back to light. Pressing again during the storm stops it. Tap
+/// tempo takes Z presses first, if enabled.
+const STORM_SEQUENCE_ENABLED: bool = false;
+/// Time to sweep from light to heavy rain, in logic loop ticks (1 minute)
+const STORM_BUILD_TICKS: u32 = 60 * LOGIC_RATE_HZ;
+/// Time at heavy rain before easing back (30 seconds)
+const STORM_HOLD_TICKS: u32 = 30 * LOGIC_RATE_HZ;
+/// Time to ease back to light rain (2 minutes), `None` stays heavy until Z
+/// is pressed again
+const STORM_EASE_TICKS: Option<u32> = Some(120 * LOGIC_RATE_HZ);
+/// Shape of the storm's sweeps
+const STORM_CURVE: FadeCurve = FadeCurve::Linear;
+
+/// What pulse output 2 shows, thresholds are at [`RAIN_EVENT_THRESHOLD`]
+const PULSE2_SOURCE: PulseSource = PulseSource::Busy;
+/// Intensity where the rain starts counting as heavy for pulse output 2,
+/// halfway from medium to heavy
+const RAIN_EVENT_THRESHOLD: i32 = Sample::MAX / 2;
+/// How far intensity has to go past the threshold to switch, so small
+/// wanders around it don't fire repeatedly
+const RAIN_EVENT_HYSTERESIS: i32 = 128;
+/// Length of rain event triggers, in samples (10ms)
+const RAIN_EVENT_TRIGGER_SAMPLES: u32 = SAMPLE_RATE_HZ / 100;
+
+/// Z presses tap the clock output's tempo instead of locking the main knob.
+/// Two taps set a tempo, which holds until tapped again.
+const TAP_TEMPO_ENABLED: bool = false;
+/// Number of tap intervals averaged into the tempo
+const TAP_TEMPO_TAPS: usize = 4;
+/// Longest gap between taps (2 seconds, 30 BPM), a longer pause starts over
+const TAP_TEMPO_TIMEOUT_TICKS: u32 = 2 * LOGIC_RATE_HZ;
+
+/// Play the thunder recording ([`audio::THUNDER`]) over the rain, once per
+/// rising edge on pulse input 1
+const THUNDER_ENABLED: bool = false;
+/// Z presses also trigger thunder, instead of locking the main knob. Tap
+/// tempo and the storm sequence take Z presses first, if enabled.
+const THUNDER_ON_Z: bool = false;
+/// Rate pulse_input_loop() polls pulse input 1, fast enough to catch 1ms
+/// triggers
+const PULSE_INPUT_RATE_HZ: u32 = 4_000;
+/// Pulse input edges this soon after a trigger are ignored (0.25 seconds), so
+/// a fast clock doesn't keep restarting the thunder
+const THUNDER_HOLDOFF_TICKS: u32 = PULSE_INPUT_RATE_HZ / 4;
+
+/// Map intensity to the clock output tempo, faster as the rain gets heavier
+fn intensity_to_tempo(intensity: Sample) -> u32 {
+    let (light, medium, heavy) = CLOCK_TEMPO_RANGE;
+    let intensity = intensity.to_clamped();
+    let (far, distance) = if intensity >= 0 {
+        (heavy, intensity)
+    } else {
+        (light, -intensity)
+    };
+    let offset =
+        (i64::from(far) - i64::from(medium)) * i64::from(distance) / i64::from(Sample::OFFSET);
+    (i64::from(medium) + offset) as u32
+}
+
 #[embassy_executor::task]
 async fn logic_loop() {
     info!("Starting logic_loop()");
 
-    // local persistent intensity value, smoothed using Sample.update()
-    let mut smooth_intensity = Sample::from(0_i32);
+    // local persistent intensity value, rising faster than it falls
+    let (rise, fall) = Settings::default().intensity_slew;
+    let mut smooth_intensity = AsymmetricSlew::new(Sample::from(0_i32), rise, fall);
 
     let intensity_snd = INTENSITY.sender();
-    intensity_snd.send(Sample::new(0, false));
+    intensity_snd.send(Stamped::new(Sample::new(0, false), mode_epoch()));
 
     let mut lfo = TriangleWave11::new();
     let lfo_snd = LFO.sender();
@@ -244,22 +511,124 @@ async fn logic_loop() {
 
     let mut mux_rcv = MUX_INPUT.anon_receiver();
     let mut audio_rcv = AUDIO_INPUT.anon_receiver();
+    let mut settings_rcv = SETTINGS.anon_receiver();
+    let mut intensity_curve = Settings::default().intensity_curve;
+    let rain_states = RAIN_STATES.map(Sample::from);
+    // can't fail, the states are sorted
+    let rain_quantizer = unwrap!(Quantizer::new(&rain_states));
+    // none yet, start at the nearest
+    let mut rain_state = usize::MAX;
+
+    // pressing Z locks the main knob, so CV can drive intensity without
+    // knob bumps getting in the way
+    let mut main_knob = Pickup::new(PICKUP_THRESHOLD);
+    // treat Z as already down, so holding it at power on (for calibration)
+    // doesn't lock the knob
+    let mut z_was_down = true;
+    let mut last_zswitch = None;
+    let mut taps = TapTempo::<TAP_TEMPO_TAPS>::new(TAP_TEMPO_TIMEOUT_TICKS);
+    // only the crossings are used, not the output levels
+    let mut rain_events = Comparator::new(
+        Sample::from(RAIN_EVENT_THRESHOLD),
+        Sample::from(RAIN_EVENT_HYSTERESIS),
+        Sample::from(0_i32),
+        Sample::from(0_i32),
+    );
+    // clock output follows the rain until tapped
+    let mut tapped_tempo = None;
+    let mut storm = StormSequence::new(
+        STORM_BUILD_TICKS,
+        STORM_HOLD_TICKS,
+        STORM_EASE_TICKS,
+        STORM_CURVE,
+    );
+
+    // the first settings received are those loaded at power on
+    let mut restored = false;
+    let mut resume_state = (Sample::from(0_i32), false);
+    let mut resume_throttle = SaveThrottle::new(RESUME_SETTLE_TICKS, RESUME_INTERVAL_TICKS);
+    let resume_snd = RESUME_STATE.sender();
 
     let mut counter = 0_usize;
-    let mut ticker = Ticker::every(Duration::from_hz(480));
+    let mut ticker = Ticker::every(Duration::from_hz(LOGIC_RATE_HZ.into()));
     loop {
         counter = counter.wrapping_add(1);
+        taps.tick();
 
         // update LFO slowly
-        if counter % 2_usize.pow(6) == 0 {
+        if counter.is_multiple_of(2_usize.pow(6)) {
             lfo.tick();
             lfo_snd.send(lfo.current());
         }
 
         // update intensity
         if let Some(mux_state) = mux_rcv.try_get() {
-            // map intensity directly to main knob to start
-            let mut intensity = mux_state.main_knob;
+            if let Some(settings) = settings_rcv.try_changed() {
+                intensity_curve = settings.intensity_curve;
+                let (rise, fall) = settings.intensity_slew;
+                smooth_intensity.set_shifts(rise, fall);
+                if RESUME_ENABLED && !restored {
+                    // the startup fade in covers the jump
+                    resume_state = (
+                        Sample::from(i32::from(settings.resume_intensity)),
+                        settings.resume_locked,
+                    );
+                    main_knob.restore(resume_state.0, resume_state.1);
+                    info!(
+                        "resuming at intensity {}, locked: {}",
+                        settings.resume_intensity, settings.resume_locked
+                    );
+                }
+                restored = true;
+            }
+
+            let z_down = matches!(mux_state.zswitch, ZSwitch::Momentary);
+            // Z press to save calibration isn't a lock toggle
+            if z_down && !z_was_down && !CALIBRATING.load(Ordering::Relaxed) {
+                if TAP_TEMPO_ENABLED {
+                    if let Some(interval) = taps.tap() {
+                        let tempo = tempo_for_interval(interval, LOGIC_RATE_HZ);
+                        tapped_tempo = Some(tempo);
+                        info!("tapped tempo: {} milliBPM", tempo);
+                    }
+                } else if STORM_SEQUENCE_ENABLED {
+                    match storm.stage() {
+                        SequenceStage::Idle => storm.start(),
+                        _ => storm.stop(),
+                    }
+                    info!("storm sequence: {}", storm.stage());
+                } else if THUNDER_ENABLED && THUNDER_ON_Z {
+                    THUNDER_TRIGGERS.add(1, Ordering::Relaxed);
+                    info!("thunder");
+                } else {
+                    main_knob.toggle_lock();
+                    info!("main knob lock: {}", main_knob.state());
+                }
+            }
+            z_was_down = z_down;
+            if last_zswitch.is_some_and(|zswitch| zswitch != mux_state.zswitch) {
+                MODE_EPOCH.store(mode_epoch().next().raw(), Ordering::Relaxed);
+            }
+            last_zswitch = Some(mux_state.zswitch);
+
+            // map intensity directly to main knob to start, offset by CV 1
+            // when patched
+            let knob = main_knob
+                .update(mux_state.main_knob)
+                .with_deadzone(MAIN_KNOB_DEADZONE);
+            let mut intensity = apply_cv(knob, &mux_state.cv1, Sample::from(CV1_DEPTH));
+            // a rolling storm takes over from the knob
+            if let Some(storm_intensity) = storm.tick() {
+                intensity = storm_intensity;
+            }
+            if RESUME_ENABLED && restored {
+                let locked = main_knob.state() == PickupState::Locked;
+                let moved = main_knob.held().to_clamped() - resume_state.0.to_clamped();
+                if locked != resume_state.1 || moved.abs() > RESUME_THRESHOLD {
+                    resume_state = (main_knob.held(), locked);
+                    resume_throttle.changed();
+                }
+            }
 
             if let Some(audio_state) = audio_rcv.try_get() {
                 // If cable plugged into audio1 input, then offset that signal
@@ -271,13 +640,151 @@ async fn logic_loop() {
                 }
             }
 
-            smooth_intensity.update(intensity);
-            intensity_snd.send(smooth_intensity);
+            if RAIN_STATES_ENABLED {
+                (intensity, rain_state) = rain_quantizer.quantize_hysteresis(
+                    intensity,
+                    rain_state,
+                    RAIN_STATE_HYSTERESIS,
+                );
+            }
+
+            // smoothing glides between rain states
+            let intensity = intensity_curve.apply(smooth_intensity.tick(intensity));
+            intensity_snd.send(Stamped::new(intensity, mode_epoch()));
+            if let Some(crossing) = rain_events.crossing(intensity) {
+                RAIN_EVENTS.add(1, Ordering::Relaxed);
+                RAIN_HEAVY.store(crossing == Crossing::Rising, Ordering::Relaxed);
+                info!("heavy rain threshold crossed: {}", crossing);
+            }
+            let tempo = tapped_tempo.unwrap_or_else(|| intensity_to_tempo(intensity));
+            CLOCK_PERIOD.store(period_for_tempo(tempo, SAMPLE_RATE_HZ), Ordering::Relaxed);
+        }
+        if resume_throttle.tick() {
+            resume_snd.send(resume_state);
+        }
+        ticker.next().await
+    }
+}
+
+/// Trigger thunder on rising edges at pulse input 1, with [`THUNDER_ENABLED`]
+#[embassy_executor::task]
+async fn pulse_input_loop(pulse1_pin: peripherals::PIN_2) {
+    if !THUNDER_ENABLED {
+        return;
+    }
+    info!("Starting pulse_input_loop()");
+
+    // pulse inputs are inverted, the pin is pulled low while the jack is high
+    let pulse1 = gpio::Input::new(pulse1_pin, gpio::Pull::Up);
+    let mut edge = EdgeTrigger::new(THUNDER_HOLDOFF_TICKS);
+    let mut ticker = Ticker::every(Duration::from_hz(PULSE_INPUT_RATE_HZ.into()));
+    loop {
+        if edge.update(pulse1.is_low()) {
+            THUNDER_TRIGGERS.add(1, Ordering::Relaxed);
+            info!("thunder");
         }
         ticker.next().await
     }
 }
 
+/// Load settings, and run output offset calibration if requested
+///
+/// Hold the Z switch down while powering up to calibrate. Both audio outputs
+/// are held at 0V, the X knob trims output 1 and the Y knob trims output 2.
+/// Release Z, adjust until a meter reads 0V, then press Z down again to save.
+///
+/// The Z switch is calibrated at the same time, from its readings held down,
+/// released and (if it's flicked up before saving) up.
+///
+/// Afterwards, saves the intensity to resume at with [`RESUME_ENABLED`].
+#[embassy_executor::task]
+async fn settings_loop(flash_peripheral: peripherals::FLASH) {
+    info!("Starting settings_loop()");
+
+    let mut store = SettingsStore::new(SettingsFlash::new_blocking(flash_peripheral));
+    let mut settings = store.load();
+    info!("loaded settings: {}", settings);
+    let settings_snd = SETTINGS.sender();
+    settings_snd.send(settings.clone());
+
+    let mut mux_rcv = MUX_INPUT.anon_receiver();
+    let mut ticker = Ticker::every(Duration::from_hz(60));
+    // wait for the first scan of the inputs
+    let mux_state = loop {
+        if let Some(mux_state) = mux_rcv.try_get() {
+            break mux_state;
+        }
+        ticker.next().await;
+    };
+    if !matches!(mux_state.zswitch, ZSwitch::Momentary) {
+        save_resume_state(&mut store, settings).await;
+        return;
+    }
+
+    info!("Starting output offset calibration");
+    CALIBRATING.store(true, Ordering::Relaxed);
+    let mut zswitch_calibration = SwitchCalibration::new();
+    let mut released = false;
+    loop {
+        ticker.next().await;
+        let Some(mux_state) = mux_rcv.try_get() else {
+            continue;
+        };
+        zswitch_calibration.observe(mux_state.zswitch.into(), mux_state.zswitch_level);
+        match mux_state.zswitch {
+            ZSwitch::Momentary if released => break,
+            ZSwitch::Momentary => (),
+            _ => released = true,
+        }
+        settings.output_trim = [
+            knob_to_trim(&mux_state.x_knob),
+            knob_to_trim(&mux_state.y_knob),
+        ];
+        settings_snd.send(settings.clone());
+    }
+
+    match zswitch_calibration.thresholds(settings.zswitch_thresholds) {
+        Ok(thresholds) => settings.zswitch_thresholds = thresholds,
+        Err(e) => warn!("Z switch not calibrated, positions too close: {}", e),
+    }
+    settings_snd.send(settings.clone());
+    match store.save(&settings) {
+        Ok(()) => info!("saved settings: {}", settings),
+        Err(e) => error!("error saving settings: {}", e),
+    }
+    CALIBRATING.store(false, Ordering::Relaxed);
+    save_resume_state(&mut store, settings).await;
+}
+
+/// Save each intensity and lock state logic_loop() sends for resuming
+async fn save_resume_state(store: &mut SettingsStore<'_>, mut settings: Settings) {
+    if !RESUME_ENABLED {
+        return;
+    }
+    let Some(mut resume_rcv) = RESUME_STATE.receiver() else {
+        error!("resume state already has a receiver, not saving it");
+        return;
+    };
+    loop {
+        let (intensity, locked) = resume_rcv.changed().await;
+        settings.resume_intensity = intensity.to_clamped() as i16;
+        settings.resume_locked = locked;
+        match store.save(&settings) {
+            Ok(()) => debug!(
+                "saved intensity {} to resume, locked: {}",
+                intensity, locked
+            ),
+            Err(e) => error!("error saving settings: {}", e),
+        }
+    }
+}
+
+/// Map a knob to an output trim of +/- [`Settings::MAX_TRIM`] DAC codes
+fn knob_to_trim(knob: &Sample) -> i16 {
+    let codes_per_step = Sample::OFFSET / i32::from(Settings::MAX_TRIM);
+    (knob.to_clamped() / codes_per_step) as i16
+}
+
 /// Rough LED brightness correction
 fn led_gamma(value: u16) -> u16 {
     // based on: https://github.com/TomWhitwell/Workshop_Computer/blob/main/Demonstrations%2BHelloWorlds/CircuitPython/mtm_computer.py
@@ -291,6 +798,23 @@ fn set_led(led: &mut pwm::PwmOutput, value: u16) {
         .unwrap_or_else(|_| error!("error setting LED 3 PWM to : {}", led_gamma(value)));
 }
 
+/// Rate of update_pwm_loop(), a multiple of [`LOGIC_RATE_HZ`]
+///
+/// LED brightness and CV out glide between intensity updates from
+/// logic_loop(), so a faster rate gives smoother fades.
+const LED_RATE_HZ: u32 = 2 * LOGIC_RATE_HZ;
+
+/// Falloff of the medium rain LED away from medium intensity
+///
+/// `PeakCurve::Sharp` makes the LED mark medium rain more distinctly.
+const MEDIUM_LED_CURVE: PeakCurve = PeakCurve::Linear;
+/// Brightness of the medium rain LED at full light or heavy rain
+const MEDIUM_LED_MINIMUM: u16 = 2048;
+/// Level LED peak decay per LED tick, out of
+/// [`wscomp::decay::DECAY_UNITY`]. Falls by half in about 0.3 seconds, lower
+/// values fall faster.
+const LEVEL_LED_DECAY: u16 = 65378;
+
 #[allow(clippy::too_many_arguments)]
 #[embassy_executor::task]
 async fn update_pwm_loop(
@@ -315,7 +839,7 @@ async fn update_pwm_loop(
     led_pwm_config.top = 40950;
 
     let pwm5 = pwm::Pwm::new_output_ab(led12_pwm_slice, led1_pin, led2_pin, led_pwm_config.clone());
-    let (Some(mut led1), Some(_led2)) = pwm5.split() else {
+    let (Some(mut led1), Some(mut led2)) = pwm5.split() else {
         error!("Error setting up LED PWM channels for 1 & 2");
         return;
     };
@@ -359,10 +883,37 @@ async fn update_pwm_loop(
     };
 
     let mut intensity_rcv = INTENSITY.anon_receiver();
+    // about one second
+    let mut intensity_timeout = FirstValueTimeout::new(LED_RATE_HZ);
+    // default to medium rain until logic_loop() sends a value
+    let mut intensity_ramp = Ramp::new(Sample::from(0_i32), LED_RATE_HZ / LOGIC_RATE_HZ);
     let mut lfo_rcv = LFO.anon_receiver();
-
-    let mut ticker = Ticker::every(Duration::from_hz(480));
+    let mut level_rcv = OUTPUT_LEVEL.anon_receiver();
+    let mut level_meter = PeakMeter::new(LEVEL_LED_DECAY);
+    let mut settings_rcv = SETTINGS.anon_receiver();
+    let mut routing = Settings::default().routing;
+    // the rain isn't available here, audio sources routed to CV are 0V
+    let mut signals = Signals::new();
+
+    let mut fault_counter = 0_u32;
+    let mut ticker = Ticker::every(Duration::from_hz(LED_RATE_HZ.into()));
     loop {
+        // blink intensity LEDs when inputs aren't being read, rather than
+        // silently showing stale values
+        if ADC_FAULT.load(Ordering::Relaxed) {
+            fault_counter = fault_counter.wrapping_add(1);
+            let level = if (fault_counter / (LED_RATE_HZ / 4)).is_multiple_of(2) {
+                U12_MAX
+            } else {
+                0
+            };
+            set_led(&mut led1, level);
+            set_led(&mut led3, level);
+            set_led(&mut led5, level);
+            ticker.next().await;
+            continue;
+        }
+
         // LEDs
         // set_led(&mut led1, Sample::from(0_i32).to_output_abs());
         // set_led(&mut led3, Sample::from(0_i32).to_output_abs());
@@ -370,50 +921,184 @@ async fn update_pwm_loop(
 
         // left three leds visualize rain intensity
 
-        if let Some(intensity) = intensity_rcv.try_get() {
-            // led2 represents heavy rain
-            if intensity > Sample::from(0_i32) {
-                set_led(&mut led1, intensity.to_output_abs());
-            } else {
-                set_led(&mut led1, Sample::from(0_i32).to_output_abs());
-            }
+        if let Some(intensity) = intensity_rcv
+            .try_changed()
+            .and_then(|intensity| intensity.fresh(mode_epoch()))
+        {
+            intensity_ramp.set_target(intensity);
+        }
+        if intensity_timeout.tick(intensity_rcv.try_get().is_some()) {
+            warn!("no intensity from logic_loop() yet, showing medium rain");
+        }
+        let intensity = intensity_ramp.tick();
 
-            // led4 represents medium rain
-            set_led(&mut led3, intensity.to_output_abs_inverted());
+        // led2 represents heavy rain
+        if intensity > Sample::from(0_i32) {
+            set_led(&mut led1, intensity.to_output_abs());
+        } else {
+            set_led(&mut led1, Sample::from(0_i32).to_output_abs());
+        }
 
-            // led 6 represents light rain
-            if intensity < Sample::from(0_i32) {
-                set_led(&mut led5, intensity.to_output_abs());
-            } else {
-                set_led(&mut led5, Sample::from(0_i32).to_output_abs());
-            }
+        // led4 represents medium rain
+        set_led(
+            &mut led3,
+            center_peak(intensity, MEDIUM_LED_CURVE, MEDIUM_LED_MINIMUM),
+        );
 
-            // set CV1 to intensity
-            cv1_pwm
-                .set_duty_cycle_fraction(intensity.to_output_inverted(), U12_MAX)
-                .unwrap_or_else(|_| {
-                    error!(
-                        "error setting CV1 PWM to : {}",
-                        intensity.to_output_inverted()
-                    )
-                });
+        // led 6 represents light rain
+        if intensity < Sample::from(0_i32) {
+            set_led(&mut led5, intensity.to_output_abs());
+        } else {
+            set_led(&mut led5, Sample::from(0_i32).to_output_abs());
+        }
 
-            // set CV2 and LED4 to LFO value
-            if let Some(lfo) = lfo_rcv.try_get() {
-                set_led(&mut led4, lfo.to_output());
-                cv2_pwm
-                    .set_duty_cycle_fraction(lfo.to_output_inverted(), U12_MAX)
-                    .unwrap_or_else(|_| {
-                        error!("error setting CV2 PWM to : {}", lfo.to_output_inverted())
-                    });
-            };
+        if let Some(settings) = settings_rcv.try_changed() {
+            routing = settings.routing;
+            warn_routing_conflicts(&routing);
+        }
+
+        // LED2 meters the rain mix, doubled as the rain rarely gets near full
+        // scale. Holding peaks keeps it from flickering.
+        if let Some(level) = level_rcv.try_get() {
+            let level = (level.to_output_abs() * 2).min(U12_MAX);
+            set_led(&mut led2, level_meter.process(level));
         }
 
+        // LED4 shows the LFO value
+        if let Some(lfo) = lfo_rcv.try_get() {
+            set_led(&mut led4, lfo.to_output());
+            signals.lfo = lfo;
+        };
+        signals.intensity = intensity;
+
+        // CV1 & CV2 as routed, by default intensity & LFO
+        let cv1 = routing.resolve(Destination::Cv1, &signals);
+        cv1_pwm
+            .set_duty_cycle_fraction(cv1.to_output_inverted(), U12_MAX)
+            .unwrap_or_else(|_| error!("error setting CV1 PWM to : {}", cv1.to_output_inverted()));
+        let cv2 = routing.resolve(Destination::Cv2, &signals);
+        cv2_pwm
+            .set_duty_cycle_fraction(cv2.to_output_inverted(), U12_MAX)
+            .unwrap_or_else(|_| error!("error setting CV2 PWM to : {}", cv2.to_output_inverted()));
+
         ticker.next().await
     }
 }
 
-// this loop should probably be moved into a shared library
+/// Report audio routed to the CV outputs, which can't carry it
+fn warn_routing_conflicts(routing: &Routing) {
+    for destination in routing.conflicts() {
+        warn!(
+            "{} is routed to {}, which only carries CV, it will be 0V",
+            routing.source(destination),
+            destination
+        );
+    }
+}
+
+/// input_loop() scans between die temperature readings
+const TEMPERATURE_INTERVAL_SCANS: usize = 60;
+
+/// Number of times input_loop() sets up the ADC before reporting a fault
+const ADC_INIT_ATTEMPTS: u8 = 5;
+
+/// Learn each CV input's 0V offset and subtract it
+const CV_CENTER_TRACKING_ENABLED: bool = false;
+/// Learns offsets of up to 64 steps from CV readings which stayed within 4
+/// steps for 2 seconds of scans, averaged over 2^6 scans
+const CV_CENTER_TRACKER: CenterTracker = CenterTracker::new(4, 120, 64, 6);
+
+/// Waits for the mux inputs to settle before reading
+///
+/// With a `settle_threshold`, unsettled mux reads are detected and waited for
+/// longer, rather than relying on the fixed settle delay alone. It's the
+/// largest difference between two reads of a settled mux channel, in ADC
+/// codes, above the ADC's noise. The Z switch is debounced as the default.
+///
+/// Knobs and CVs are oversampled, averaging back to back reads so single
+/// noisy conversions don't move them. CVs get more, as they're read from
+/// patched modules, the Z switch reads once as it's debounced anyway.
+const SCAN_TIMING: ScanTiming = ScanTiming {
+    mux_settle_micros: 20,
+    settle_threshold: Some(24),
+    resettle_micros: 40,
+    knob_oversampling: 2,
+    cv_oversampling: 4,
+    switch_oversampling: 1,
+    ..ScanTiming::default()
+};
+
+/// The mux address pins, probe pin and ADC, scanned by [`ComputerInputs`]
+struct MuxHardware<'d> {
+    adc: adc::Adc<'d, adc::Async>,
+    mux_io_1: adc::Channel<'d>,
+    mux_io_2: adc::Channel<'d>,
+    muxlogic_a: Output<'d>,
+    muxlogic_b: Output<'d>,
+    probe: Output<'d>,
+}
+
+impl InputHardware for MuxHardware<'_> {
+    type Error = adc::Error;
+
+    fn select(&mut self, a: bool, b: bool) {
+        self.muxlogic_a.set_level(Level::from(a));
+        self.muxlogic_b.set_level(Level::from(b));
+    }
+
+    fn set_probe(&mut self, high: bool) {
+        self.probe.set_level(Level::from(high));
+    }
+
+    async fn read(&mut self, pin: MuxPin) -> Result<u16, adc::Error> {
+        let channel = match pin {
+            MuxPin::Io1 => &mut self.mux_io_1,
+            MuxPin::Io2 => &mut self.mux_io_2,
+        };
+        self.adc.read(channel).await
+    }
+
+    async fn delay_micros(&mut self, micros: u32) {
+        Timer::after_micros(micros.into()).await;
+    }
+}
+
+/// How the normalization probe is driven and read, for detecting patched
+/// jacks
+const PROBE: ProbeConfig = ProbeConfig {
+    settle_micros: 200,
+    polarity: ProbePolarity::ActiveHigh,
+    threshold: 300,
+};
+
+/// Pin level with the probe driven (`true`) or idle
+fn probe_level(driven: bool) -> Level {
+    Level::from(driven == PROBE.polarity.probing_high())
+}
+
+/// Smoothing shift of each [`smooth_inputs()`] channel, from
+/// [`Settings::input_smoothing`]
+///
+/// Jacks smooth their probe reading like their raw reading, so plug
+/// detection still compares like with like.
+fn input_smoothing_shifts(shifts: [u8; INPUT_CHANNELS]) -> [u8; 7] {
+    let [main_knob, x_knob, y_knob, cv1, cv2] = shifts;
+    [main_knob, x_knob, y_knob, cv1, cv1, cv2, cv2]
+}
+
+/// `mux_state` with each input's own extra smoothing applied
+fn smooth_inputs(smoothing: &mut ChannelSmoothing<7>, mux_state: &MuxState) -> MuxState {
+    let mut smoothed = mux_state.clone();
+    smoothed.main_knob = smoothing.tick(0, mux_state.main_knob);
+    smoothed.x_knob = smoothing.tick(1, mux_state.x_knob);
+    smoothed.y_knob = smoothing.tick(2, mux_state.y_knob);
+    smoothed.cv1.raw = smoothing.tick(3, mux_state.cv1.raw);
+    smoothed.cv1.probe = smoothing.tick(4, mux_state.cv1.probe);
+    smoothed.cv2.raw = smoothing.tick(5, mux_state.cv2.raw);
+    smoothed.cv2.probe = smoothing.tick(6, mux_state.cv2.probe);
+    smoothed
+}
+
 #[allow(clippy::too_many_arguments)]
 #[embassy_executor::task]
 async fn input_loop(
@@ -421,6 +1106,7 @@ async fn input_loop(
     muxlogic_a_pin: peripherals::PIN_24,
     muxlogic_b_pin: peripherals::PIN_25,
     p_adc: peripherals::ADC,
+    temperature_sensor: peripherals::ADC_TEMP_SENSOR,
     mux_io_1_pin: peripherals::PIN_28,
     mux_io_2_pin: peripherals::PIN_29,
     audio1_pin: peripherals::PIN_27,
@@ -429,26 +1115,74 @@ async fn input_loop(
     info!("Starting input_loop()");
 
     // Normalization probe
-    let mut probe = Output::new(probe_pin, Level::Low);
+    let probe = Output::new(probe_pin, probe_level(false));
 
     // audio input setup (used for CV in this card)
     let mut audio1 = adc::Channel::new_pin(audio1_pin, gpio::Pull::None);
     let mut audio2 = adc::Channel::new_pin(audio2_pin, gpio::Pull::None);
     let mut audio_state = AudioState::default();
+    audio_state.audio1.set_probe(PROBE);
+    audio_state.audio2.set_probe(PROBE);
     let audio_snd = AUDIO_INPUT.sender();
 
-    // Set mux to read switch Z
-    let mut muxlogic_a = Output::new(muxlogic_a_pin, Level::Low);
-    let mut muxlogic_b = Output::new(muxlogic_b_pin, Level::Low);
+    // mux address, set by each scan
+    let muxlogic_a = Output::new(muxlogic_a_pin, Level::Low);
+    let muxlogic_b = Output::new(muxlogic_b_pin, Level::Low);
 
-    let mut adc_device = adc::Adc::new(p_adc, Irqs, adc::Config::default());
+    let mut p_adc = p_adc;
     let mut mux_io_1 = adc::Channel::new_pin(mux_io_1_pin, gpio::Pull::None);
-    let mut mux_io_2 = adc::Channel::new_pin(mux_io_2_pin, gpio::Pull::None);
+    let mux_io_2 = adc::Channel::new_pin(mux_io_2_pin, gpio::Pull::None);
+
+    // Adc::new() can't fail, so confirm the ADC works with a first conversion
+    // and reinitialize a few times before giving up.
+    let mut adc_retry = Retry::new(ADC_INIT_ATTEMPTS, 10, 500);
+    let adc_device = loop {
+        let mut adc_device = adc::Adc::new(&mut p_adc, Irqs, adc::Config::default());
+        match adc_device.read(&mut mux_io_1).await {
+            Ok(_) => break adc_device,
+            Err(e) => {
+                error!("ADC init check failed: {}", e);
+                drop(adc_device);
+                match adc_retry.failed() {
+                    RetryDecision::RetryAfter(delay_ms) => {
+                        Timer::after_millis(delay_ms.into()).await;
+                    }
+                    RetryDecision::GiveUp => {
+                        error!(
+                            "ADC init failed after {} attempts, input_loop() stopped",
+                            adc_retry.failures()
+                        );
+                        ADC_FAULT.store(true, Ordering::Relaxed);
+                        return;
+                    }
+                }
+            }
+        }
+    };
 
+    // created after the ADC, as ADC setup turns the sensor off
+    let mut temperature = adc::Channel::new_temp_sensor(temperature_sensor);
+
+    let mut inputs = ComputerInputs::new(
+        MuxHardware {
+            adc: adc_device,
+            mux_io_1,
+            mux_io_2,
+            muxlogic_a,
+            muxlogic_b,
+            probe,
+        },
+        SCAN_TIMING,
+        PROBE,
+    );
     let mut mux_state = MuxState::default();
     let mux_snd = MUX_INPUT.sender();
-    let mux_settle_micros = 20;
-    let probe_settle_micros = 200;
+    let mut settings_rcv = SETTINGS.anon_receiver();
+    let mut cv_centers = [CV_CENTER_TRACKER, CV_CENTER_TRACKER];
+    let mut smoothing = ChannelSmoothing::new(
+        Sample::from(0_i32),
+        input_smoothing_shifts(Settings::default().input_smoothing),
+    );
 
     let mut ticker = Ticker::every(Duration::from_hz(60));
     // read from physical knobs, inputs and switch, write to `mux_state`
@@ -456,14 +1190,15 @@ async fn input_loop(
         mux_state.sequence_counter = mux_state.sequence_counter.wrapping_add(1);
 
         // read audio inputs and normalization probe input
-        match adc_device.read(&mut audio1).await {
+        let hardware = inputs.hardware_mut();
+        match hardware.adc.read(&mut audio1).await {
             Ok(level) => {
                 audio_state.audio1.raw.update(level);
                 // info!("audio1: {}, {}", level, mux_state.audio1.to_output());
             }
             Err(e) => error!("ADC read failed, while reading audio1: {}", e),
         };
-        match adc_device.read(&mut audio2).await {
+        match hardware.adc.read(&mut audio2).await {
             Ok(level) => {
                 audio_state.audio2.raw.update(level);
                 // info!("audio2: {}, {}", level, mux_state.audio2.to_output());
@@ -471,134 +1206,76 @@ async fn input_loop(
             Err(e) => error!("ADC read failed, while reading audio2: {}", e),
         };
 
-        probe.set_high();
-        Timer::after_micros(mux_settle_micros).await;
-        match adc_device.read(&mut audio1).await {
+        hardware.probe.set_level(probe_level(true));
+        Timer::after_micros(SCAN_TIMING.mux_settle_micros.into()).await;
+        match hardware.adc.read(&mut audio1).await {
             Ok(level) => {
                 audio_state.audio1.probe.update(level);
                 // info!("audio1: {}, {}", level, mux_state.audio1.to_output());
             }
             Err(e) => error!("ADC read failed, while reading audio1: {}", e),
         };
-        match adc_device.read(&mut audio2).await {
+        match hardware.adc.read(&mut audio2).await {
             Ok(level) => {
                 audio_state.audio2.probe.update(level);
                 // info!("audio2: {}, {}", level, mux_state.audio2.to_output());
             }
             Err(e) => error!("ADC read failed, while reading audio2: {}", e),
         };
-        probe.set_low();
-
-        // read Main knob & cv1
-        muxlogic_a.set_low();
-        muxlogic_b.set_low();
-        // this seems to need a delay for pins to settle before reading.
-        Timer::after_micros(mux_settle_micros).await;
-
-        match adc_device.read(&mut mux_io_1).await {
-            Ok(level) => {
-                mux_state.main_knob.update(level);
-                // info!("M knob: {}, {}", level, mux_state.main_knob.to_output());
-            }
-            Err(e) => error!("ADC read failed, while reading Main: {}", e),
-        };
-
-        // read cv1 (inverted data)
-        match adc_device.read(&mut mux_io_2).await {
-            Ok(level) => {
-                mux_state.cv1.raw.update(level);
-                // info!("cv1: {}, {}", level, mux_state.cv1.raw.to_output());
-            }
-            Err(e) => error!("ADC read failed, while reading CV1: {}", e),
-        };
-        probe.set_high();
-        Timer::after_micros(probe_settle_micros).await;
-        match adc_device.read(&mut mux_io_2).await {
-            Ok(level) => {
-                mux_state.cv1.probe.update(level);
-                // info!("cv1: {}, {}", level, mux_state.cv1.probe.to_output());
-            }
-            Err(e) => error!("ADC read failed, while reading CV1: {}", e),
-        };
-        probe.set_low();
-        Timer::after_micros(probe_settle_micros).await;
-
-        // read X knob & cv2
-        // NOTE: X and Y appear to be swapped compared to how I read the logic table
-        // not sure why.... :/
-        muxlogic_a.set_high();
-        muxlogic_b.set_low();
-        // this seems to need a delay for pins to settle before reading.
-        Timer::after_micros(mux_settle_micros).await;
-
-        match adc_device.read(&mut mux_io_1).await {
-            Ok(level) => {
-                mux_state.x_knob.update(level);
-                // info!("x knob: {}, {}", level, mux_state.x_knob.to_output());
-            }
-            Err(e) => error!("ADC read failed, while reading X: {}", e),
-        };
+        hardware.probe.set_level(probe_level(false));
 
-        // read cv2 (inverted data)
-        match adc_device.read(&mut mux_io_2).await {
-            Ok(level) => {
-                mux_state.cv2.raw.update(level);
-                // info!("cv2: {}, {}", level, mux_state.cv2.raw.to_output());
-            }
-            Err(e) => error!("ADC read failed, while reading CV2: {}", e),
-        };
-        probe.set_high();
-        Timer::after_micros(probe_settle_micros).await;
-        match adc_device.read(&mut mux_io_2).await {
-            Ok(level) => {
-                mux_state.cv2.probe.update(level);
-                // info!("cv2: {}, {}", level, mux_state.cv2.probe.to_output());
-            }
-            Err(e) => error!("ADC read failed, while reading CV2: {}", e),
-        };
-        probe.set_low();
-        Timer::after_micros(probe_settle_micros).await;
-
-        // read Y knob
-        muxlogic_a.set_low();
-        muxlogic_b.set_high();
-        // this seems to need 1us delay for pins to 'settle' before reading.
-        Timer::after_micros(mux_settle_micros).await;
-
-        match adc_device.read(&mut mux_io_1).await {
-            Ok(level) => {
-                mux_state.y_knob.update(level);
-                // info!("y knob: {}, {}", level, mux_state.y_knob.to_output());
+        if let Some(settings) = settings_rcv.try_changed() {
+            inputs.set_switch_thresholds(settings.zswitch_thresholds);
+            smoothing.set_shifts(input_smoothing_shifts(settings.input_smoothing));
+        }
+        let readings = inputs
+            .scan(|input, e| error!("ADC read failed, while reading {}: {}", input, e))
+            .await;
+        mux_state.set_readings(readings);
+        if CV_CENTER_TRACKING_ENABLED {
+            for (center, cv) in cv_centers
+                .iter_mut()
+                .zip([&mut mux_state.cv1, &mut mux_state.cv2])
+            {
+                center.observe(cv.raw);
+                // correct both readings, so their difference still detects a
+                // cable the same way
+                cv.raw = center.correct(cv.raw);
+                cv.probe = center.correct(cv.probe);
             }
-            Err(e) => error!("ADC read failed, while reading Y: {}", e),
-        };
-
-        // read Z switch
-        muxlogic_a.set_high();
-        muxlogic_b.set_high();
-        // this seems to need 1us delay for pins to 'settle' before reading.
-        Timer::after_micros(mux_settle_micros).await;
-
-        match adc_device.read(&mut mux_io_1).await {
-            Ok(level) => {
-                // info!("MUX_IO_1 ADC: {}", level);
-                mux_state.zswitch = match level {
-                    level if level < 1000 => ZSwitch::Momentary,
-                    level if level > 3000 => ZSwitch::On,
-                    _ => ZSwitch::Off,
-                };
-            }
-            Err(e) => error!("ADC read failed, while reading Z: {}", e),
-        };
+        }
+        MUX_RESETTLES.store(inputs.resettles(), Ordering::Relaxed);
+
+        // temperature changes slowly, read it about once a second
+        if mux_state
+            .sequence_counter
+            .is_multiple_of(TEMPERATURE_INTERVAL_SCANS)
+        {
+            match inputs.hardware_mut().adc.read(&mut temperature).await {
+                Ok(level) => mux_state.die_temperature = Some(die_temperature_millicelsius(level)),
+                Err(e) => error!("ADC read failed, while reading temperature: {}", e),
+            };
+        }
 
         audio_snd.send(audio_state.clone());
-        mux_snd.send(mux_state.clone());
+        mux_snd.send(smooth_inputs(&mut smoothing, &mux_state));
 
         ticker.next().await;
         // yield_now().await;
     }
 }
 
+/// How often periodic_stats() reports
+const STATS_PERIOD_MS: u32 = 1000;
+/// Stats periods between reports of the audio rate's long run drift (1 minute)
+const DRIFT_REPORT_PERIODS: u32 = 60;
+
+/// Beep quietly on the audio outputs while there are faults
+const FAULT_BEEP_ENABLED: bool = true;
+/// Underruns in one stats period which count as a fault, occasional ones
+/// (like while flash is erased) are expected
+const FAULT_UNDERRUNS_PER_PERIOD: u32 = 100;
+
 #[embassy_executor::task]
 async fn periodic_stats() {
     info!("Starting periodic_stats()");
@@ -608,70 +1285,173 @@ async fn periodic_stats() {
     let mut last_sequence: usize = 0;
     let mut last_audio_counter: u32 = 0;
     let mut current_audio_counter: u32;
-
-    let mut ticker = Ticker::every(Duration::from_millis(1000));
+    let mut last_dac_timeouts: u32 = 0;
+    let mut last_underruns: u32 = 0;
+    let mut last_resettles: u32 = 0;
+    // timed by the system timer's crystal, rather than trusting the ticker
+    let mut drift = RateDrift::new(SAMPLE_RATE_HZ);
+    #[cfg(feature = "pwm_audio_clock")]
+    let mut clock_drift = RateDrift::new(SAMPLE_RATE_HZ);
+    #[cfg(feature = "pwm_audio_clock")]
+    let mut last_clock_ticks = AUDIO_CLOCK_TICKS.load(Ordering::Relaxed);
+    let mut last_reading = Instant::now();
+    let mut periods = 0_u32;
+
+    let mut ticker = Ticker::every(Duration::from_millis(STATS_PERIOD_MS.into()));
     loop {
         current_audio_counter = AUDIO_FREQ_COUNTER.load(Ordering::Relaxed);
+        let now = Instant::now();
+        #[cfg(feature = "pwm_audio_clock")]
+        let clock_ticks = AUDIO_CLOCK_TICKS.load(Ordering::Relaxed);
+        // the first reading has no start
+        if periods > 0 {
+            drift.record(
+                last_audio_counter,
+                current_audio_counter,
+                (now - last_reading).as_micros(),
+            );
+            #[cfg(feature = "pwm_audio_clock")]
+            clock_drift.record(
+                last_clock_ticks,
+                clock_ticks,
+                (now - last_reading).as_micros(),
+            );
+        }
+        #[cfg(feature = "pwm_audio_clock")]
+        {
+            last_clock_ticks = clock_ticks;
+        }
+        last_reading = now;
+        periods = periods.wrapping_add(1);
+        if periods.is_multiple_of(DRIFT_REPORT_PERIODS) {
+            if let (Some(millihz), Some(ppm)) = (drift.average_millihz(), drift.drift_ppm()) {
+                info!(
+                    "audio rate: {} mHz average, {} ppm from nominal",
+                    millihz, ppm
+                );
+            }
+            // the clock itself, the audio rate falls behind it if samples
+            // are late
+            #[cfg(feature = "pwm_audio_clock")]
+            if let (Some(millihz), Some(ppm)) =
+                (clock_drift.average_millihz(), clock_drift.drift_ppm())
+            {
+                info!(
+                    "audio clock: {} mHz average, {} ppm from nominal",
+                    millihz, ppm
+                );
+            }
+        }
         debug!("current_audio_counter: {}", current_audio_counter);
+        let audio_rate =
+            rate_per_second(last_audio_counter, current_audio_counter, STATS_PERIOD_MS);
         if let Some(mux_state) = mux_rcv.try_get() {
             info!(
-                "rates: input: {}, audio: {} per sec, max: {}",
+                "rates: input: {}, audio: {} per sec of {}, max: {}",
                 mux_state.sequence_counter - last_sequence,
-                current_audio_counter - last_audio_counter,
+                audio_rate,
+                SAMPLE_RATE_HZ,
                 AUDIO_MAX_TICKS.load(Ordering::Relaxed),
             );
+            if let Some(temperature) = mux_state.die_temperature {
+                info!("die temperature: {} m°C", temperature);
+            }
             last_sequence = mux_state.sequence_counter;
         } else {
             info!(
-                "rates: audio: {} per sec, max: {}",
-                current_audio_counter - last_audio_counter,
+                "rates: audio: {} per sec of {}, max: {}",
+                audio_rate,
+                SAMPLE_RATE_HZ,
                 AUDIO_MAX_TICKS.load(Ordering::Relaxed),
             );
         }
+        let dac_timeouts = DAC_TIMEOUTS.load(Ordering::Relaxed);
+        let new_dac_timeouts = dac_timeouts.wrapping_sub(last_dac_timeouts);
+        if new_dac_timeouts > 0 {
+            warn!(
+                "DMA DAC stalled and restarted: {} ({} total)",
+                new_dac_timeouts, dac_timeouts
+            );
+            last_dac_timeouts = dac_timeouts;
+        }
+        let underruns = AUDIO_UNDERRUNS.load(Ordering::Relaxed);
+        let new_underruns = underruns.wrapping_sub(last_underruns);
+        if new_underruns > 0 {
+            warn!("audio underruns: {} ({} total)", new_underruns, underruns);
+            last_underruns = underruns;
+        }
+        let resettles = MUX_RESETTLES.load(Ordering::Relaxed);
+        if resettles != last_resettles {
+            debug!(
+                "unsettled mux reads: {} ({} total)",
+                resettles.wrapping_sub(last_resettles),
+                resettles
+            );
+            last_resettles = resettles;
+        }
+        let fault = ADC_FAULT.load(Ordering::Relaxed)
+            || new_underruns > FAULT_UNDERRUNS_PER_PERIOD
+            || new_dac_timeouts > 0;
+        FAULT_BEEP.store(FAULT_BEEP_ENABLED && fault, Ordering::Relaxed);
         last_audio_counter = current_audio_counter;
 
         ticker.next().await
     }
 }
 
-/// Raw data ready to send to the DAC
+/// DAC codes ready to send to the DAC, audio1 on channel A and audio2 on B
 struct DACSamplePair {
     pub audio1: u16,
     pub audio2: u16,
 }
 
 impl DACSamplePair {
-    // DAC config bits
-    // 0: channel select 0 = A, 1 = B
-    // 1: unused
-    // 2: 0 = 2x gain, 1 = 1x
-    // 3: 0 = shutdown channel
-    const CONFIG1: u16 = 0b0011000000000000u16;
-    const CONFIG2: u16 = 0b1011000000000000u16;
-
-    fn new(sample1: u16, sample2: u16) -> Self {
+    /// New pair of DAC codes, with output offset `trim` (in DAC codes) applied
+    fn new(sample1: u16, sample2: u16, trim: [i16; 2]) -> Self {
         Self {
-            audio1: sample1 << 4 >> 4 | DACSamplePair::CONFIG1,
-            audio2: sample2 << 4 >> 4 | DACSamplePair::CONFIG2,
+            audio1: apply_trim(sample1, trim[0]),
+            audio2: apply_trim(sample2, trim[1]),
         }
     }
 }
 
 #[cfg(feature = "audio_sine")]
 mod audio {
+    pub const FLASH_SIZE: usize = 2 * 1024 * 1024;
     pub const AUDIO_LIGHT: &[u8; 12432] = include_bytes!("../data/sine_light.wav");
     pub const AUDIO_MEDIUM: &[u8; 12432] = include_bytes!("../data/sine_medium.wav");
     pub const AUDIO_HEAVY: &[u8; 12432] = include_bytes!("../data/sine_heavy.wav");
+    pub const THUNDER: Option<&[u8]> = Some(include_bytes!("../data/backyard_thunder_01.wav"));
+    // start offsets in samples, see mixer_loop()
+    pub const START_LIGHT: usize = 0;
+    pub const START_MEDIUM: usize = 0;
+    pub const START_HEAVY: usize = 0;
+    // samples at the end crossfaded into the start, 0 for recordings made to
+    // loop
+    pub const TAIL_LIGHT: usize = 0;
+    pub const TAIL_MEDIUM: usize = 0;
+    pub const TAIL_HEAVY: usize = 0;
 }
 
 #[cfg(feature = "audio_micro")]
 mod audio {
+    pub const FLASH_SIZE: usize = 2 * 1024 * 1024;
     pub const AUDIO_LIGHT: &[u8; 50320] =
         include_bytes!("../data/backyard_rain_light_loop_micro.wav");
     pub const AUDIO_MEDIUM: &[u8; 50320] =
         include_bytes!("../data/backyard_rain_medium_loop_micro.wav");
     pub const AUDIO_HEAVY: &[u8; 50320] =
         include_bytes!("../data/backyard_rain_heavy_loop_micro.wav");
+    pub const THUNDER: Option<&[u8]> = Some(include_bytes!("../data/backyard_thunder_01.wav"));
+    // start offsets in samples, at quiet points found from ADPCM block step sizes
+    pub const START_LIGHT: usize = 79599;
+    pub const START_MEDIUM: usize = 18369;
+    pub const START_HEAVY: usize = 79599;
+    // samples at the end crossfaded into the start, 0 for recordings made to
+    // loop
+    pub const TAIL_LIGHT: usize = 0;
+    pub const TAIL_MEDIUM: usize = 0;
+    pub const TAIL_HEAVY: usize = 0;
 }
 
 // default to "audio_2mb" if no other audio_* feature is set
@@ -681,122 +1461,699 @@ mod audio {
     feature = "audio_16mb"
 )))]
 mod audio {
+    pub const FLASH_SIZE: usize = 2 * 1024 * 1024;
     pub const AUDIO_LIGHT: &[u8; 461844] =
         include_bytes!("../data/backyard_rain_light_loop_short.wav");
     pub const AUDIO_MEDIUM: &[u8; 1067054] =
         include_bytes!("../data/backyard_rain_medium_loop_short.wav");
     pub const AUDIO_HEAVY: &[u8; 482464] =
         include_bytes!("../data/backyard_rain_heavy_loop_short.wav");
+    // no room left for thunder beside the rain
+    pub const THUNDER: Option<&[u8]> = None;
+    // start offsets in samples, at quiet points found from ADPCM block step sizes
+    pub const START_LIGHT: usize = 346970;
+    pub const START_MEDIUM: usize = 369421;
+    pub const START_HEAVY: usize = 626587;
+    // samples at the end crossfaded into the start, 0 for recordings made to
+    // loop
+    pub const TAIL_LIGHT: usize = 0;
+    pub const TAIL_MEDIUM: usize = 0;
+    pub const TAIL_HEAVY: usize = 0;
 }
 
 #[cfg(feature = "audio_16mb")]
 mod audio {
+    pub const FLASH_SIZE: usize = 16 * 1024 * 1024;
     pub const AUDIO_LIGHT: &[u8; 4696052] = include_bytes!("../data/backyard_rain_light_loop.wav");
     pub const AUDIO_MEDIUM: &[u8; 7428102] =
         include_bytes!("../data/backyard_rain_medium_loop.wav");
     pub const AUDIO_HEAVY: &[u8; 4053120] = include_bytes!("../data/backyard_rain_heavy_loop.wav");
+    pub const THUNDER: Option<&[u8]> = Some(include_bytes!("../data/backyard_thunder_01.wav"));
+    // start offsets in samples, at quiet points found from ADPCM block step sizes
+    pub const START_LIGHT: usize = 3918720;
+    pub const START_MEDIUM: usize = 8631389;
+    pub const START_HEAVY: usize = 2022631;
+    // samples at the end crossfaded into the start, 0 for recordings made to
+    // loop
+    pub const TAIL_LIGHT: usize = 0;
+    pub const TAIL_MEDIUM: usize = 0;
+    pub const TAIL_HEAVY: usize = 0;
 }
 
 // alternates for testing
 // const AUDIO_MEDIUM: &[u8; 123024] = include_bytes!("../data/sine_long.wav");
 
-/// A very simplistic WAVE parser, returns slice of samples in DATA chunk
+/// Layout of the bundled WAVs, read from their headers at compile time
+const AUDIO_FORMAT: AdpcmFormat = match adpcm_format(audio::AUDIO_LIGHT) {
+    Ok(format) => format,
+    Err(_) => core::panic!("light rain isn't a mono IMA ADPCM WAV"),
+};
+/// IMA ADPCM block size in bytes, for all bundled WAVs
+const BLOCK_SIZE: usize = AUDIO_FORMAT.block_size;
+/// Number of samples decoded from one ADPCM block
+const DECODED_BLOCK_LEN: usize = AUDIO_FORMAT.samples_per_block;
+
+/// Whether `wav` has the same block layout and sample rate as the light rain
+const fn same_blocks(wav: &[u8]) -> bool {
+    match adpcm_format(wav) {
+        Ok(format) => {
+            format.block_size == BLOCK_SIZE && format.sample_rate == AUDIO_FORMAT.sample_rate
+        }
+        Err(_) => false,
+    }
+}
+const _: () = core::assert!(
+    same_blocks(audio::AUDIO_MEDIUM) && same_blocks(audio::AUDIO_HEAVY),
+    "bundled WAVs must share a block size and sample rate"
+);
+const _: () = core::assert!(
+    match audio::THUNDER {
+        Some(wav) => same_blocks(wav),
+        None => true,
+    },
+    "bundled WAVs must share a block size and sample rate"
+);
+/// Decoded samples queued ahead of the mixer for each stream
 ///
-/// Assumes DATA chunk starts at offset 136, which is true for these specific files.
-/// Will panic if DATA not found.
-fn data_chunk(wav: &[u8]) -> &[u8] {
-    let mut offset = 12;
-    loop {
-        let chunk = &wav[offset..offset + 4];
-        let mut length_bytes = [0_u8; 4];
-        length_bytes.clone_from_slice(&wav[offset + 4..offset + 8]);
-        let length = u32::from_le_bytes(length_bytes) as usize;
-        if b"data" != chunk {
-            offset += length + 8;
-            continue;
+/// When a stream's queue drops to this many samples, the next block is
+/// decoded. Larger values give the mixer more freedom to spread block decodes
+/// across samples, at the cost of 2 bytes of RAM per sample per stream.
+const DECODE_AHEAD: usize = 256;
+/// Blocks in a row which fail to decode before a stream counts as failed
+const DECODE_FAILURE_LIMIT: u32 = 4;
+
+/// Play synthesized rain when none of the recordings can be played, rather
+/// than silence
+const FALLBACK_RAIN_ENABLED: bool = true;
+/// Level of the synthesized rain, quieter than the recordings
+const FALLBACK_RAIN_LEVEL: i32 = Sample::MAX / 4;
+/// Low pass of the synthesized rain, 2^4 samples (~3kHz)
+const FALLBACK_RAIN_SMOOTHING_SHIFT: u8 = 4;
+
+fn adpcm_to_stream(data: &[u8], sample_offset: usize, tail_samples: usize) -> AdpcmStream<'_> {
+    // IMA ADPCM files are 4 bits per sample, in blocks of BLOCK_SIZE, read
+    // from the WAV headers. This is ignoring any data after the end of the
+    // last full BLOCK_SIZE.. but in theory, IMA ADPCM DATA chunks should be a
+    // multiple of BLOCK_SIZE.
+    let blocks = adpcm_blocks::<BLOCK_SIZE>(data).unwrap_or_else(|e| {
+        // keep the other layers playing, this one is silent
+        error!("can't play WAV, using silence: {}", e);
+        &[]
+    });
+    info!("WAV ADPCM blocks: {}", blocks.len());
+    let tail_loop = TailLoop::new(blocks.len(), DECODED_BLOCK_LEN, tail_samples);
+    // skip whole blocks without decoding them, then samples within a block
+    let (skip_blocks, skip_samples) =
+        start_position(sample_offset, DECODED_BLOCK_LEN, tail_loop.cycle_blocks());
+    let mut stream = AdpcmStream {
+        blocks,
+        tail_loop,
+        next_block: skip_blocks,
+        queue: SampleQueue::new(DECODE_AHEAD),
+        health: StreamHealth::new(DECODE_FAILURE_LIMIT),
+    };
+    for _ in 0..skip_samples {
+        stream.next();
+    }
+    stream
+}
+
+/// Endless stream of decoded samples, cycling through ADPCM blocks
+///
+/// Decoded samples are queued, so the owner can decode the next block early
+/// with [`AdpcmStream::decode_block()`] when convenient.
+struct AdpcmStream<'a> {
+    blocks: &'a [[u8; BLOCK_SIZE]],
+    /// Loop length, and tail blocks crossfaded into the start of each cycle
+    tail_loop: TailLoop,
+    next_block: usize,
+    queue: SampleQueue<{ DECODED_BLOCK_LEN + DECODE_AHEAD }>,
+    health: StreamHealth,
+}
+
+impl AdpcmStream<'_> {
+    /// Number of decoded samples waiting to be played
+    fn queued(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Whether the recording is missing or keeps failing to decode
+    fn health(&self) -> StreamHealth {
+        self.health
+    }
+
+    /// Number of samples in one cycle of the recording
+    fn len(&self) -> usize {
+        self.tail_loop.cycle_blocks() * DECODED_BLOCK_LEN
+    }
+
+    /// Play from the first block again, dropping queued samples
+    fn restart(&mut self) {
+        self.next_block = 0;
+        self.queue.clear();
+    }
+
+    /// Decode the next ADPCM block into the queue
+    ///
+    /// Blocks in the tail crossfade decode two blocks, head and tail. A
+    /// stream without blocks queues silence.
+    fn decode_block(&mut self) {
+        if self.blocks.is_empty() {
+            self.health.record(false);
+            self.queue.extend(&[0; DECODED_BLOCK_LEN]);
+            return;
+        }
+        let index = self.next_block;
+        self.next_block = (index + 1) % self.tail_loop.cycle_blocks().max(1);
+        let mut adpcm_output_buffer = [0_i16; DECODED_BLOCK_LEN];
+        let decoded = decode_block(self.blocks.get(index), &mut adpcm_output_buffer);
+        self.health.record(decoded);
+        if let Some(tail) = self.tail_loop.tail_for(index) {
+            let mut tail_buffer = [0_i16; DECODED_BLOCK_LEN];
+            decode_block(self.blocks.get(tail), &mut tail_buffer);
+            for (sample, (head, tail)) in
+                adpcm_output_buffer.iter_mut().zip(tail_buffer).enumerate()
+            {
+                let (position, length) = self.tail_loop.fade(index, sample);
+                // full 16 bit samples, so shift down into Sample's range and
+                // back
+                let faded = fade_between(
+                    Sample::from(tail >> 4),
+                    Sample::from(*head >> 4),
+                    position,
+                    length,
+                    FADE_CURVE,
+                );
+                *head = (faded.to_clamped() << 4) as i16;
+            }
+        }
+        if self.queue.extend(&adpcm_output_buffer) < DECODED_BLOCK_LEN {
+            error!("decode queue overflow, dropped samples");
         }
-        info!("WAV DATA offset, size: {}, {}", offset, length);
-        return &wav[offset + 8..length];
     }
+
+    /// Next sample, decoding a block first if the queue ran dry
+    fn next(&mut self) -> i16 {
+        if self.queue.is_empty() {
+            self.decode_block();
+        }
+        self.queue.pop().unwrap_or(0)
+    }
+}
+
+/// Decode one ADPCM `block` into `output`, silence if it's missing or invalid
+///
+/// Returns whether it decoded.
+fn decode_block(block: Option<&[u8; BLOCK_SIZE]>, output: &mut [i16; DECODED_BLOCK_LEN]) -> bool {
+    let decoded = block.ok_or(wscomp::Error::BadAdpcmBlock).and_then(|block| {
+        adpcm_block_header(block)?;
+        decode_adpcm_ima_ms(block, false, output).map_err(|_| wscomp::Error::BadAdpcmBlock)
+    });
+    if let Err(e) = &decoded {
+        // play a block of silence rather than stopping the audio
+        error!("error decoding ADPCM block: {}", e);
+        output.fill(0);
+    }
+    decoded.is_ok()
 }
 
-fn adpcm_to_stream(data: &[u8], sample_offset: usize) -> impl Iterator<Item = i16> + use<'_> {
-    const BLOCK_SIZE: usize = 1024;
+/// Output resolution of the rain audio with the `reduced_resolution` feature
+#[cfg(feature = "reduced_resolution")]
+const REDUCED_RESOLUTION_BITS: u8 = 10;
 
-    // IMA ADPCM files are 4 bits per sample, these files have a consistent
-    // 1024 byte block size and the WAV DATA chunk starts at byte 136.
-    // It would probably be better to actually parse the WAV files if they
-    // were updatable... but... they aren't and this works for now.
-    // This is ignoring any data after the end of the last full BLOCK_SIZE..
-    // but in theory, IMA ADPCM DATA chunks should be a multiple of BLOCK_SIZE.
-    data_chunk(data)
-        .chunks_exact(BLOCK_SIZE)
-        .cycle()
-        .flat_map(|data| {
-            let mut adpcm_output_buffer = [0_i16; 2 * BLOCK_SIZE - 7];
-            decode_adpcm_ima_ms(data, false, &mut adpcm_output_buffer).unwrap();
-            adpcm_output_buffer
-        })
-        .skip(sample_offset)
+/// Playback speed of each rain layer, as a fixed point ratio
+///
+/// Slightly different speeds make the layers drift against each other, so
+/// the same parts of the loops rarely line up and repeats are harder to hear.
+/// About -0.3%, unity and +0.2%, a few cents of pitch. Corrected for the
+/// recordings' sample rate if it isn't [`SAMPLE_RATE_HZ`].
+const LAYER_SPEEDS: [u32; 3] = [UNITY_RATIO - 197, UNITY_RATIO, UNITY_RATIO + 131];
+
+/// Match the loudness of the three layers at startup, rather than playing
+/// the recordings at their own levels
+const AUTO_GAIN_ENABLED: bool = true;
+/// Samples of each layer measured for [`AUTO_GAIN_ENABLED`], from its start
+/// position (1 second)
+const AUTO_GAIN_ANALYSIS_SAMPLES: usize = SAMPLE_RATE_HZ as usize;
+
+/// Measure each layer and compute gains matching their loudness
+///
+/// Decodes [`AUTO_GAIN_ANALYSIS_SAMPLES`] of each layer, about 70ms of work
+/// per layer, before any audio plays.
+fn layer_gains() -> [Fixed; 3] {
+    if !AUTO_GAIN_ENABLED {
+        return [fixed::ONE; 3];
+    }
+    let layers: [(&[u8], usize, usize); 3] = [
+        (audio::AUDIO_LIGHT, audio::START_LIGHT, audio::TAIL_LIGHT),
+        (audio::AUDIO_MEDIUM, audio::START_MEDIUM, audio::TAIL_MEDIUM),
+        (audio::AUDIO_HEAVY, audio::START_HEAVY, audio::TAIL_HEAVY),
+    ];
+    let levels = layers.map(|(data, start, tail)| {
+        let mut stream = adpcm_to_stream(data, start, tail);
+        let samples = core::iter::from_fn(|| Some(Sample::from(stream.next() >> 4)));
+        rms_level(samples, AUTO_GAIN_ANALYSIS_SAMPLES)
+    });
+    let gains = level_match_gains(levels);
+    info!(
+        "layer levels: {}, {}, {}, gains (1.0 = {}): {}",
+        levels[0].to_clamped(),
+        levels[1].to_clamped(),
+        levels[2].to_clamped(),
+        fixed::ONE,
+        gains
+    );
+    gains
+}
+
+/// Z switch up plays all three layers at once ("full storm"), at
+/// [`FULL_STORM_LEVELS`], rather than soloing the layer chosen by the main knob
+const FULL_STORM_ENABLED: bool = false;
+/// Light, medium and heavy levels in full storm, summed then limited
+const FULL_STORM_LEVELS: [Fixed; 3] = [fixed::ONE * 3 / 4, fixed::ONE * 3 / 4, fixed::ONE];
+/// Full storm limiter release, 2^12 samples (~85ms)
+const FULL_STORM_RELEASE_SHIFT: u8 = 12;
+
+/// X and Y knobs morph between [`MORPH_CORNERS`] instead of the main knob's
+/// crossfade, and no longer set density and stereo width
+const MORPH_ENABLED: bool = false;
+/// Layer at each corner of the X/Y morph: X and Y fully counter-clockwise,
+/// X clockwise, Y clockwise, both clockwise
+const MORPH_CORNERS: [Layer; 4] = [Layer::Light, Layer::Medium, Layer::Medium, Layer::Heavy];
+
+/// X knob sets the output volume and Y muffles the rain, instead of density
+/// and stereo width. `MORPH_ENABLED` takes the knobs first.
+const VOLUME_MUFFLE_ENABLED: bool = false;
+/// Octaves the muffle's cutoff falls with Y fully clockwise, 5 is about 250Hz
+const MUFFLE_DEPTH: u8 = 5;
+/// Volume and muffle glide between knob readings over about `2^shift`
+/// samples (5ms), so they don't step audibly
+const VOLUME_MUFFLE_SMOOTHING_SHIFT: u8 = 8;
+
+/// Level of the thunder over the rain, [`Sample::MAX`] plays it as recorded
+const THUNDER_LEVEL: i32 = Sample::MAX;
+/// Turn the rain down to [`THUNDER_DUCK_LEVEL`] while thunder plays
+const THUNDER_DUCKS_RAIN: bool = false;
+/// Rain level while ducked, [`Sample::MAX`] is unchanged
+const THUNDER_DUCK_LEVEL: i32 = Sample::MAX / 2;
+/// Ducking glides over about `2^shift` samples (85ms)
+const THUNDER_DUCK_SHIFT: u8 = 12;
+
+/// Shape of the startup fade in, fault beeps and loop tail crossfades
+const FADE_CURVE: FadeCurve = FadeCurve::Linear;
+
+/// Length of the fade in from silence at power on (0.5 seconds)
+const STARTUP_FADE_SAMPLES: u32 = SAMPLE_RATE_HZ / 2;
+
+/// Mixer samples between updates of the rain density modulation and output
+/// level (750Hz)
+const DENSITY_TICK_SAMPLES: u32 = 64;
+/// Window of the output level meter (20ms)
+const METER_WINDOW_SAMPLES: usize = SAMPLE_RATE_HZ as usize / 50;
+/// Ticks between new random density targets (~3 seconds)
+const DENSITY_HOLD_TICKS: u32 = 2250;
+/// Density glide time constant, 2^9 ticks (~0.7 seconds)
+const DENSITY_SMOOTHING_SHIFT: u8 = 9;
+/// Most the crossfade intensity moves per sample, gliding between updates
+/// from logic_loop() (full range in ~20ms)
+const INTENSITY_SLEW_PER_SAMPLE: i32 = 4;
+
+/// Debug builds warn when the audio path peaks above this, within 1/16 of
+/// full scale
+#[cfg(debug_assertions)]
+const HEADROOM_WARN_ABOVE: u32 = (Sample::MAX - Sample::MAX / 16) as u32;
+
+/// Log when `sample` first takes `monitor` near full scale
+#[cfg(debug_assertions)]
+fn check_headroom(monitor: &mut HeadroomMonitor, point: &str, sample: Sample) {
+    if monitor.observe(sample.to_unclamped()) {
+        warn!(
+            "little headroom {}: peak {} of {}",
+            point,
+            monitor.peak(),
+            Sample::MAX
+        );
+    }
+}
+
+/// Parameter CV 2 offsets, like CV 1 offsets intensity
+const CV2_TARGET: CvTarget = CvTarget::Density;
+/// How much CV 2 moves its target, [`Sample::MAX`] is the knob's full range
+/// and negative values invert it
+const CV2_DEPTH: i32 = Sample::MAX;
+
+/// `knob` offset by CV 2, if it controls `target`
+fn cv2_modulated(knob: Sample, cv2: &JackSample, target: CvTarget) -> Sample {
+    if CV2_TARGET == target {
+        apply_cv(knob, cv2, Sample::from(CV2_DEPTH))
+    } else {
+        knob
+    }
+}
+
+/// Map the X knob to rain density modulation depth, 0..256 intensity steps
+///
+/// Fully counter-clockwise is off. Fully clockwise moves the crossfade by up
+/// to 1/8 of the distance between two layers.
+fn density_depth(knob: &Sample) -> Sample {
+    Sample::new((knob.to_clamped() - Sample::MIN) >> 4, false)
+}
+
+/// Map the Y knob to stereo width, mono when fully counter-clockwise
+fn knob_to_width(knob: &Sample) -> Sample {
+    Sample::new((knob.to_clamped() - Sample::MIN) / 2, false)
 }
 
 #[embassy_executor::task]
 async fn mixer_loop() {
     info!("Starting mixer_loop()");
 
-    // Create three iterators which produce full range i16 samples by decoding
-    // the ADPCM blocks and repeatedly cylcing through the data. Offset the
-    // starting samples with prime numbers, so the three buffers don't run out
-    // and process a full block at the same time.
-    let mut light_samples = adpcm_to_stream(audio::AUDIO_LIGHT, 0);
-    let mut medium_samples = adpcm_to_stream(audio::AUDIO_MEDIUM, 277);
-    let mut heavy_samples = adpcm_to_stream(audio::AUDIO_HEAVY, 691);
+    let [light_gain, medium_gain, heavy_gain] = layer_gains();
+
+    // Create three streams which produce full range i16 samples by decoding
+    // the ADPCM blocks and repeatedly cylcing through the data. Each starts
+    // at a quiet point in its loop (audio::START_*), so the rain doesn't
+    // begin mid-transient. Offset the starting samples with prime numbers,
+    // so the three buffers don't run out and process a full block at the
+    // same time.
+    let mut light_samples =
+        adpcm_to_stream(audio::AUDIO_LIGHT, audio::START_LIGHT, audio::TAIL_LIGHT);
+    let mut medium_samples = adpcm_to_stream(
+        audio::AUDIO_MEDIUM,
+        audio::START_MEDIUM + 277,
+        audio::TAIL_MEDIUM,
+    );
+    let mut heavy_samples = adpcm_to_stream(
+        audio::AUDIO_HEAVY,
+        audio::START_HEAVY + 691,
+        audio::TAIL_HEAVY,
+    );
+    let [mut light_speed, mut medium_speed, mut heavy_speed] = LAYER_SPEEDS.map(|speed| {
+        Resampler::new(speed_for_rate(
+            speed,
+            AUDIO_FORMAT.sample_rate,
+            SAMPLE_RATE_HZ,
+        ))
+    });
+    // thunder plays once per trigger over the rain, if this audio variant
+    // has room for it
+    if THUNDER_ENABLED && audio::THUNDER.is_none() {
+        warn!("no thunder recording with this audio feature");
+    }
+    let mut thunder_samples = audio::THUNDER
+        .filter(|_| THUNDER_ENABLED)
+        .map(|wav| adpcm_to_stream(wav, 0, 0));
+    let mut thunder_shot = OneShot::new(thunder_samples.as_ref().map_or(0, AdpcmStream::len));
+    let mut thunder_speed = Resampler::new(speed_for_rate(
+        UNITY_RATIO,
+        AUDIO_FORMAT.sample_rate,
+        SAMPLE_RATE_HZ,
+    ));
+    let mut thunder_triggers = THUNDER_TRIGGERS.load(Ordering::Relaxed);
+    let shift = THUNDER_DUCK_SHIFT;
+    let mut duck_slew = AsymmetricSlew::new(Sample::from(Sample::MAX), shift, shift);
+    // fade in from silence after power on
+    let mut fade_in = Ramp::new(Sample::from(0_i32), STARTUP_FADE_SAMPLES);
+    fade_in.set_target(Sample::from(Sample::MAX));
 
     let mut intensity_rcv = INTENSITY.anon_receiver();
-    let mut saw_value = 0u16;
+    // about one second of samples
+    let mut intensity_timeout = FirstValueTimeout::new(SAMPLE_RATE_HZ);
+    let mut settings_rcv = SETTINGS.anon_receiver();
+    let mut output_trim = Settings::default().output_trim;
+    let mut routing = Settings::default().routing;
+    let mut lfo_rcv = LFO.anon_receiver();
+    let mut signals = Signals::new();
+
+    // audio output 2 is a decorrelated copy of the rain, blended with the
+    // direct rain by the Y knob, from mono to wide stereo
+    let mut decorrelate_first = Decorrelator::<557>::new();
+    let mut decorrelate_second = Decorrelator::<331>::new();
+    let mut decorrelate_stages: [&mut dyn Processor; 2] =
+        [&mut decorrelate_first, &mut decorrelate_second];
+    let mut decorrelate = Chain::new(&mut decorrelate_stages);
+    let mut stereo_width = Sample::from(0_i32);
+
+    let mut level = Rms::<METER_WINDOW_SAMPLES>::new();
+    // peaks after mixing, after the stereo effects and before the DAC,
+    // compiled out of release builds
+    #[cfg(debug_assertions)]
+    let mut headroom = [const { HeadroomMonitor::new(HEADROOM_WARN_ABOVE) }; 3];
+    // 880Hz, 50ms every 2 seconds, -24dB
+    let mut fault_beep = Beep::new(
+        880,
+        SAMPLE_RATE_HZ,
+        SAMPLE_RATE_HZ / 20,
+        2 * SAMPLE_RATE_HZ,
+        Sample::from(Sample::MAX / 16),
+    );
+    fault_beep.set_fade_curve(FADE_CURVE);
+    let level_snd = OUTPUT_LEVEL.sender();
+
+    // slow random wander of the crossfade position, so steady settings don't
+    // sound static
+    let mut mux_rcv = MUX_INPUT.anon_receiver();
+    let mut density = SmoothNoise::new(0x5eed_4a1d, DENSITY_HOLD_TICKS, DENSITY_SMOOTHING_SHIFT);
+    let mut density_offset = Sample::from(0_i32);
+    let mut density_counter = 0_u32;
+    // Z switch up plays a single layer, selected by the main knob, or all
+    // three with FULL_STORM_ENABLED
+    let mut solo_layer = None;
+    let mut storm = false;
+    let mut storm_limiter = Limiter::new(FULL_STORM_RELEASE_SHIFT);
+    // X and Y knob positions with MORPH_ENABLED
+    let mut morph_position = None;
+    // full volume and open until the knobs are read
+    let volume_muffle = VOLUME_MUFFLE_ENABLED && !MORPH_ENABLED;
+    let mut volume_knob = Sample::from(Sample::MAX);
+    let mut muffle_knob = Sample::from(Sample::MIN);
+    let shift = VOLUME_MUFFLE_SMOOTHING_SHIFT;
+    let mut volume_slew = AsymmetricSlew::new(volume_knob, shift, shift);
+    let mut muffle_slew = AsymmetricSlew::new(muffle_knob, shift, shift);
+    let mut muffle = Muffle::new(MUFFLE_DEPTH);
+    let mut fallback_rain = RainNoise::new(
+        0x4a17_0f0e,
+        FALLBACK_RAIN_SMOOTHING_SHIFT,
+        Sample::from(FALLBACK_RAIN_LEVEL),
+    );
+    let mut fallback_active = false;
+    let mut epoch = mode_epoch();
+    let mut intensity = None;
+    let mut intensity_slew = Slew::new(INTENSITY_SLEW_PER_SAMPLE, INTENSITY_SLEW_PER_SAMPLE);
 
-    // TODO: need to smooth intensity changes over time
-    // let mut counter = 0_isize;
+    #[cfg(feature = "reduced_resolution")]
+    let mut dither = Noise::new(0x0d17_4e25);
 
     loop {
-        let mut light = light_samples
-            .next()
-            .expect("iterator over cycle() returned None somehow?!?!");
+        // decode at most one block per sample, for the emptiest stream at the
+        // watermark, so block decodes don't line up into one long sample
+        // thunder only while it's playing
+        let thunder_queued = match &thunder_samples {
+            Some(stream) if thunder_shot.is_playing() => stream.queued(),
+            _ => usize::MAX,
+        };
+        let queued = [
+            light_samples.queued(),
+            medium_samples.queued(),
+            heavy_samples.queued(),
+            thunder_queued,
+        ];
+        match refill_candidate(&queued, DECODE_AHEAD) {
+            Some(0) => light_samples.decode_block(),
+            Some(1) => medium_samples.decode_block(),
+            Some(2) => heavy_samples.decode_block(),
+            Some(3) => thunder_samples
+                .iter_mut()
+                .for_each(AdpcmStream::decode_block),
+            _ => (),
+        }
+
+        let mut light = light_speed.next(|| light_samples.next());
         // down sample from 16 to 12 bit
         light >>= 4;
-        let light = Sample::from(light);
+        let light = apply_gain(Sample::from(light), light_gain);
 
-        let mut medium = medium_samples
-            .next()
-            .expect("iterator over cycle() returned None somehow?!?!");
+        let mut medium = medium_speed.next(|| medium_samples.next());
         // down sample from 16 to 12 bit
         medium >>= 4;
-        let medium = Sample::from(medium);
+        let medium = apply_gain(Sample::from(medium), medium_gain);
 
-        let mut heavy = heavy_samples
-            .next()
-            .expect("iterator over cycle() returned None somehow?!?!");
+        let mut heavy = heavy_speed.next(|| heavy_samples.next());
         // down sample from 16 to 12 bit
         heavy >>= 4;
-        let heavy = Sample::from(heavy);
+        let heavy = apply_gain(Sample::from(heavy), heavy_gain);
 
-        let mut mixed = medium;
-        if let Some(intensity) = intensity_rcv.try_get() {
-            match intensity {
-                intensity if intensity >= Sample::from(0_i32) => {
-                    mixed = medium.scale_inverted(intensity) + heavy.scale(intensity)
+        let thunder = match &mut thunder_samples {
+            Some(stream) if thunder_shot.is_playing() => {
+                let mut thunder = thunder_speed.next(|| match thunder_shot.next() {
+                    Some(_) => stream.next(),
+                    None => 0,
+                });
+                // down sample from 16 to 12 bit
+                thunder >>= 4;
+                Sample::from(thunder).scale(Sample::from(THUNDER_LEVEL))
+            }
+            _ => Sample::from(0_i32),
+        };
+
+        density_counter = density_counter.wrapping_add(1);
+        if density_counter.is_multiple_of(DENSITY_TICK_SAMPLES) {
+            epoch = mode_epoch();
+            let mux_state = mux_rcv.try_get();
+            let depth = match &mux_state {
+                _ if MORPH_ENABLED || volume_muffle => Sample::from(0_i32),
+                Some(mux_state) => density_depth(&cv2_modulated(
+                    mux_state.x_knob,
+                    &mux_state.cv2,
+                    CvTarget::Density,
+                )),
+                None => Sample::from(0_i32),
+            };
+            density_offset = density.tick().scale(depth);
+            if MORPH_ENABLED {
+                // mux_state has X and Y the right way round, see wscomp::inputs
+                morph_position = mux_state
+                    .as_ref()
+                    .map(|mux_state| (mux_state.x_knob, mux_state.y_knob));
+            } else if volume_muffle {
+                if let Some(mux_state) = &mux_state {
+                    (volume_knob, muffle_knob) = (mux_state.x_knob, mux_state.y_knob);
                 }
-                _ => mixed = medium.scale_inverted(intensity.abs()) + light.scale(intensity.abs()),
+            } else if let Some(mux_state) = &mux_state {
+                stereo_width = knob_to_width(&cv2_modulated(
+                    mux_state.y_knob,
+                    &mux_state.cv2,
+                    CvTarget::Width,
+                ));
+            }
+            if let Some(lfo) = lfo_rcv.try_get() {
+                signals.lfo = lfo;
+            }
+            level_snd.send(level.level());
+            fault_beep.set_enabled(FAULT_BEEP.load(Ordering::Relaxed));
+            let triggers = THUNDER_TRIGGERS.load(Ordering::Relaxed);
+            if triggers != thunder_triggers {
+                thunder_triggers = triggers;
+                if let Some(stream) = &mut thunder_samples {
+                    stream.restart();
+                    thunder_shot.trigger();
+                }
+            }
+            (solo_layer, storm) = match mux_state {
+                Some(MuxState {
+                    zswitch: ZSwitch::On,
+                    ..
+                }) if FULL_STORM_ENABLED => (None, true),
+                Some(MuxState {
+                    zswitch: ZSwitch::On,
+                    main_knob,
+                    ..
+                }) => (Some(Layer::from_position(main_knob)), false),
+                _ => (None, false),
+            };
+        }
+
+        let received = intensity_rcv.try_get();
+        if intensity_timeout.tick(received.is_some()) {
+            warn!("no intensity from logic_loop() yet, playing medium rain");
+        }
+        // ignore intensity from before a mode change, keep playing the last
+        // one until logic_loop() catches up
+        if let Some(fresh) = received.and_then(|received| received.fresh(epoch)) {
+            intensity = Some(fresh);
+        }
+        // logic_loop() updates 100 times less often, glide between updates
+        // rather than stepping
+        let intensity = intensity.map(|intensity| intensity_slew.process(intensity));
+
+        let failed = all_failed(&[
+            light_samples.health(),
+            medium_samples.health(),
+            heavy_samples.health(),
+        ]);
+        if FALLBACK_RAIN_ENABLED && failed != fallback_active {
+            fallback_active = failed;
+            if failed {
+                error!("no rain recordings can be played, synthesizing rain");
+            } else {
+                info!("rain recordings playing again");
             }
         }
 
-        // saw from audio output 2, just because
-        saw_value += 16;
-        if saw_value > U12_MAX {
-            saw_value = 0
+        // default to medium rain until logic_loop() sends a value
+        let mut mixed = medium;
+        if fallback_active {
+            mixed = fallback_rain.next_sample();
+        } else if storm {
+            mixed = full_storm(light, medium, heavy, FULL_STORM_LEVELS, &mut storm_limiter);
+        } else if let Some(layer) = solo_layer {
+            mixed = solo(light, medium, heavy, layer);
+        } else if let Some((x, y)) = morph_position {
+            let corners = MORPH_CORNERS.map(|layer| solo(light, medium, heavy, layer));
+            mixed = morph4(corners, x, y);
+        } else if let Some(intensity) = intensity {
+            mixed = crossfade3(light, medium, heavy, intensity + density_offset);
+        }
+        let duck = if THUNDER_DUCKS_RAIN && thunder_shot.is_playing() {
+            THUNDER_DUCK_LEVEL
+        } else {
+            Sample::MAX
         };
+        mixed = mixed.scale(duck_slew.tick(Sample::from(duck))) + thunder;
+        if volume_muffle {
+            muffle.set_knob(muffle_slew.tick(muffle_knob));
+            let volume = knob_volume(volume_slew.tick(volume_knob));
+            mixed = muffle.process(mixed).scale(volume);
+        }
 
-        let dac_sample = DACSamplePair::new(mixed.to_output(), saw_value);
+        level.push(mixed);
+
+        let decorrelated = decorrelate.process(mixed);
+        let (left, right) = widen(mixed, decorrelated, stereo_width);
+        let beep = fault_beep.next_sample();
+        signals.rain = left + beep;
+        signals.rain_right = right + beep;
+        #[cfg(debug_assertions)]
+        {
+            check_headroom(&mut headroom[0], "post-mix", mixed);
+            check_headroom(&mut headroom[1], "post-effects", signals.rain);
+            check_headroom(&mut headroom[1], "post-effects", signals.rain_right);
+        }
+        signals.intensity = intensity.unwrap_or(Sample::from(0_i32));
+
+        if let Some(settings) = settings_rcv.try_changed() {
+            output_trim = settings.output_trim;
+            routing = settings.routing;
+        }
+
+        let fade = fade_level(fade_in.tick(), FADE_CURVE);
+        let left_output = routing.resolve(Destination::Audio1, &signals).scale(fade);
+        let right_output = routing.resolve(Destination::Audio2, &signals).scale(fade);
+        #[cfg(debug_assertions)]
+        {
+            check_headroom(&mut headroom[2], "pre-DAC", left_output);
+            check_headroom(&mut headroom[2], "pre-DAC", right_output);
+        }
+        let (left_output, right_output) = (left_output.to_output(), right_output.to_output());
+        #[cfg(feature = "reduced_resolution")]
+        let (left_output, right_output) = {
+            let step = 1 << (12 - REDUCED_RESOLUTION_BITS);
+            (
+                reduce_resolution(left_output, REDUCED_RESOLUTION_BITS, dither.next_tpdf(step)),
+                reduce_resolution(
+                    right_output,
+                    REDUCED_RESOLUTION_BITS,
+                    dither.next_tpdf(step),
+                ),
+            )
+        };
+
+        let dac_sample = if CALIBRATING.load(Ordering::Relaxed) {
+            // hold both outputs at 0V while offsets are measured
+            DACSamplePair::new(ZERO_VOLT_CODE, ZERO_VOLT_CODE, output_trim)
+        } else {
+            DACSamplePair::new(left_output, right_output, output_trim)
+        };
 
         // counter += 1;
         // if counter % 2_isize.pow(15) == 0 {
@@ -812,9 +2169,341 @@ async fn mixer_loop() {
 
 // ==== ==== CORE1 data and processing ==== ====
 
+/// Minimum time from CS low to the first clock edge, for slower DACs
+///
+/// The MCP4822 needs 15ns, which instruction timing already gives it.
+#[cfg(not(any(feature = "benchmark", feature = "dma_dac")))]
+const DAC_CS_SETUP_NS: u32 = 0;
+/// Minimum time from the last clock edge to CS high
+#[cfg(not(any(feature = "benchmark", feature = "dma_dac")))]
+const DAC_CS_HOLD_NS: u32 = 0;
+
+/// `DAC_CS_SETUP_NS` and `DAC_CS_HOLD_NS` in CPU cycles
+#[cfg(not(any(feature = "benchmark", feature = "dma_dac")))]
+#[derive(Clone, Copy)]
+struct CsDelay {
+    setup_cycles: u32,
+    hold_cycles: u32,
+}
+
+#[cfg(not(any(feature = "benchmark", feature = "dma_dac")))]
+impl CsDelay {
+    fn new() -> Self {
+        let clock_hz = clocks::clk_sys_freq();
+        CsDelay {
+            setup_cycles: delay_cycles(DAC_CS_SETUP_NS, clock_hz),
+            hold_cycles: delay_cycles(DAC_CS_HOLD_NS, clock_hz),
+        }
+    }
+}
+
+/// Busy wait at least `cycles`, not at all for 0
+#[cfg(not(any(feature = "benchmark", feature = "dma_dac")))]
+fn wait_cycles(cycles: u32) {
+    if cycles > 0 {
+        cortex_m::asm::delay(cycles);
+    }
+}
+
+/// SPI and chip select of the DAC
+#[cfg(not(any(feature = "benchmark", feature = "dma_dac")))]
+struct SpiDacBus<'d> {
+    spi: spi::Spi<'d, peripherals::SPI0, spi::Async>,
+    cs: Output<'d>,
+    cs_delay: CsDelay,
+}
+
+#[cfg(not(any(feature = "benchmark", feature = "dma_dac")))]
+impl DacBus for SpiDacBus<'_> {
+    type Error = spi::Error;
+
+    /// Send one word, blocking for the 2µs it takes at 8MHz
+    ///
+    /// Blocking keeps each sample's writes tight, without waking the
+    /// executor for every word.
+    async fn write_word(&mut self, word: u16) -> Result<(), spi::Error> {
+        self.cs.set_low();
+        wait_cycles(self.cs_delay.setup_cycles);
+        let result = self.spi.blocking_write(&word.to_be_bytes());
+        wait_cycles(self.cs_delay.hold_cycles);
+        self.cs.set_high();
+        result
+    }
+}
+
+/// Words in the DMA ring, two per sample (5.3ms)
+#[cfg(feature = "dma_dac")]
+const DMA_DAC_RING_WORDS: usize = 512;
+/// Words kept ahead of the DMA, which is the output latency (4ms)
+#[cfg(feature = "dma_dac")]
+const DMA_DAC_FILL_WORDS: u32 = 384;
+/// How long sample_write_loop() sleeps once the ring is full, about 96 words
+/// at 48kHz. The rest of the fill is slack for it waking late.
+#[cfg(feature = "dma_dac")]
+const DMA_DAC_REFILL_INTERVAL: Duration = Duration::from_millis(1);
+/// DMA channel sending the ring, matching the `DMA_CH0` peripheral
+#[cfg(feature = "dma_dac")]
+const DMA_DAC_CHANNEL: usize = 0;
+/// How long the DMA can go without sending a word before it's restarted, in
+/// timer ticks (2ms, half the fill)
+#[cfg(feature = "dma_dac")]
+const DMA_DAC_STALL_TICKS: u64 = embassy_time::TICK_HZ / 500;
+
+/// Ring of DAC words, aligned to its size as the DMA's address wrap needs
+#[cfg(feature = "dma_dac")]
+#[repr(C, align(1024))]
+struct DmaDacWords([u16; DMA_DAC_RING_WORDS]);
+
+#[cfg(feature = "dma_dac")]
+const _: () = core::assert!(
+    core::mem::size_of::<DmaDacWords>() == core::mem::align_of::<DmaDacWords>(),
+    "DmaDacWords alignment must match DMA_DAC_RING_WORDS"
+);
+
+#[cfg(feature = "dma_dac")]
+static DMA_DAC_WORDS: StaticCell<DmaDacWords> = StaticCell::new();
+
+/// DAC fed from a ring buffer by DMA, paced by DMA timer 0
+///
+/// SPI0 sends 16 bit frames, pulsing chip select between them itself, so
+/// each DAC word is a single DMA transfer. Words carry their channel, so an
+/// underrun that slips the ring by one doesn't swap the outputs.
+#[cfg(feature = "dma_dac")]
+struct DmaDac<'d> {
+    /// Kept to hold SPI0 configured, the DMA writes its data register
+    _spi: spi::Spi<'d, peripherals::SPI0, spi::Blocking>,
+    words: &'static mut DmaDacWords,
+    ring: DmaRing,
+    /// Words the DMA had read when its transfer count was last set
+    read_base: u32,
+    /// Notices the DMA no longer reading, such as after a SPI or pacing fault
+    stall: StallTimeout,
+}
+
+#[cfg(feature = "dma_dac")]
+impl<'d> DmaDac<'d> {
+    /// Start the DMA sending 0V to both outputs, at two words per sample
+    fn new(
+        spi: spi::Spi<'d, peripherals::SPI0, spi::Blocking>,
+        _dma: peripherals::DMA_CH0,
+        _cs_pin: peripherals::PIN_21,
+    ) -> Self {
+        let spi_regs = pac::SPI0;
+        spi_regs.cr1().modify(|w| w.set_sse(false));
+        // 16 bit frames, with SPH clear the PL022 raises CS between them
+        spi_regs.cr0().modify(|w| {
+            w.set_dss(15);
+            w.set_frf(0);
+            w.set_spo(false);
+            w.set_sph(false);
+        });
+        spi_regs.cr1().modify(|w| w.set_sse(true));
+        // chip select driven by SPI0 (SPI0_SS_N) rather than as a GPIO
+        pac::IO_BANK0.gpio(21).ctrl().write(|w| w.set_funcsel(1));
+
+        let clock_hz = clocks::clk_sys_freq();
+        let (x, y) = unwrap!(
+            pacing_fraction(2 * SAMPLE_RATE_HZ, clock_hz),
+            "no DMA pacing for the sample rate"
+        );
+        pac::DMA.timer(0).write(|w| {
+            w.set_x(x);
+            w.set_y(y);
+        });
+        info!(
+            "DMA DAC: {} mHz for {} Hz",
+            u64::from(clock_hz) * u64::from(x) * 500 / u64::from(y),
+            SAMPLE_RATE_HZ
+        );
+
+        let words = DMA_DAC_WORDS.init(DmaDacWords([0; DMA_DAC_RING_WORDS]));
+        for (index, word) in words.0.iter_mut().enumerate() {
+            let channel = if index % 2 == 0 {
+                DacChannel::A
+            } else {
+                DacChannel::B
+            };
+            *word = DacCommand::new(channel).value(ZERO_VOLT_CODE).to_word();
+        }
+
+        pac::DMA
+            .ch(DMA_DAC_CHANNEL)
+            .write_addr()
+            .write_value(spi_regs.dr().as_ptr() as u32);
+        let mut dac = DmaDac {
+            _spi: spi,
+            words,
+            ring: DmaRing::new(DMA_DAC_RING_WORDS, DMA_DAC_FILL_WORDS),
+            read_base: 0,
+            stall: StallTimeout::new(DMA_DAC_STALL_TICKS, 0, Instant::now().as_ticks()),
+        };
+        dac.start(0);
+        dac
+    }
+
+    /// Start the DMA sending the ring from word `read`, counting from 0
+    fn start(&mut self, read: u32) {
+        self.read_base = read;
+        let index = read as usize % DMA_DAC_RING_WORDS;
+        let channel = pac::DMA.ch(DMA_DAC_CHANNEL);
+        channel
+            .read_addr()
+            .write_value(self.words.0[index..].as_ptr() as u32);
+        channel.trans_count().write_value(u32::MAX);
+        // the ring contents must be in memory before the DMA reads them
+        core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::SeqCst);
+        channel.ctrl_trig().write(|w| {
+            w.set_data_size(pac::dma::vals::DataSize::SIZE_HALFWORD);
+            w.set_incr_read(true);
+            w.set_incr_write(false);
+            // wrap reads at the ring size, in bytes
+            w.set_ring_size((DMA_DAC_RING_WORDS * 2).trailing_zeros() as u8);
+            w.set_ring_sel(false);
+            // chaining to itself means no chaining
+            w.set_chain_to(DMA_DAC_CHANNEL as u8);
+            w.set_treq_sel(pac::dma::vals::TreqSel::TIMER0);
+            w.set_irq_quiet(true);
+            w.set_en(true);
+        });
+    }
+
+    /// Abort a DMA which stopped sending, and start it again where it stopped
+    fn restart(&mut self, read: u32) {
+        let mask = 1 << DMA_DAC_CHANNEL;
+        pac::DMA.chan_abort().write(|w| w.set_chan_abort(mask));
+        while pac::DMA.chan_abort().read().chan_abort() & mask != 0 {}
+        DAC_TIMEOUTS.add(1, Ordering::Relaxed);
+        self.start(read);
+    }
+
+    /// Words the DMA has read since it started, wrapping
+    fn read_words(&mut self) -> u32 {
+        let channel = pac::DMA.ch(DMA_DAC_CHANNEL);
+        if !channel.ctrl_trig().read().busy() {
+            // the transfer count ran out, after about 12 hours, restart it
+            // where it stopped
+            self.read_base = self.read_base.wrapping_add(u32::MAX);
+            channel.trans_count().write_value(u32::MAX);
+            channel.ctrl_trig().modify(|w| w.set_en(true));
+        }
+        let remaining = channel.trans_count().read();
+        self.read_base.wrapping_add(u32::MAX - remaining)
+    }
+
+    /// Put the next samples in the ring, waiting for room
+    async fn write(&mut self, pair: &DACSamplePair) {
+        loop {
+            let read = self.read_words();
+            if self.stall.check(read, Instant::now().as_ticks()) {
+                self.restart(read);
+            }
+            let refill = self.ring.refill(read);
+            if refill.underrun {
+                AUDIO_UNDERRUNS.add(1, Ordering::Relaxed);
+            }
+            if refill.words >= 2 {
+                let words = [
+                    DacCommand::new(DacChannel::A).value(pair.audio1).to_word(),
+                    DacCommand::new(DacChannel::B).value(pair.audio2).to_word(),
+                ];
+                for (offset, word) in words.into_iter().enumerate() {
+                    let index = (refill.start + offset) % DMA_DAC_RING_WORDS;
+                    // the DMA reads the ring as it goes, keep every write
+                    unsafe { core::ptr::write_volatile(&mut self.words.0[index], word) };
+                }
+                core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::SeqCst);
+                self.ring.wrote(words.len());
+                return;
+            }
+            Timer::after(DMA_DAC_REFILL_INTERVAL).await;
+        }
+    }
+}
+
+/// PWM slice timing sample_write_loop(), matching the `PWM_SLICE0` peripheral
+#[cfg(feature = "pwm_audio_clock")]
+const AUDIO_CLOCK_SLICE: usize = 0;
+
+#[cfg(feature = "pwm_audio_clock")]
+#[cortex_m_rt::interrupt]
+fn PWM_IRQ_WRAP() {
+    pac::PWM.intr().write(|w| w.set_ch0(true));
+    AUDIO_CLOCK_TICKS.add(1, Ordering::Relaxed);
+    AUDIO_CLOCK_WAKER.wake();
+}
+
+/// Sample clock from a PWM slice wrapping at `SAMPLE_RATE_HZ`
+///
+/// The slice's fractional divider gets much closer to the rate than
+/// embassy's 1MHz ticker, and its interrupt wakes sample_write_loop() without
+/// waiting on the timer queue. Like [`Ticker`], it catches up on ticks
+/// missed while the loop ran late.
+#[cfg(feature = "pwm_audio_clock")]
+struct PwmAudioClock<'d> {
+    /// Kept to hold the slice running
+    _pwm: pwm::Pwm<'d>,
+    ticks_seen: u32,
+}
+
+#[cfg(feature = "pwm_audio_clock")]
+impl<'d> PwmAudioClock<'d> {
+    /// Start the clock, its interrupt runs on the calling core
+    fn new(slice: peripherals::PWM_SLICE0) -> Self {
+        let clock_hz = clocks::clk_sys_freq();
+        let wrap = unwrap!(
+            PwmWrap::for_rate(SAMPLE_RATE_HZ, clock_hz),
+            "no PWM audio clock for the sample rate"
+        );
+        let mut config = pwm::Config::default();
+        config.top = wrap.top;
+        config.enable = false;
+        let pwm = pwm::Pwm::new_free(slice, config);
+        // the 8.4 fractional divider, which pwm::Config only takes through
+        // the fixed crate
+        let channel = pac::PWM.ch(AUDIO_CLOCK_SLICE);
+        channel.div().write(|w| {
+            w.set_int((wrap.divider >> 4) as u8);
+            w.set_frac((wrap.divider & 0xF) as u8);
+        });
+        info!(
+            "PWM audio clock: {} mHz for {} Hz",
+            wrap.millihz(clock_hz),
+            SAMPLE_RATE_HZ
+        );
+
+        let ticks_seen = AUDIO_CLOCK_TICKS.load(Ordering::Relaxed);
+        pac::PWM.intr().write(|w| w.set_ch0(true));
+        pac::PWM.inte().modify(|w| w.set_ch0(true));
+        interrupt::PWM_IRQ_WRAP.unpend();
+        // nothing else uses the interrupt, so there's no critical section to
+        // break
+        unsafe { interrupt::PWM_IRQ_WRAP.enable() };
+        channel.csr().modify(|w| w.set_en(true));
+        PwmAudioClock {
+            _pwm: pwm,
+            ticks_seen,
+        }
+    }
+
+    /// Wait for the next tick, or return at once for one already missed
+    async fn next(&mut self) {
+        core::future::poll_fn(|cx| {
+            AUDIO_CLOCK_WAKER.register(cx.waker());
+            if AUDIO_CLOCK_TICKS.load(Ordering::Relaxed) != self.ticks_seen {
+                self.ticks_seen = self.ticks_seen.wrapping_add(1);
+                core::task::Poll::Ready(())
+            } else {
+                core::task::Poll::Pending
+            }
+        })
+        .await
+    }
+}
+
 /// Audio sample writing loop
 ///
 /// Runs on the second core (CORE1), all shared data must be safe for concurrency.
+#[allow(clippy::too_many_arguments)]
 #[embassy_executor::task]
 async fn sample_write_loop(
     spi0: peripherals::SPI0,
@@ -824,70 +2513,147 @@ async fn sample_write_loop(
     cs_pin: peripherals::PIN_21,
     pulse1_pin: peripherals::PIN_8, // maybe temp, for measuring sample rate
     pulse2_pin: peripherals::PIN_9,
+    clock_slice: peripherals::PWM_SLICE0,
 ) {
     info!("Starting sample_write_loop()");
-    let mut local_counter = 0u32;
-    let mut local_max_ticks = 0u32;
+    // reset max about once a second, for better reporting
+    let mut stats = LoopStats::new(SAMPLE_RATE_HZ);
     let mut previous_loop_end = Instant::now();
 
     // pulse setup
     let mut pulse1 = Output::new(pulse1_pin, Level::High);
     let mut pulse2 = Output::new(pulse2_pin, Level::High);
+    // counted in samples here, rather than in logic_loop(), for low jitter
+    let mut clock = PulseClock::new(
+        period_for_tempo(intensity_to_tempo(Sample::from(0_i32)), SAMPLE_RATE_HZ),
+        CLOCK_PULSE_SAMPLES,
+    );
+    let mut rain_events_seen = RAIN_EVENTS.load(Ordering::Relaxed);
+    let mut rain_heavy = false;
+    let mut trigger_remaining = 0_u32;
 
     // DAC setup
     let mut config = spi::Config::default();
     config.frequency = 8_000_000;
 
-    let mut spi = spi::Spi::new_txonly(spi0, clk, mosi, dma0, config);
-    let mut cs = Output::new(cs_pin, Level::High);
+    #[cfg(not(any(feature = "benchmark", feature = "dma_dac")))]
+    let mut dac = Mcp4822::new(SpiDacBus {
+        spi: spi::Spi::new_txonly(spi0, clk, mosi, dma0, config),
+        cs: Output::new(cs_pin, Level::High),
+        cs_delay: CsDelay::new(),
+    });
+    #[cfg(feature = "dma_dac")]
+    let mut dac = DmaDac::new(
+        spi::Spi::new_blocking_txonly(spi0, clk, mosi, config),
+        dma0,
+        cs_pin,
+    );
+    // leave the DAC idle, only the cost of producing samples is measured
+    #[cfg(feature = "benchmark")]
+    let _ = (spi0, clk, mosi, dma0, cs_pin, config);
 
     // Since embassy_rp only supports a fixed 1_000_000 hz tick rate, we can
-    // only approximate 48_000 hz. Measured at ~ 47_630, with significant jitter.
-    // TODO: look into configuring a custom interrupt and running this task
-    // from it. (Or maybe even just outside of embassy?)
-    let mut ticker = Ticker::every(Duration::from_hz(48_000));
+    // only approximate SAMPLE_RATE_HZ, 47_619 hz for 48_000. Measured at
+    // ~ 47_630, with significant jitter.
+    // The pwm_audio_clock feature paces this loop from a PWM interrupt
+    // instead, and dma_dac sidesteps it, the DMA paces the DAC and this loop
+    // only keeps up.
+    #[cfg(not(any(feature = "dma_dac", feature = "pwm_audio_clock")))]
+    info!(
+        "audio ticker: {} mHz for {} Hz",
+        ticker_millihz(AUDIO_TICKER_PERIOD, embassy_time::TICK_HZ as u32),
+        SAMPLE_RATE_HZ
+    );
+    #[cfg(not(any(
+        feature = "benchmark",
+        feature = "dma_dac",
+        feature = "pwm_audio_clock"
+    )))]
+    let mut ticker = Ticker::every(Duration::from_ticks(AUDIO_TICKER_PERIOD.into()));
+    #[cfg(feature = "pwm_audio_clock")]
+    let mut ticker = PwmAudioClock::new(clock_slice);
+    #[cfg(not(feature = "pwm_audio_clock"))]
+    let _ = clock_slice;
     loop {
-        pulse1.toggle();
-        pulse2.set_high();
-        local_counter += 1;
+        // pulse outputs are inverted, low is +5V
+        pulse1.set_level(if clock.tick() {
+            Level::Low
+        } else {
+            Level::High
+        });
+        match PULSE2_SOURCE {
+            PulseSource::Busy => pulse2.set_high(),
+            PulseSource::ThresholdTriggers => {
+                pulse2.set_level(if trigger_remaining > 0 {
+                    Level::Low
+                } else {
+                    Level::High
+                });
+                trigger_remaining = trigger_remaining.saturating_sub(1);
+            }
+            PulseSource::ThresholdGate => {
+                pulse2.set_level(if rain_heavy { Level::Low } else { Level::High })
+            }
+        }
 
-        if local_counter % 16 == 0 {
-            AUDIO_FREQ_COUNTER.store(local_counter, Ordering::Relaxed);
+        if stats.count().is_multiple_of(16) {
+            AUDIO_FREQ_COUNTER.store(stats.count(), Ordering::Relaxed);
+            match CLOCK_PERIOD.load(Ordering::Relaxed) {
+                0 => (),
+                period => clock.set_period(period),
+            }
+            let rain_events = RAIN_EVENTS.load(Ordering::Relaxed);
+            if rain_events != rain_events_seen {
+                rain_events_seen = rain_events;
+                trigger_remaining = RAIN_EVENT_TRIGGER_SAMPLES;
+            }
+            rain_heavy = RAIN_HEAVY.load(Ordering::Relaxed);
         }
 
+        // everything is waiting on mixer_loop() while benchmarking
+        #[cfg(not(any(feature = "benchmark", feature = "dma_dac")))]
+        if AUDIO_OUT_SAMPLES.is_empty() {
+            AUDIO_UNDERRUNS.add(1, Ordering::Relaxed);
+        }
         let dac_sample_pair = AUDIO_OUT_SAMPLES.receive().await;
 
-        cs.set_low();
-        spi.blocking_write(&dac_sample_pair.audio1.to_be_bytes())
-            .unwrap_or_else(|e| error!("error writing buff a to DAC: {}", e));
-        cs.set_high();
-        cs.set_low();
-        spi.blocking_write(&dac_sample_pair.audio2.to_be_bytes())
-            .unwrap_or_else(|e| error!("error writing buff b to DAC: {}", e));
-        cs.set_high();
-
-        // update max ticks this loop has ever taken
+        #[cfg(not(any(feature = "benchmark", feature = "dma_dac")))]
+        {
+            if let Err(e) = dac.write_a(dac_sample_pair.audio1).await {
+                error!("error writing to DAC: {}", e);
+            }
+            if let Err(e) = dac.write_b(dac_sample_pair.audio2).await {
+                error!("error writing to DAC: {}", e);
+            }
+        }
+        // with dma_dac the ticker's jitter is gone, the loop runs in bursts
+        // and AUDIO_MAX_TICKS below is the gap between them
+        #[cfg(feature = "dma_dac")]
+        dac.write(&dac_sample_pair).await;
+        // discard the samples, but make sure they're still computed
+        #[cfg(feature = "benchmark")]
+        core::hint::black_box((dac_sample_pair.audio1, dac_sample_pair.audio2));
+
+        // update max ticks this loop has taken in the current window
         let end = Instant::now();
         let diff = end.saturating_duration_since(previous_loop_end);
         // we're just going to hope a tick never takes more than 71.5 hours,
         // and deal with a rollover if it does
         let diff = diff.as_ticks() as u32;
         previous_loop_end = end;
-        // Using this local variable to only mess with locks when the values
-        // are actually different. Seems to make a small difference... ~15 ticks
-        // added to max if updating atomic each loop
-        if diff > local_max_ticks {
-            // fetch_max() also updates the atomic value to the max
-            AUDIO_MAX_TICKS.fetch_max(diff, Ordering::Relaxed);
-            local_max_ticks = diff;
-        }
-        // reset max every second, for better reporting
-        if local_counter % 48000 == 0 {
-            local_max_ticks = 0;
-            AUDIO_MAX_TICKS.store(0, Ordering::Relaxed);
+        let previous_max = stats.max_ticks();
+        stats.record(diff);
+        // Only mess with locks when the values are actually different. Seems
+        // to make a small difference... ~15 ticks added to max if updating
+        // atomic each loop
+        if stats.max_ticks() != previous_max {
+            AUDIO_MAX_TICKS.store(stats.max_ticks(), Ordering::Relaxed);
         }
 
-        pulse2.set_low();
+        if PULSE2_SOURCE == PulseSource::Busy {
+            pulse2.set_low();
+        }
+        #[cfg(not(any(feature = "benchmark", feature = "dma_dac")))]
         ticker.next().await
     }
 }
@@ -0,0 +1,264 @@
+//! Card settings persisted in the last two sectors of the program card's flash
+//!
+//! Saves alternate between the two sectors, see [`wscomp::storage`], so
+//! losing power while saving falls back to the previously saved settings.
+
+use defmt::*;
+use embassy_rp::flash::{self, Blocking, Flash, ERASE_SIZE};
+use embassy_rp::peripherals::FLASH;
+use wscomp::curve::Breakpoints;
+use wscomp::dac::check_trim;
+use wscomp::routing::Routing;
+use wscomp::slew::MAX_SLEW_SHIFT;
+use wscomp::storage::{decode_record, encode_record, newest_slot, RECORD_OVERHEAD};
+use wscomp::switch::SwitchThresholds;
+use wscomp::{Error, Sample};
+
+use crate::audio::FLASH_SIZE;
+
+/// Offsets of the two settings sectors from the start of flash
+const SLOT_OFFSETS: [u32; 2] = [
+    (FLASH_SIZE - 2 * ERASE_SIZE) as u32,
+    (FLASH_SIZE - ERASE_SIZE) as u32,
+];
+/// Identifies a settings record, change when the layout changes
+const MAGIC: [u8; 4] = *b"BRS8";
+const SETTINGS_LEN: usize = 4 + 4 * INTENSITY_CURVE_POINTS + 4 + 2 + 6 + 3 + INPUT_CHANNELS;
+/// Offset of [`Settings::routing`] in the serialized settings
+const ROUTING_OFFSET: usize = 4 + 4 * INTENSITY_CURVE_POINTS;
+/// Offset of [`Settings::intensity_slew`] in the serialized settings
+const SLEW_OFFSET: usize = ROUTING_OFFSET + 4;
+/// Offset of [`Settings::zswitch_thresholds`] in the serialized settings
+const ZSWITCH_OFFSET: usize = SLEW_OFFSET + 2;
+/// Offset of [`Settings::resume_intensity`] and [`Settings::resume_locked`]
+const RESUME_OFFSET: usize = ZSWITCH_OFFSET + 6;
+/// Offset of [`Settings::input_smoothing`] in the serialized settings
+const SMOOTHING_OFFSET: usize = RESUME_OFFSET + 3;
+const RECORD_LEN: usize = SETTINGS_LEN + RECORD_OVERHEAD;
+/// Number of points in [`Settings::intensity_curve`]
+pub const INTENSITY_CURVE_POINTS: usize = 5;
+/// Number of inputs in [`Settings::input_smoothing`]
+pub const INPUT_CHANNELS: usize = 5;
+
+pub type SettingsFlash<'d> = Flash<'d, FLASH, Blocking, FLASH_SIZE>;
+
+/// User adjustable settings which survive power cycles
+#[derive(Clone, Format)]
+pub struct Settings {
+    /// DC offset correction for audio outputs 1 & 2, in DAC codes
+    pub output_trim: [i16; 2],
+    /// Maps the combined knob/CV/LFO position to rain intensity
+    ///
+    /// The default is linear. For example, a wide medium plateau over the
+    /// middle half of the knob, with quicker transitions near the ends:
+    /// `[(MIN, MIN), (-1024, 0), (0, 0), (1024, 0), (MAX, MAX)]`
+    pub intensity_curve: Breakpoints<INTENSITY_CURVE_POINTS>,
+    /// Signal sent to each audio and CV output
+    pub routing: Routing,
+    /// Intensity smoothing time constants as `(rise, fall)` shifts, `2^shift`
+    /// logic loop ticks
+    ///
+    /// Rain builds quickly and trails off slowly. Shifts are at most
+    /// [`MAX_SLEW_SHIFT`].
+    pub intensity_slew: (u8, u8),
+    /// ADC levels separating the Z switch positions, set by calibration
+    pub zswitch_thresholds: SwitchThresholds,
+    /// Main knob intensity to resume at power on, saved during play
+    pub resume_intensity: i16,
+    /// Whether the main knob was locked, saved with
+    /// [`Settings::resume_intensity`]
+    pub resume_locked: bool,
+    /// Extra smoothing of the main knob, X knob, Y knob, CV 1 and CV 2, as
+    /// `2^shift` input scans (60Hz)
+    ///
+    /// 0 is only the smoothing every input gets. Shifts are at most
+    /// [`MAX_SLEW_SHIFT`].
+    pub input_smoothing: [u8; INPUT_CHANNELS],
+}
+
+impl Settings {
+    /// Largest allowed output trim, in either direction
+    pub const MAX_TRIM: i16 = 64;
+
+    pub const fn default() -> Self {
+        Settings {
+            output_trim: [0, 0],
+            intensity_curve: Breakpoints::new([
+                (Sample::MIN, Sample::MIN),
+                (-1024, -1024),
+                (0, 0),
+                (1024, 1024),
+                (Sample::MAX, Sample::MAX),
+            ]),
+            routing: Routing::default(),
+            // ~17ms up, ~0.5s down at 480Hz
+            intensity_slew: (3, 8),
+            zswitch_thresholds: SwitchThresholds::default(),
+            // medium rain
+            resume_intensity: 0,
+            resume_locked: false,
+            input_smoothing: [0; INPUT_CHANNELS],
+        }
+    }
+
+    fn to_bytes(&self) -> [u8; SETTINGS_LEN] {
+        let mut bytes = [0_u8; SETTINGS_LEN];
+        bytes[0..2].copy_from_slice(&self.output_trim[0].to_le_bytes());
+        bytes[2..4].copy_from_slice(&self.output_trim[1].to_le_bytes());
+        for (index, (input, output)) in self.intensity_curve.points().iter().enumerate() {
+            let offset = 4 + index * 4;
+            bytes[offset..offset + 2].copy_from_slice(&(*input as i16).to_le_bytes());
+            bytes[offset + 2..offset + 4].copy_from_slice(&(*output as i16).to_le_bytes());
+        }
+        bytes[ROUTING_OFFSET..SLEW_OFFSET].copy_from_slice(&self.routing.to_bytes());
+        bytes[SLEW_OFFSET] = self.intensity_slew.0;
+        bytes[SLEW_OFFSET + 1] = self.intensity_slew.1;
+        bytes[ZSWITCH_OFFSET..RESUME_OFFSET].copy_from_slice(&self.zswitch_thresholds.to_bytes());
+        bytes[RESUME_OFFSET..RESUME_OFFSET + 2]
+            .copy_from_slice(&self.resume_intensity.to_le_bytes());
+        bytes[RESUME_OFFSET + 2] = self.resume_locked.into();
+        bytes[SMOOTHING_OFFSET..].copy_from_slice(&self.input_smoothing);
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() != SETTINGS_LEN {
+            return Err(Error::MissingRecord);
+        }
+        let read_i16 = |offset: usize| i16::from_le_bytes([bytes[offset], bytes[offset + 1]]);
+        let trim = |offset: usize| check_trim(read_i16(offset), Self::MAX_TRIM);
+        let intensity_curve = Breakpoints::new(core::array::from_fn(|index| {
+            let offset = 4 + index * 4;
+            (read_i16(offset).into(), read_i16(offset + 2).into())
+        }));
+        if !intensity_curve.is_valid() {
+            return Err(Error::MissingRecord);
+        }
+        let routing = Routing::from_bytes([
+            bytes[ROUTING_OFFSET],
+            bytes[ROUTING_OFFSET + 1],
+            bytes[ROUTING_OFFSET + 2],
+            bytes[ROUTING_OFFSET + 3],
+        ])?;
+        let intensity_slew = (bytes[SLEW_OFFSET], bytes[SLEW_OFFSET + 1]);
+        if intensity_slew.0 > MAX_SLEW_SHIFT || intensity_slew.1 > MAX_SLEW_SHIFT {
+            return Err(Error::MissingRecord);
+        }
+        let zswitch_thresholds = SwitchThresholds::from_bytes(core::array::from_fn(|index| {
+            bytes[ZSWITCH_OFFSET + index]
+        }))?;
+        let resume_intensity = read_i16(RESUME_OFFSET);
+        let resume_locked = match bytes[RESUME_OFFSET + 2] {
+            0 => false,
+            1 => true,
+            _ => return Err(Error::MissingRecord),
+        };
+        if !(Sample::MIN..=Sample::MAX).contains(&i32::from(resume_intensity)) {
+            return Err(Error::MissingRecord);
+        }
+        let input_smoothing: [u8; INPUT_CHANNELS] =
+            core::array::from_fn(|index| bytes[SMOOTHING_OFFSET + index]);
+        if input_smoothing.iter().any(|&shift| shift > MAX_SLEW_SHIFT) {
+            return Err(Error::MissingRecord);
+        }
+        Ok(Settings {
+            output_trim: [trim(0)?, trim(2)?],
+            intensity_curve,
+            routing,
+            intensity_slew,
+            zswitch_thresholds,
+            resume_intensity,
+            resume_locked,
+            input_smoothing,
+        })
+    }
+}
+
+/// Reads and writes [`Settings`] records in flash
+pub struct SettingsStore<'d> {
+    flash: SettingsFlash<'d>,
+    /// Slot and sequence number of the newest valid record
+    newest: Option<(usize, u32)>,
+}
+
+impl<'d> SettingsStore<'d> {
+    pub fn new(flash: SettingsFlash<'d>) -> Self {
+        SettingsStore {
+            flash,
+            newest: None,
+        }
+    }
+
+    fn read_slot(&mut self, slot: usize, bytes: &mut [u8; RECORD_LEN]) -> Option<u32> {
+        if let Err(e) = self.flash.blocking_read(SLOT_OFFSETS[slot], bytes) {
+            error!("error reading settings slot {} from flash: {}", slot, e);
+            return None;
+        }
+        match decode_record(MAGIC, bytes) {
+            Ok((sequence, _)) => Some(sequence),
+            // erased slot, nothing saved there yet
+            Err(Error::MissingRecord) => None,
+            Err(e) => {
+                warn!("ignoring settings slot {}: {}", slot, e);
+                None
+            }
+        }
+    }
+
+    /// Load the newest valid settings, falling back to defaults if none are saved
+    pub fn load(&mut self) -> Settings {
+        let mut bytes = [[0_u8; RECORD_LEN]; 2];
+        let [first, second] = &mut bytes;
+        let sequences = [self.read_slot(0, first), self.read_slot(1, second)];
+        self.newest = newest_slot(sequences).and_then(|slot| Some((slot, sequences[slot]?)));
+        let Some((newest, sequence)) = self.newest else {
+            info!("no saved settings found, using defaults");
+            return Settings::default();
+        };
+        // fall back to the older record if the newest doesn't decode, such as
+        // after a firmware change to the settings layout
+        for slot in [newest, 1 - newest] {
+            if sequences[slot].is_none() {
+                continue;
+            }
+            match decode_record(MAGIC, &bytes[slot])
+                .and_then(|(_, payload)| Settings::from_bytes(payload))
+            {
+                Ok(settings) => {
+                    // the next save replaces the other slot, keeping this
+                    // one, and numbered to be newer than both
+                    self.newest = Some((slot, sequence));
+                    return settings;
+                }
+                Err(e) => error!("invalid saved settings in slot {}: {}", slot, e),
+            }
+        }
+        error!("no valid saved settings, using defaults");
+        Settings::default()
+    }
+
+    /// Save settings to the slot not holding the newest record
+    ///
+    /// Erasing flash pauses CORE1 (and audio) for tens of milliseconds, so
+    /// only save in response to user actions.
+    pub fn save(&mut self, settings: &Settings) -> Result<(), flash::Error> {
+        let (slot, sequence) = match self.newest {
+            Some((slot, sequence)) => (1 - slot, sequence.wrapping_add(1)),
+            None => (0, 0),
+        };
+        let mut bytes = [0_u8; RECORD_LEN];
+        // can't fail, the buffer is sized for the record
+        let length = unwrap!(encode_record(
+            MAGIC,
+            sequence,
+            &settings.to_bytes(),
+            &mut bytes
+        ));
+        let offset = SLOT_OFFSETS[slot];
+        self.flash
+            .blocking_erase(offset, offset + ERASE_SIZE as u32)?;
+        self.flash.blocking_write(offset, &bytes[..length])?;
+        self.newest = Some((slot, sequence));
+        Ok(())
+    }
+}
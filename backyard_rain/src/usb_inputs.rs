@@ -0,0 +1,91 @@
+//! Input readings streamed over USB serial, with the `usb_inputs` feature
+//!
+//! Each scan of the inputs is sent as a [`wscomp::report::InputReport`]
+//! frame, while a host has the serial port open.
+
+use defmt::*;
+use embassy_futures::join::join;
+use embassy_rp::bind_interrupts;
+use embassy_rp::peripherals::USB;
+use embassy_rp::usb::{Driver, InterruptHandler};
+use embassy_time::{Duration, Ticker};
+use embassy_usb::class::cdc_acm::{CdcAcmClass, State};
+use embassy_usb::driver::EndpointError;
+use embassy_usb::{Builder, Config};
+use wscomp::report::InputReport;
+
+use crate::{MuxState, MUX_INPUT};
+
+bind_interrupts!(struct Irqs {
+    USBCTRL_IRQ => InterruptHandler<USB>;
+});
+
+/// Full speed USB bulk packets, one report always fits
+const MAX_PACKET_SIZE: u16 = 64;
+
+fn report(mux_state: &MuxState, sequence: u8) -> InputReport {
+    InputReport {
+        sequence,
+        main_knob: mux_state.main_knob,
+        x_knob: mux_state.x_knob,
+        y_knob: mux_state.y_knob,
+        zswitch: mux_state.zswitch.into(),
+        cv1: mux_state.cv1.plugged_value().copied(),
+        cv2: mux_state.cv2.plugged_value().copied(),
+    }
+}
+
+/// Run the USB device, sending a report for each new [`MUX_INPUT`]
+///
+/// Polls at the rate input_loop() scans, so each scan is sent once.
+#[embassy_executor::task]
+pub async fn usb_inputs_loop(usb: USB) {
+    info!("Starting usb_inputs_loop()");
+    let driver = Driver::new(usb, Irqs);
+
+    // pid.codes test VID/PID, only for development
+    let mut config = Config::new(0x1209, 0x0001);
+    config.manufacturer = Some("Music Thing Modular");
+    config.product = Some("Backyard Rain inputs");
+    config.max_power = 100;
+    config.max_packet_size_0 = 64;
+
+    // the task's future is static, so these can live here
+    let mut config_descriptor = [0; 256];
+    let mut bos_descriptor = [0; 256];
+    let mut control_buf = [0; 64];
+    let mut state = State::new();
+    let mut builder = Builder::new(
+        driver,
+        config,
+        &mut config_descriptor,
+        &mut bos_descriptor,
+        &mut [],
+        &mut control_buf,
+    );
+    let mut class = CdcAcmClass::new(&mut builder, &mut state, MAX_PACKET_SIZE);
+    let mut usb = builder.build();
+
+    let send_reports = async {
+        let mut mux_rcv = MUX_INPUT.anon_receiver();
+        let mut ticker = Ticker::every(Duration::from_hz(60));
+        let mut sequence = 0_u8;
+        loop {
+            class.wait_connection().await;
+            info!("USB serial connected, sending input reports");
+            loop {
+                ticker.next().await;
+                let Some(mux_state) = mux_rcv.try_changed() else {
+                    continue;
+                };
+                let bytes = report(&mux_state, sequence).to_bytes();
+                sequence = sequence.wrapping_add(1);
+                if let Err(EndpointError::Disabled) = class.write_packet(&bytes).await {
+                    info!("USB serial disconnected");
+                    break;
+                }
+            }
+        }
+    };
+    join(usb.run(), send_reports).await;
+}
@@ -0,0 +1,122 @@
+//! Audible fault indicator
+//!
+//! Without a debugger attached, errors which only get logged are invisible.
+//! [`Beep`] makes a short, quiet, periodic sine tone to mix into the output
+//! while something is wrong, so the problem can at least be heard. Each beep
+//! fades in and out, so it doesn't click.
+
+use crate::fade::{fade_between, FadeCurve};
+use crate::math::sine;
+use crate::Sample;
+
+/// Samples to fade each beep in and out over
+const FADE_SAMPLES: u32 = 64;
+
+/// Intermittent sine tone, silent while disabled
+pub struct Beep {
+    enabled: bool,
+    phase: u32,
+    phase_step: u32,
+    on_samples: u32,
+    period_samples: u32,
+    /// Position in the current period
+    position: u32,
+    level: Sample,
+    curve: FadeCurve,
+}
+
+impl Beep {
+    /// New disabled beep of `frequency_hz`, sounding for `on_samples` every
+    /// `period_samples`, at `level` (peak amplitude)
+    pub fn new(
+        frequency_hz: u32,
+        sample_rate: u32,
+        on_samples: u32,
+        period_samples: u32,
+        level: Sample,
+    ) -> Self {
+        let phase_step = (u64::from(frequency_hz) << 32) / u64::from(sample_rate.max(1));
+        Beep {
+            enabled: false,
+            phase: 0,
+            phase_step: phase_step as u32,
+            on_samples: on_samples.min(period_samples),
+            period_samples: period_samples.max(1),
+            position: 0,
+            level,
+            curve: FadeCurve::Linear,
+        }
+    }
+
+    /// Shape of each beep's fade in and out, linear by default
+    pub fn set_fade_curve(&mut self, curve: FadeCurve) {
+        self.curve = curve;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Start or stop beeping, each fault starts from the beginning of a beep
+    pub fn set_enabled(&mut self, enabled: bool) {
+        if enabled && !self.enabled {
+            self.position = 0;
+            self.phase = 0;
+        }
+        self.enabled = enabled;
+    }
+
+    pub fn next_sample(&mut self) -> Sample {
+        if !self.enabled {
+            return Sample::from(0_i32);
+        }
+        let position = self.position;
+        self.position = (self.position + 1) % self.period_samples;
+        if position >= self.on_samples {
+            return Sample::from(0_i32);
+        }
+        let tone = sine(self.phase).scale(self.level);
+        self.phase = self.phase.wrapping_add(self.phase_step);
+        let fade = position
+            .min(self.on_samples - 1 - position)
+            .min(FADE_SAMPLES);
+        fade_between(Sample::from(0_i32), tone, fade, FADE_SAMPLES, self.curve)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Beep;
+    use crate::Sample;
+
+    #[test]
+    fn test_beep_intermittent_tone() {
+        // 1kHz at 48kHz, on for 480 samples (10ms) every 4800
+        let mut beep = Beep::new(1000, 48_000, 480, 4800, Sample::from(Sample::MAX));
+        beep.set_enabled(true);
+        for _period in 0..3 {
+            let samples: Vec<i32> = (0..4800).map(|_| beep.next_sample().to_clamped()).collect();
+            let (on, off) = samples.split_at(480);
+            assert!(off.iter().all(|&s| s == 0));
+            // faded at the edges
+            assert_eq!(on[0], 0);
+            assert!(on[..32].iter().all(|s| s.abs() < 1100));
+            // 48 samples per cycle, full level in the middle
+            let peak = on.iter().map(|s| s.abs()).max().unwrap();
+            assert!(peak > 1900, "{}", peak);
+            let crossings = on.windows(2).filter(|w| w[0] < 0 && w[1] >= 0).count();
+            assert_eq!(crossings, 10);
+        }
+    }
+
+    #[test]
+    fn test_beep_silent_when_disabled() {
+        let mut beep = Beep::new(1000, 48_000, 480, 4800, Sample::from(Sample::MAX));
+        assert!(!beep.is_enabled());
+        assert!((0..10_000).all(|_| beep.next_sample().to_clamped() == 0));
+        beep.set_enabled(true);
+        assert!((0..480).any(|_| beep.next_sample().to_clamped() != 0));
+        beep.set_enabled(false);
+        assert!((0..10_000).all(|_| beep.next_sample().to_clamped() == 0));
+    }
+}
@@ -0,0 +1,165 @@
+//! Learning the 0V offset of CV inputs
+//!
+//! CV inputs don't read exactly [`Sample::CENTER`] at 0V: each unit rests a
+//! little off (about 2030 to 2060 rather than 2048 raw), and that drifts with
+//! temperature. [`CenterTracker`] slowly learns the resting offset of an input
+//! and subtracts it.
+//!
+//! It only learns from readings which have stayed still for a while, close to
+//! center, so a patched CV moving or held at a voltage isn't learned out.
+//! A slow CV ramp can still pass for drift, so learning can be frozen, for
+//! example while a cable is patched.
+
+use crate::fixed::{self, Fixed};
+use crate::Sample;
+
+/// Learned 0V offset of one input
+pub struct CenterTracker {
+    offset: Fixed,
+    anchor: i32,
+    still_ticks: u32,
+    frozen: bool,
+    window: i32,
+    settle_ticks: u32,
+    max_offset: i32,
+    learn_shift: u8,
+}
+
+impl CenterTracker {
+    /// Tracker with no offset yet
+    ///
+    /// Learns offsets of up to `max_offset` from readings which have stayed
+    /// within `window` of each other for `settle_ticks`, averaged over about
+    /// `2^learn_shift` of them.
+    pub const fn new(window: i32, settle_ticks: u32, max_offset: i32, learn_shift: u8) -> Self {
+        CenterTracker {
+            offset: 0,
+            anchor: Sample::CENTER,
+            still_ticks: 0,
+            frozen: false,
+            window,
+            settle_ticks,
+            max_offset,
+            learn_shift,
+        }
+    }
+
+    /// Learn from the next uncorrected reading
+    pub fn observe(&mut self, value: Sample) {
+        let value = value.to_clamped();
+        if (value - self.anchor).abs() > self.window {
+            self.anchor = value;
+            self.still_ticks = 0;
+            return;
+        }
+        self.still_ticks = self.still_ticks.saturating_add(1);
+        if self.frozen || self.still_ticks < self.settle_ticks || value.abs() > self.max_offset {
+            return;
+        }
+        let difference = fixed::from_sample(Sample::from(value)) - self.offset;
+        self.offset += difference >> self.learn_shift;
+    }
+
+    /// `value` with the learned offset removed
+    pub fn correct(&self, value: Sample) -> Sample {
+        value - Sample::from(self.offset())
+    }
+
+    /// Learned offset, rounded to the nearest step
+    pub fn offset(&self) -> i32 {
+        fixed::to_sample(self.offset + fixed::ONE / 2).to_clamped()
+    }
+
+    /// Stop or resume learning, keeping the offset learned so far
+    pub fn set_frozen(&mut self, frozen: bool) {
+        self.frozen = frozen;
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.frozen
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::CenterTracker;
+    use crate::Sample;
+
+    fn tracker() -> CenterTracker {
+        CenterTracker::new(4, 100, 64, 4)
+    }
+
+    #[test]
+    fn test_center_tracker_learns_offset() {
+        let mut tracker = tracker();
+        // a unit resting 17 steps high, with a little noise
+        for n in 0..2000 {
+            tracker.observe(Sample::from(17 + n % 3 - 1));
+        }
+        assert_eq!(tracker.offset(), 17);
+        assert_eq!(tracker.correct(Sample::from(17_i32)).to_clamped(), 0);
+        assert_eq!(tracker.correct(Sample::from(1017_i32)).to_clamped(), 1000);
+
+        // and follows it drifting low
+        for _ in 0..2000 {
+            tracker.observe(Sample::from(-9_i32));
+        }
+        assert_eq!(tracker.offset(), -9);
+        // inverted CV keeps its flag
+        let inverted = Sample::new(9, true);
+        assert_eq!(
+            format!("{:?}", tracker.correct(inverted)),
+            format!("{:?}", Sample::new(0, true))
+        );
+    }
+
+    #[test]
+    fn test_center_tracker_ignores_signals() {
+        let mut tracker = tracker();
+        // an LFO never stays still long enough
+        for n in 0..10_000 {
+            let phase = n % 200;
+            let triangle = if phase < 100 { phase } else { 200 - phase };
+            tracker.observe(Sample::from(triangle * 20 - 1000));
+        }
+        assert_eq!(tracker.offset(), 0);
+        let value = Sample::from(345_i32);
+        assert_eq!(tracker.correct(value), value);
+
+        // a CV held well away from center isn't drift
+        for _ in 0..10_000 {
+            tracker.observe(Sample::from(800_i32));
+        }
+        assert_eq!(tracker.offset(), 0);
+
+        // learning starts only once the input has rested
+        for _ in 0..99 {
+            tracker.observe(Sample::from(30_i32));
+        }
+        assert_eq!(tracker.offset(), 0);
+        for _ in 0..1000 {
+            tracker.observe(Sample::from(30_i32));
+        }
+        assert_eq!(tracker.offset(), 30);
+    }
+
+    #[test]
+    fn test_center_tracker_frozen() {
+        let mut tracker = tracker();
+        for _ in 0..2000 {
+            tracker.observe(Sample::from(12_i32));
+        }
+        tracker.set_frozen(true);
+        assert!(tracker.is_frozen());
+        // a ramp slow enough to pass for drift is left alone while frozen
+        for n in 0..40_000 {
+            tracker.observe(Sample::from(12 + n / 1000));
+        }
+        assert_eq!(tracker.offset(), 12);
+        tracker.set_frozen(false);
+        for _ in 0..2000 {
+            tracker.observe(Sample::from(52_i32));
+        }
+        assert_eq!(tracker.offset(), 52);
+    }
+}
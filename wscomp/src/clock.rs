@@ -0,0 +1,261 @@
+//! Clock pulses timed in audio samples
+//!
+//! Counting samples gives clock edges the timing of the audio clock, rather
+//! than the jitter of a slower control loop. [`PulseClock`] keeps its period
+//! in fixed point, so tempos which aren't a whole number of samples per pulse
+//! don't drift: each edge lands on the sample at or just after its exact
+//! time.
+//!
+//! Without a clock source, [`TapTempo`] sets a tempo from the time between
+//! taps of a switch instead.
+
+use crate::fixed;
+
+/// One sample in [`PulseClock`] periods, which are unsigned
+/// [`fixed::Fixed`]
+pub const PERIOD_UNITY: u32 = fixed::ONE as u32;
+
+/// Samples per pulse (in [`PERIOD_UNITY`]s) for `tempo_millibpm` thousandths
+/// of a beat per minute, saturating for very slow tempos
+pub fn period_for_tempo(tempo_millibpm: u32, sample_rate: u32) -> u32 {
+    let samples_per_minute = u64::from(sample_rate) * 60 * 1000 * u64::from(PERIOD_UNITY);
+    let period = samples_per_minute / u64::from(tempo_millibpm.max(1));
+    period.min(u64::from(u32::MAX)) as u32
+}
+
+/// Square clock pulses, `width` samples high once per period
+pub struct PulseClock {
+    period: u32,
+    width: u32,
+    /// Position in the current period, in [`PERIOD_UNITY`]s
+    phase: u32,
+}
+
+impl PulseClock {
+    /// New clock with the first rising edge on the first tick
+    pub fn new(period: u32, width: u32) -> Self {
+        PulseClock {
+            period: period.max(PERIOD_UNITY),
+            width,
+            phase: 0,
+        }
+    }
+
+    /// Change the tempo, keeping the position within the current pulse
+    ///
+    /// If the new period is already over, the next pulse starts on the next
+    /// tick.
+    pub fn set_period(&mut self, period: u32) {
+        self.period = period.max(PERIOD_UNITY);
+        self.phase %= self.period;
+    }
+
+    /// Advance one sample, returning whether the pulse is high
+    pub fn tick(&mut self) -> bool {
+        let high = self.phase < self.width.saturating_mul(PERIOD_UNITY);
+        self.phase = self.phase.saturating_add(PERIOD_UNITY);
+        if self.phase >= self.period {
+            // keep the fraction, so rounding doesn't accumulate
+            self.phase = (self.phase - self.period) % self.period;
+        }
+        high
+    }
+}
+
+/// Tempo in thousandths of a BPM for a beat every `interval` ticks of a
+/// `tick_rate` loop, saturating for very fast tempos
+pub fn tempo_for_interval(interval: u32, tick_rate: u32) -> u32 {
+    let ticks_per_minute = u64::from(tick_rate) * 60 * 1000;
+    let tempo = ticks_per_minute / u64::from(interval.max(1));
+    tempo.min(u64::from(u32::MAX)) as u32
+}
+
+/// Beat interval from the average time between the last `N` taps
+///
+/// Time is counted in calls to [`TapTempo::tick()`]. A gap longer than the
+/// timeout starts a new sequence of taps, so a lone tap sets nothing.
+pub struct TapTempo<const N: usize> {
+    intervals: [u32; N],
+    /// Intervals recorded in this sequence of taps, up to `N`
+    count: usize,
+    /// Where the next interval is recorded
+    next: usize,
+    /// Ticks since the last tap, none before the first of a sequence
+    since_tap: Option<u32>,
+    timeout: u32,
+}
+
+impl<const N: usize> TapTempo<N> {
+    /// Taps at most `timeout` ticks apart count toward the tempo
+    pub const fn new(timeout: u32) -> Self {
+        TapTempo {
+            intervals: [0; N],
+            count: 0,
+            next: 0,
+            since_tap: None,
+            timeout,
+        }
+    }
+
+    /// Advance one tick, forgetting the taps after the timeout
+    pub fn tick(&mut self) {
+        self.since_tap = match self.since_tap {
+            Some(since) if since < self.timeout => Some(since + 1),
+            _ => {
+                self.count = 0;
+                None
+            }
+        };
+    }
+
+    /// Record a tap, returning the new beat interval from the second tap on
+    pub fn tap(&mut self) -> Option<u32> {
+        if let Some(since) = self.since_tap.replace(0) {
+            if N > 0 {
+                self.intervals[self.next] = since;
+                self.next = (self.next + 1) % N;
+                self.count = (self.count + 1).min(N);
+            }
+        }
+        self.interval()
+    }
+
+    /// Average interval of this sequence of taps, in ticks
+    pub fn interval(&self) -> Option<u32> {
+        if self.count == 0 {
+            return None;
+        }
+        // the latest `count` intervals, wherever they are in the ring
+        let sum: u64 = (0..self.count)
+            .map(|back| u64::from(self.intervals[(self.next + N - 1 - back) % N]))
+            .sum();
+        Some((sum / self.count as u64) as u32)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{period_for_tempo, tempo_for_interval, PulseClock, TapTempo, PERIOD_UNITY};
+
+    /// Samples of the rising edges in the first `samples` ticks
+    fn rising_edges(clock: &mut PulseClock, samples: usize) -> Vec<usize> {
+        let mut edges = Vec::new();
+        let mut previous = false;
+        for sample in 0..samples {
+            let high = clock.tick();
+            if high && !previous {
+                edges.push(sample);
+            }
+            previous = high;
+        }
+        edges
+    }
+
+    #[test]
+    fn test_period_for_tempo() {
+        assert_eq!(period_for_tempo(120_000, 48_000), 24_000 * PERIOD_UNITY);
+        assert_eq!(period_for_tempo(90_000, 48_000), 32_000 * PERIOD_UNITY);
+        // 22153.85 samples
+        let exact = 48_000 * 60 * u64::from(PERIOD_UNITY) / 130;
+        assert_eq!(u64::from(period_for_tempo(130_000, 48_000)), exact);
+        // too slow to represent
+        assert_eq!(period_for_tempo(1, 48_000), u32::MAX);
+    }
+
+    #[test]
+    fn test_clock_edges_whole_samples() {
+        let mut clock = PulseClock::new(period_for_tempo(120_000, 48_000), 480);
+        let edges = rising_edges(&mut clock, 100_000);
+        assert_eq!(edges, [0, 24_000, 48_000, 72_000, 96_000]);
+
+        // pulse width in samples
+        let mut clock = PulseClock::new(10 * PERIOD_UNITY, 3);
+        let levels: Vec<bool> = (0..12).map(|_| clock.tick()).collect();
+        let high = [true, true, true];
+        let low = [false; 7];
+        assert_eq!(levels, [&high[..], &low[..], &high[..2]].concat());
+    }
+
+    #[test]
+    fn test_clock_edges_fractional_samples_dont_drift() {
+        // 130 BPM is 22153.85 samples per beat
+        let period = period_for_tempo(130_000, 48_000);
+        let mut clock = PulseClock::new(period, 480);
+        let edges = rising_edges(&mut clock, 48_000 * 60);
+        assert_eq!(edges.len(), 130);
+        for (beat, &edge) in edges.iter().enumerate() {
+            let exact = beat as f64 * 48_000.0 * 60.0 / 130.0;
+            assert!(
+                edge as f64 >= exact && (edge as f64) < exact + 1.0,
+                "{}: {}",
+                beat,
+                edge
+            );
+        }
+    }
+
+    #[test]
+    fn test_clock_tempo_change_keeps_position() {
+        let mut clock = PulseClock::new(100 * PERIOD_UNITY, 1);
+        rising_edges(&mut clock, 50);
+        // twice as fast, half way through already, so the next edge is now
+        clock.set_period(50 * PERIOD_UNITY);
+        assert!(clock.tick());
+        assert_eq!(rising_edges(&mut clock, 120), [49, 99]);
+    }
+
+    /// Tap after `ticks` ticks, returning the interval
+    fn tap_after<const N: usize>(taps: &mut TapTempo<N>, ticks: u32) -> Option<u32> {
+        for _ in 0..ticks {
+            taps.tick();
+        }
+        taps.tap()
+    }
+
+    #[test]
+    fn test_tempo_for_interval() {
+        // half a second at 480Hz
+        assert_eq!(tempo_for_interval(240, 480), 120_000);
+        assert_eq!(tempo_for_interval(320, 480), 90_000);
+        // and back to the same clock period
+        let tempo = tempo_for_interval(333, 480);
+        assert_eq!(tempo, 86_486);
+        let period = period_for_tempo(tempo, 48_000);
+        assert!((period / PERIOD_UNITY).abs_diff(33_300) <= 1);
+        // instant taps are as fast as it goes
+        assert_eq!(tempo_for_interval(0, 480), 28_800_000);
+    }
+
+    #[test]
+    fn test_tap_tempo_averages_taps() {
+        let mut taps = TapTempo::<4>::new(960);
+        // one tap isn't a tempo yet
+        assert_eq!(taps.tap(), None);
+        assert_eq!(tap_after(&mut taps, 240), Some(240));
+        // uneven taps are averaged
+        assert_eq!(tap_after(&mut taps, 250), Some(245));
+        assert_eq!(tap_after(&mut taps, 230), Some(240));
+        assert_eq!(tap_after(&mut taps, 260), Some(245));
+        // over the last 4 intervals, the first drops out
+        assert_eq!(tap_after(&mut taps, 300), Some(260));
+        assert_eq!(taps.interval(), Some(260));
+    }
+
+    #[test]
+    fn test_tap_tempo_timeout() {
+        let mut taps = TapTempo::<4>::new(960);
+        taps.tap();
+        // a lone tap times out, the next starts over
+        assert_eq!(tap_after(&mut taps, 961), None);
+        assert_eq!(tap_after(&mut taps, 480), Some(480));
+        // up to the timeout still counts
+        assert_eq!(tap_after(&mut taps, 960), Some(720));
+        // pausing forgets the sequence, new taps aren't averaged with it
+        for _ in 0..961 {
+            taps.tick();
+        }
+        assert_eq!(taps.interval(), None);
+        taps.tap();
+        assert_eq!(tap_after(&mut taps, 100), Some(100));
+    }
+}
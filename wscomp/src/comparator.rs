@@ -0,0 +1,305 @@
+//! Comparator, turning a continuous CV into a gate
+//!
+//! [`SchmittTrigger`] detects a CV crossing a level, for envelope follower
+//! gates or finding the tempo of an LFO. Like [`crate::gate::Gate`], it
+//! switches at separate high and low thresholds (hysteresis), so a slow or
+//! noisy input near the level gives one clean edge instead of a burst, and
+//! its state holds while the input is between them.
+//!
+//! [`Comparator`] builds on it, outputting one of two levels depending on
+//! whether its input is above or below a threshold.
+//! [`Comparator::crossing()`] reports the edges, for triggering events.
+
+use crate::Sample;
+
+/// Direction the input crossed a [`Comparator`]'s threshold
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Crossing {
+    /// Rose above the threshold, the output switched high
+    Rising,
+    /// Fell below the threshold, the output switched low
+    Falling,
+}
+
+/// Threshold crossing detector with hysteresis
+pub struct SchmittTrigger {
+    /// Input level the state switches high above
+    high: i32,
+    /// Input level the state switches low below
+    low: i32,
+    is_high: bool,
+    rose: bool,
+    fell: bool,
+}
+
+impl SchmittTrigger {
+    /// New trigger, starting low
+    ///
+    /// Switches high when the input rises above `high`, and low when it falls
+    /// below `low`. The low threshold is limited to the high one. Thresholds
+    /// beyond the sample range are never crossed.
+    pub fn new(high: Sample, low: Sample) -> Self {
+        let high = high.to_clamped();
+        SchmittTrigger {
+            high,
+            low: low.to_clamped().min(high),
+            is_high: false,
+            rose: false,
+            fell: false,
+        }
+    }
+
+    pub fn is_high(&self) -> bool {
+        self.is_high
+    }
+
+    /// Follow `input`, returning the current state
+    pub fn process(&mut self, input: Sample) -> bool {
+        let was_high = self.is_high;
+        let input = input.to_clamped();
+        if input > self.high {
+            self.is_high = true;
+        } else if input < self.low {
+            self.is_high = false;
+        }
+        self.rose = self.is_high && !was_high;
+        self.fell = was_high && !self.is_high;
+        self.is_high
+    }
+
+    /// Whether the last [`Self::process()`] switched high
+    pub fn rose(&self) -> bool {
+        self.rose
+    }
+
+    /// Whether the last [`Self::process()`] switched low
+    pub fn fell(&self) -> bool {
+        self.fell
+    }
+}
+
+/// Two level comparator with hysteresis
+pub struct Comparator {
+    trigger: SchmittTrigger,
+    low: Sample,
+    high: Sample,
+}
+
+impl Comparator {
+    /// New comparator, starting low
+    ///
+    /// The output switches to `high` when the input rises above
+    /// `threshold + hysteresis`, and back to `low` when it falls below
+    /// `threshold - hysteresis`. Thresholds beyond the sample range are
+    /// never crossed. Negative hysteresis is treated as 0.
+    pub fn new(threshold: Sample, hysteresis: Sample, low: Sample, high: Sample) -> Self {
+        let threshold = threshold.to_clamped();
+        let hysteresis = hysteresis.to_clamped().max(0);
+        Comparator {
+            trigger: SchmittTrigger::new(
+                Sample::from(threshold + hysteresis),
+                Sample::from(threshold - hysteresis),
+            ),
+            low,
+            high,
+        }
+    }
+
+    pub fn is_high(&self) -> bool {
+        self.trigger.is_high()
+    }
+
+    pub fn process(&mut self, input: Sample) -> Sample {
+        if self.trigger.process(input) {
+            self.high
+        } else {
+            self.low
+        }
+    }
+
+    /// Process `input`, returning the crossing if the output switched
+    pub fn crossing(&mut self, input: Sample) -> Option<Crossing> {
+        self.process(input);
+        if self.trigger.rose() {
+            Some(Crossing::Rising)
+        } else if self.trigger.fell() {
+            Some(Crossing::Falling)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Comparator, Crossing, SchmittTrigger};
+    use crate::Sample;
+
+    /// 0V/+5V gate levels, roughly
+    fn gate(threshold: i32, hysteresis: i32) -> Comparator {
+        Comparator::new(
+            Sample::from(threshold),
+            Sample::from(hysteresis),
+            Sample::from(0_i32),
+            Sample::from(1706_i32),
+        )
+    }
+
+    fn process(comparator: &mut Comparator, input: i32) -> i32 {
+        comparator.process(Sample::from(input)).to_clamped()
+    }
+
+    #[test]
+    fn test_comparator_output_levels() {
+        let mut comparator = gate(0, 0);
+        assert!(!comparator.is_high());
+        assert_eq!(process(&mut comparator, -500), 0);
+        assert_eq!(process(&mut comparator, 500), 1706);
+        assert!(comparator.is_high());
+        assert_eq!(process(&mut comparator, -1), 0);
+
+        // any two levels, including inverted
+        let mut comparator = Comparator::new(
+            Sample::from(100_i32),
+            Sample::from(0_i32),
+            Sample::from(Sample::MAX),
+            Sample::from(Sample::MIN),
+        );
+        assert_eq!(process(&mut comparator, 0), Sample::MAX);
+        assert_eq!(process(&mut comparator, 200), Sample::MIN);
+    }
+
+    #[test]
+    fn test_comparator_hysteresis() {
+        let mut comparator = gate(1000, 50);
+        // inside the band it stays low on the way up
+        for input in [0, 950, 1000, 1050] {
+            assert_eq!(process(&mut comparator, input), 0, "{}", input);
+        }
+        assert_eq!(process(&mut comparator, 1051), 1706);
+        // and high on the way down
+        for input in [1051, 1000, 950] {
+            assert_eq!(process(&mut comparator, input), 1706, "{}", input);
+        }
+        assert_eq!(process(&mut comparator, 949), 0);
+
+        // noise within the band around the threshold gives one edge
+        let mut comparator = gate(0, 40);
+        let mut edges = 0;
+        let mut was_high = false;
+        for n in 0..2000 {
+            let ramp = n - 1000;
+            let noise = (n * 53 % 61) - 30;
+            process(&mut comparator, ramp + noise);
+            if comparator.is_high() != was_high {
+                edges += 1;
+                was_high = comparator.is_high();
+            }
+        }
+        assert_eq!(edges, 1);
+    }
+
+    #[test]
+    fn test_comparator_threshold_edges() {
+        // without hysteresis, exactly at the threshold holds the output
+        let mut comparator = gate(0, 0);
+        assert_eq!(process(&mut comparator, 0), 0);
+        process(&mut comparator, 1);
+        assert_eq!(process(&mut comparator, 0), 1706);
+
+        // thresholds at the ends of the range can't be crossed past
+        let mut comparator = gate(Sample::MAX, 0);
+        assert_eq!(process(&mut comparator, Sample::MAX), 0);
+        let mut comparator = gate(Sample::MIN, 0);
+        assert_eq!(process(&mut comparator, Sample::MIN + 1), 1706);
+        assert_eq!(process(&mut comparator, Sample::MIN), 1706);
+        // out of range inputs are clamped
+        let mut comparator = gate(2000, 100);
+        assert_eq!(process(&mut comparator, 5000), 0);
+
+        // negative hysteresis is none
+        let mut comparator = gate(0, -100);
+        assert_eq!(process(&mut comparator, 1), 1706);
+        assert_eq!(process(&mut comparator, -1), 0);
+    }
+
+    #[test]
+    fn test_comparator_crossings_fire_once() {
+        let mut comparator = gate(1000, 100);
+        let mut crossings = Vec::new();
+        // a noisy rise through the threshold, and back down
+        let rise = (0..2400).map(|n| n + (n * 37 % 151) - 75);
+        let fall = (0..2400).rev().map(|n| n + (n * 41 % 151) - 75);
+        for (n, input) in rise.chain(fall).enumerate() {
+            if let Some(crossing) = comparator.crossing(Sample::from(input)) {
+                crossings.push((n, crossing));
+            }
+        }
+        assert_eq!(crossings.len(), 2, "{:?}", crossings);
+        assert_eq!(crossings[0].1, Crossing::Rising);
+        assert_eq!(crossings[1].1, Crossing::Falling);
+        // near the thresholds, not at the first noisy touch of 1000
+        assert!((1000..1200).contains(&crossings[0].0), "{:?}", crossings);
+        assert!((3700..3900).contains(&crossings[1].0), "{:?}", crossings);
+
+        // holding steady doesn't fire again
+        for _ in 0..100 {
+            assert_eq!(comparator.crossing(Sample::from(0_i32)), None);
+        }
+    }
+
+    fn schmitt(high: i32, low: i32) -> SchmittTrigger {
+        SchmittTrigger::new(Sample::from(high), Sample::from(low))
+    }
+
+    #[test]
+    fn test_schmitt_trigger_holds_between_thresholds() {
+        let mut trigger = schmitt(300, -300);
+        assert!(!trigger.is_high());
+        // noise inside the band never switches it, from either state
+        let noise = [0, 250, -250, 299, -299, 300, -300, 120, -80];
+        for input in noise {
+            assert!(!trigger.process(Sample::from(input)));
+            assert!(!trigger.rose() && !trigger.fell());
+        }
+        assert!(trigger.process(Sample::from(301_i32)));
+        for input in noise {
+            assert!(trigger.process(Sample::from(input)));
+            assert!(!trigger.rose() && !trigger.fell());
+        }
+        assert!(!trigger.process(Sample::from(-301_i32)));
+        assert!(trigger.fell());
+    }
+
+    #[test]
+    fn test_schmitt_trigger_one_edge_per_crossing() {
+        let mut trigger = schmitt(200, -200);
+        let (mut rises, mut falls) = (0, 0);
+        // a slow triangle from -1000 to 1000 and back, five times, with noise
+        // bigger than a step but within the band
+        let mut level = -1000_i32;
+        let mut step = 10;
+        for n in 0..5 * 400 {
+            if level.abs() >= 1000 && n > 0 {
+                step = -step;
+            }
+            level += step;
+            let noise = [0, 150, -150, 90, -120][n % 5];
+            trigger.process(Sample::from(level + noise));
+            assert!(!(trigger.rose() && trigger.fell()));
+            rises += i32::from(trigger.rose());
+            falls += i32::from(trigger.fell());
+        }
+        assert_eq!((rises, falls), (5, 5));
+
+        // a low threshold above the high one is limited to it
+        let mut trigger = schmitt(100, 500);
+        assert!(trigger.process(Sample::from(101_i32)));
+        assert!(trigger.process(Sample::from(100_i32)));
+        assert!(!trigger.process(Sample::from(99_i32)));
+        // thresholds beyond the range are never crossed
+        let mut trigger = schmitt(Sample::MAX, Sample::MIN);
+        assert!(!trigger.process(Sample::from(5000_i32)));
+    }
+}
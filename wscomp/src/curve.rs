@@ -0,0 +1,104 @@
+//! Response curves for mapping one [`Sample`] range onto another
+
+use crate::Sample;
+
+/// Piecewise linear curve through a fixed number of `(input, output)` points
+///
+/// Points must be sorted by input. Inputs below the first point map to the
+/// first output, inputs above the last point map to the last output. Flat
+/// segments (equal outputs) create plateaus. Two points with the same input
+/// make a vertical step, where the step input maps to the lower segment.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Breakpoints<const N: usize> {
+    points: [(i32, i32); N],
+}
+
+impl<const N: usize> Breakpoints<N> {
+    pub const fn new(points: [(i32, i32); N]) -> Self {
+        Breakpoints { points }
+    }
+
+    pub fn points(&self) -> &[(i32, i32); N] {
+        &self.points
+    }
+
+    /// True when there is at least one point and inputs are in order
+    pub fn is_valid(&self) -> bool {
+        N > 0 && self.points.windows(2).all(|pair| pair[0].0 <= pair[1].0)
+    }
+
+    /// Map `value` through the curve
+    pub fn apply(&self, value: Sample) -> Sample {
+        let Some(&(first_in, first_out)) = self.points.first() else {
+            return value;
+        };
+        let input = value.to_clamped();
+        if input <= first_in {
+            return Sample::from(first_out);
+        }
+        for pair in self.points.windows(2) {
+            let ((x0, y0), (x1, y1)) = (pair[0], pair[1]);
+            if input <= x1 {
+                if x1 == x0 {
+                    return Sample::from(y1);
+                }
+                return Sample::from(y0 + (y1 - y0) * (input - x0) / (x1 - x0));
+            }
+        }
+        Sample::from(self.points[N - 1].1)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Breakpoints;
+    use crate::Sample;
+
+    fn map<const N: usize>(curve: &Breakpoints<N>, value: i32) -> i32 {
+        curve.apply(Sample::from(value)).to_clamped()
+    }
+
+    #[test]
+    fn test_breakpoints_identity() {
+        let curve = Breakpoints::new([(Sample::MIN, Sample::MIN), (Sample::MAX, Sample::MAX)]);
+        for value in [Sample::MIN, -1000, 0, 1, 1000, Sample::MAX] {
+            assert_eq!(map(&curve, value), value);
+        }
+    }
+
+    #[test]
+    fn test_breakpoints_plateau() {
+        // wide medium plateau, with fast transitions at the ends of the knob
+        let curve = Breakpoints::new([
+            (Sample::MIN, Sample::MIN),
+            (-1024, 0),
+            (1024, 0),
+            (Sample::MAX, Sample::MAX),
+        ]);
+        assert!(curve.is_valid());
+        assert_eq!(map(&curve, Sample::MIN), Sample::MIN);
+        assert_eq!(map(&curve, -1536), -1024);
+        assert_eq!(map(&curve, -1024), 0);
+        assert_eq!(map(&curve, -500), 0);
+        assert_eq!(map(&curve, 0), 0);
+        assert_eq!(map(&curve, 1024), 0);
+        assert_eq!(map(&curve, 1536), 1024);
+        assert_eq!(map(&curve, Sample::MAX), Sample::MAX);
+    }
+
+    #[test]
+    fn test_breakpoints_beyond_ends_and_steps() {
+        let curve = Breakpoints::new([(-100, -50), (0, 0), (0, 500), (100, 600)]);
+        assert_eq!(map(&curve, -2000), -50);
+        assert_eq!(map(&curve, 2000), 600);
+        // at a vertical step, the step input itself stays on the lower segment
+        assert_eq!(map(&curve, 0), 0);
+        assert_eq!(map(&curve, 1), 501);
+        assert_eq!(map(&curve, 50), 550);
+
+        let unsorted = Breakpoints::new([(100, 0), (0, 0)]);
+        assert!(!unsorted.is_valid());
+        assert!(!Breakpoints::<0>::new([]).is_valid());
+    }
+}
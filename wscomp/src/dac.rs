@@ -0,0 +1,631 @@
+//! Helpers for the Computer's MCP4822 audio/CV output DAC
+//!
+//! The DAC takes 12 bit codes (0..=[`U12_MAX`]). The analog output stages
+//! after it have small per-unit DC offsets, which are corrected by shifting
+//! codes with a per-channel trim.
+//!
+//! Codes are straight binary, from 0V to the full scale voltage of the gain
+//! setting ([`dac_millivolts()`]). The Computer's output stages remove half of
+//! full scale and amplify, so [`ZERO_VOLT_CODE`], mid-scale, is 0V at the
+//! jack whatever the output polarity. [`crate::Sample::to_output()`] maps
+//! [`crate::Sample::CENTER`] to exactly that code.
+//!
+//! Each SPI write to the DAC is a 16 bit [`DacCommand`] word:
+//!
+//! ```text
+//! bit 15   : channel, 0 = A, 1 = B
+//! bit 14   : unused
+//! bit 13   : gain, 0 = 2x, 1 = 1x
+//! bit 12   : 0 = shutdown channel, 1 = active
+//! bits 0-11: value
+//! ```
+//!
+//! [`Mcp4822`] keeps a command for each channel and writes codes through a
+//! [`DacBus`], which the firmware implements over SPI with chip select.
+//!
+//! Alternatively, DMA can feed words to the SPI at a rate set by one of the
+//! RP2040's DMA pacing timers ([`pacing_fraction()`]), reading them from a
+//! ring buffer the CPU keeps topped up ([`DmaRing`]). Each word carries its
+//! channel, so the DAC stays in step even if the ring slips by a word.
+
+use core::future::Future;
+
+use crate::error::Error;
+use crate::U12_MAX;
+
+/// Mid-scale DAC code, 0V at the output jacks (before trim)
+pub const ZERO_VOLT_CODE: u16 = 2048;
+
+/// DAC output channel
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DacChannel {
+    A,
+    B,
+}
+
+/// DAC output gain, relative to the internal 2.048V reference
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DacGain {
+    /// 0 to 2.048V
+    Single,
+    /// 0 to 4.096V
+    Double,
+}
+
+/// Builder for a 16 bit DAC command word
+///
+/// Defaults to 1x gain with the channel active, which is what the Computer's
+/// output stages expect.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DacCommand {
+    channel: DacChannel,
+    gain: DacGain,
+    shutdown: bool,
+    value: u16,
+}
+
+impl DacCommand {
+    const CHANNEL_B: u16 = 1 << 15;
+    const GAIN_SINGLE: u16 = 1 << 13;
+    const ACTIVE: u16 = 1 << 12;
+
+    pub const fn new(channel: DacChannel) -> Self {
+        DacCommand {
+            channel,
+            gain: DacGain::Single,
+            shutdown: false,
+            value: 0,
+        }
+    }
+
+    pub const fn gain(mut self, gain: DacGain) -> Self {
+        self.gain = gain;
+        self
+    }
+
+    /// Turn the channel's output off (high impedance) when `shutdown` is true
+    pub const fn shutdown(mut self, shutdown: bool) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    /// 12 bit output code, saturating at [`U12_MAX`]
+    pub const fn value(mut self, value: u16) -> Self {
+        self.value = if value > U12_MAX { U12_MAX } else { value };
+        self
+    }
+
+    /// Word to write to the DAC
+    pub const fn to_word(self) -> u16 {
+        let mut word = self.value;
+        if let DacChannel::B = self.channel {
+            word |= Self::CHANNEL_B;
+        }
+        if let DacGain::Single = self.gain {
+            word |= Self::GAIN_SINGLE;
+        }
+        if !self.shutdown {
+            word |= Self::ACTIVE;
+        }
+        word
+    }
+}
+
+/// SPI bus and chip select an [`Mcp4822`] is written through
+pub trait DacBus {
+    type Error;
+
+    /// Write one 16 bit command word, framed by chip select
+    fn write_word(&mut self, word: u16) -> impl Future<Output = Result<(), Self::Error>>;
+}
+
+/// MCP4822 dual 12 bit DAC
+///
+/// Both channels start at 1x gain and active, like [`DacCommand::new()`].
+pub struct Mcp4822<B> {
+    bus: B,
+    channel_a: DacCommand,
+    channel_b: DacCommand,
+}
+
+impl<B: DacBus> Mcp4822<B> {
+    pub const fn new(bus: B) -> Self {
+        Mcp4822 {
+            bus,
+            channel_a: DacCommand::new(DacChannel::A),
+            channel_b: DacCommand::new(DacChannel::B),
+        }
+    }
+
+    fn command(&mut self, channel: DacChannel) -> &mut DacCommand {
+        match channel {
+            DacChannel::A => &mut self.channel_a,
+            DacChannel::B => &mut self.channel_b,
+        }
+    }
+
+    /// Gain for later writes to `channel`
+    pub fn set_gain(&mut self, channel: DacChannel, gain: DacGain) {
+        let command = self.command(channel);
+        *command = command.gain(gain);
+    }
+
+    /// Shut down (or wake) `channel` from its next write
+    pub fn set_shutdown(&mut self, channel: DacChannel, shutdown: bool) {
+        let command = self.command(channel);
+        *command = command.shutdown(shutdown);
+    }
+
+    /// Write a 12 bit `code` to `channel`, saturating at [`U12_MAX`]
+    pub async fn write(&mut self, channel: DacChannel, code: u16) -> Result<(), B::Error> {
+        let word = self.command(channel).value(code).to_word();
+        self.bus.write_word(word).await
+    }
+
+    pub async fn write_a(&mut self, code: u16) -> Result<(), B::Error> {
+        self.write(DacChannel::A, code).await
+    }
+
+    pub async fn write_b(&mut self, code: u16) -> Result<(), B::Error> {
+        self.write(DacChannel::B, code).await
+    }
+
+    pub fn bus_mut(&mut self) -> &mut B {
+        &mut self.bus
+    }
+}
+
+/// Where, and how many words, to write into a [`DmaRing`]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Refill {
+    /// Ring index of the first word, later words wrap around the ring
+    pub start: usize,
+    pub words: usize,
+    /// The DMA caught up with the CPU and replayed old words since the last
+    /// refill
+    pub underrun: bool,
+}
+
+/// CPU side of a ring buffer of `len` words which DMA reads continuously
+///
+/// Positions are counts of words since the DMA started, wrapping, so they
+/// stay in order however long it runs. The CPU keeps `fill` words ahead of
+/// the DMA, that's the output latency and the slack for refills running
+/// late.
+pub struct DmaRing {
+    len: usize,
+    fill: u32,
+    written: u32,
+}
+
+impl DmaRing {
+    /// `len` must be a power of two, like the DMA's ring wrap, and `fill` is
+    /// limited to below it, so unread words are never overwritten
+    pub const fn new(len: usize, fill: u32) -> Self {
+        core::assert!(len.is_power_of_two());
+        let most = len as u32 - 1;
+        DmaRing {
+            len,
+            fill: if fill > most { most } else { fill },
+            written: 0,
+        }
+    }
+
+    /// Words to write to get `fill` ahead of the DMA, once it has read
+    /// `read` words in total
+    ///
+    /// After an underrun the writes restart right at the DMA's position.
+    pub fn refill(&mut self, read: u32) -> Refill {
+        let ahead = self.written.wrapping_sub(read) as i32;
+        let underrun = ahead < 0;
+        let ahead = if underrun {
+            self.written = read;
+            0
+        } else {
+            ahead as u32
+        };
+        Refill {
+            start: self.written as usize & (self.len - 1),
+            words: self.fill.saturating_sub(ahead) as usize,
+            underrun,
+        }
+    }
+
+    /// Record `words` written from the last [`Self::refill()`]'s start
+    pub fn wrote(&mut self, words: usize) {
+        self.written = self.written.wrapping_add(words as u32);
+    }
+
+    /// Words written that the DMA hasn't read yet, negative after an underrun
+    pub fn ahead(&self, read: u32) -> i32 {
+        self.written.wrapping_sub(read) as i32
+    }
+}
+
+/// DMA pacing timer fraction `(x, y)` closest to `rate_hz` from a
+/// `clock_hz` system clock
+///
+/// The RP2040's DMA timers request a transfer on `x` of every `y` clock
+/// cycles, both 16 bit. `None` for rates it can't reach: above the clock,
+/// or below 1/65535 of it.
+pub fn pacing_fraction(rate_hz: u32, clock_hz: u32) -> Option<(u16, u16)> {
+    let (rate, clock) = (u64::from(rate_hz), u64::from(clock_hz));
+    if rate > clock || rate * u64::from(u16::MAX) < clock {
+        return None;
+    }
+    // error of x/y as |x * clock - rate * y| / y, compared cross multiplied
+    let mut best: Option<(u64, u64, u64)> = None;
+    for y in 1..=u64::from(u16::MAX) {
+        let x = (rate * y + clock / 2) / clock;
+        if x == 0 || x > u64::from(u16::MAX) {
+            continue;
+        }
+        let error = (x * clock).abs_diff(rate * y);
+        let better = match best {
+            Some((_, best_y, best_error)) => {
+                u128::from(error) * u128::from(best_y) < u128::from(best_error) * u128::from(y)
+            }
+            None => true,
+        };
+        if better {
+            best = Some((x, y, error));
+            if error == 0 {
+                break;
+            }
+        }
+    }
+    best.map(|(x, y, _)| (x as u16, y as u16))
+}
+
+/// Voltage at the DAC pin for `code`, in millivolts
+///
+/// The internal reference is 2.048V, so at [`DacGain::Single`] each code is
+/// 0.5mV, and [`DacGain::Double`] doubles that.
+pub const fn dac_millivolts(code: u16, gain: DacGain) -> u32 {
+    let code = if code > U12_MAX { U12_MAX } else { code } as u32;
+    match gain {
+        DacGain::Single => code * 2048 / 4096,
+        DacGain::Double => code * 4096 / 4096,
+    }
+}
+
+/// CPU cycles to wait for at least `ns` nanoseconds at `clock_hz`
+///
+/// Used for chip select setup/hold times. Rounds up, so the wait is never
+/// shorter than asked for, and 0ns is no wait at all.
+pub const fn delay_cycles(ns: u32, clock_hz: u32) -> u32 {
+    let cycles = (ns as u64 * clock_hz as u64).div_ceil(1_000_000_000);
+    if cycles > u32::MAX as u64 {
+        u32::MAX
+    } else {
+        cycles as u32
+    }
+}
+
+/// Check a calibration `trim` is within +/- `limit` codes
+pub fn check_trim(trim: i16, limit: i16) -> Result<i16, Error> {
+    if trim.unsigned_abs() > limit.unsigned_abs() {
+        return Err(Error::CalibrationOutOfRange);
+    }
+    Ok(trim)
+}
+
+/// Shift a 12 bit DAC `code` by `trim` codes, saturating at the rails
+pub fn apply_trim(code: u16, trim: i16) -> u16 {
+    (i32::from(code) + i32::from(trim)).clamp(0, i32::from(U12_MAX)) as u16
+}
+
+/// Reduce a 12 bit DAC `code` to `bits` of resolution
+///
+/// `dither` is added before rounding to the nearest step, use
+/// [`crate::noise::Noise::next_tpdf()`] with a step of `1 << (12 - bits)` to
+/// spread the rounding error into noise. The result is always on the reduced
+/// grid (a multiple of the step size).
+pub fn reduce_resolution(code: u16, bits: u8, dither: i32) -> u16 {
+    let step = 1_i32 << (12 - bits.clamp(1, 12));
+    let rounded = i32::from(code) + dither + step / 2;
+    (rounded.clamp(0, i32::from(U12_MAX)) & !(step - 1)) as u16
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        apply_trim, check_trim, dac_millivolts, delay_cycles, pacing_fraction, reduce_resolution,
+        DacBus, DacChannel, DacCommand, DacGain, DmaRing, Mcp4822, Refill, ZERO_VOLT_CODE,
+    };
+    use crate::error::Error;
+    use crate::noise::Noise;
+    use crate::{Sample, U12_MAX};
+    use core::future::Future;
+    use core::pin::pin;
+    use core::task::{Context, Poll, Waker};
+
+    /// Records the words written, failing when `fail` is set
+    #[derive(Default)]
+    struct MockBus {
+        words: Vec<u16>,
+        fail: bool,
+    }
+
+    impl DacBus for MockBus {
+        type Error = &'static str;
+
+        async fn write_word(&mut self, word: u16) -> Result<(), &'static str> {
+            if self.fail {
+                return Err("bus failed");
+            }
+            self.words.push(word);
+            Ok(())
+        }
+    }
+
+    /// Run a future which never waits, as the mock bus doesn't
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = pin!(future);
+        let mut context = Context::from_waker(Waker::noop());
+        match future.as_mut().poll(&mut context) {
+            Poll::Ready(output) => output,
+            Poll::Pending => panic!("mock bus never waits"),
+        }
+    }
+
+    #[test]
+    fn test_mcp4822_words() {
+        let mut dac = Mcp4822::new(MockBus::default());
+        block_on(dac.write_a(0)).unwrap();
+        block_on(dac.write_b(0)).unwrap();
+        block_on(dac.write_a(ZERO_VOLT_CODE)).unwrap();
+        block_on(dac.write_b(0x0abc)).unwrap();
+        block_on(dac.write_a(U12_MAX)).unwrap();
+        // out of range codes saturate, never reaching the config bits
+        block_on(dac.write_b(0xffff)).unwrap();
+        assert_eq!(
+            dac.bus_mut().words,
+            [0x3000, 0xb000, 0x3800, 0xbabc, 0x3fff, 0xbfff]
+        );
+
+        // gain and shutdown are per channel
+        dac.bus_mut().words.clear();
+        dac.set_gain(DacChannel::A, DacGain::Double);
+        block_on(dac.write_a(0x0123)).unwrap();
+        block_on(dac.write_b(0x0123)).unwrap();
+        dac.set_shutdown(DacChannel::B, true);
+        block_on(dac.write(DacChannel::B, 0x0123)).unwrap();
+        block_on(dac.write_a(0x0123)).unwrap();
+        dac.set_shutdown(DacChannel::B, false);
+        dac.set_gain(DacChannel::A, DacGain::Single);
+        block_on(dac.write_b(0x0123)).unwrap();
+        block_on(dac.write_a(0x0123)).unwrap();
+        assert_eq!(
+            dac.bus_mut().words,
+            [0x1123, 0xb123, 0xa123, 0x1123, 0xb123, 0x3123]
+        );
+
+        dac.bus_mut().fail = true;
+        assert_eq!(block_on(dac.write_a(7)), Err("bus failed"));
+    }
+
+    #[test]
+    fn test_center_is_zero_volts() {
+        // mid-scale of the default 1x gain: half of the 2.048V reference
+        assert_eq!(dac_millivolts(ZERO_VOLT_CODE, DacGain::Single), 1024);
+        assert_eq!(dac_millivolts(U12_MAX, DacGain::Single), 2047);
+        assert_eq!(dac_millivolts(0, DacGain::Single), 0);
+        assert_eq!(dac_millivolts(ZERO_VOLT_CODE, DacGain::Double), 2048);
+        assert_eq!(dac_millivolts(u16::MAX, DacGain::Single), 2047);
+
+        // centered samples land on the 0V code, from either input polarity
+        for invert in [false, true] {
+            let center = Sample::new(Sample::CENTER, invert);
+            assert_eq!(center.to_output(), ZERO_VOLT_CODE);
+            assert_eq!(center.to_output_inverted(), ZERO_VOLT_CODE - 1);
+        }
+        // and one code either side is equally far from it
+        assert_eq!(Sample::from(1_i32).to_output() - ZERO_VOLT_CODE, 1);
+        assert_eq!(ZERO_VOLT_CODE - Sample::from(-1_i32).to_output(), 1);
+
+        // the word sent to the DAC for 0V, without trim
+        let word = DacCommand::new(DacChannel::A)
+            .value(apply_trim(Sample::from(0_i32).to_output(), 0))
+            .to_word();
+        assert_eq!(word, 0x3800);
+    }
+
+    #[test]
+    fn test_delay_cycles() {
+        // no delay by default
+        assert_eq!(delay_cycles(0, 125_000_000), 0);
+        // 8ns per cycle at 125MHz
+        assert_eq!(delay_cycles(40, 125_000_000), 5);
+        // never shorter than asked for
+        assert_eq!(delay_cycles(41, 125_000_000), 6);
+        assert_eq!(delay_cycles(1, 125_000_000), 1);
+        assert_eq!(delay_cycles(15, 200_000_000), 3);
+        assert_eq!(delay_cycles(1_000_000_000, 133_000_000), 133_000_000);
+        assert_eq!(delay_cycles(u32::MAX, u32::MAX), u32::MAX);
+    }
+
+    #[test]
+    fn test_dac_command_fields() {
+        let base = DacCommand::new(DacChannel::A);
+        assert_eq!(base.to_word(), 0b0011_0000_0000_0000);
+        assert_eq!(
+            DacCommand::new(DacChannel::B).to_word(),
+            0b1011_0000_0000_0000
+        );
+        assert_eq!(base.gain(DacGain::Double).to_word(), 0b0001_0000_0000_0000);
+        assert_eq!(base.gain(DacGain::Single).to_word(), 0b0011_0000_0000_0000);
+        assert_eq!(base.shutdown(true).to_word(), 0b0010_0000_0000_0000);
+        assert_eq!(
+            DacCommand::new(DacChannel::B)
+                .gain(DacGain::Double)
+                .shutdown(true)
+                .to_word(),
+            0b1000_0000_0000_0000
+        );
+    }
+
+    #[test]
+    fn test_dac_command_value_in_low_bits() {
+        let base = DacCommand::new(DacChannel::B);
+        for value in [0, 1, 0x0a5a, 2048, U12_MAX] {
+            let word = base.value(value).to_word();
+            assert_eq!(word & 0x0fff, value);
+            assert_eq!(word & 0xf000, base.to_word());
+        }
+        // out of range values saturate instead of touching the config bits
+        assert_eq!(base.value(0x1234).to_word(), 0b1011_1111_1111_1111);
+        assert_eq!(base.value(u16::MAX).to_word() & 0xf000, base.to_word());
+    }
+
+    #[test]
+    fn test_dac_command_value_boundaries() {
+        for (channel, config) in [(DacChannel::A, 0x3000), (DacChannel::B, 0xb000)] {
+            let base = DacCommand::new(channel);
+            assert_eq!(base.value(0).to_word(), config);
+            assert_eq!(base.value(0x0fff).to_word(), config | 0x0fff);
+            // one past 12 bits saturates, rather than masking to 0 (a jump
+            // from full scale to the bottom rail) or carrying into the gain
+            // bit
+            assert_eq!(base.value(0x1000).to_word(), config | 0x0fff);
+        }
+    }
+
+    #[test]
+    fn test_dma_ring_keeps_fill_ahead() {
+        let mut ring = DmaRing::new(16, 8);
+        let refill = ring.refill(0);
+        assert_eq!(
+            refill,
+            Refill {
+                start: 0,
+                words: 8,
+                underrun: false
+            }
+        );
+        ring.wrote(refill.words);
+        // the DMA reads 5, so 5 more tops it up, continuing where it left off
+        assert_eq!(ring.ahead(5), 3);
+        let refill = ring.refill(5);
+        assert_eq!((refill.start, refill.words), (8, 5));
+        ring.wrote(refill.words);
+        // and wraps around the end of the ring
+        let refill = ring.refill(13);
+        assert_eq!((refill.start, refill.words), (13, 8));
+        ring.wrote(refill.words);
+        assert_eq!(ring.refill(21).start, 21 & 15);
+        // already full, nothing to do
+        assert_eq!(ring.refill(13).words, 0);
+    }
+
+    #[test]
+    fn test_dma_ring_underrun_and_wrap() {
+        let mut ring = DmaRing::new(16, 8);
+        let words = ring.refill(0).words;
+        ring.wrote(words);
+        // the DMA overtakes, and writes restart at its position
+        assert_eq!(ring.ahead(11), -3);
+        let refill = ring.refill(11);
+        assert!(refill.underrun);
+        assert_eq!((refill.start, refill.words), (11, 8));
+        ring.wrote(refill.words);
+        assert!(!ring.refill(12).underrun);
+        assert_eq!(ring.ahead(12), 7);
+
+        // positions wrap around u32 without losing their order
+        let mut ring = DmaRing::new(16, 8);
+        ring.wrote(u32::MAX as usize - 3);
+        let refill = ring.refill(u32::MAX - 6);
+        assert!(!refill.underrun);
+        assert_eq!(refill.words, 5);
+        ring.wrote(refill.words);
+        assert_eq!(ring.ahead(u32::MAX - 6), 8);
+        assert_eq!(ring.ahead(1), 0);
+        // never filled so far that the DMA's unread words are overwritten
+        assert_eq!(DmaRing::new(16, 100).refill(0).words, 15);
+    }
+
+    #[test]
+    fn test_pacing_fraction() {
+        // two words per sample at 48kHz, from the default 125MHz clock
+        assert_eq!(pacing_fraction(96_000, 125_000_000), Some((12, 15_625)));
+        assert_eq!(pacing_fraction(125_000_000, 125_000_000), Some((1, 1)));
+        assert_eq!(pacing_fraction(0, 125_000_000), None);
+        assert_eq!(pacing_fraction(200_000_000, 125_000_000), None);
+        assert_eq!(pacing_fraction(1_000, 125_000_000), None);
+        // rates without an exact fraction get very close
+        for (rate, clock) in [
+            (88_200, 125_000_000),
+            (96_000, 133_000_000),
+            (44_100, 125_000_000),
+        ] {
+            let (x, y) = pacing_fraction(rate, clock).unwrap();
+            let achieved = f64::from(clock) * f64::from(x) / f64::from(y);
+            assert!(
+                (achieved - f64::from(rate)).abs() < 0.5,
+                "{} {}",
+                rate,
+                achieved
+            );
+        }
+    }
+
+    #[test]
+    fn test_apply_trim_shifts_code() {
+        assert_eq!(apply_trim(2048, 0), 2048);
+        assert_eq!(apply_trim(2048, 7), 2055);
+        assert_eq!(apply_trim(2048, -12), 2036);
+    }
+
+    #[test]
+    fn test_apply_trim_saturates() {
+        assert_eq!(apply_trim(U12_MAX - 2, 10), U12_MAX);
+        assert_eq!(apply_trim(U12_MAX, i16::MAX), U12_MAX);
+        assert_eq!(apply_trim(3, -10), 0);
+        assert_eq!(apply_trim(0, i16::MIN), 0);
+    }
+
+    #[test]
+    fn test_check_trim() {
+        assert_eq!(check_trim(0, 64), Ok(0));
+        assert_eq!(check_trim(-64, 64), Ok(-64));
+        assert_eq!(check_trim(64, 64), Ok(64));
+        assert_eq!(check_trim(65, 64), Err(Error::CalibrationOutOfRange));
+        assert_eq!(check_trim(i16::MIN, 64), Err(Error::CalibrationOutOfRange));
+    }
+
+    #[test]
+    fn test_reduce_resolution_on_grid() {
+        assert_eq!(reduce_resolution(2048, 10, 0), 2048);
+        assert_eq!(reduce_resolution(2049, 10, 0), 2048);
+        assert_eq!(reduce_resolution(2050, 10, 0), 2052);
+        assert_eq!(reduce_resolution(U12_MAX, 10, 0), 4092);
+        assert_eq!(reduce_resolution(0, 10, -3), 0);
+        assert_eq!(reduce_resolution(1234, 12, 0), 1234);
+    }
+
+    #[test]
+    fn test_reduce_resolution_dithered_average() {
+        let mut noise = Noise::new(11);
+        for code in [100_u16, 2049, 2050, 2051, 3000] {
+            let mut sum = 0_u32;
+            for _ in 0..20_000 {
+                let reduced = reduce_resolution(code, 10, noise.next_tpdf(4));
+                assert_eq!(reduced % 4, 0, "off the 10 bit grid: {}", reduced);
+                sum += u32::from(reduced);
+            }
+            // dither lets the average resolve values between grid steps
+            let average = f64::from(sum) / 20_000.0;
+            assert!(
+                (average - f64::from(code)).abs() < 0.25,
+                "{}: {}",
+                code,
+                average
+            );
+        }
+    }
+}
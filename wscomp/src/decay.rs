@@ -0,0 +1,76 @@
+//! Fixed point exponential decay
+//!
+//! Envelopes, meters, tails and smoothing all decay toward zero by repeatedly
+//! multiplying by a coefficient just below one. Doing that directly with
+//! integer math hangs: negative values round toward negative infinity and get
+//! stuck at -1. [`decay_step()`] rounds the magnitude toward zero instead, so
+//! every value reaches exactly zero.
+
+/// Fixed point scale of decay coefficients, `DECAY_UNITY` would be 1.0
+pub const DECAY_UNITY: u32 = 1 << 16;
+
+/// Multiply `value` by `coefficient / DECAY_UNITY`, rounding toward zero
+///
+/// Any coefficient below [`DECAY_UNITY`] strictly shrinks non-zero values, so
+/// repeated steps always reach zero.
+pub fn decay_step(value: i32, coefficient: u16) -> i32 {
+    let magnitude = (u64::from(value.unsigned_abs()) * u64::from(coefficient)) >> 16;
+    // magnitude is always smaller than |value|, so it fits back into i32
+    if value < 0 {
+        -(magnitude as i32)
+    } else {
+        magnitude as i32
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{decay_step, DECAY_UNITY};
+
+    #[test]
+    fn test_decay_reaches_zero() {
+        for start in [i32::MAX, 2047, 1, 0, -1, -2048, i32::MIN] {
+            for coefficient in [0, 1, 32768, 65000, u16::MAX] {
+                let mut value = start;
+                let mut steps = 0;
+                while value != 0 {
+                    let next = decay_step(value, coefficient);
+                    assert!(next.unsigned_abs() < value.unsigned_abs());
+                    assert!(next == 0 || next.signum() == value.signum());
+                    value = next;
+                    steps += 1;
+                    assert!(steps < 2_000_000, "stuck at {}", value);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_decay_follows_exponential_curve() {
+        let coefficient = 62_000_u16;
+        let ratio = f64::from(coefficient) / f64::from(DECAY_UNITY);
+        for start in [20_000, -20_000] {
+            let mut value = start;
+            for step in 1..=40 {
+                value = decay_step(value, coefficient);
+                let expected = f64::from(start) * ratio.powi(step);
+                // truncation loses less than one per step
+                assert!(
+                    (f64::from(value) - expected).abs() <= f64::from(step),
+                    "step {}: {} vs {}",
+                    step,
+                    value,
+                    expected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_decay_half() {
+        assert_eq!(decay_step(1000, 32768), 500);
+        assert_eq!(decay_step(-1000, 32768), -500);
+        assert_eq!(decay_step(-1, 32768), 0);
+        assert_eq!(decay_step(1000, 0), 0);
+    }
+}
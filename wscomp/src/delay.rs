@@ -0,0 +1,128 @@
+//! Circular delay line
+//!
+//! Delay effects all need the last `N` samples of a signal, and getting the
+//! index wrap right in each of them is error prone. [`DelayLine`] keeps the
+//! wrap in one place, with taps counted back from the newest sample.
+//!
+//! Samples are stored as `i16`, which holds a [`crate::Sample`]'s clamped
+//! value or intermediate filter states with headroom, in half the memory.
+
+use crate::fixed::{self, Fixed};
+
+/// The last `N` values pushed, statically allocated
+pub struct DelayLine<const N: usize> {
+    buffer: [i16; N],
+    /// Index the next value is written to, which holds the oldest value
+    position: usize,
+}
+
+impl<const N: usize> DelayLine<N> {
+    /// New delay line full of zeros
+    pub const fn new() -> Self {
+        DelayLine {
+            buffer: [0; N],
+            position: 0,
+        }
+    }
+
+    /// Add a value, dropping the oldest
+    pub fn push(&mut self, value: i16) {
+        if N == 0 {
+            return;
+        }
+        self.buffer[self.position] = value;
+        self.position = (self.position + 1) % N;
+    }
+
+    /// Value pushed `offset` pushes before the newest, 0 is the newest
+    ///
+    /// Offsets beyond the length read the oldest value, an empty line reads
+    /// 0.
+    pub fn tap(&self, offset: usize) -> i16 {
+        if N == 0 {
+            return 0;
+        }
+        let offset = offset.min(N - 1);
+        self.buffer[(self.position + N - 1 - offset) % N]
+    }
+
+    /// Value at a fractional `offset` (in samples), interpolated linearly
+    /// between the two neighbouring taps
+    ///
+    /// Negative offsets read the newest value.
+    pub fn read_interpolated(&self, offset: Fixed) -> i16 {
+        let offset = offset.max(0);
+        let whole = (offset >> fixed::FRAC_BITS) as usize;
+        let fraction = offset & (fixed::ONE - 1);
+        let newer = fixed::from_i16(self.tap(whole));
+        let older = fixed::from_i16(self.tap(whole + 1));
+        fixed::to_i16(newer + fixed::mul(older - newer, fraction))
+    }
+}
+
+impl<const N: usize> Default for DelayLine<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::DelayLine;
+    use crate::fixed::{self, Fixed};
+
+    #[test]
+    fn test_delay_wraps() {
+        let mut delay = DelayLine::<4>::new();
+        assert_eq!((0..4).map(|n| delay.tap(n)).collect::<Vec<_>>(), [0; 4]);
+        // several times around the buffer
+        for value in 1..=10 {
+            delay.push(value * 100);
+            assert_eq!(delay.tap(0), value * 100);
+        }
+        let taps: Vec<i16> = (0..4).map(|n| delay.tap(n)).collect();
+        assert_eq!(taps, [1000, 900, 800, 700]);
+        // past the end reads the oldest
+        assert_eq!(delay.tap(4), 700);
+        assert_eq!(delay.tap(usize::MAX), 700);
+
+        // nothing to delay
+        let mut empty = DelayLine::<0>::new();
+        empty.push(5);
+        assert_eq!(empty.tap(0), 0);
+        assert_eq!(empty.read_interpolated(fixed::ONE / 2), 0);
+    }
+
+    #[test]
+    fn test_delay_taps_delay_by_offset() {
+        let mut delay = DelayLine::<64>::new();
+        for n in 0..1000_i16 {
+            delay.push(n);
+            for offset in [0, 1, 17, 63] {
+                let expected = (n - offset as i16).max(0);
+                assert_eq!(delay.tap(offset), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_delay_interpolated_reads() {
+        let mut delay = DelayLine::<8>::new();
+        for value in [0, 100, -100, 400, 1000] {
+            delay.push(value);
+        }
+        let read = |offset: Fixed| delay.read_interpolated(offset);
+        // whole offsets are the taps
+        assert_eq!(read(0), 1000);
+        assert_eq!(read(2 * fixed::ONE), -100);
+        // between taps
+        assert_eq!(read(fixed::ONE / 2), 700);
+        assert_eq!(read(fixed::ONE + fixed::ONE / 4), 275);
+        assert_eq!(read(2 * fixed::ONE + 3 * fixed::ONE / 4), 50);
+        assert_eq!(read(3 * fixed::ONE + fixed::ONE / 10), 90);
+        // clamped at both ends
+        assert_eq!(read(-fixed::ONE), 1000);
+        assert_eq!(read(20 * fixed::ONE), 0);
+        assert_eq!(read(fixed::ONE / 2 + 7 * fixed::ONE), 0);
+    }
+}
@@ -0,0 +1,88 @@
+//! Detecting shared values from before a mode change
+//!
+//! A `Watch` holds its last value until the producer sends another, so right
+//! after a mode change a consumer can still read a value computed for the
+//! old mode. Producers tag values with the current [`Epoch`], advanced on
+//! each mode change, and consumers use [`Stamped::fresh()`] to ignore values
+//! from earlier epochs.
+
+/// Mode generation counter, compared with wrapping
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Epoch(u32);
+
+impl Epoch {
+    /// Epoch from its raw count, as stored in an atomic
+    pub const fn from_raw(raw: u32) -> Self {
+        Epoch(raw)
+    }
+
+    pub const fn raw(self) -> u32 {
+        self.0
+    }
+
+    /// The epoch after this one
+    pub const fn next(self) -> Self {
+        Epoch(self.0.wrapping_add(1))
+    }
+
+    /// True if `self` is older than `other`
+    pub const fn is_before(self, other: Epoch) -> bool {
+        (other.0.wrapping_sub(self.0) as i32) > 0
+    }
+}
+
+/// Value tagged with the [`Epoch`] it was produced in
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stamped<T> {
+    pub epoch: Epoch,
+    pub value: T,
+}
+
+impl<T> Stamped<T> {
+    pub const fn new(value: T, epoch: Epoch) -> Self {
+        Stamped { epoch, value }
+    }
+
+    /// The value, unless it was produced before `current`
+    ///
+    /// Values from a later epoch are accepted: the producer saw the mode
+    /// change before the consumer did.
+    pub fn fresh(self, current: Epoch) -> Option<T> {
+        (!self.epoch.is_before(current)).then_some(self.value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Epoch, Stamped};
+
+    #[test]
+    fn test_stale_values_rejected() {
+        let before = Epoch::from_raw(7);
+        let current = before.next();
+        assert_eq!(Stamped::new(100, before).fresh(current), None);
+        assert_eq!(Stamped::new(100, Epoch::from_raw(0)).fresh(current), None);
+    }
+
+    #[test]
+    fn test_fresh_values_accepted() {
+        let current = Epoch::from_raw(8);
+        assert_eq!(Stamped::new(100, current).fresh(current), Some(100));
+        // producer is already in the next mode
+        assert_eq!(Stamped::new(100, current.next()).fresh(current), Some(100));
+    }
+
+    #[test]
+    fn test_epoch_wraps() {
+        let last = Epoch::from_raw(u32::MAX);
+        let first = last.next();
+        assert_eq!(first, Epoch::from_raw(0));
+        assert!(last.is_before(first));
+        assert!(!first.is_before(last));
+        assert!(!first.is_before(first));
+        assert_eq!(Stamped::new(1, last).fresh(first), None);
+        assert_eq!(Stamped::new(1, first).fresh(last), Some(1));
+    }
+}
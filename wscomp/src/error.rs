@@ -0,0 +1,26 @@
+//! Errors from the fallible wscomp APIs
+
+/// Why a wscomp operation failed
+///
+/// Implements `defmt::Format` (with the default `defmt` feature) so callers
+/// can log it with `error!("{}", e)` instead of panicking.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    /// WAV file is truncated, missing its RIFF header or has no data chunk
+    MalformedWav,
+    /// WAV data chunk doesn't hold a single whole block, nothing to play
+    EmptyWav,
+    /// WAV file isn't mono IMA ADPCM
+    UnsupportedWav,
+    /// ADPCM block is too short or its header is out of range
+    BadAdpcmBlock,
+    /// No record found, the storage is erased or holds something else
+    MissingRecord,
+    /// Record CRC doesn't match its contents, usually from a torn write
+    BadCrc,
+    /// Buffer is too small for the data
+    BufferTooSmall,
+    /// Calibration value is beyond the allowed adjustment range
+    CalibrationOutOfRange,
+}
@@ -0,0 +1,146 @@
+//! Fade curve shapes
+//!
+//! Everything that fades (the startup fade in, the gate, the fault beep and
+//! the loop tail crossfade) maps its linear position through
+//! [`apply_fade()`], so the fade character is set in one place.
+//!
+//! Hearing is roughly logarithmic, so a linear amplitude fade seems to jump
+//! at the quiet end and then barely change. [`FadeCurve::Exponential`]
+//! changes by a constant number of dB per step instead, over a 48dB range
+//! that ends in silence at position 0. [`FadeCurve::Logarithmic`] is its
+//! mirror image, rising fast and settling slowly.
+
+use crate::fixed::{self, Fixed};
+use crate::Sample;
+
+/// Shape of a fade, from silence at position 0 to unity at the end
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FadeCurve {
+    /// Gain proportional to position
+    Linear,
+    /// Slow start, fast finish (convex), even in dB
+    Exponential,
+    /// Fast start, slow finish (concave)
+    Logarithmic,
+}
+
+/// Octaves (6dB steps) covered by the exponential curves
+const EXP_OCTAVES: i32 = 8;
+
+/// `2^(EXP_OCTAVES * position) - 1`, scaled to `0..=fixed::ONE`
+fn exponential(position: Fixed) -> Fixed {
+    let power = i64::from(fixed::exp2(position * EXP_OCTAVES));
+    let full = (i64::from(fixed::ONE) << EXP_OCTAVES) - i64::from(fixed::ONE);
+    ((power - i64::from(fixed::ONE)) * i64::from(fixed::ONE) / full) as Fixed
+}
+
+/// Gain for a fade at linear `position`, both from 0 to [`fixed::ONE`]
+///
+/// Positions outside that range are clamped.
+pub fn apply_fade(position: Fixed, curve: FadeCurve) -> Fixed {
+    let position = position.clamp(0, fixed::ONE);
+    match curve {
+        FadeCurve::Linear => position,
+        FadeCurve::Exponential => exponential(position),
+        FadeCurve::Logarithmic => fixed::ONE - exponential(fixed::ONE - position),
+    }
+}
+
+/// Fade from `from` toward `to`, `step` of `length` steps along `curve`
+///
+/// `step` 0 is `from` and `length` or more is exactly `to`, like
+/// [`Sample::interpolate_to()`].
+pub fn fade_between(from: Sample, to: Sample, step: u32, length: u32, curve: FadeCurve) -> Sample {
+    if length == 0 || step >= length {
+        return to;
+    }
+    let position = (i64::from(step) * i64::from(fixed::ONE) / i64::from(length)) as Fixed;
+    let gain = apply_fade(position, curve);
+    from.interpolate_to(to, gain as u32, fixed::ONE as u32)
+}
+
+/// Map a linear gain `level` (0 to [`Sample::MAX`]) along `curve`
+///
+/// For fades driven by a [`crate::ramp::Ramp`] rather than a step count.
+pub fn fade_level(level: Sample, curve: FadeCurve) -> Sample {
+    let level = level.to_clamped().clamp(0, Sample::MAX);
+    fade_between(
+        Sample::from(0_i32),
+        Sample::from(Sample::MAX),
+        level as u32,
+        Sample::MAX as u32,
+        curve,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::{apply_fade, fade_between, fade_level, FadeCurve};
+    use crate::fixed::{self, Fixed};
+    use crate::Sample;
+
+    const CURVES: [FadeCurve; 3] = [
+        FadeCurve::Linear,
+        FadeCurve::Exponential,
+        FadeCurve::Logarithmic,
+    ];
+
+    #[test]
+    fn test_fade_end_points() {
+        for curve in CURVES {
+            assert_eq!(apply_fade(0, curve), 0, "{:?}", curve);
+            assert_eq!(apply_fade(fixed::ONE, curve), fixed::ONE, "{:?}", curve);
+            // clamped outside the fade
+            assert_eq!(apply_fade(-fixed::ONE, curve), 0);
+            assert_eq!(apply_fade(2 * fixed::ONE, curve), fixed::ONE);
+
+            let (from, to) = (Sample::from(-1000_i32), Sample::from(1000_i32));
+            assert_eq!(fade_between(from, to, 0, 480, curve), from);
+            assert_eq!(fade_between(from, to, 480, 480, curve), to);
+            assert_eq!(fade_level(Sample::from(0_i32), curve).to_clamped(), 0);
+            let max = Sample::from(Sample::MAX);
+            assert_eq!(fade_level(max, curve).to_clamped(), Sample::MAX);
+        }
+    }
+
+    #[test]
+    fn test_fade_concavity() {
+        let curve_points = |curve| -> Vec<Fixed> {
+            (0..=64)
+                .map(|n| apply_fade(n * fixed::ONE / 64, curve))
+                .collect()
+        };
+        for curve in CURVES {
+            let points = curve_points(curve);
+            // always rising
+            assert!(
+                points.windows(2).all(|pair| pair[0] < pair[1]),
+                "{:?}",
+                curve
+            );
+            for (n, pair) in points.windows(3).enumerate() {
+                let bend = pair[0] + pair[2] - 2 * pair[1];
+                let linear = (n as Fixed + 1) * fixed::ONE / 64;
+                match curve {
+                    FadeCurve::Linear => assert!(bend.abs() <= 1),
+                    FadeCurve::Exponential => {
+                        assert!(bend >= 0, "{}", n);
+                        assert!(pair[1] < linear);
+                    }
+                    FadeCurve::Logarithmic => {
+                        assert!(bend <= 0, "{}", n);
+                        assert!(pair[1] > linear);
+                    }
+                }
+            }
+        }
+
+        // exponential is even in dB: half way is about 24dB (4 octaves) down,
+        // (2^4 - 1) / (2^8 - 1)
+        let half = apply_fade(fixed::ONE / 2, FadeCurve::Exponential);
+        assert_eq!(half, 15 * fixed::ONE / 255);
+        let quarter = apply_fade(fixed::ONE / 4, FadeCurve::Exponential);
+        assert_eq!(quarter, 3 * fixed::ONE / 255);
+    }
+}
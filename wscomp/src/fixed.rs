@@ -0,0 +1,149 @@
+//! Fixed point format for the DSP helpers
+//!
+//! Filters, resampling and clock timing need more precision than whole
+//! samples. Rather than each shifting by its own amount, they share [`Fixed`]
+//! with [`FRAC_BITS`] fractional bits, so the precision/headroom tradeoff is
+//! tuned here, in one place.
+//!
+//! [`Fixed`] is an `i32`. Full scale `i16` audio needs 16 integer bits, so up
+//! to 16 fractional bits fit: more precision, less headroom for sums.
+//! Multiply with [`mul()`], which widens to avoid overflow. [`exp2()`]
+//! covers exponential curves, such as fades and decibels.
+//!
+//! [`Sample`]'s own `ACCUM_BITS` are a smoothing accumulator, not part of
+//! this format, conversions go through [`Sample::to_clamped()`]. Decay
+//! coefficients ([`crate::decay`]) are also separate, unsigned fractions
+//! sized to fit a `u16`.
+
+use crate::Sample;
+
+/// Fractional bits of [`Fixed`] values
+pub const FRAC_BITS: u32 = 16;
+
+// a full scale i16 has to fit
+const _: () = assert!(FRAC_BITS <= 16);
+
+/// Fixed point number with [`FRAC_BITS`] fractional bits
+pub type Fixed = i32;
+
+/// 1.0 as [`Fixed`]
+pub const ONE: Fixed = 1 << FRAC_BITS;
+
+pub const fn from_i16(value: i16) -> Fixed {
+    (value as i32) << FRAC_BITS
+}
+
+/// Whole part of `value`, rounded toward negative infinity and saturating
+pub const fn to_i16(value: Fixed) -> i16 {
+    let whole = value >> FRAC_BITS;
+    if whole > i16::MAX as i32 {
+        i16::MAX
+    } else if whole < i16::MIN as i32 {
+        i16::MIN
+    } else {
+        whole as i16
+    }
+}
+
+pub fn from_sample(value: Sample) -> Fixed {
+    value.to_clamped() << FRAC_BITS
+}
+
+/// Whole part of `value` as a [`Sample`], rounded toward negative infinity
+pub fn to_sample(value: Fixed) -> Sample {
+    Sample::from(value >> FRAC_BITS)
+}
+
+/// `a * b`, saturating
+pub fn mul(a: Fixed, b: Fixed) -> Fixed {
+    let product = (i64::from(a) * i64::from(b)) >> FRAC_BITS;
+    product.clamp(i64::from(Fixed::MIN), i64::from(Fixed::MAX)) as Fixed
+}
+
+/// `2^exponent`, saturating
+///
+/// A cubic approximation for the fraction, exact for whole exponents and
+/// within about 0.01% between them.
+pub fn exp2(exponent: Fixed) -> Fixed {
+    let whole = exponent >> FRAC_BITS;
+    let fraction = exponent & (ONE - 1);
+    // coefficients sum to one, so 2^1 is exact
+    const A: Fixed = 45_574; // 0.6954
+    const B: Fixed = 14_837; // 0.2264
+    const C: Fixed = 5_125; // 0.0782
+    let power = i64::from(ONE + mul(fraction, A + mul(fraction, B + mul(fraction, C))));
+    let power = match whole {
+        ..=-32 => 0,
+        -31..=-1 => power >> -whole,
+        0..=31 => power << whole,
+        _ => i64::MAX,
+    };
+    power.min(i64::from(Fixed::MAX)) as Fixed
+}
+
+#[cfg(test)]
+mod test {
+    use super::{exp2, from_i16, from_sample, mul, to_i16, to_sample, Fixed, FRAC_BITS, ONE};
+    use crate::Sample;
+
+    #[test]
+    fn test_i16_round_trip() {
+        for value in i16::MIN..=i16::MAX {
+            assert_eq!(to_i16(from_i16(value)), value);
+        }
+        assert_eq!(to_i16(Fixed::MAX), i16::MAX);
+        assert_eq!(to_i16(Fixed::MIN), i16::MIN);
+    }
+
+    #[test]
+    fn test_sample_round_trip() {
+        for value in Sample::MIN..=Sample::MAX {
+            let sample = Sample::from(value);
+            assert_eq!(to_sample(from_sample(sample)).to_clamped(), value);
+        }
+    }
+
+    #[test]
+    fn test_fraction_within_precision() {
+        // dropping the fraction loses less than one whole step, and never
+        // rounds up
+        for value in [ONE + 1, 3 * ONE / 2, -ONE / 3, 1000 * ONE + ONE - 1, -1] {
+            let whole = from_i16(to_i16(value));
+            assert!(whole <= value && value - whole < ONE, "{}", value);
+        }
+        // the smallest step is representable
+        assert_eq!(from_i16(1) >> FRAC_BITS, 1);
+        assert_ne!(ONE / 2 + 1, ONE / 2);
+    }
+
+    #[test]
+    fn test_mul() {
+        assert_eq!(mul(from_i16(1000), ONE / 2), from_i16(500));
+        assert_eq!(mul(from_i16(-1000), ONE / 4), from_i16(-250));
+        assert_eq!(mul(from_i16(i16::MAX), ONE), from_i16(i16::MAX));
+        assert_eq!(mul(from_i16(i16::MAX), from_i16(i16::MAX)), Fixed::MAX);
+    }
+
+    #[test]
+    fn test_exp2() {
+        // whole powers are exact
+        assert_eq!(exp2(0), ONE);
+        assert_eq!(exp2(ONE), 2 * ONE);
+        assert_eq!(exp2(-ONE), ONE / 2);
+        assert_eq!(exp2(8 * ONE), 256 * ONE);
+        assert_eq!(exp2(-16 * ONE), 1);
+        // out of range
+        assert_eq!(exp2(-17 * ONE), 0);
+        assert_eq!(exp2(-40 * ONE), 0);
+        assert_eq!(exp2(15 * ONE), Fixed::MAX);
+        assert_eq!(exp2(Fixed::MAX), Fixed::MAX);
+        assert_eq!(exp2(Fixed::MIN), 0);
+        // fractions within 0.01%, plus rounding of small results
+        for step in -64..=64 {
+            let exponent = step * ONE / 16;
+            let exact = 2.0_f64.powf(f64::from(exponent) / f64::from(ONE)) * f64::from(ONE);
+            let error = (f64::from(exp2(exponent)) - exact).abs();
+            assert!(error <= exact / 9000.0 + 2.0, "{}: {}", step, error);
+        }
+    }
+}
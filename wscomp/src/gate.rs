@@ -0,0 +1,163 @@
+//! Noise gate
+//!
+//! [`Gate`] passes a signal while it's loud and mutes it while it's quiet, for
+//! gated rain or for cleaning up the noise floor of a CV input. Separate open
+//! and close thresholds (hysteresis) stop a level near the threshold from
+//! chattering, and a hold time keeps the gate open through brief dips like
+//! zero crossings. Opening and closing fade rather than switch, so there's no
+//! click.
+
+use crate::fade::{fade_between, FadeCurve};
+use crate::Sample;
+
+/// Whether a [`Gate`] is passing its input
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GateState {
+    Open,
+    /// Below the close threshold, still open for the rest of the hold time
+    Holding,
+    Closed,
+}
+
+/// Hard gate with hysteresis, hold and a short fade
+pub struct Gate {
+    open_threshold: i32,
+    close_threshold: i32,
+    hold_samples: u32,
+    fade_samples: u32,
+    state: GateState,
+    /// Samples left before closing, while holding
+    hold: u32,
+    /// Output gain, from 0 (muted) to `fade_samples` (unity)
+    gain: u32,
+    curve: FadeCurve,
+}
+
+impl Gate {
+    /// New closed gate
+    ///
+    /// Opens when the level reaches `open_threshold`, and closes
+    /// `hold_samples` after it drops below `close_threshold`. The close
+    /// threshold is limited to the open threshold. Gain fades linearly over
+    /// `fade_samples`, see [`Gate::set_fade_curve()`].
+    pub fn new(
+        open_threshold: Sample,
+        close_threshold: Sample,
+        hold_samples: u32,
+        fade_samples: u32,
+    ) -> Self {
+        let open_threshold = open_threshold.to_clamped().abs();
+        Gate {
+            open_threshold,
+            close_threshold: close_threshold.to_clamped().abs().min(open_threshold),
+            hold_samples,
+            fade_samples: fade_samples.max(1),
+            state: GateState::Closed,
+            hold: 0,
+            gain: 0,
+            curve: FadeCurve::Linear,
+        }
+    }
+
+    pub fn set_fade_curve(&mut self, curve: FadeCurve) {
+        self.curve = curve;
+    }
+
+    pub fn state(&self) -> GateState {
+        self.state
+    }
+
+    pub fn process(&mut self, input: Sample) -> Sample {
+        let level = input.to_clamped().abs();
+        self.state = match self.state {
+            _ if level >= self.open_threshold => GateState::Open,
+            GateState::Open | GateState::Holding if level >= self.close_threshold => {
+                GateState::Open
+            }
+            GateState::Open if self.hold_samples > 0 => {
+                self.hold = self.hold_samples;
+                GateState::Holding
+            }
+            GateState::Holding if self.hold > 1 => {
+                self.hold -= 1;
+                GateState::Holding
+            }
+            _ => GateState::Closed,
+        };
+        self.gain = match self.state {
+            GateState::Closed => self.gain.saturating_sub(1),
+            _ => (self.gain + 1).min(self.fade_samples),
+        };
+        let silence = Sample::from(Sample::CENTER);
+        fade_between(silence, input, self.gain, self.fade_samples, self.curve)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Gate, GateState};
+    use crate::fade::FadeCurve;
+    use crate::Sample;
+
+    fn gate() -> Gate {
+        // no fade, to check the state timing exactly
+        Gate::new(Sample::from(500_i32), Sample::from(200_i32), 3, 1)
+    }
+
+    fn process(gate: &mut Gate, input: i32) -> i32 {
+        gate.process(Sample::from(input)).to_clamped()
+    }
+
+    #[test]
+    fn test_gate_hysteresis() {
+        let mut gate = Gate::new(Sample::from(500_i32), Sample::from(200_i32), 0, 1);
+        assert_eq!(process(&mut gate, 300), 0);
+        assert_eq!(gate.state(), GateState::Closed);
+        // negative levels count too
+        assert_eq!(process(&mut gate, -500), -500);
+        assert_eq!(gate.state(), GateState::Open);
+        // between the thresholds, stays open
+        assert_eq!(process(&mut gate, 300), 300);
+        assert_eq!(process(&mut gate, -200), -200);
+        assert_eq!(process(&mut gate, 199), 0);
+        assert_eq!(gate.state(), GateState::Closed);
+        // between the thresholds, stays closed
+        assert_eq!(process(&mut gate, 499), 0);
+        assert_eq!(gate.state(), GateState::Closed);
+    }
+
+    #[test]
+    fn test_gate_holds_before_closing() {
+        let mut gate = gate();
+        process(&mut gate, 1000);
+        let outputs: Vec<i32> = [100, 100, 100, 100, 100]
+            .map(|input| process(&mut gate, input))
+            .into();
+        assert_eq!(outputs, [100, 100, 100, 0, 0]);
+        assert_eq!(gate.state(), GateState::Closed);
+
+        // a dip shorter than the hold doesn't close the gate
+        process(&mut gate, 1000);
+        for input in [0, 0, 300, 0, 0, 0] {
+            assert_eq!(process(&mut gate, input), input);
+            assert_ne!(gate.state(), GateState::Closed);
+        }
+        assert_eq!(process(&mut gate, 100), 0);
+    }
+
+    #[test]
+    fn test_gate_fades() {
+        let mut gate = Gate::new(Sample::from(500_i32), Sample::from(200_i32), 0, 4);
+        let opening: Vec<i32> = [1000; 5].map(|input| process(&mut gate, input)).into();
+        assert_eq!(opening, [250, 500, 750, 1000, 1000]);
+        let closing: Vec<i32> = [100; 5].map(|input| process(&mut gate, input)).into();
+        assert_eq!(closing, [75, 50, 25, 0, 0]);
+
+        // exponential fades start quieter
+        gate.set_fade_curve(FadeCurve::Exponential);
+        let opening: Vec<i32> = [1000; 5].map(|input| process(&mut gate, input)).into();
+        assert_eq!(opening[3..], [1000, 1000]);
+        assert!(opening[0] < 250 && opening[1] < 500 && opening[2] < 750);
+    }
+}
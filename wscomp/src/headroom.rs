@@ -0,0 +1,81 @@
+//! Headroom monitoring, for catching overflow while developing
+//!
+//! Sums of samples can go beyond [`crate::Sample::MAX`] and are then
+//! silently clamped at the output. [`HeadroomMonitor`] tracks the peak
+//! absolute value seen at one point of the audio path, and reports when it
+//! first gets close to the limit, so a stage with too little headroom shows
+//! up in the log rather than as distortion.
+
+/// Peak tracker for one point in the audio path
+pub struct HeadroomMonitor {
+    peak: u32,
+    warn_above: u32,
+    warned: bool,
+}
+
+impl HeadroomMonitor {
+    /// Monitor warning once the peak goes above `warn_above`
+    pub const fn new(warn_above: u32) -> Self {
+        HeadroomMonitor {
+            peak: 0,
+            warn_above,
+            warned: false,
+        }
+    }
+
+    /// Largest absolute value observed
+    pub fn peak(&self) -> u32 {
+        self.peak
+    }
+
+    /// Track `value`, returns true the first time the peak passes the
+    /// warning level, so the caller logs once rather than every sample
+    pub fn observe(&mut self, value: i32) -> bool {
+        self.peak = self.peak.max(value.unsigned_abs());
+        if self.peak > self.warn_above && !self.warned {
+            self.warned = true;
+            return true;
+        }
+        false
+    }
+
+    /// Forget the peak, and warn again
+    pub fn reset(&mut self) {
+        self.peak = 0;
+        self.warned = false;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::HeadroomMonitor;
+
+    #[test]
+    fn test_headroom_tracks_peak() {
+        let mut monitor = HeadroomMonitor::new(2000);
+        assert_eq!(monitor.peak(), 0);
+        for (value, peak) in [(100, 100), (-700, 700), (300, 700), (i32::MIN, 1 << 31)] {
+            monitor.observe(value);
+            assert_eq!(monitor.peak(), peak, "{}", value);
+        }
+        monitor.reset();
+        assert_eq!(monitor.peak(), 0);
+    }
+
+    #[test]
+    fn test_headroom_warns_once_above_threshold() {
+        let mut monitor = HeadroomMonitor::new(2000);
+        // at the threshold is fine
+        assert!(!monitor.observe(2000));
+        assert!(!monitor.observe(-2000));
+        assert!(monitor.observe(-2001));
+        // once is enough, even for higher peaks
+        assert!(!monitor.observe(2001));
+        assert!(!monitor.observe(5000));
+        assert_eq!(monitor.peak(), 5000);
+        // until reset
+        monitor.reset();
+        assert!(!monitor.observe(1500));
+        assert!(monitor.observe(3000));
+    }
+}
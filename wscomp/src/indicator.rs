@@ -0,0 +1,137 @@
+//! Brightness mappings for indicator LEDs
+
+use crate::decay::decay_step;
+use crate::{Sample, U12_MAX};
+
+/// Shape of a [`center_peak()`] indicator's falloff away from center
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PeakCurve {
+    /// Brightness falls evenly with distance from center
+    Linear,
+    /// Brightness falls quickly just away from center, for a narrow peak
+    Sharp,
+}
+
+/// Brightness (0..=[`U12_MAX`]) which peaks when `value` is at center
+///
+/// Full brightness at center, falling to `minimum` at either extreme.
+pub fn center_peak(value: Sample, curve: PeakCurve, minimum: u16) -> u16 {
+    let max = Sample::MAX as u32;
+    // 0 at the extremes, `max` at center
+    let closeness = max - value.to_clamped().unsigned_abs().min(max);
+    let shaped = match curve {
+        PeakCurve::Linear => closeness,
+        PeakCurve::Sharp => closeness * closeness / max,
+    };
+    let minimum = minimum.min(U12_MAX);
+    let range = u32::from(U12_MAX - minimum);
+    minimum + (range * shaped / max) as u16
+}
+
+/// Level meter which holds peaks, for a steadier LED
+///
+/// Rises to a new peak at once, then decays slowly toward lower levels
+/// rather than following every dip, which would flicker.
+pub struct PeakMeter {
+    peak: i32,
+    coefficient: u16,
+}
+
+impl PeakMeter {
+    /// Meter starting at 0, decaying by `coefficient /`
+    /// [`crate::decay::DECAY_UNITY`] per [`PeakMeter::process()`]
+    pub const fn new(coefficient: u16) -> Self {
+        PeakMeter {
+            peak: 0,
+            coefficient,
+        }
+    }
+
+    pub fn peak(&self) -> u16 {
+        self.peak as u16
+    }
+
+    /// Update with the current `level`, returning the held peak
+    pub fn process(&mut self, level: u16) -> u16 {
+        let level = i32::from(level);
+        self.peak = decay_step(self.peak, self.coefficient).max(level);
+        self.peak()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{center_peak, PeakCurve, PeakMeter};
+    use crate::decay::decay_step;
+    use crate::{Sample, U12_MAX};
+
+    #[test]
+    fn test_center_peak_ends() {
+        for curve in [PeakCurve::Linear, PeakCurve::Sharp] {
+            for minimum in [0, 1024, 2048] {
+                let at = |v: i32| center_peak(Sample::new(v, false), curve, minimum);
+                assert_eq!(at(0), U12_MAX);
+                assert_eq!(at(Sample::MIN), minimum);
+                assert_eq!(at(Sample::MAX), minimum);
+                // peaks at center, falling off on both sides
+                for v in 1..=Sample::MAX {
+                    assert!(at(v) <= at(v - 1), "{:?} rises at {}", curve, v);
+                    assert!(at(-v) <= at(-v + 1), "{:?} rises at {}", curve, -v);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_center_peak_shapes() {
+        // linear from half brightness matches the inverted absolute value
+        for v in [Sample::MIN, -1500, -1, 0, 700, Sample::MAX] {
+            let value = Sample::new(v, false);
+            assert_eq!(
+                center_peak(value, PeakCurve::Linear, 2048),
+                value.to_output_abs_inverted()
+            );
+        }
+        let at = |v: i32, curve| center_peak(Sample::new(v, false), curve, 0);
+        // sharp is narrower, dimmer away from center
+        assert!(at(1024, PeakCurve::Sharp) < at(1024, PeakCurve::Linear));
+        assert!(at(-1024, PeakCurve::Sharp) < at(-1024, PeakCurve::Linear));
+    }
+
+    #[test]
+    fn test_peak_meter_jumps_to_peaks() {
+        let mut meter = PeakMeter::new(65000);
+        assert_eq!(meter.peak(), 0);
+        assert_eq!(meter.process(3000), 3000);
+        // a higher peak while decaying is caught at once
+        meter.process(100);
+        assert_eq!(meter.process(U12_MAX), U12_MAX);
+        // the slowest decay falls one step per update
+        let mut slow = PeakMeter::new(u16::MAX);
+        slow.process(2000);
+        for _ in 0..100 {
+            slow.process(0);
+        }
+        assert_eq!(slow.peak(), 1900);
+    }
+
+    #[test]
+    fn test_peak_meter_decay_rate() {
+        for coefficient in [32768, 60000, 65378] {
+            let mut meter = PeakMeter::new(coefficient);
+            meter.process(U12_MAX);
+            let mut expected = i32::from(U12_MAX);
+            for _ in 0..500 {
+                expected = decay_step(expected, coefficient);
+                assert_eq!(i32::from(meter.process(0)), expected);
+            }
+        }
+        // decays toward, but not below, the current level
+        let mut meter = PeakMeter::new(32768);
+        meter.process(4000);
+        assert_eq!(meter.process(1000), 2000);
+        assert_eq!(meter.process(1000), 1000);
+        assert_eq!(meter.process(1000), 1000);
+    }
+}
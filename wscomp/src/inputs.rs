@@ -0,0 +1,600 @@
+//! Scanning the knobs, CV inputs and Z switch through the ADC mux
+//!
+//! The Computer reads the Main, X and Y knobs, the Z switch and the two CV
+//! inputs through a 4 way mux in front of two ADC pins. Each scan steps the
+//! mux through its addresses, waiting for each to settle, and drives the
+//! normalization probe to see which CV jacks are patched.
+//!
+//! ```text
+//! a b : ADC pin 1 : ADC pin 2
+//! 0 0 : Main knob : CV 1
+//! 1 0 : X knob    : CV 2
+//! 0 1 : Y knob    : -
+//! 1 1 : Z switch  : -
+//! ```
+//!
+//! [`ComputerInputs`] runs the scan over an [`InputHardware`], which the
+//! firmware implements for its pins and ADC, so the sequence can be tested
+//! without the hardware.
+//!
+//! Each input can be oversampled, averaging several back to back ADC reads
+//! into the level passed to [`SampleUpdate::update()`], so a single noisy
+//! conversion doesn't nudge a knob. The number of reads is set per kind of
+//! input in [`ScanTiming`], as more reads make the scan take longer.
+
+use core::future::Future;
+
+use crate::probe::ProbeConfig;
+use crate::settle::{check_settled, Settling};
+use crate::switch::{SwitchPosition, SwitchThresholds, ZSwitchDecoder};
+use crate::{JackSample, Sample, SampleUpdate};
+
+/// ADC pin behind the mux
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MuxPin {
+    /// Knobs and the Z switch
+    Io1,
+    /// CV inputs
+    Io2,
+}
+
+/// Input read by a scan, for reporting failed reads
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MuxInput {
+    MainKnob,
+    XKnob,
+    YKnob,
+    ZSwitch,
+    Cv1,
+    Cv1Probe,
+    Cv2,
+    Cv2Probe,
+}
+
+/// Mux address pins, probe pin and ADC a scan drives
+pub trait InputHardware {
+    type Error;
+
+    /// Set the mux address pins
+    fn select(&mut self, a: bool, b: bool);
+
+    /// Set the probe pin's level
+    fn set_probe(&mut self, high: bool);
+
+    /// One ADC conversion of `pin`
+    fn read(&mut self, pin: MuxPin) -> impl Future<Output = Result<u16, Self::Error>>;
+
+    fn delay_micros(&mut self, micros: u32) -> impl Future<Output = ()>;
+}
+
+/// Waits between switching the mux or probe and reading, reads averaged per
+/// input, and Z switch timing in scans
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScanTiming {
+    /// Wait after switching the mux, in microseconds
+    pub mux_settle_micros: u32,
+    /// Largest difference between two reads of a settled channel, see
+    /// [`check_settled()`]. `None` reads once after the settle delay.
+    pub settle_threshold: Option<u16>,
+    /// Extra wait before reading an unsettled channel again
+    pub resettle_micros: u32,
+    /// ADC reads averaged for each knob once settled, 1 (or 0) reads once
+    pub knob_oversampling: u8,
+    /// ADC reads averaged for each CV input, and again for its probe
+    pub cv_oversampling: u8,
+    /// ADC reads averaged for the Z switch, which is debounced as well
+    pub switch_oversampling: u8,
+    /// Scans which must agree to change the Z switch's position
+    pub switch_debounce_scans: u8,
+    /// Scans in the momentary position which count as a hold
+    pub switch_hold_scans: u32,
+}
+
+impl ScanTiming {
+    /// Settings for the Computer, determined through testing
+    pub const fn default() -> Self {
+        ScanTiming {
+            mux_settle_micros: 20,
+            settle_threshold: Some(24),
+            resettle_micros: 40,
+            knob_oversampling: 1,
+            cv_oversampling: 1,
+            switch_oversampling: 1,
+            switch_debounce_scans: 2,
+            // half a second, scanning at 60Hz
+            switch_hold_scans: 30,
+        }
+    }
+}
+
+impl Default for ScanTiming {
+    fn default() -> Self {
+        Self::default()
+    }
+}
+
+/// Latest readings of the inputs behind the mux
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone)]
+pub struct MuxReadings {
+    pub main_knob: Sample,
+    pub x_knob: Sample,
+    pub y_knob: Sample,
+    pub zswitch: SwitchPosition,
+    /// Raw ADC level the Z switch was decoded from, for calibrating it
+    pub zswitch_level: u16,
+    pub cv1: JackSample,
+    pub cv2: JackSample,
+}
+
+impl MuxReadings {
+    /// Centered knobs and CVs, Z switch off
+    pub fn new(probe: ProbeConfig) -> Self {
+        // CV inputs are not inverted according to docs, but read inverted
+        let mut cv = JackSample::new(
+            Sample::new(Sample::CENTER, true),
+            Sample::new(Sample::CENTER, true),
+        );
+        cv.set_probe(probe);
+        MuxReadings {
+            main_knob: Sample::new(Sample::CENTER, false),
+            x_knob: Sample::new(Sample::CENTER, false),
+            y_knob: Sample::new(Sample::CENTER, false),
+            zswitch: SwitchPosition::Off,
+            // between the default thresholds, off
+            zswitch_level: 2048,
+            cv1: cv.clone(),
+            cv2: cv,
+        }
+    }
+}
+
+/// Driver scanning the Computer's mux inputs
+pub struct ComputerInputs<H> {
+    hardware: H,
+    timing: ScanTiming,
+    probe: ProbeConfig,
+    switch: ZSwitchDecoder,
+    readings: MuxReadings,
+    resettles: u32,
+}
+
+impl<H: InputHardware> ComputerInputs<H> {
+    /// Driver for `hardware`, leaving the probe idle
+    pub fn new(mut hardware: H, timing: ScanTiming, probe: ProbeConfig) -> Self {
+        hardware.set_probe(!probe.polarity.probing_high());
+        ComputerInputs {
+            hardware,
+            timing,
+            probe,
+            switch: ZSwitchDecoder::new(
+                SwitchThresholds::default(),
+                timing.switch_debounce_scans,
+                timing.switch_hold_scans,
+            ),
+            readings: MuxReadings::new(probe),
+            resettles: 0,
+        }
+    }
+
+    /// The hardware, for reading other ADC inputs between scans
+    pub fn hardware_mut(&mut self) -> &mut H {
+        &mut self.hardware
+    }
+
+    pub fn readings(&self) -> &MuxReadings {
+        &self.readings
+    }
+
+    /// Z switch decoder, for its press and hold edges from the last scan
+    pub fn switch(&self) -> &ZSwitchDecoder {
+        &self.switch
+    }
+
+    pub fn set_switch_thresholds(&mut self, thresholds: SwitchThresholds) {
+        self.switch.set_thresholds(thresholds);
+    }
+
+    /// Reads which needed the extra resettle wait, since the driver started
+    pub fn resettles(&self) -> u32 {
+        self.resettles
+    }
+
+    /// Read every mux input once, returning the updated readings
+    ///
+    /// A failed read is passed to `failed` and leaves that input's previous
+    /// reading, the rest of the scan carries on.
+    pub async fn scan(&mut self, mut failed: impl FnMut(MuxInput, H::Error)) -> &MuxReadings {
+        let knobs = self.timing.knob_oversampling;
+        // NOTE: X and Y appear to be swapped compared to the mux's logic
+        // table
+        self.select(false, false).await;
+        match self.read_oversampled(MuxPin::Io1, knobs).await {
+            Ok(level) => self.readings.main_knob.update(level),
+            Err(e) => failed(MuxInput::MainKnob, e),
+        }
+        self.read_cv(MuxInput::Cv1, &mut failed).await;
+
+        self.select(true, false).await;
+        match self.read_oversampled(MuxPin::Io1, knobs).await {
+            Ok(level) => self.readings.x_knob.update(level),
+            Err(e) => failed(MuxInput::XKnob, e),
+        }
+        self.read_cv(MuxInput::Cv2, &mut failed).await;
+
+        self.select(false, true).await;
+        match self.read_oversampled(MuxPin::Io1, knobs).await {
+            Ok(level) => self.readings.y_knob.update(level),
+            Err(e) => failed(MuxInput::YKnob, e),
+        }
+
+        // the switch jumps between levels, it's debounced rather than
+        // smoothed or resettled
+        self.select(true, true).await;
+        let switch = self.timing.switch_oversampling;
+        match self.read_averaged(MuxPin::Io1, switch).await {
+            Ok(level) => {
+                self.readings.zswitch_level = level;
+                self.readings.zswitch = self.switch.update(level);
+            }
+            Err(e) => failed(MuxInput::ZSwitch, e),
+        }
+
+        &self.readings
+    }
+
+    /// Switch the mux to address `a`, `b` and wait for it to settle
+    async fn select(&mut self, a: bool, b: bool) {
+        self.hardware.select(a, b);
+        self.hardware
+            .delay_micros(self.timing.mux_settle_micros)
+            .await;
+    }
+
+    /// Read a mux channel just after switching the mux
+    ///
+    /// With a settle threshold, reads twice and if the reads disagree waits
+    /// the resettle time and reads a third time, so the longer delay is only
+    /// paid when it's needed.
+    async fn read_settled(&mut self, pin: MuxPin) -> Result<u16, H::Error> {
+        let first = self.hardware.read(pin).await?;
+        let Some(threshold) = self.timing.settle_threshold else {
+            return Ok(first);
+        };
+        let second = self.hardware.read(pin).await?;
+        match check_settled(first, second, threshold) {
+            Settling::Settled(level) => Ok(level),
+            Settling::Unsettled => {
+                self.resettles = self.resettles.wrapping_add(1);
+                self.hardware
+                    .delay_micros(self.timing.resettle_micros)
+                    .await;
+                self.hardware.read(pin).await
+            }
+        }
+    }
+
+    /// Read a mux channel just after switching the mux, averaging `count`
+    /// reads once it's settled
+    async fn read_oversampled(&mut self, pin: MuxPin, count: u8) -> Result<u16, H::Error> {
+        let first = self.read_settled(pin).await?;
+        self.average_reads(pin, first, count).await
+    }
+
+    /// Average `count` reads of a mux channel
+    async fn read_averaged(&mut self, pin: MuxPin, count: u8) -> Result<u16, H::Error> {
+        let first = self.hardware.read(pin).await?;
+        self.average_reads(pin, first, count).await
+    }
+
+    /// Average `first` with `count - 1` more reads of `pin`, rounded to
+    /// nearest
+    async fn average_reads(&mut self, pin: MuxPin, first: u16, count: u8) -> Result<u16, H::Error> {
+        let count = u32::from(count.max(1));
+        let mut sum = u32::from(first);
+        for _ in 1..count {
+            sum += u32::from(self.hardware.read(pin).await?);
+        }
+        Ok(((sum + count / 2) / count) as u16)
+    }
+
+    /// Read the CV jack at the mux's current address, idle and probed
+    async fn read_cv(&mut self, input: MuxInput, failed: &mut impl FnMut(MuxInput, H::Error)) {
+        let probe_input = match input {
+            MuxInput::Cv1 => MuxInput::Cv1Probe,
+            _ => MuxInput::Cv2Probe,
+        };
+        let settle = self.probe.settle_micros;
+        let probing_high = self.probe.polarity.probing_high();
+
+        let count = self.timing.cv_oversampling;
+        let raw = self.read_oversampled(MuxPin::Io2, count).await;
+        self.hardware.set_probe(probing_high);
+        self.hardware.delay_micros(settle).await;
+        let probe = self.read_averaged(MuxPin::Io2, count).await;
+        self.hardware.set_probe(!probing_high);
+        self.hardware.delay_micros(settle).await;
+
+        let jack = match input {
+            MuxInput::Cv1 => &mut self.readings.cv1,
+            _ => &mut self.readings.cv2,
+        };
+        match raw {
+            Ok(level) => jack.raw.update(level),
+            Err(e) => failed(input, e),
+        }
+        match probe {
+            Ok(level) => jack.probe.update(level),
+            Err(e) => failed(probe_input, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::future::Future;
+    use core::pin::pin;
+    use core::task::{Context, Poll, Waker};
+
+    use super::{ComputerInputs, InputHardware, MuxInput, MuxPin, ScanTiming};
+    use crate::probe::ProbeConfig;
+    use crate::switch::SwitchPosition;
+    use crate::Sample;
+
+    /// Mux, probe and ADC simulated with fixed levels for each input
+    struct MockHardware {
+        address: (bool, bool),
+        probe: bool,
+        /// Levels at addresses 0..4 (a + 2b) for each pin
+        io1: [u16; 4],
+        io2: [u16; 4],
+        /// How much driving the probe lowers the CV levels (raising the
+        /// inverted readings), 0 when patched
+        probe_offset: [u16; 4],
+        /// Reads left which give the previous address's level, unsettled
+        unsettled_reads: u8,
+        /// Address of reads to fail
+        fail_address: Option<(bool, bool)>,
+        /// Added to successive reads in turn, cycling
+        noise: Vec<i32>,
+        reads: Vec<(MuxPin, (bool, bool), bool)>,
+        delays: Vec<u32>,
+    }
+
+    impl MockHardware {
+        fn new() -> Self {
+            MockHardware {
+                address: (false, false),
+                probe: true,
+                io1: [3048, 1048, 2048, 3600],
+                io2: [1548, 2548, 0, 0],
+                probe_offset: [0, 0, 0, 0],
+                unsettled_reads: 0,
+                fail_address: None,
+                noise: Vec::new(),
+                reads: Vec::new(),
+                delays: Vec::new(),
+            }
+        }
+
+        fn index(&self) -> usize {
+            usize::from(self.address.0) + 2 * usize::from(self.address.1)
+        }
+    }
+
+    impl InputHardware for MockHardware {
+        type Error = &'static str;
+
+        fn select(&mut self, a: bool, b: bool) {
+            self.address = (a, b);
+        }
+
+        fn set_probe(&mut self, high: bool) {
+            self.probe = high;
+        }
+
+        async fn read(&mut self, pin: MuxPin) -> Result<u16, Self::Error> {
+            self.reads.push((pin, self.address, self.probe));
+            if self.fail_address == Some(self.address) {
+                return Err("failed");
+            }
+            if self.unsettled_reads > 0 {
+                self.unsettled_reads -= 1;
+                return Ok(0);
+            }
+            let index = self.index();
+            let level = match pin {
+                MuxPin::Io1 => self.io1[index],
+                MuxPin::Io2 if self.probe => self.io2[index] - self.probe_offset[index],
+                MuxPin::Io2 => self.io2[index],
+            };
+            let noise = match self.noise.len() {
+                0 => 0,
+                len => self.noise[(self.reads.len() - 1) % len],
+            };
+            Ok((i32::from(level) + noise).clamp(0, 4095) as u16)
+        }
+
+        async fn delay_micros(&mut self, micros: u32) {
+            self.delays.push(micros);
+        }
+    }
+
+    /// Run a future which never waits, as the mock hardware doesn't
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = pin!(future);
+        let mut context = Context::from_waker(Waker::noop());
+        match future.as_mut().poll(&mut context) {
+            Poll::Ready(output) => output,
+            Poll::Pending => panic!("mock hardware never waits"),
+        }
+    }
+
+    fn scan_times(inputs: &mut ComputerInputs<MockHardware>, times: usize) {
+        for _ in 0..times {
+            block_on(inputs.scan(|input, e| panic!("{:?} {}", input, e)));
+        }
+    }
+
+    #[test]
+    fn test_scan_reads_each_input() {
+        let mut inputs = ComputerInputs::new(
+            MockHardware::new(),
+            ScanTiming::default(),
+            ProbeConfig::default(),
+        );
+        // the probe starts idle
+        assert!(!inputs.hardware_mut().probe);
+        scan_times(&mut inputs, 64);
+        let readings = inputs.readings();
+        assert_eq!(readings.main_knob.to_clamped(), 1000);
+        assert_eq!(readings.x_knob.to_clamped(), -1000);
+        assert_eq!(readings.y_knob.to_clamped(), 0);
+        assert_eq!(readings.zswitch, SwitchPosition::On);
+        assert_eq!(readings.zswitch_level, 3600);
+        // CVs read inverted
+        assert_eq!(readings.cv1.raw.to_clamped(), 500);
+        assert_eq!(readings.cv2.raw.to_clamped(), -500);
+        // patched, the probe doesn't move them
+        assert_eq!(readings.cv1.plugged_value().unwrap().to_clamped(), 500);
+        assert_eq!(readings.cv2.plugged_value().unwrap().to_clamped(), -500);
+
+        // unpatched jacks follow the probe
+        let mut hardware = MockHardware::new();
+        hardware.probe_offset = [1000, 0, 0, 0];
+        let mut inputs =
+            ComputerInputs::new(hardware, ScanTiming::default(), ProbeConfig::default());
+        scan_times(&mut inputs, 64);
+        assert!(inputs.readings().cv1.plugged_value().is_none());
+        assert!(inputs.readings().cv2.plugged_value().is_some());
+        // and the switch follows its level, once debounced
+        inputs.hardware_mut().io1[3] = 100;
+        scan_times(&mut inputs, 1);
+        assert_eq!(inputs.readings().zswitch, SwitchPosition::On);
+        scan_times(&mut inputs, 1);
+        assert_eq!(inputs.readings().zswitch, SwitchPosition::Momentary);
+        assert!(inputs.switch().just_pressed());
+    }
+
+    #[test]
+    fn test_scan_sequence() {
+        let timing = ScanTiming {
+            mux_settle_micros: 7,
+            settle_threshold: None,
+            resettle_micros: 11,
+            ..ScanTiming::default()
+        };
+        let mut inputs = ComputerInputs::new(MockHardware::new(), timing, ProbeConfig::default());
+        scan_times(&mut inputs, 1);
+        let hardware = inputs.hardware_mut();
+        let (low, high) = (false, true);
+        let ff = (false, false);
+        let tf = (true, false);
+        assert_eq!(
+            hardware.reads,
+            [
+                (MuxPin::Io1, ff, low),
+                (MuxPin::Io2, ff, low),
+                (MuxPin::Io2, ff, high),
+                (MuxPin::Io1, tf, low),
+                (MuxPin::Io2, tf, low),
+                (MuxPin::Io2, tf, high),
+                (MuxPin::Io1, (false, true), low),
+                (MuxPin::Io1, (true, true), low),
+            ]
+        );
+        // mux settle, then the probe settles on and off for each CV
+        let probe = ProbeConfig::default().settle_micros;
+        assert_eq!(hardware.delays, [7, probe, probe, 7, probe, probe, 7, 7]);
+        assert!(!hardware.probe);
+    }
+
+    #[test]
+    fn test_scan_resettles() {
+        let mut hardware = MockHardware::new();
+        hardware.unsettled_reads = 1;
+        let mut inputs =
+            ComputerInputs::new(hardware, ScanTiming::default(), ProbeConfig::default());
+        scan_times(&mut inputs, 1);
+        assert_eq!(inputs.resettles(), 1);
+        // two reads of each settled channel, a third of the main knob after
+        // the extra wait
+        assert_eq!(inputs.hardware_mut().reads.len(), 3 + 2 * 4 + 2 + 1);
+        assert_eq!(inputs.hardware_mut().delays[..2], [20, 40]);
+        // the third read was used, not the unsettled first
+        assert!(inputs.readings().main_knob.to_clamped() > 0);
+    }
+
+    #[test]
+    fn test_oversampling_averages_noise() {
+        let mut hardware = MockHardware::new();
+        // averages to nothing over the whole cycle
+        hardware.noise = vec![37, -52, 11, -8, 45, -33, 6, -6];
+        let timing = ScanTiming {
+            settle_threshold: None,
+            ..ScanTiming::default()
+        };
+        let mut inputs = ComputerInputs::new(hardware, timing, ProbeConfig::default());
+        // the main knob is at 3048, single reads are well off it
+        let single: Vec<u16> = (0..8)
+            .map(|_| block_on(inputs.read_oversampled(MuxPin::Io1, 1)).unwrap())
+            .collect();
+        assert!(single.iter().any(|&level| level.abs_diff(3048) > 40));
+        // a whole cycle of noise averages out exactly
+        assert_eq!(block_on(inputs.read_oversampled(MuxPin::Io1, 8)), Ok(3048));
+        // and part of one gets close, wherever in the cycle it starts
+        for _ in 0..16 {
+            let level = block_on(inputs.read_oversampled(MuxPin::Io1, 5)).unwrap();
+            assert!(level.abs_diff(3048) <= 10, "{}", level);
+        }
+        // no oversampling reads once
+        let reads = inputs.hardware_mut().reads.len();
+        block_on(inputs.read_averaged(MuxPin::Io1, 0)).unwrap();
+        assert_eq!(inputs.hardware_mut().reads.len(), reads + 1);
+    }
+
+    #[test]
+    fn test_scan_oversampling_per_input() {
+        let timing = ScanTiming {
+            settle_threshold: None,
+            knob_oversampling: 2,
+            cv_oversampling: 4,
+            switch_oversampling: 1,
+            ..ScanTiming::default()
+        };
+        let mut hardware = MockHardware::new();
+        hardware.noise = vec![20, -20];
+        let mut inputs = ComputerInputs::new(hardware, timing, ProbeConfig::default());
+        scan_times(&mut inputs, 1);
+        // three knobs, two CVs each read idle and probed, and the switch
+        assert_eq!(inputs.hardware_mut().reads.len(), 3 * 2 + 2 * 2 * 4 + 1);
+        // averaged, the readings land on the true levels
+        scan_times(&mut inputs, 63);
+        let readings = inputs.readings();
+        assert_eq!(readings.main_knob.to_clamped(), 1000);
+        assert_eq!(readings.x_knob.to_clamped(), -1000);
+        assert_eq!(readings.cv1.raw.to_clamped(), 500);
+        assert_eq!(readings.cv2.raw.to_clamped(), -500);
+        assert_eq!(readings.zswitch, SwitchPosition::On);
+    }
+
+    #[test]
+    fn test_scan_reports_failed_reads() {
+        let mut hardware = MockHardware::new();
+        hardware.fail_address = Some((true, false));
+        let mut inputs =
+            ComputerInputs::new(hardware, ScanTiming::default(), ProbeConfig::default());
+        let mut failed = Vec::new();
+        block_on(inputs.scan(|input, _| failed.push(input)));
+        assert_eq!(failed, [MuxInput::XKnob, MuxInput::Cv2, MuxInput::Cv2Probe]);
+        // failed inputs keep their reading, the rest of the scan continues
+        let readings = inputs.readings();
+        assert_eq!(readings.x_knob.to_clamped(), Sample::CENTER);
+        assert!(readings.main_knob.to_clamped() > 0);
+        assert!(readings.y_knob.to_clamped() == 0);
+        assert_eq!(readings.zswitch_level, 3600);
+    }
+}
@@ -1,9 +1,56 @@
 #![cfg_attr(not(test), no_std)]
 
+use core::cmp::Ordering;
 use core::fmt::Debug;
-use core::ops::{Add, Div, Mul, Sub};
+use core::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
 
-use defmt::*;
+pub use error::Error;
+use fixed::Fixed;
+use probe::{ProbeConfig, ProbePolarity};
+
+pub mod beep;
+pub mod center;
+pub mod clock;
+pub mod comparator;
+pub mod curve;
+pub mod dac;
+pub mod decay;
+pub mod delay;
+pub mod epoch;
+pub mod error;
+pub mod fade;
+pub mod fixed;
+pub mod gate;
+pub mod headroom;
+pub mod indicator;
+pub mod inputs;
+pub mod limiter;
+pub mod math;
+pub mod mixer;
+pub mod modulation;
+pub mod noise;
+pub mod oneshot;
+pub mod pickup;
+pub mod probe;
+pub mod processor;
+pub mod quantizer;
+pub mod ramp;
+pub mod report;
+pub mod resample;
+pub mod retry;
+pub mod routing;
+pub mod sequence;
+pub mod settle;
+pub mod slew;
+pub mod stats;
+pub mod stereo;
+pub mod storage;
+pub mod stream;
+pub mod switch;
+pub mod temperature;
+pub mod timeout;
+pub mod tone;
+pub mod wav;
 
 // Sample todos
 //
@@ -20,8 +67,14 @@ pub const U12_MAX: u16 = 2u16.pow(12) - 1;
 /// values without giving errors. Before converting, raw internal value will be
 /// outside of 12 bit range (allowing for math & accumulations, etc).
 ///
-/// Values are smoothed over recent updates (count based on `ACCUM_BITS`).
-#[derive(Format, PartialEq, Copy, Clone, PartialOrd)]
+/// Values are smoothed over recent updates, a time constant of
+/// `2^SMOOTHING_SHIFT` updates.
+///
+/// Samples compare by their value, including the fraction kept for smoothing
+/// and sums beyond the range, so they agree with [`Sample::to_clamped()`]
+/// within it. Whether the source was inverted doesn't affect comparisons.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Copy, Clone)]
 pub struct Sample {
     accumulated_raw: i32,
     inverted_source: bool,
@@ -38,6 +91,26 @@ impl Debug for Sample {
     }
 }
 
+impl PartialEq for Sample {
+    fn eq(&self, other: &Self) -> bool {
+        self.accumulated_raw == other.accumulated_raw
+    }
+}
+
+impl Eq for Sample {}
+
+impl PartialOrd for Sample {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Sample {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.accumulated_raw.cmp(&other.accumulated_raw)
+    }
+}
+
 impl Sample {
     // CONST for min/max values (12 bit limits, 11 on each positive/negative)
     pub const MIN: i32 = -2_i32.pow(11);
@@ -45,6 +118,25 @@ impl Sample {
     pub const CENTER: i32 = 0;
     pub const OFFSET: i32 = 2_i32.pow(11);
     const ACCUM_BITS: u8 = 3;
+    /// Updates are smoothed over about `2^SMOOTHING_SHIFT` updates, at most
+    /// `ACCUM_BITS`, which hold the fraction
+    const SMOOTHING_SHIFT: u8 = 3;
+
+    /// `value` shifted into the accumulator, saturating rather than
+    /// overflowing for values far beyond the sample range
+    ///
+    /// The limits are symmetric, so saturated values can still be negated.
+    const fn accumulate(value: i32) -> i32 {
+        let limit = i32::MAX >> Self::ACCUM_BITS;
+        let value = if value > limit {
+            limit
+        } else if value < -limit {
+            -limit
+        } else {
+            value
+        };
+        value << Self::ACCUM_BITS
+    }
 
     /// New `InputValue` from i32
     ///
@@ -71,6 +163,12 @@ impl Sample {
     }
 
     /// Saturating conversion into 12 bit safe u16 for output
+    ///
+    /// Offset binary, so it's already bipolar: [`Self::MIN`] is code 0,
+    /// [`Self::CENTER`] is 2048 and [`Self::MAX`] is [`U12_MAX`], the full
+    /// range of the DAC. 2048 is mid-scale, which is 0V at the output jacks
+    /// ([`dac::ZERO_VOLT_CODE`]). [`Self::to_output_abs()`] is the unipolar
+    /// one, for LEDs.
     pub fn to_output(&self) -> u16 {
         // clamp self and convert to u16
         (self.to_clamped() + Self::OFFSET) as u16
@@ -97,6 +195,27 @@ impl Sample {
         (self.accumulated_raw >> Self::ACCUM_BITS).clamp(Self::MIN, Self::MAX)
     }
 
+    /// Value without clamping, beyond the sample range when sums overflow it
+    pub fn to_unclamped(&self) -> i32 {
+        self.accumulated_raw >> Self::ACCUM_BITS
+    }
+
+    /// Clamped value normalized to `-1.0..1.0`, exactly -1.0 at [`Self::MIN`]
+    #[cfg(feature = "float")]
+    pub fn to_f32(&self) -> f32 {
+        self.to_clamped() as f32 / Self::OFFSET as f32
+    }
+
+    /// Sample from a `-1.0..1.0` float, rounded to nearest and saturating
+    /// outside that range, see [`Self::new()`] for `invert`
+    #[cfg(feature = "float")]
+    pub fn from_f32(value: f32, invert: bool) -> Self {
+        let scaled = value * Self::OFFSET as f32;
+        // core has no f32::round(), `as` truncates toward zero and saturates
+        let rounded = (scaled + if scaled < 0.0 { -0.5 } else { 0.5 }) as i32;
+        Self::new(rounded.clamp(Self::MIN, Self::MAX), invert)
+    }
+
     pub fn to_inverted(&self) -> Self {
         Self::new(-self.accumulated_raw, self.inverted_source)
     }
@@ -106,6 +225,73 @@ impl Sample {
         Self::new(self.to_clamped().saturating_abs(), self.inverted_source)
     }
 
+    /// This sample kept within `lo` to `hi`, keeping its inversion flag
+    ///
+    /// Values within the bounds are unchanged, fraction included. Unlike
+    /// [`Ord::clamp()`] this doesn't panic when `lo` is above `hi`, `hi` wins,
+    /// so the result never exceeds `hi`. Use [`Ord::min()`] and [`Ord::max()`]
+    /// for a single bound.
+    pub fn clamp_to(mut self, lo: Self, hi: Self) -> Self {
+        self.accumulated_raw = self
+            .accumulated_raw
+            .max(lo.accumulated_raw)
+            .min(hi.accumulated_raw);
+        self
+    }
+
+    /// This sample with values within `width` of [`Self::CENTER`] snapped to
+    /// it, a detent for knobs which never rest exactly at noon
+    ///
+    /// The rest of the range is stretched to fill the gap, so values leave
+    /// the center smoothly at the edge of the dead zone and the ends still
+    /// reach [`Self::MIN`] and [`Self::MAX`]. `width` 0 changes nothing.
+    pub fn with_deadzone(&self, width: i32) -> Self {
+        let width = width.clamp(0, Self::MAX - 1);
+        let value = self.to_clamped();
+        let stretched = if value > width {
+            (value - width) * Self::MAX / (Self::MAX - width)
+        } else if value < -width {
+            (value + width) * Self::OFFSET / (Self::OFFSET - width)
+        } else {
+            Self::CENTER
+        };
+        Sample {
+            accumulated_raw: stretched << Self::ACCUM_BITS,
+            inverted_source: self.inverted_source,
+        }
+    }
+
+    /// This sample along an exponential curve, for fine control near the
+    /// center and coarse toward the ends
+    ///
+    /// The distance from center, as a fraction `x` of the way to the end, is
+    /// mapped to `(2^(amount * x) - 1) / (2^amount - 1)` of it, so each
+    /// `amount` makes the curve an octave steeper. The sign is kept, and
+    /// center and both ends are unchanged. `amount` 0 (or less) is linear,
+    /// and it's limited to 14.
+    pub fn curve_exp(&self, amount: i32) -> Self {
+        let amount = amount.clamp(0, 14);
+        let value = self.to_clamped();
+        let curved = if amount == 0 {
+            value
+        } else {
+            let end = i64::from(if value < 0 { Self::OFFSET } else { Self::MAX });
+            let fraction = (i64::from(value.abs()) * i64::from(fixed::ONE) / end) as Fixed;
+            let power = i64::from(fixed::exp2(fraction * amount)) - i64::from(fixed::ONE);
+            let full = (i64::from(fixed::ONE) << amount) - i64::from(fixed::ONE);
+            let magnitude = (power * end / full) as i32;
+            if value < 0 {
+                -magnitude
+            } else {
+                magnitude
+            }
+        };
+        Sample {
+            accumulated_raw: curved << Self::ACCUM_BITS,
+            inverted_source: self.inverted_source,
+        }
+    }
+
     /// Scale this sample to the ratio of another sample to [`MAX`]
     ///
     /// Used for mixing, crossfading and attenuverting signals.
@@ -116,6 +302,119 @@ impl Sample {
         )
     }
 
+    /// Semitones over the full sample range, for 1V/oct pitch
+    ///
+    /// Assumes the Computer's ±6V range: 12 volts, so 12 octaves of 12
+    /// semitones, about 28.4 steps per semitone. [`Self::CENTER`] is 0V, a C.
+    pub const SEMITONES: i32 = 144;
+
+    /// Value of semitone `note`, counting from 0V
+    fn semitone_value(note: i32) -> i32 {
+        // rounded to nearest, so steps are spread evenly
+        let range = 2 * (Self::MAX - Self::MIN + 1);
+        (note * range + Self::SEMITONES).div_euclid(2 * Self::SEMITONES)
+    }
+
+    /// Nearest semitone to this value, for 1V/oct pitch ([`Self::SEMITONES`])
+    ///
+    /// Values exactly between two semitones snap to the lower one, like
+    /// [`quantizer::Quantizer`]. The top semitone, 6V, is clamped to
+    /// [`Self::MAX`].
+    pub fn quantize_semitones(&self) -> Self {
+        self.quantize_scale(0xfff)
+    }
+
+    /// Nearest semitone in a scale to this value, for 1V/oct pitch
+    ///
+    /// `mask` bit 0 is C (0V), bit 1 C#, up to bit 11 for B. Bits above 11 are
+    /// ignored, and a mask without any notes leaves the value unchanged. Ties
+    /// snap to the lower note.
+    pub fn quantize_scale(&self, mask: u16) -> Self {
+        let mask = mask & 0xfff;
+        if mask == 0 {
+            return *self;
+        }
+        let value = self.to_clamped();
+        // semitone at or below value, scale notes are at most 11 away
+        let below = (value * Self::SEMITONES).div_euclid(Self::MAX - Self::MIN + 1);
+        let in_scale = |note: i32| mask & (1 << note.rem_euclid(12)) != 0;
+        // ascending, and min_by_key keeps the first of equals, so ties
+        // snap to the lower note
+        let nearest = (below - 11..=below + 12)
+            .filter(|&note| in_scale(note))
+            .map(Self::semitone_value)
+            .min_by_key(|step| (step - value).abs())
+            .unwrap_or(value);
+        Sample {
+            accumulated_raw: nearest.clamp(Self::MIN, Self::MAX) << Self::ACCUM_BITS,
+            inverted_source: self.inverted_source,
+        }
+    }
+
+    /// This value linearly mapped from `in_min..=in_max` to
+    /// `out_min..=out_max`
+    ///
+    /// For turning a knob into a parameter's range, such as a tempo or LED
+    /// brightness. Values beyond the input range map to the ends of the
+    /// output range. Either range can be reversed, `in_min` always maps to
+    /// `out_min`. An empty input range (`in_min == in_max`) maps everything
+    /// to `out_min`. Rounds toward `out_min`.
+    pub fn map_range(&self, in_min: i32, in_max: i32, out_min: i32, out_max: i32) -> i32 {
+        if in_min == in_max {
+            return out_min;
+        }
+        let value = self
+            .to_clamped()
+            .clamp(in_min.min(in_max), in_min.max(in_max));
+        let offset = (i64::from(value) - i64::from(in_min))
+            * (i64::from(out_max) - i64::from(out_min))
+            / (i64::from(in_max) - i64::from(in_min));
+        (i64::from(out_min) + offset).clamp(i64::from(i32::MIN), i64::from(i32::MAX)) as i32
+    }
+
+    /// Linear interpolation from this sample toward `target`
+    ///
+    /// Returns this sample at `fraction` 0 and exactly `target` at
+    /// `fraction_max`, larger fractions stop at `target`. For gliding a
+    /// parameter between logic rate updates, advance `fraction` once per
+    /// audio sample.
+    pub fn interpolate_to(&self, target: Self, fraction: u32, fraction_max: u32) -> Self {
+        if fraction_max == 0 || fraction >= fraction_max {
+            return target;
+        }
+        let distance = i64::from(target.accumulated_raw) - i64::from(self.accumulated_raw);
+        let offset = distance * i64::from(fraction) / i64::from(fraction_max);
+        Sample {
+            accumulated_raw: self.accumulated_raw + offset as i32,
+            inverted_source: self.inverted_source,
+        }
+    }
+
+    /// Linear gain for `db` decibels, where [`fixed::ONE`] is unity
+    ///
+    /// -6dB is about half and +6dB about double. Saturates above +90dB.
+    pub fn from_db(db: i32) -> Fixed {
+        // 10^(db / 20) = 2^(db * log2(10) / 20)
+        const OCTAVES_PER_DB: i64 = 10_885; // 0.16610
+        let exponent =
+            (i64::from(db) * OCTAVES_PER_DB).clamp(i64::from(Fixed::MIN), i64::from(Fixed::MAX));
+        fixed::exp2(exponent as Fixed)
+    }
+
+    /// This sample with a gain of `db` decibels
+    ///
+    /// Like other math on samples, the result may be outside the 12 bit range
+    /// until clamped, so a boost followed by a cut gets back the original.
+    pub fn apply_gain_db(&self, db: i32) -> Self {
+        let scaled = i64::from(self.accumulated_raw) * i64::from(Self::from_db(db));
+        Sample {
+            accumulated_raw: (scaled >> fixed::FRAC_BITS)
+                .clamp(i64::from(i32::MIN), i64::from(i32::MAX))
+                as i32,
+            inverted_source: self.inverted_source,
+        }
+    }
+
     /// Scale this sample to the inverted ratio of another sample to [`MAX`]
     ///
     /// Used for mixing, crossfading and attenuverting signals.
@@ -125,6 +424,29 @@ impl Sample {
             self.inverted_source,
         )
     }
+
+    /// Gain of an equal power fade at `amount`, `sin(amount / MAX * 90°)` of
+    /// [`MAX`], from the quarter sine table
+    fn eqpower_gain(amount: i32) -> Self {
+        let amount = amount.clamp(0, Self::MAX) as u64;
+        crate::math::sine(((amount << 30) / Self::MAX as u64) as u32)
+    }
+
+    /// Scale this sample along an equal power fade in, by `amount` of [`MAX`]
+    ///
+    /// Paired with [`Self::scale_eqpower_inverted()`], the squares of the two
+    /// gains sum to full scale, so crossfading between uncorrelated signals
+    /// doesn't dip in loudness halfway like [`Self::scale()`] does. Zero and
+    /// [`MAX`] match the linear case.
+    pub fn scale_eqpower(&self, amount: Self) -> Self {
+        self.scale(Self::eqpower_gain(amount.to_clamped()))
+    }
+
+    /// Scale this sample along an equal power fade out, by `amount` of
+    /// [`MAX`]
+    pub fn scale_eqpower_inverted(&self, amount: Self) -> Self {
+        self.scale(Self::eqpower_gain(Self::MAX - amount.to_clamped()))
+    }
 }
 
 pub trait SampleUpdate<V> {
@@ -132,6 +454,9 @@ pub trait SampleUpdate<V> {
     fn update(&mut self, value: V);
 }
 
+// the new value's share has to fit in the accumulator's fraction
+const _: () = assert!(Sample::SMOOTHING_SHIFT <= Sample::ACCUM_BITS);
+
 impl SampleUpdate<u16> for Sample {
     /// Update with new value from 12 bit u16
     ///
@@ -164,16 +489,20 @@ impl SampleUpdate<i32> for Sample {
     fn update(&mut self, value: i32) {
         // first-order infinite impulse response filter, logic from:
         // https://electronics.stackexchange.com/a/176740
-        self.accumulated_raw =
-            (self.accumulated_raw - (self.accumulated_raw >> Self::ACCUM_BITS)) + value;
+        self.accumulated_raw = (self.accumulated_raw
+            - (self.accumulated_raw >> Self::SMOOTHING_SHIFT))
+            + (value << (Self::ACCUM_BITS - Self::SMOOTHING_SHIFT));
     }
 }
 
+/// Raw value, not inverted and not clamped until read, see
+/// [`Sample::from_u16()`] for 12 bit readings
 impl From<i32> for Sample {
     fn from(value: i32) -> Self {
         Self::new(value, false)
     }
 }
+/// Raw value, sign extended, as for `i32`
 impl From<i16> for Sample {
     fn from(value: i16) -> Self {
         Self::new(value.into(), false)
@@ -189,6 +518,23 @@ impl Add for Sample {
     }
 }
 
+impl AddAssign for Sample {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl Neg for Sample {
+    type Output = Self;
+
+    /// Negated value, keeping the inversion flag. `-MIN` is one beyond
+    /// [`Sample::MAX`], and clamps to it.
+    fn neg(mut self) -> Self::Output {
+        self.accumulated_raw = -self.accumulated_raw;
+        self
+    }
+}
+
 impl Sub for Sample {
     type Output = Self;
 
@@ -198,13 +544,19 @@ impl Sub for Sample {
     }
 }
 
+impl SubAssign for Sample {
+    fn sub_assign(&mut self, rhs: Self) {
+        *self = *self - rhs;
+    }
+}
+
 impl Mul for Sample {
     type Output = Self;
 
+    /// Product, saturating for unclamped values whose product overflows
     fn mul(mut self, rhs: Self) -> Self::Output {
-        self.accumulated_raw = ((self.accumulated_raw >> Self::ACCUM_BITS)
-            * (rhs.accumulated_raw >> Self::ACCUM_BITS))
-            << Self::ACCUM_BITS;
+        self.accumulated_raw =
+            Self::accumulate(self.to_unclamped().saturating_mul(rhs.to_unclamped()));
         self
     }
 }
@@ -213,22 +565,39 @@ impl Mul<i32> for Sample {
     type Output = Self;
 
     fn mul(mut self, rhs: i32) -> Self::Output {
-        self.accumulated_raw =
-            ((self.accumulated_raw >> Self::ACCUM_BITS) * rhs) << Self::ACCUM_BITS;
+        self.accumulated_raw = Self::accumulate(self.to_unclamped().saturating_mul(rhs));
         self
     }
 }
 
+impl MulAssign<i32> for Sample {
+    fn mul_assign(&mut self, rhs: i32) {
+        *self = *self * rhs;
+    }
+}
+
 impl Div<i32> for Sample {
     type Output = Self;
 
+    /// Divide by `rhs`, leaving the value unchanged when `rhs` is 0
+    ///
+    /// Dividing by a knob or CV reading can easily hit 0, and a divide by
+    /// zero panic would stop the module.
     fn div(mut self, rhs: i32) -> Self::Output {
-        self.accumulated_raw =
-            ((self.accumulated_raw >> Self::ACCUM_BITS) / rhs) << Self::ACCUM_BITS;
+        if let Some(value) = (self.accumulated_raw >> Self::ACCUM_BITS).checked_div(rhs) {
+            self.accumulated_raw = value << Self::ACCUM_BITS;
+        }
         self
     }
 }
 
+impl DivAssign<i32> for Sample {
+    /// Divide in place, leaving the value unchanged when `rhs` is 0, as [`Div`]
+    fn div_assign(&mut self, rhs: i32) {
+        *self = *self / rhs;
+    }
+}
+
 /// `JackValue` represents input values from a jack when a cable is plugged.
 ///
 /// This struct expects both `raw` and `probe` values to be updated regularly.
@@ -243,34 +612,139 @@ impl Div<i32> for Sample {
 /// be smoothed to avoid false negatives from short term voltages on the cable
 /// which happen to have the right voltage difference between them from a single
 /// sample.
-#[derive(Format, Clone)]
+///
+/// The probe's polarity and threshold are set with [`JackSample::set_probe()`],
+/// the default suits the Computer. For other detection algorithms, the two
+/// readings are available as `raw` and `probe`.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone)]
 pub struct JackSample {
+    /// Reading with the probe idle, the jack's value when patched
     pub raw: Sample,
+    /// Reading with the probe driven
     pub probe: Sample,
+    config: ProbeConfig,
 }
 
-// TODO: implement probe logic
 impl JackSample {
     pub fn new(raw: Sample, probe: Sample) -> JackSample {
-        JackSample { raw, probe }
+        JackSample {
+            raw,
+            probe,
+            config: ProbeConfig::default(),
+        }
     }
 
-    pub fn plugged_value(&self) -> Option<&Sample> {
-        let mut diff = self.probe.accumulated_raw - self.raw.accumulated_raw;
-        diff >>= Sample::ACCUM_BITS;
-        // determined through testing my unit, may need adjusting
-        if diff > 300 {
-            None
-        } else {
-            Some(&self.raw)
+    pub fn set_probe(&mut self, config: ProbeConfig) {
+        self.config = config;
+    }
+
+    /// Change in the reading from driving the probe, positive in the
+    /// direction the probe pulls unplugged jacks
+    pub fn probe_difference(&self) -> i32 {
+        let diff = (self.probe.accumulated_raw - self.raw.accumulated_raw) >> Sample::ACCUM_BITS;
+        match self.config.polarity {
+            ProbePolarity::ActiveHigh => diff,
+            ProbePolarity::ActiveLow => -diff,
         }
     }
+
+    /// Whether a cable is patched, the probe isn't passing through
+    pub fn is_connected(&self) -> bool {
+        self.probe_difference() <= self.config.threshold
+    }
+
+    pub fn plugged_value(&self) -> Option<&Sample> {
+        self.is_connected().then_some(&self.raw)
+    }
+
+    /// Plugged value, or `default` when nothing is plugged in
+    pub fn value_or(&self, default: Sample) -> Sample {
+        self.plugged_value().copied().unwrap_or(default)
+    }
+
+    /// Sum of this jack and `other`, for treating two jacks as a pair
+    ///
+    /// Unplugged jacks read as `default`, so with one cable patched the
+    /// result is that jack's value plus `default`. With a `default` of
+    /// center, one patched jack passes through unchanged and neither patched
+    /// gives center.
+    pub fn sum(&self, other: &JackSample, default: Sample) -> Sample {
+        self.value_or(default) + other.value_or(default)
+    }
+
+    /// This jack minus `other`, unplugged jacks read as `default`
+    ///
+    /// With a `default` of center and only `other` patched, the result is
+    /// `other` inverted.
+    pub fn difference(&self, other: &JackSample, default: Sample) -> Sample {
+        self.value_or(default) - other.value_or(default)
+    }
 }
 
 #[cfg(test)]
 mod test {
     // Note this useful idiom: importing names from outer (for mod tests) scope.
-    use super::{Sample, SampleUpdate, U12_MAX};
+    use super::{JackSample, Sample, SampleUpdate, U12_MAX};
+    use crate::fixed;
+    use crate::probe::{ProbeConfig, ProbePolarity};
+
+    #[test]
+    #[cfg(not(feature = "defmt"))]
+    fn test_debug_without_defmt() {
+        // built with --no-default-features: Debug is all there is, and enough
+        // to log values and errors
+        let jack = JackSample::new(Sample::from(12_i32), Sample::from(400_i32));
+        assert_eq!(
+            format!("{:?}", jack),
+            "JackSample { raw: InputValue::new(12, false), probe: InputValue::new(400, false), \
+             config: ProbeConfig { settle_micros: 200, polarity: ActiveHigh, threshold: 300 } }"
+        );
+        assert_eq!(format!("{:?}", crate::Error::BadCrc), "BadCrc");
+    }
+
+    #[test]
+    fn test_db_gain() {
+        assert_eq!(Sample::from_db(0), fixed::ONE);
+        // 0.501 and 1.995
+        let half = Sample::from_db(-6);
+        assert!((half - fixed::ONE / 2).abs() < fixed::ONE / 200, "{}", half);
+        let double = Sample::from_db(6);
+        assert!(
+            (double - 2 * fixed::ONE).abs() < fixed::ONE / 100,
+            "{}",
+            double
+        );
+        // 20dB is a factor of ten
+        let tenth = Sample::from_db(-20);
+        assert!(
+            (tenth - fixed::ONE / 10).abs() < fixed::ONE / 10_000,
+            "{}",
+            tenth
+        );
+        assert_eq!(Sample::from_db(-200), 0);
+        assert_eq!(Sample::from_db(i32::MAX), fixed::Fixed::MAX);
+
+        for value in [Sample::MIN, -1000, -1, 0, 1, 500, 1000] {
+            let sample = Sample::from(value);
+            assert_eq!(sample.apply_gain_db(0).to_clamped(), value);
+            let half = sample.apply_gain_db(-6).to_clamped();
+            assert!(
+                (half - value * 501 / 1000).abs() <= 1,
+                "{}: {}",
+                value,
+                half
+            );
+            let round_trip = sample.apply_gain_db(6).apply_gain_db(-6).to_clamped();
+            assert!((round_trip - value).abs() <= 2, "{}: {}", value, round_trip);
+        }
+        // positive gain can exceed the 12 bit range, without losing anything
+        // until clamped
+        let loud = Sample::from(Sample::MAX).apply_gain_db(6);
+        assert_eq!(loud.to_clamped(), Sample::MAX);
+        let round_trip = loud.apply_gain_db(-6).to_clamped();
+        assert!((round_trip - Sample::MAX).abs() <= 2, "{}", round_trip);
+    }
 
     #[test]
     fn test_input_value_basics() {
@@ -291,6 +765,25 @@ mod test {
         assert_eq!(Sample::from_u16(0, false).to_clamped(), Sample::MIN);
         assert_eq!(Sample::from_u16(2048, false).to_clamped(), 0);
         assert_eq!(Sample::from_u16(U12_MAX, false).to_clamped(), Sample::MAX);
+
+        // From takes the raw value as is, never inverted
+        for value in [Sample::MIN, -700, -1, 0, 1, 700, Sample::MAX] {
+            assert_eq!(Sample::from(value), Sample::new(value, false));
+            assert_eq!(Sample::from(value as i16), Sample::new(value, false));
+        }
+        // negative i16s sign extend, rather than wrapping to large values
+        assert_eq!(Sample::from(-1_i16).to_unclamped(), -1);
+        assert_eq!(Sample::from(i16::MIN).to_unclamped(), i32::from(i16::MIN));
+        // beyond the sample range is kept, and clamped when read
+        assert_eq!(Sample::from(5000_i32).to_unclamped(), 5000);
+        assert_eq!(Sample::from(5000_i32).to_clamped(), Sample::MAX);
+        assert_eq!(Sample::from(i16::MIN).to_clamped(), Sample::MIN);
+        assert_eq!(Sample::from(i16::MAX).to_clamped(), Sample::MAX);
+        // so generic code can convert either
+        fn convert<T: Into<Sample>>(value: T) -> i32 {
+            value.into().to_clamped()
+        }
+        assert_eq!(convert(-300_i16), convert(-300_i32));
     }
 
     #[test]
@@ -309,6 +802,22 @@ mod test {
 
         let below_range = Sample::from_u16(0, false) - Sample::new(5000, false);
         assert_eq!(below_range.to_output(), 0_u16);
+
+        // bipolar, rail to rail around the DAC's mid-scale
+        assert_eq!(Sample::from(Sample::MIN).to_output(), 0);
+        assert_eq!(
+            Sample::from(Sample::CENTER).to_output(),
+            crate::dac::ZERO_VOLT_CODE
+        );
+        assert_eq!(Sample::from(Sample::MAX).to_output(), U12_MAX);
+        let mut previous = None;
+        for value in Sample::MIN..=Sample::MAX {
+            let code = Sample::from(value).to_output();
+            if let Some(previous) = previous {
+                assert_eq!(code, previous + 1, "{}", value);
+            }
+            previous = Some(code);
+        }
     }
 
     #[test]
@@ -379,6 +888,348 @@ mod test {
         assert_eq!(Sample::new(123, false) / -1, Sample::new(-123, false));
     }
 
+    #[test]
+    fn test_input_value_scale_eqpower() {
+        let full = Sample::from(Sample::MAX);
+        // endpoints match the linear fade
+        for level in [-2048, -700, 0, 1, 700, 2047] {
+            let sample = Sample::from(level);
+            for amount in [0, Sample::MAX].map(Sample::from) {
+                assert_eq!(sample.scale_eqpower(amount), sample.scale(amount));
+                assert_eq!(
+                    sample.scale_eqpower_inverted(amount),
+                    sample.scale_inverted(amount)
+                );
+            }
+        }
+        // halfway is 3dB down on each side, rather than 6dB
+        let half = full
+            .scale_eqpower(Sample::from(Sample::MAX / 2))
+            .to_clamped();
+        assert!((half - 1448).abs() <= 2, "{}", half);
+
+        let full_power = Sample::MAX * Sample::MAX;
+        let mut previous = 0;
+        for amount in 0..=Sample::MAX {
+            let fade_in = full.scale_eqpower(Sample::from(amount)).to_clamped();
+            let fade_out = full
+                .scale_eqpower_inverted(Sample::from(amount))
+                .to_clamped();
+            // constant power across the whole fade, within 0.5%
+            let power = fade_in * fade_in + fade_out * fade_out;
+            assert!(
+                (power - full_power).abs() < full_power / 200,
+                "amount {}: {} and {}",
+                amount,
+                fade_in,
+                fade_out
+            );
+            assert!(fade_in >= previous, "{}", amount);
+            previous = fade_in;
+        }
+        // amounts outside the fade stop at its ends
+        assert_eq!(full.scale_eqpower(Sample::from(-100)).to_clamped(), 0);
+        assert_eq!(
+            full.scale_eqpower_inverted(Sample::from(-100)).to_clamped(),
+            Sample::MAX
+        );
+    }
+
+    #[test]
+    fn test_input_value_add() {
+        assert_eq!(
+            Sample::new(100, false) + Sample::new(50, false),
+            Sample::new(150, false)
+        );
+        let mut sum = Sample::new(100, false);
+        sum += Sample::new(-250, false);
+        assert_eq!(sum, Sample::new(-150, false));
+        // the flag comes from the left hand side
+        let mut inverted = Sample::new(-100, true);
+        inverted += Sample::new(50, false);
+        assert_eq!(
+            format!("{:?}", inverted),
+            format!("{:?}", Sample::new(-150, true))
+        );
+
+        // sums beyond the range are kept, and clamped on conversion
+        let mut loud = Sample::new(Sample::MAX, false);
+        loud += Sample::new(1000, false);
+        assert_eq!(loud.to_clamped(), Sample::MAX);
+        assert_eq!(loud.to_unclamped(), Sample::MAX + 1000);
+        loud += Sample::new(-1500, false);
+        assert_eq!(loud.to_clamped(), Sample::MAX - 500);
+        let quiet = Sample::new(Sample::MIN, false) + Sample::new(-1, false);
+        assert_eq!(quiet.to_clamped(), Sample::MIN);
+    }
+
+    #[test]
+    fn test_input_value_assign_ops() {
+        let values = [Sample::MIN, -1000, -1, 0, 1, 777, Sample::MAX, 5000, -9000];
+        for a in values {
+            for b in values {
+                let (x, y) = (Sample::new(a, a < -1000), Sample::new(b, false));
+                let mut difference = x;
+                difference -= y;
+                assert_eq!(difference, x - y, "{} - {}", a, b);
+                assert_eq!(format!("{:?}", difference), format!("{:?}", x - y));
+                let mut product = x;
+                product *= b;
+                assert_eq!(product, x * b, "{} * {}", a, b);
+                let mut quotient = x;
+                quotient /= b;
+                assert_eq!(quotient, x / b, "{} / {}", a, b);
+            }
+        }
+
+        // accumulating beyond the range is kept, and clamped on conversion
+        let mut level = Sample::new(Sample::MIN, false);
+        level -= Sample::new(1000, false);
+        assert_eq!(level.to_unclamped(), Sample::MIN - 1000);
+        assert_eq!(level.to_clamped(), Sample::MIN);
+        level -= Sample::new(-1500, false);
+        assert_eq!(level.to_clamped(), Sample::MIN + 500);
+        // products saturate rather than overflow, however often repeated
+        let mut loud = Sample::new(Sample::MAX, false);
+        for _ in 0..8 {
+            loud *= 1000;
+        }
+        assert_eq!(loud.to_clamped(), Sample::MAX);
+        // dividing by zero leaves the value
+        let mut value = Sample::new(600, false);
+        value /= 0;
+        assert_eq!(value, Sample::new(600, false));
+        value /= -4;
+        assert_eq!(value, Sample::new(-150, false));
+    }
+
+    #[test]
+    fn test_input_value_mul_saturates() {
+        // in range products are unchanged
+        assert_eq!(
+            (Sample::new(-100, false) * Sample::new(20, false)).to_clamped(),
+            -2000
+        );
+        // sums far beyond the range, whose product overflows
+        let mut loud = Sample::new(Sample::MAX, false);
+        for _ in 0..8 {
+            loud += loud;
+        }
+        assert_eq!(loud.to_unclamped(), Sample::MAX * 256);
+        let quiet = -loud;
+        assert_eq!((loud * loud).to_clamped(), Sample::MAX);
+        assert_eq!((quiet * quiet).to_clamped(), Sample::MAX);
+        assert_eq!((loud * quiet).to_clamped(), Sample::MIN);
+        assert_eq!((loud * quiet * loud).to_clamped(), Sample::MIN);
+        assert!((loud * loud).to_unclamped() > 0);
+        assert_eq!((loud * i32::MAX).to_clamped(), Sample::MAX);
+        assert_eq!((loud * i32::MIN).to_clamped(), Sample::MIN);
+        assert_eq!((-(loud * i32::MIN)).to_clamped(), Sample::MAX);
+    }
+
+    #[test]
+    #[cfg(feature = "float")]
+    fn test_input_value_f32_round_trip() {
+        for value in Sample::MIN..=Sample::MAX {
+            let sample = Sample::new(value, false);
+            let round_trip = Sample::from_f32(sample.to_f32(), false);
+            assert!(
+                (round_trip.to_clamped() - value).abs() <= 1,
+                "{} {:?}",
+                value,
+                round_trip
+            );
+        }
+        assert_eq!(Sample::new(Sample::MIN, false).to_f32(), -1.0);
+        assert_eq!(Sample::new(0, false).to_f32(), 0.0);
+        assert_eq!(Sample::from_f32(0.5, false).to_clamped(), 1024);
+        // saturates outside -1.0..1.0, and NaN is silence
+        assert_eq!(Sample::from_f32(3.0, false).to_clamped(), Sample::MAX);
+        assert_eq!(Sample::from_f32(-3.0, false).to_clamped(), Sample::MIN);
+        assert_eq!(Sample::from_f32(f32::NAN, false).to_clamped(), 0);
+        // inverted like a sample read from an inverted source
+        assert_eq!(Sample::from_f32(0.25, true), Sample::new(512, true));
+    }
+
+    #[test]
+    fn test_input_value_ord() {
+        let values = [Sample::MIN, -700, -1, 0, 1, 5, 2000, Sample::MAX];
+        for a in values {
+            for b in values {
+                let (left, right) = (Sample::new(a, false), Sample::new(b, false));
+                assert_eq!(left.cmp(&right), a.cmp(&b));
+                assert_eq!(left.cmp(&right), left.to_clamped().cmp(&right.to_clamped()));
+            }
+        }
+        // the logical value counts, not how it was read
+        assert_eq!(Sample::new(-123, true), Sample::new(123, false));
+        assert!(Sample::new(100, true) < Sample::new(0, false));
+        assert_eq!(Sample::from(0_i32), Sample::new(0, true));
+
+        let mut sorted = [
+            Sample::from(30_i32),
+            Sample::new(40, true),
+            Sample::from(-10_i32),
+        ];
+        sorted.sort();
+        assert_eq!(sorted, [-40, -10, 30].map(Sample::from));
+        assert_eq!(sorted.iter().max(), Some(&Sample::from(30_i32)));
+        assert_eq!(
+            Sample::from(12_i32).min(Sample::from(7_i32)),
+            Sample::from(7_i32)
+        );
+        // sums beyond the range order beyond it
+        assert!(Sample::from(Sample::MAX) + Sample::from(1_i32) > Sample::from(Sample::MAX));
+    }
+
+    #[test]
+    fn test_input_value_clamp_to() {
+        let (lo, hi) = (Sample::from(-100_i32), Sample::from(200_i32));
+        for (value, expected) in [
+            (-2000, -100),
+            (-101, -100),
+            (-100, -100),
+            (0, 0),
+            (199, 199),
+            (200, 200),
+            (2000, 200),
+        ] {
+            assert_eq!(
+                Sample::from(value).clamp_to(lo, hi),
+                Sample::from(expected),
+                "{}",
+                value
+            );
+        }
+        // the whole range, and its asymmetric ends
+        let (min, max) = (Sample::from(Sample::MIN), Sample::from(Sample::MAX));
+        assert_eq!(min.clamp_to(min, max), min);
+        assert_eq!(max.clamp_to(min, max), max);
+        assert_eq!((-min).clamp_to(min, max), max);
+        assert_eq!((max + max).clamp_to(min, max), max);
+        assert_eq!((min + min).clamp_to(min, max), min);
+        // the smoothing fraction survives within the bounds
+        let mut smoothed = Sample::from(0_i32);
+        smoothed.update(1000);
+        assert_eq!(smoothed.clamp_to(min, max), smoothed);
+        // inverted sources keep their flag
+        let inverted = Sample::new(500, true).clamp_to(lo, hi);
+        assert_eq!(
+            format!("{:?}", inverted),
+            format!("{:?}", Sample::new(100, true))
+        );
+        // crossed bounds give hi
+        assert_eq!(Sample::from(0_i32).clamp_to(hi, lo), lo);
+        assert_eq!(Sample::from(1000_i32).clamp_to(hi, lo), lo);
+
+        // single bounds from Ord
+        assert_eq!(Sample::from(300_i32).min(hi), hi);
+        assert_eq!(Sample::from(-300_i32).max(lo), lo);
+    }
+
+    #[test]
+    fn test_input_value_with_deadzone() {
+        let deadzone = |value: i32| Sample::from(value).with_deadzone(64).to_clamped();
+        for value in [-64, -63, -1, 0, 1, 63, 64] {
+            assert_eq!(deadzone(value), Sample::CENTER, "{}", value);
+        }
+        // just outside leaves the center one step at a time
+        assert_eq!(deadzone(65), 1);
+        assert_eq!(deadzone(-65), -1);
+        assert_eq!(deadzone(66), 2);
+        // the ends are still reachable
+        assert_eq!(deadzone(Sample::MAX), Sample::MAX);
+        assert_eq!(deadzone(Sample::MIN), Sample::MIN);
+        // no jumps across the whole range, and never reversing
+        let mut previous = deadzone(Sample::MIN);
+        for value in Sample::MIN + 1..=Sample::MAX {
+            let current = deadzone(value);
+            assert!((0..=2).contains(&(current - previous)), "{}", value);
+            previous = current;
+        }
+        // no dead zone changes nothing, beyond the range clamps
+        for value in [Sample::MIN, -5, 0, 7, Sample::MAX] {
+            assert_eq!(Sample::from(value).with_deadzone(0).to_clamped(), value);
+        }
+        assert_eq!(Sample::from(100_i32).with_deadzone(-3).to_clamped(), 100);
+        assert_eq!(
+            Sample::from(100_i32).with_deadzone(5000).to_clamped(),
+            Sample::CENTER
+        );
+    }
+
+    #[test]
+    fn test_input_value_curve_exp() {
+        let curve = |value: i32, amount: i32| Sample::from(value).curve_exp(amount).to_clamped();
+        for amount in [0, 1, 4, 8, 14] {
+            // center and ends stay put
+            assert_eq!(curve(0, amount), 0);
+            assert_eq!(curve(Sample::MAX, amount), Sample::MAX, "{}", amount);
+            assert_eq!(curve(Sample::MIN, amount), Sample::MIN, "{}", amount);
+            let mut previous = curve(Sample::MIN, amount);
+            for value in Sample::MIN + 1..=Sample::MAX {
+                let current = curve(value, amount);
+                assert!(current >= previous, "{} {}", amount, value);
+                // bipolar, the sign is kept and the curve is mirrored
+                assert!(current.signum() * value.signum() >= 0);
+                // the negative side reaches one step further, which steep
+                // curves stretch near the ends
+                if value > 0 {
+                    assert!((current + curve(-value, amount)).abs() <= 1 + amount);
+                }
+                previous = current;
+            }
+        }
+        // 0 is linear, beyond the range clamps and silly amounts are limited
+        for value in [Sample::MIN, -300, 1, 1500, Sample::MAX] {
+            assert_eq!(curve(value, 0), value);
+            assert_eq!(curve(value, -4), value);
+        }
+        assert_eq!(curve(1000, 100), curve(1000, 14));
+        // steeper curves spend more of the knob near the center
+        let halfway = [1, 2, 4, 8].map(|amount| curve(Sample::MAX / 2, amount));
+        assert!(
+            halfway.windows(2).all(|pair| pair[1] < pair[0]),
+            "{:?}",
+            halfway
+        );
+        assert!(halfway[0] < Sample::MAX / 2);
+        // one octave: halfway is (sqrt(2) - 1) of the way
+        assert!((halfway[0] - 848).abs() <= 2, "{}", halfway[0]);
+    }
+
+    #[test]
+    fn test_input_value_neg() {
+        assert_eq!(-Sample::new(123, false), Sample::new(-123, false));
+        assert_eq!(-Sample::new(-123, true), Sample::new(123, true));
+        assert_eq!(-Sample::new(0, false), Sample::new(0, false));
+        for value in [Sample::MIN, -1, 0, 1, 1234, Sample::MAX] {
+            let sample = Sample::new(value, false);
+            assert_eq!(-(-sample), sample);
+            assert_eq!(-sample, sample * -1);
+        }
+        // the range isn't symmetric
+        assert_eq!((-Sample::new(Sample::MIN, false)).to_clamped(), Sample::MAX);
+        assert_eq!(
+            (-Sample::new(Sample::MAX, false)).to_clamped(),
+            Sample::MIN + 1
+        );
+    }
+
+    #[test]
+    fn test_input_value_divide_by_zero() {
+        for value in [Sample::MIN, -123, 0, 123, Sample::MAX] {
+            let sample = Sample::new(value, false);
+            assert_eq!(sample / 0, sample);
+        }
+        // including values beyond the range, and inverted sources
+        let loud = Sample::new(1500, false) + Sample::new(1500, false);
+        assert_eq!(loud / 0, loud);
+        assert_eq!((loud / 0).to_unclamped(), 3000);
+        let inverted = Sample::new(300, true);
+        assert_eq!(inverted / 0, inverted);
+    }
+
     #[test]
     fn test_input_value_update() {
         let mut sample = Sample::from(0_i32);
@@ -394,4 +1245,350 @@ mod test {
         }
         assert_eq!(sample.to_clamped(), Sample::MIN, "should converge to MIN");
     }
+
+    #[test]
+    fn test_input_value_update_step_response() {
+        for (start, target) in [
+            (0, 1000),
+            (0, -1000),
+            (Sample::MIN, Sample::MAX),
+            (500, 499),
+        ] {
+            let mut sample = Sample::new(start, false);
+            let mut previous = start;
+            let mut settled_after = None;
+            for update in 0..200 {
+                sample.update(target);
+                let value = sample.to_clamped();
+                // moves toward the target, never past it
+                assert!(
+                    (target - value).abs() <= (target - previous).abs(),
+                    "{} -> {}: {} after {}",
+                    start,
+                    target,
+                    value,
+                    previous
+                );
+                assert!((target - value).signum() * (target - start).signum() >= 0);
+                if value == target && settled_after.is_none() {
+                    settled_after = Some(update);
+                }
+                previous = value;
+            }
+            assert_eq!(sample.to_clamped(), target);
+            // a 2^SMOOTHING_SHIFT time constant settles in well under 200
+            let settled_after = settled_after.unwrap();
+            assert!(settled_after < 100, "{}", settled_after);
+        }
+    }
+
+    #[test]
+    fn test_input_value_inverted_update() {
+        // CV inputs read inverted, high ADC levels are negative values
+        for (level, expected) in [(2048_u16, 0), (3048, -1000), (1048, 1000), (0, Sample::MAX)] {
+            let mut sample = Sample::new(0, true);
+            for _ in 0..128 {
+                sample.update(level);
+            }
+            let offset = i32::from(level) - Sample::OFFSET;
+            assert_eq!(sample.to_clamped(), expected, "{}", level);
+            assert_eq!(
+                sample.to_clamped(),
+                (-offset).clamp(Sample::MIN, Sample::MAX)
+            );
+        }
+        // the same levels read plainly aren't negated
+        let mut sample = Sample::new(0, false);
+        for _ in 0..128 {
+            sample.update(3048_u16);
+        }
+        assert_eq!(sample.to_clamped(), 1000);
+    }
+
+    fn patched(value: i32) -> JackSample {
+        // with a cable, the probe doesn't change the reading
+        JackSample::new(Sample::new(value, false), Sample::new(value, false))
+    }
+
+    fn unpatched() -> JackSample {
+        JackSample::new(Sample::new(-1000, false), Sample::new(1000, false))
+    }
+
+    #[test]
+    fn test_jack_is_connected() {
+        assert!(patched(600).is_connected());
+        assert!(patched(Sample::MIN).is_connected());
+        assert!(!unpatched().is_connected());
+        // noise on a patched cable's readings isn't the probe
+        let noisy = JackSample::new(Sample::new(480, false), Sample::new(530, false));
+        assert!(noisy.is_connected());
+        // a cable pulling against the probe can't look unplugged
+        let opposed = JackSample::new(Sample::new(1000, false), Sample::new(-1000, false));
+        assert!(opposed.is_connected());
+
+        // normalled to the fallback when unplugged
+        let fallback = Sample::new(-321, false);
+        assert_eq!(patched(600).value_or(fallback).to_clamped(), 600);
+        assert_eq!(noisy.value_or(fallback).to_clamped(), 480);
+        assert_eq!(unpatched().value_or(fallback), fallback);
+    }
+
+    #[test]
+    fn test_jack_pair_both_patched() {
+        let center = Sample::new(Sample::CENTER, false);
+        let (a, b) = (patched(600), patched(-200));
+        assert_eq!(a.value_or(center).to_clamped(), 600);
+        assert_eq!(a.sum(&b, center).to_clamped(), 400);
+        assert_eq!(a.difference(&b, center).to_clamped(), 800);
+        assert_eq!(b.difference(&a, center).to_clamped(), -800);
+        // still clamped when converted
+        assert_eq!(
+            patched(2000).sum(&patched(2000), center).to_clamped(),
+            Sample::MAX
+        );
+    }
+
+    #[test]
+    fn test_jack_pair_one_patched() {
+        let center = Sample::new(Sample::CENTER, false);
+        let (a, b) = (patched(600), unpatched());
+        assert_eq!(b.value_or(center).to_clamped(), 0);
+        assert_eq!(a.sum(&b, center).to_clamped(), 600);
+        assert_eq!(b.sum(&a, center).to_clamped(), 600);
+        assert_eq!(a.difference(&b, center).to_clamped(), 600);
+        assert_eq!(b.difference(&a, center).to_clamped(), -600);
+        // other defaults offset the patched value
+        let offset = Sample::new(100, false);
+        assert_eq!(a.sum(&b, offset).to_clamped(), 700);
+    }
+
+    #[test]
+    fn test_jack_pair_neither_patched() {
+        let (a, b) = (unpatched(), unpatched());
+        let center = Sample::new(Sample::CENTER, false);
+        assert_eq!(a.sum(&b, center).to_clamped(), 0);
+        assert_eq!(a.difference(&b, center).to_clamped(), 0);
+        let default = Sample::new(300, false);
+        assert_eq!(a.sum(&b, default).to_clamped(), 600);
+        assert_eq!(a.difference(&b, default).to_clamped(), 0);
+    }
+
+    #[test]
+    fn test_interpolate_to() {
+        let start = Sample::new(-1000, false);
+        let target = Sample::new(1000, false);
+        assert_eq!(start.interpolate_to(target, 0, 480), start);
+        assert_eq!(start.interpolate_to(target, 240, 480).to_clamped(), 0);
+        assert_eq!(start.interpolate_to(target, 120, 480).to_clamped(), -500);
+        assert_eq!(start.interpolate_to(target, 480, 480), target);
+        // beyond the end stops at the target
+        assert_eq!(start.interpolate_to(target, 1000, 480), target);
+        assert_eq!(start.interpolate_to(target, 5, 0), target);
+    }
+
+    #[test]
+    fn test_interpolate_to_exact_endpoints() {
+        // smoothed values keep their fractional accumulator bits at the ends
+        let mut start = Sample::new(0, false);
+        start.update(3_i32);
+        let mut target = Sample::new(0, false);
+        target.update(-5_i32);
+        for max in [1, 7, 100, u32::MAX] {
+            assert_eq!(start.interpolate_to(target, 0, max), start);
+            assert_eq!(start.interpolate_to(target, max, max), target);
+        }
+        // midpoint of a full range glide is -0.5, which rounds down
+        let low = Sample::new(Sample::MIN, false);
+        let high = Sample::new(Sample::MAX, false);
+        assert_eq!(low.interpolate_to(high, 1, 2).to_clamped(), -1);
+        assert_eq!(high.interpolate_to(low, 1, 2).to_clamped(), -1);
+    }
+
+    #[test]
+    fn test_map_range() {
+        // identity
+        for value in [Sample::MIN, -1, 0, 1, 1234, Sample::MAX] {
+            let sample = Sample::new(value, false);
+            assert_eq!(
+                sample.map_range(Sample::MIN, Sample::MAX, Sample::MIN, Sample::MAX),
+                value
+            );
+        }
+        // knob to a tempo
+        let tempo =
+            |value: i32| Sample::new(value, false).map_range(Sample::MIN, Sample::MAX, 0, 60);
+        assert_eq!(tempo(Sample::MIN), 0);
+        assert_eq!(tempo(0), 30);
+        assert_eq!(tempo(Sample::MAX), 60);
+        // beyond the input range stops at the ends
+        let part = |value: i32| Sample::new(value, false).map_range(-100, 100, 0, 1000);
+        assert_eq!(part(-2000), 0);
+        assert_eq!(part(50), 750);
+        assert_eq!(part(2000), 1000);
+        // the full i32 output range doesn't overflow
+        assert_eq!(
+            Sample::new(Sample::MAX, false).map_range(Sample::MIN, Sample::MAX, i32::MIN, i32::MAX),
+            i32::MAX
+        );
+    }
+
+    #[test]
+    fn test_map_range_inverted() {
+        // reversed output
+        let reversed = |value: i32| Sample::new(value, false).map_range(0, 1000, 100, 0);
+        assert_eq!(reversed(0), 100);
+        assert_eq!(reversed(250), 75);
+        assert_eq!(reversed(1000), 0);
+        assert_eq!(reversed(-500), 100);
+        // reversed input, in_min still maps to out_min
+        let reversed = |value: i32| Sample::new(value, false).map_range(1000, 0, 0, 100);
+        assert_eq!(reversed(1000), 0);
+        assert_eq!(reversed(250), 75);
+        assert_eq!(reversed(0), 100);
+        assert_eq!(reversed(2000), 0);
+        assert_eq!(reversed(-2000), 100);
+    }
+
+    #[test]
+    fn test_map_range_empty_input() {
+        for value in [Sample::MIN, 0, 500, Sample::MAX] {
+            assert_eq!(Sample::new(value, false).map_range(500, 500, 7, 99), 7);
+        }
+    }
+
+    #[test]
+    fn test_quantize_semitones_staircase() {
+        let mut previous = Sample::new(Sample::MIN, false).quantize_semitones();
+        assert_eq!(previous.to_clamped(), Sample::MIN);
+        let mut steps = 0;
+        for value in Sample::MIN..=Sample::MAX {
+            let quantized = Sample::new(value, false).quantize_semitones();
+            let rise = quantized.to_clamped() - previous.to_clamped();
+            // never more than half a semitone (14.2) from the input
+            assert!((quantized.to_clamped() - value).abs() <= 15, "{}", value);
+            // rising one semitone at a time, evenly spaced, except the
+            // clamped top step
+            let top = quantized.to_clamped() == Sample::MAX;
+            assert!(
+                rise == 0 || (28..=29).contains(&rise) || (top && rise == 27),
+                "{} {}",
+                value,
+                rise
+            );
+            if rise != 0 {
+                steps += 1;
+            }
+            previous = quantized;
+        }
+        assert_eq!(steps, Sample::SEMITONES);
+        assert_eq!(previous.to_clamped(), Sample::MAX);
+        // an octave is a twelfth of the range, 0V is a step
+        assert_eq!(Sample::new(0, false).quantize_semitones().to_clamped(), 0);
+        for octave in [-6, -1, 1, 5] {
+            let octave_value = octave * 4096 / 12;
+            let quantized = Sample::new(octave_value + 5, false).quantize_semitones();
+            assert!(
+                (quantized.to_clamped() - octave_value).abs() <= 1,
+                "{}",
+                octave
+            );
+        }
+    }
+
+    #[test]
+    fn test_quantize_semitones_ties() {
+        // semitones 2 and 3 are at 57 and 85, midway is 71
+        let quantize = |value: i32| Sample::new(value, false).quantize_semitones().to_clamped();
+        assert_eq!(quantize(70), 57);
+        assert_eq!(quantize(71), 57);
+        assert_eq!(quantize(72), 85);
+        // below 0V too, -3 and -2 are at -85 and -57
+        assert_eq!(quantize(-72), -85);
+        assert_eq!(quantize(-71), -85);
+        assert_eq!(quantize(-70), -57);
+        // ties in a scale snap down too: C and E only, midway is D
+        let quantize = |value: i32| {
+            Sample::new(value, false)
+                .quantize_scale(0b1_0001)
+                .to_clamped()
+        };
+        assert_eq!(quantize(57), 0);
+        assert_eq!(quantize(58), 114);
+    }
+
+    #[test]
+    fn test_quantize_scale() {
+        const MAJOR: u16 = 0b1010_1011_0101;
+        let mut previous = i32::MIN;
+        for value in Sample::MIN..=Sample::MAX {
+            let quantized = Sample::new(value, false).quantize_scale(MAJOR).to_clamped();
+            let note = (2 * quantized * Sample::SEMITONES + 4096).div_euclid(8192);
+            // only scale notes, the note's value rounds back to the same note
+            assert_eq!(
+                Sample::new(quantized, false)
+                    .quantize_semitones()
+                    .to_clamped(),
+                quantized
+            );
+            assert!(
+                MAJOR & (1 << note.rem_euclid(12)) != 0,
+                "{} {}",
+                value,
+                note
+            );
+            assert!(quantized >= previous);
+            previous = quantized;
+        }
+        // inverted sources keep their flag, unlike Sample::new
+        let inverted = Sample::new(-300, true).quantize_scale(MAJOR);
+        // 300 is between A (256) and B (313)
+        assert_eq!(inverted.to_clamped(), 313);
+        // bits above B are ignored, no notes is unchanged
+        let value = Sample::new(1234, false);
+        assert_eq!(value.quantize_scale(0xf000), value);
+        assert_eq!(
+            value.quantize_scale(MAJOR | 0xf000),
+            value.quantize_scale(MAJOR)
+        );
+    }
+
+    #[test]
+    fn test_jack_probe_polarity() {
+        // unplugged, driving the probe pulls the reading up
+        let mut jack = JackSample::new(Sample::new(-1000, false), Sample::new(1000, false));
+        assert_eq!(jack.probe_difference(), 2000);
+        assert_eq!(jack.plugged_value(), None);
+        // with an inverted probe, the same readings look like a cable
+        let inverted = ProbeConfig {
+            polarity: ProbePolarity::ActiveLow,
+            ..ProbeConfig::default()
+        };
+        jack.set_probe(inverted);
+        assert_eq!(jack.probe_difference(), -2000);
+        assert_eq!(jack.plugged_value().map(Sample::to_clamped), Some(-1000));
+
+        // unplugged with an inverted probe, which pulls the reading down
+        let mut jack = JackSample::new(Sample::new(800, false), Sample::new(100, false));
+        jack.set_probe(inverted);
+        assert_eq!(jack.plugged_value(), None);
+
+        // patched, the probe makes no difference with either polarity
+        for polarity in [ProbePolarity::ActiveHigh, ProbePolarity::ActiveLow] {
+            let mut jack = JackSample::new(Sample::new(500, false), Sample::new(520, false));
+            jack.set_probe(ProbeConfig {
+                polarity,
+                ..ProbeConfig::default()
+            });
+            assert_eq!(jack.plugged_value().map(Sample::to_clamped), Some(500));
+        }
+
+        // the threshold is configurable, not reached is patched
+        let mut jack = JackSample::new(Sample::new(0, false), Sample::new(300, false));
+        assert!(jack.plugged_value().is_some());
+        jack.set_probe(ProbeConfig {
+            threshold: 299,
+            ..ProbeConfig::default()
+        });
+        assert!(jack.plugged_value().is_none());
+    }
 }
@@ -0,0 +1,97 @@
+//! Peak limiter for sums beyond the sample range
+//!
+//! Mixing several loud signals can add up to more than [`Sample::MAX`], which
+//! would clip harshly at the output. [`Limiter`] turns the gain down as soon
+//! as a peak would clip (instant attack), then lets it recover slowly
+//! (release), so loud passages are compressed rather than clipped.
+//!
+//! Inputs are [`Fixed`] sums, which have headroom for several full scale
+//! samples.
+
+use crate::fixed::{self, Fixed};
+use crate::Sample;
+
+/// Peak limiter with instant attack and a `2^release_shift` sample release
+pub struct Limiter {
+    gain: Fixed,
+    release_shift: u8,
+}
+
+impl Limiter {
+    pub const fn new(release_shift: u8) -> Self {
+        Limiter {
+            gain: fixed::ONE,
+            release_shift,
+        }
+    }
+
+    /// Current gain, [`fixed::ONE`] when not limiting
+    pub fn gain(&self) -> Fixed {
+        self.gain
+    }
+
+    /// Limit `input` into the sample range
+    pub fn process(&mut self, input: Fixed) -> Sample {
+        // recover toward unity, at least one step so it gets all the way
+        let recovery = ((fixed::ONE - self.gain) >> self.release_shift).max(1);
+        self.gain = (self.gain + recovery).min(fixed::ONE);
+
+        let ceiling = match input {
+            0.. => i64::from(Sample::MAX),
+            _ => -i64::from(Sample::MIN),
+        } << fixed::FRAC_BITS;
+        let peak = i64::from(input).abs();
+        if (peak * i64::from(self.gain)) >> fixed::FRAC_BITS > ceiling {
+            self.gain = (ceiling * i64::from(fixed::ONE) / peak) as Fixed;
+        }
+        fixed::to_sample(fixed::mul(input, self.gain))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Limiter;
+    use crate::fixed;
+    use crate::math::sine;
+    use crate::Sample;
+
+    #[test]
+    fn test_limiter_passes_quiet_signals() {
+        let mut limiter = Limiter::new(10);
+        for value in [0, 1, -1, 1000, -2048, Sample::MAX] {
+            let output = limiter.process(value << fixed::FRAC_BITS);
+            assert_eq!(output.to_clamped(), value);
+            assert_eq!(limiter.gain(), fixed::ONE);
+        }
+    }
+
+    #[test]
+    fn test_limiter_catches_peaks_and_releases() {
+        let mut limiter = Limiter::new(8);
+        // three times full scale, in either direction
+        for value in [6000, -6000] {
+            let output = limiter.process(value << fixed::FRAC_BITS);
+            assert!(output.to_clamped().abs() >= Sample::MAX - 1, "{}", value);
+        }
+        let limited = limiter.gain();
+        assert!((limited - fixed::ONE / 3).abs() < fixed::ONE / 100);
+
+        // a loud sine never clips, at full scale on its peaks
+        let mut peak = 0;
+        for n in 0..4800_u32 {
+            let sample = sine(n.wrapping_mul(1 << 24)).to_clamped() << fixed::FRAC_BITS;
+            let output = limiter.process(sample * 2).to_clamped();
+            // clamping would hide clipping, check the gain applied
+            let unclamped = fixed::mul(sample * 2, limiter.gain()) >> fixed::FRAC_BITS;
+            assert!((Sample::MIN..=Sample::MAX).contains(&unclamped), "{}", n);
+            peak = peak.max(output.abs());
+        }
+        assert!(peak > Sample::MAX * 9 / 10, "{}", peak);
+
+        // quiet again, the gain recovers
+        for _ in 0..4800 {
+            limiter.process(100 << fixed::FRAC_BITS);
+        }
+        assert_eq!(limiter.gain(), fixed::ONE);
+    }
+}
@@ -0,0 +1,315 @@
+//! Integer math helpers
+//!
+//! The RP2040 has no FPU, so anything beyond add/multiply is done here in
+//! integers. [`Rms`] uses [`isqrt()`] for level metering, which follows
+//! loudness more closely than peak detection, and [`rms_level()`] measures
+//! a whole stretch of audio at once. [`sine()`] looks up a quarter
+//! wave table for test tones and equal power fades.
+//!
+//! [`MovingAverage`] smooths slow CVs over a fixed window. Unlike the one pole
+//! smoothing of [`crate::SampleUpdate`], a step settles completely, and
+//! linearly, after the window's length.
+
+use crate::Sample;
+
+/// Integer square root, rounded down
+///
+/// Digit by digit (binary) method, one result bit per iteration and no
+/// division.
+pub fn isqrt(value: u64) -> u32 {
+    let mut remainder = value;
+    let mut root = 0_u64;
+    // highest power of four not above value
+    let mut bit = 1_u64 << 62;
+    while bit > value {
+        bit >>= 2;
+    }
+    while bit != 0 {
+        if remainder >= root + bit {
+            remainder -= root + bit;
+            root = (root >> 1) + bit;
+        } else {
+            root >>= 1;
+        }
+        bit >>= 2;
+    }
+    root as u32
+}
+
+/// First quarter of a full scale sine wave, 64 steps plus the peak
+const QUARTER_SINE: [i16; 65] = [
+    0, 50, 100, 151, 201, 251, 300, 350, 399, 449, 497, 546, 594, 642, 690, 737, 783, 830, 875,
+    920, 965, 1009, 1052, 1095, 1137, 1179, 1219, 1259, 1299, 1337, 1375, 1411, 1447, 1483, 1517,
+    1550, 1582, 1614, 1644, 1674, 1702, 1729, 1756, 1781, 1805, 1828, 1850, 1871, 1891, 1910, 1927,
+    1944, 1959, 1973, 1986, 1997, 2008, 2017, 2025, 2032, 2037, 2041, 2045, 2046, 2047,
+];
+
+/// Full scale sine wave at `phase`, where a whole cycle is `u32::MAX + 1`
+///
+/// Interpolates linearly between the 256 steps of the table.
+pub fn sine(phase: u32) -> Sample {
+    let step = (phase >> 24) as usize;
+    let fraction = ((phase >> 16) & 0xff) as i32;
+    let lookup = |step: usize| {
+        let step = step % 256;
+        let index = step % 64;
+        let value = match step / 64 {
+            0 => QUARTER_SINE[index],
+            1 => QUARTER_SINE[64 - index],
+            2 => -QUARTER_SINE[index],
+            _ => -QUARTER_SINE[64 - index],
+        };
+        i32::from(value)
+    };
+    let (from, to) = (lookup(step), lookup(step + 1));
+    Sample::from(from + (to - from) * fraction / 256)
+}
+
+/// Root mean square level over a sliding window of the last `N` samples
+pub struct Rms<const N: usize> {
+    squares: [u32; N],
+    position: usize,
+    /// Sum of `squares`, updated as samples enter and leave the window
+    sum: u64,
+}
+
+impl<const N: usize> Rms<N> {
+    pub const fn new() -> Self {
+        Rms {
+            squares: [0; N],
+            position: 0,
+            sum: 0,
+        }
+    }
+
+    pub fn push(&mut self, sample: Sample) {
+        if N == 0 {
+            return;
+        }
+        let square = sample.to_clamped().unsigned_abs().pow(2);
+        self.sum = self.sum - u64::from(self.squares[self.position]) + u64::from(square);
+        self.squares[self.position] = square;
+        self.position = (self.position + 1) % N;
+    }
+
+    /// RMS level of the window, from 0 to [`Sample::MAX`]
+    pub fn level(&self) -> Sample {
+        if N == 0 {
+            return Sample::from(0_i32);
+        }
+        Sample::from(isqrt(self.sum / N as u64) as i32)
+    }
+}
+
+/// RMS level of the first `max_samples` of `samples`, from 0 to
+/// [`Sample::MAX`]
+///
+/// The analysis is bounded by `max_samples`, so endless sources such as a
+/// looping stream can be measured.
+pub fn rms_level(samples: impl IntoIterator<Item = Sample>, max_samples: usize) -> Sample {
+    let (sum, count) =
+        samples
+            .into_iter()
+            .take(max_samples)
+            .fold((0_u64, 0_u64), |(sum, count), sample| {
+                let square = u64::from(sample.to_clamped().unsigned_abs().pow(2));
+                (sum + square, count + 1)
+            });
+    if count == 0 {
+        return Sample::from(0_i32);
+    }
+    Sample::from(isqrt(sum / count) as i32)
+}
+
+impl<const N: usize> Default for Rms<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Mean of the last `N` samples (boxcar filter)
+pub struct MovingAverage<const N: usize> {
+    samples: [i32; N],
+    position: usize,
+    /// Samples in the window, fewer than `N` until it fills
+    count: usize,
+    /// Sum of `samples`, updated as samples enter and leave the window
+    sum: i64,
+}
+
+impl<const N: usize> MovingAverage<N> {
+    pub const fn new() -> Self {
+        MovingAverage {
+            samples: [0; N],
+            position: 0,
+            count: 0,
+            sum: 0,
+        }
+    }
+
+    /// Add `sample` to the window, returning the mean of the window
+    ///
+    /// Until `N` samples have been pushed, the mean is of those there are.
+    /// Samples are clamped to the sample range as they're added.
+    pub fn push(&mut self, sample: Sample) -> Sample {
+        if N == 0 {
+            return sample;
+        }
+        let value = sample.to_clamped();
+        if self.count < N {
+            self.count += 1;
+        } else {
+            self.sum -= i64::from(self.samples[self.position]);
+        }
+        self.sum += i64::from(value);
+        self.samples[self.position] = value;
+        self.position = (self.position + 1) % N;
+        Sample::from((self.sum / self.count as i64) as i32)
+    }
+}
+
+impl<const N: usize> Default for MovingAverage<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{isqrt, rms_level, sine, MovingAverage, Rms};
+    use crate::Sample;
+
+    #[test]
+    fn test_isqrt_known_values() {
+        let known = [
+            (0, 0),
+            (1, 1),
+            (2, 1),
+            (3, 1),
+            (4, 2),
+            (15, 3),
+            (16, 4),
+            (17, 4),
+            (4_194_304, 2048),
+            (4_194_303, 2047),
+            (u64::from(u32::MAX), 65535),
+            (u64::MAX, u32::MAX),
+        ];
+        for (value, root) in known {
+            assert_eq!(isqrt(value), root, "isqrt({})", value);
+        }
+        for value in 0..10_000_u64 {
+            let root = u64::from(isqrt(value));
+            assert!(root * root <= value && (root + 1) * (root + 1) > value);
+        }
+    }
+
+    #[test]
+    fn test_rms_constant() {
+        let mut rms = Rms::<64>::new();
+        for value in [1000_i32, -1000, 2047, -2048, 0] {
+            for _ in 0..64 {
+                rms.push(Sample::from(value));
+            }
+            assert_eq!(rms.level().to_clamped(), value.abs().min(Sample::MAX));
+        }
+        // partly filled window
+        let mut rms = Rms::<64>::new();
+        for _ in 0..16 {
+            rms.push(Sample::from(800_i32));
+        }
+        assert_eq!(rms.level().to_clamped(), 400);
+    }
+
+    #[test]
+    fn test_rms_sine() {
+        // whole number of cycles in the window: level is amplitude / sqrt(2)
+        let mut rms = Rms::<480>::new();
+        let amplitude = 2000.0_f64;
+        for n in 0..2000 {
+            let phase = n as f64 * core::f64::consts::TAU / 48.0;
+            rms.push(Sample::from((amplitude * phase.sin()).round() as i32));
+        }
+        let expected = (amplitude / 2.0_f64.sqrt()) as i32;
+        assert!(
+            (rms.level().to_clamped() - expected).abs() <= 1,
+            "{}",
+            rms.level().to_clamped()
+        );
+    }
+
+    #[test]
+    fn test_rms_level_bounded() {
+        let loud_then_quiet = core::iter::repeat_n(Sample::from(-1500_i32), 100)
+            .chain(core::iter::repeat(Sample::from(10_i32)));
+        assert_eq!(rms_level(loud_then_quiet, 100).to_clamped(), 1500);
+        assert_eq!(rms_level(core::iter::empty(), 100).to_clamped(), 0);
+        // same as the sliding window, once it's full
+        let mut rms = Rms::<64>::new();
+        let ramp = || (0..64).map(|n| Sample::from(n * 30 - 900));
+        ramp().for_each(|sample| rms.push(sample));
+        assert_eq!(rms_level(ramp(), 64), rms.level());
+    }
+
+    #[test]
+    fn test_sine_table() {
+        for step in 0..1024_u32 {
+            let phase = step << 22;
+            let exact = 2047.0 * (f64::from(step) * core::f64::consts::TAU / 1024.0).sin();
+            let value = sine(phase).to_clamped();
+            assert!(
+                (f64::from(value) - exact).abs() <= 2.0,
+                "{}: {}",
+                step,
+                value
+            );
+        }
+        assert_eq!(sine(0).to_clamped(), 0);
+        assert_eq!(sine(1 << 30).to_clamped(), 2047);
+        assert_eq!(sine(3 << 30).to_clamped(), -2047);
+    }
+
+    #[test]
+    fn test_moving_average_constant() {
+        for level in [Sample::MIN, -700, 0, 1, 700, Sample::MAX] {
+            let mut average = MovingAverage::<16>::new();
+            // passed through unchanged, from the first sample
+            for _ in 0..40 {
+                assert_eq!(average.push(Sample::from(level)).to_clamped(), level);
+            }
+        }
+        // out of range samples are clamped as they're added
+        let mut average = MovingAverage::<4>::new();
+        assert_eq!(
+            average.push(Sample::from(9000_i32)).to_clamped(),
+            Sample::MAX
+        );
+        // an empty window passes samples straight through
+        let mut average = MovingAverage::<0>::new();
+        assert_eq!(average.push(Sample::from(-321_i32)).to_clamped(), -321);
+    }
+
+    #[test]
+    fn test_moving_average_step_settles_linearly() {
+        let mut average = MovingAverage::<8>::new();
+        for _ in 0..8 {
+            average.push(Sample::from(0_i32));
+        }
+        // each step of the window moves an eighth of the way, then it's there
+        for k in 1..=8 {
+            assert_eq!(average.push(Sample::from(1000_i32)).to_clamped(), 125 * k);
+        }
+        assert_eq!(average.push(Sample::from(1000_i32)).to_clamped(), 1000);
+        // and back down the same way
+        for k in 1..=8 {
+            let expected = 1000 - 125 * k;
+            assert_eq!(average.push(Sample::from(0_i32)).to_clamped(), expected);
+        }
+
+        // while warming up, only the samples so far are averaged
+        let mut average = MovingAverage::<8>::new();
+        assert_eq!(average.push(Sample::from(800_i32)).to_clamped(), 800);
+        assert_eq!(average.push(Sample::from(400_i32)).to_clamped(), 600);
+        assert_eq!(average.push(Sample::from(-600_i32)).to_clamped(), 200);
+    }
+}
@@ -0,0 +1,433 @@
+//! Mixing of the rain layers
+//!
+//! The rain soundscape crossfades between three layers based on an intensity
+//! [`Sample`]. For intensity `i` in `Sample::MIN..=Sample::MAX`, every layer
+//! has a gain ([`crossfade3_gains()`]) which is continuous across the whole
+//! range:
+//!
+//! ```text
+//! light:  max(-i, 0) / MAX
+//! medium: (MAX - |i|) / MAX
+//! heavy:  max(i, 0) / MAX
+//! ```
+//!
+//! So `MIN` is 100% light, center is 100% medium and `MAX` is 100% heavy.
+//! Medium peaks at center and falls off symmetrically as light or heavy ramps
+//! in toward its end, and the three gains always sum to unity.
+//!
+//! For checking the recordings, [`solo()`] plays a single layer at full level
+//! instead. For a maximal storm, [`full_storm()`] plays all three at once, at
+//! fixed levels, summed with headroom and then limited so they can't clip.
+//!
+//! [`morph4()`] instead blends four layers on a 2D pad, such as the X and Y
+//! knobs, with a layer at each corner. [`morph_weights()`] gives the
+//! bilinear weights, which also sum to unity.
+//!
+//! Recordings of different rain are rarely at matching levels.
+//! [`level_match_gains()`] computes a gain for each layer from its measured
+//! RMS level, which [`apply_gain()`] applies before mixing.
+
+use crate::fixed::{self, Fixed};
+use crate::limiter::Limiter;
+use crate::Sample;
+
+/// Most a quiet layer is boosted to match the others (4x, +12dB)
+pub const MAX_MATCH_GAIN: Fixed = 4 * fixed::ONE;
+
+/// One of the three rain layers
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Layer {
+    Light,
+    Medium,
+    Heavy,
+}
+
+impl Layer {
+    /// Select a layer by splitting a knob's range into thirds
+    pub fn from_position(position: Sample) -> Self {
+        let third = (Sample::MAX - Sample::MIN + 1) / 3;
+        match position.to_clamped() {
+            p if p < Sample::MIN + third => Layer::Light,
+            p if p < Sample::MAX - third => Layer::Medium,
+            _ => Layer::Heavy,
+        }
+    }
+}
+
+/// Gains of the light, medium and heavy layers at `intensity`, for
+/// [`Sample::scale()`]
+///
+/// They sum to exactly [`Sample::MAX`] at every intensity, see module docs.
+pub fn crossfade3_gains(intensity: Sample) -> [Sample; 3] {
+    let intensity = intensity.to_clamped();
+    let light = (-intensity).clamp(0, Sample::MAX);
+    let heavy = intensity.max(0);
+    [light, Sample::MAX - light - heavy, heavy].map(Sample::from)
+}
+
+/// Crossfade the light, medium and heavy layers according to `intensity`
+pub fn crossfade3(light: Sample, medium: Sample, heavy: Sample, intensity: Sample) -> Sample {
+    let [light_gain, medium_gain, heavy_gain] = crossfade3_gains(intensity);
+    light.scale(light_gain) + medium.scale(medium_gain) + heavy.scale(heavy_gain)
+}
+
+/// Play only `layer` at full level, bypassing the crossfade
+pub fn solo(light: Sample, medium: Sample, heavy: Sample, layer: Layer) -> Sample {
+    match layer {
+        Layer::Light => light,
+        Layer::Medium => medium,
+        Layer::Heavy => heavy,
+    }
+}
+
+/// Play all three layers at once, at fixed `levels`, through `limiter`
+///
+/// The layers are summed in [`Fixed`] rather than [`Sample`], so loud layers
+/// add up beyond full scale and the limiter turns them down, instead of
+/// clipping.
+pub fn full_storm(
+    light: Sample,
+    medium: Sample,
+    heavy: Sample,
+    levels: [Fixed; 3],
+    limiter: &mut Limiter,
+) -> Sample {
+    let sum = [light, medium, heavy]
+        .iter()
+        .zip(levels)
+        .map(|(layer, level)| fixed::mul(fixed::from_sample(*layer), level))
+        .fold(0, Fixed::saturating_add);
+    limiter.process(sum)
+}
+
+/// Bilinear weights of the four corners of a `x`, `y` pad
+///
+/// Corners are ordered `[min x min y, max x min y, min x max y, max x max y]`.
+/// The weights always sum to exactly [`fixed::ONE`].
+pub fn morph_weights(x: Sample, y: Sample) -> [Fixed; 4] {
+    // 0 at min to fixed::ONE at max
+    let position = |value: Sample| {
+        (value.to_clamped() - Sample::MIN) * fixed::ONE / (Sample::MAX - Sample::MIN)
+    };
+    let (x, y) = (position(x), position(y));
+    let bottom = fixed::ONE - y;
+    let bottom_right = fixed::mul(x, bottom);
+    let top_right = fixed::mul(x, y);
+    [
+        bottom - bottom_right,
+        bottom_right,
+        y - top_right,
+        top_right,
+    ]
+}
+
+/// Blend four `corners` by the `x`, `y` position on a pad
+///
+/// See [`morph_weights()`] for the corner order.
+pub fn morph4(corners: [Sample; 4], x: Sample, y: Sample) -> Sample {
+    let sum = corners
+        .iter()
+        .zip(morph_weights(x, y))
+        .map(|(corner, weight)| fixed::mul(fixed::from_sample(*corner), weight))
+        .fold(0, Fixed::saturating_add);
+    fixed::to_sample(sum)
+}
+
+/// Gains bringing each of `levels` (RMS) to their average
+///
+/// Silent layers are left at unity, as they have nothing to match, and boosts
+/// are limited to [`MAX_MATCH_GAIN`] so near silent layers aren't turned up
+/// into noise.
+pub fn level_match_gains<const N: usize>(levels: [Sample; N]) -> [Fixed; N] {
+    let levels = levels.map(|level| level.to_clamped().max(0));
+    let (sum, count) = levels
+        .iter()
+        .filter(|&&level| level > 0)
+        .fold((0, 0), |(sum, count), &level| (sum + level, count + 1));
+    if count == 0 {
+        return [fixed::ONE; N];
+    }
+    let target = sum / count;
+    levels.map(|level| match level {
+        0 => fixed::ONE,
+        level => (fixed::from_sample(Sample::from(target)) / level).min(MAX_MATCH_GAIN),
+    })
+}
+
+/// Scale `sample` by a fixed point `gain`
+pub fn apply_gain(sample: Sample, gain: Fixed) -> Sample {
+    fixed::to_sample(fixed::mul(fixed::from_sample(sample), gain))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        apply_gain, crossfade3, crossfade3_gains, full_storm, level_match_gains, morph4,
+        morph_weights, solo, Layer, MAX_MATCH_GAIN,
+    };
+    use crate::fixed;
+    use crate::limiter::Limiter;
+    use crate::math::{rms_level, sine};
+    use crate::Sample;
+
+    /// Expected crossfade curve, see module docs
+    fn expected(light: i32, medium: i32, heavy: i32, intensity: i32) -> f32 {
+        let position = intensity.abs().min(Sample::MAX) as f32 / Sample::MAX as f32;
+        let outer = if intensity >= 0 { heavy } else { light };
+        medium as f32 * (1.0 - position) + outer as f32 * position
+    }
+
+    #[test]
+    fn test_crossfade3_endpoints() {
+        let (light, medium, heavy) = (
+            Sample::new(-1000, false),
+            Sample::new(200, false),
+            Sample::new(1500, false),
+        );
+        let mix = |i: i32| crossfade3(light, medium, heavy, Sample::new(i, false)).to_clamped();
+        assert_eq!(mix(Sample::MIN), -1000);
+        assert_eq!(mix(Sample::CENTER), 200);
+        assert_eq!(mix(Sample::MAX), 1500);
+    }
+
+    #[test]
+    fn test_crossfade3_sweep_follows_curve() {
+        let layer_sets = [(-1000, 200, 1500), (2047, -2048, 2047), (500, 500, 500)];
+        for (light, medium, heavy) in layer_sets {
+            let mut previous: Option<i32> = None;
+            for intensity in Sample::MIN..=Sample::MAX {
+                let output = crossfade3(
+                    Sample::new(light, false),
+                    Sample::new(medium, false),
+                    Sample::new(heavy, false),
+                    Sample::new(intensity, false),
+                )
+                .to_clamped();
+                let target = expected(light, medium, heavy, intensity);
+                assert!(
+                    (output as f32 - target).abs() <= 2.0,
+                    "intensity {}: got {}, expected {}",
+                    intensity,
+                    output,
+                    target
+                );
+                // no jumps, including at center where the branches switch
+                if let Some(previous) = previous {
+                    assert!(
+                        (output - previous).abs() <= 4,
+                        "discontinuity at intensity {}: {} -> {}",
+                        intensity,
+                        previous,
+                        output
+                    );
+                }
+                previous = Some(output);
+            }
+        }
+    }
+
+    #[test]
+    fn test_crossfade3_conserves_equal_layers() {
+        // equal layers should pass through at the same level at every intensity
+        for level in [-2048, -700, 0, 1, 700, 2047] {
+            let layer = Sample::new(level, false);
+            for intensity in Sample::MIN..=Sample::MAX {
+                let output = crossfade3(layer, layer, layer, Sample::new(intensity, false));
+                assert!(
+                    (output.to_clamped() - level).abs() <= 1,
+                    "level {} at intensity {}: {}",
+                    level,
+                    intensity,
+                    output.to_clamped()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_crossfade3_gains_are_continuous() {
+        let gains = |i: i32| crossfade3_gains(Sample::new(i, false)).map(|gain| gain.to_clamped());
+        assert_eq!(gains(Sample::MIN), [Sample::MAX, 0, 0]);
+        assert_eq!(gains(Sample::CENTER), [0, Sample::MAX, 0]);
+        assert_eq!(gains(Sample::MAX), [0, 0, Sample::MAX]);
+        // halfway to either end is an even blend with medium
+        assert_eq!(gains(-1024), [1024, Sample::MAX - 1024, 0]);
+        assert_eq!(gains(1024), [0, Sample::MAX - 1024, 1024]);
+
+        let mut previous = gains(Sample::MIN);
+        for intensity in Sample::MIN..=Sample::MAX {
+            let now = gains(intensity);
+            // loudness is constant, with no dip at center
+            assert_eq!(now.iter().sum::<i32>(), Sample::MAX, "{}", intensity);
+            // every gain moves smoothly, including through center
+            for (gain, before) in now.iter().zip(previous) {
+                assert!((gain - before).abs() <= 1, "step at {}", intensity);
+            }
+            // medium peaks at center, light and heavy mirror each other
+            assert!(now[1] <= gains(0)[1]);
+            if intensity > Sample::MIN {
+                let mirrored = gains(-intensity);
+                assert_eq!(
+                    (now[0], now[1], now[2]),
+                    (mirrored[2], mirrored[1], mirrored[0])
+                );
+            }
+            previous = now;
+        }
+    }
+
+    #[test]
+    fn test_solo_only_selected_layer_contributes() {
+        let silent = Sample::new(0, false);
+        let loud = Sample::new(1234, false);
+        for layer in [Layer::Light, Layer::Medium, Layer::Heavy] {
+            let only = |selected: Layer| if selected == layer { loud } else { silent };
+            let layers = (only(Layer::Light), only(Layer::Medium), only(Layer::Heavy));
+            for selected in [Layer::Light, Layer::Medium, Layer::Heavy] {
+                let output = solo(layers.0, layers.1, layers.2, selected).to_clamped();
+                let expected = if selected == layer { 1234 } else { 0 };
+                assert_eq!(
+                    output, expected,
+                    "{:?} playing, {:?} soloed",
+                    layer, selected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_full_storm_mixes_all_layers() {
+        let levels = [fixed::ONE / 2, fixed::ONE / 4, fixed::ONE];
+        let mut limiter = Limiter::new(12);
+        let silent = Sample::from(0_i32);
+        let loud = Sample::from(800_i32);
+        // each layer contributes at its level
+        let mut mix = |light, medium, heavy| {
+            full_storm(light, medium, heavy, levels, &mut limiter).to_clamped()
+        };
+        assert_eq!(mix(loud, silent, silent), 400);
+        assert_eq!(mix(silent, loud, silent), 200);
+        assert_eq!(mix(silent, silent, loud), 800);
+        assert_eq!(mix(loud, loud, loud), 1400);
+        assert_eq!(mix(loud, Sample::from(-800_i32), silent), 200);
+        assert_eq!(limiter.gain(), fixed::ONE);
+    }
+
+    #[test]
+    fn test_full_storm_limits_loud_sums() {
+        let mut limiter = Limiter::new(12);
+        let full = Sample::from(Sample::MAX);
+        // all three in phase at full level, 3x full scale
+        let output = full_storm(full, full, full, [fixed::ONE; 3], &mut limiter);
+        assert!(output.to_clamped() >= Sample::MAX - 1);
+        assert!(limiter.gain() < fixed::ONE / 2);
+        // a loud sine on every layer doesn't clip, and keeps its shape
+        let mut previous = 0;
+        for n in 0..4800_u32 {
+            let layer = sine(n.wrapping_mul(1 << 24)).scale(Sample::from(1800_i32));
+            let output = full_storm(layer, layer, layer, [fixed::ONE; 3], &mut limiter);
+            let output = output.to_clamped();
+            // clamping would hide clipping, check the gain applied
+            let sum = 3 * layer.to_clamped();
+            let limited = (i64::from(sum) * i64::from(limiter.gain())) >> fixed::FRAC_BITS;
+            assert!((-2048..=2047).contains(&limited), "{}", n);
+            assert!((output - previous).abs() < 200, "jump at {}", n);
+            previous = output;
+        }
+    }
+
+    #[test]
+    fn test_layer_from_position() {
+        let layer = |p: i32| Layer::from_position(Sample::new(p, false));
+        assert_eq!(layer(Sample::MIN), Layer::Light);
+        assert_eq!(layer(-700), Layer::Light);
+        assert_eq!(layer(-600), Layer::Medium);
+        assert_eq!(layer(Sample::CENTER), Layer::Medium);
+        assert_eq!(layer(680), Layer::Medium);
+        assert_eq!(layer(700), Layer::Heavy);
+        assert_eq!(layer(Sample::MAX), Layer::Heavy);
+    }
+
+    #[test]
+    fn test_level_match_gains() {
+        // three synthetic loops, a sine at different levels
+        let amplitudes = [300, 1200, 1800];
+        let lengths = [4800, 4801, 960];
+        let looped = |layer: usize| {
+            let level = Sample::from(amplitudes[layer]);
+            (0..lengths[layer])
+                .map(move |n| sine((n as u32).wrapping_mul(1 << 26)).scale(level))
+                .cycle()
+        };
+        let levels = [0, 1, 2].map(|layer| rms_level(looped(layer), 4800));
+        let gains = level_match_gains(levels);
+        assert!(gains[0] > fixed::ONE && gains[2] < fixed::ONE);
+
+        // after the gains, all three measure the same
+        let matched = [0, 1, 2].map(|layer| {
+            let gained = looped(layer).map(|sample| apply_gain(sample, gains[layer]));
+            rms_level(gained, 4800).to_clamped()
+        });
+        let target = levels.iter().map(|level| level.to_clamped()).sum::<i32>() / 3;
+        for level in matched {
+            assert!((level - target).abs() <= 2, "{:?} {}", matched, target);
+        }
+    }
+
+    #[test]
+    fn test_level_match_gains_limits() {
+        // silent layers are left alone
+        let silent = Sample::from(0_i32);
+        assert_eq!(level_match_gains([silent; 3]), [fixed::ONE; 3]);
+        let gains = level_match_gains([silent, Sample::from(500_i32), Sample::from(500_i32)]);
+        assert_eq!(gains, [fixed::ONE; 3]);
+        // boosts are limited
+        let gains = level_match_gains([Sample::from(10_i32), Sample::from(2000_i32)]);
+        assert_eq!(gains[0], MAX_MATCH_GAIN);
+        assert_eq!(
+            apply_gain(Sample::from(-100_i32), gains[0]).to_clamped(),
+            -400
+        );
+    }
+
+    #[test]
+    fn test_morph_corners_select_one_layer() {
+        let corners = [100, -700, 1500, -2000].map(Sample::from);
+        let (min, max) = (Sample::from(Sample::MIN), Sample::from(Sample::MAX));
+        for (n, (x, y)) in [(min, min), (max, min), (min, max), (max, max)]
+            .into_iter()
+            .enumerate()
+        {
+            let mut weights = [0; 4];
+            weights[n] = fixed::ONE;
+            assert_eq!(morph_weights(x, y), weights, "corner {}", n);
+            assert_eq!(morph4(corners, x, y), corners[n]);
+        }
+    }
+
+    #[test]
+    fn test_morph_center_blends_equally() {
+        let center = Sample::from(0_i32);
+        let weights = morph_weights(center, center);
+        for weight in weights {
+            // center is half a step above the middle of the range
+            assert!(
+                (weight - fixed::ONE / 4).abs() < fixed::ONE / 1000,
+                "{:?}",
+                weights
+            );
+        }
+        let corners = [400, 800, -400, 1200].map(Sample::from);
+        assert!((morph4(corners, center, center).to_clamped() - 500).abs() <= 1);
+
+        // anywhere on the pad the weights sum to one
+        for x in (Sample::MIN..=Sample::MAX).step_by(97) {
+            for y in (Sample::MIN..=Sample::MAX).step_by(89) {
+                let weights = morph_weights(Sample::from(x), Sample::from(y));
+                assert_eq!(weights.iter().sum::<i32>(), fixed::ONE);
+                assert!(weights.iter().all(|&weight| weight >= 0));
+            }
+        }
+    }
+}
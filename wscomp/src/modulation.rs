@@ -0,0 +1,128 @@
+//! CV modulation of knob controlled parameters
+//!
+//! A patched CV input offsets the knob's position, the same way CV 1 offsets
+//! the main knob's intensity. With nothing patched the knob works alone,
+//! rather than the unpatched jack's reading pushing the parameter to one
+//! end of its range.
+
+use crate::{JackSample, Sample};
+
+/// Parameter a CV input modulates
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CvTarget {
+    /// Not used
+    None,
+    /// Depth of the random rain density wander (X knob)
+    Density,
+    /// Stereo width (Y knob)
+    Width,
+}
+
+/// `knob` offset by `cv` scaled by `depth`, limited to the sample range
+///
+/// An unpatched `cv` leaves the knob unchanged. `depth` [`Sample::MAX`] is
+/// full range, negative depths invert the CV.
+pub fn apply_cv(knob: Sample, cv: &JackSample, depth: Sample) -> Sample {
+    let Some(cv) = cv.plugged_value() else {
+        return knob;
+    };
+    // scaling by the depth keeps the CV's own inversion
+    let offset = depth.scale(*cv);
+    Sample::from((knob + offset).to_clamped())
+}
+
+#[cfg(test)]
+mod test {
+    use super::apply_cv;
+    use crate::{JackSample, Sample, SampleUpdate};
+
+    /// Jack reading ADC `level`, inverted like the Computer's CV inputs
+    fn patched(level: u16) -> JackSample {
+        let mut raw = Sample::new(Sample::CENTER, true);
+        for _ in 0..64 {
+            raw.update(level);
+        }
+        JackSample::new(raw, raw)
+    }
+
+    fn unpatched() -> JackSample {
+        JackSample::new(Sample::from(-1000_i32), Sample::from(1000_i32))
+    }
+
+    #[test]
+    fn test_cv_offsets_knob() {
+        let full = Sample::from(Sample::MAX);
+        let knob = Sample::from(200_i32);
+        let cv = patched(1548);
+        let cv_value = cv.plugged_value().unwrap().to_clamped();
+        assert_eq!(cv_value, 500);
+        assert_eq!(apply_cv(knob, &cv, full).to_clamped(), 200 + cv_value);
+        // half depth
+        let half = Sample::from(Sample::MAX / 2);
+        let offset = apply_cv(knob, &cv, half).to_clamped() - 200;
+        assert!((offset - cv_value / 2).abs() <= 1, "{}", offset);
+        // negative depth inverts
+        let inverted = Sample::from(-Sample::MAX);
+        assert_eq!(apply_cv(knob, &cv, inverted).to_clamped(), 200 - cv_value);
+        // limited to the range
+        assert_eq!(
+            apply_cv(Sample::from(Sample::MAX), &patched(48), full).to_clamped(),
+            Sample::MAX
+        );
+    }
+
+    #[test]
+    fn test_cv_respects_inversion() {
+        // an inverted input reads the opposite of its ADC level, high
+        // levels are negative
+        let cv = patched(3548);
+        assert_eq!(cv.plugged_value().unwrap().to_clamped(), -1500);
+        let modulated = apply_cv(Sample::from(0_i32), &cv, Sample::from(Sample::MAX));
+        assert_eq!(
+            modulated,
+            Sample::from(cv.plugged_value().unwrap().to_clamped())
+        );
+    }
+
+    #[test]
+    fn test_cv_sweeps_intensity() {
+        let full = Sample::from(Sample::MAX);
+        // knob only, unpatched CV adds nothing
+        let knob = Sample::from(-700_i32);
+        assert_eq!(apply_cv(knob, &unpatched(), full), knob);
+        // CV only, with the knob at noon the CV sets intensity by itself
+        let noon = Sample::from(0_i32);
+        for level in [48, 1048, 2048, 3048, 4047] {
+            let cv = patched(level);
+            let cv_value = cv.plugged_value().unwrap().to_clamped();
+            assert_eq!(apply_cv(noon, &cv, full).to_clamped(), cv_value);
+        }
+        // both together add
+        let cv = patched(1548);
+        assert_eq!(apply_cv(knob, &cv, full).to_clamped(), -700 + 500);
+        // and saturate at light and heavy rain rather than wrapping
+        let heavy = Sample::from(1800_i32);
+        assert_eq!(
+            apply_cv(heavy, &patched(48), full).to_clamped(),
+            Sample::MAX
+        );
+        let light = Sample::from(-1800_i32);
+        assert_eq!(
+            apply_cv(light, &patched(4047), full).to_clamped(),
+            Sample::MIN
+        );
+        let max = Sample::from(Sample::MAX);
+        assert_eq!(apply_cv(max, &patched(0), full).to_clamped(), Sample::MAX);
+    }
+
+    #[test]
+    fn test_unpatched_cv_leaves_knob() {
+        for knob in [Sample::MIN, -300, 0, 1234, Sample::MAX] {
+            let knob = Sample::from(knob);
+            for depth in [0, Sample::MAX, -Sample::MAX] {
+                assert_eq!(apply_cv(knob, &unpatched(), Sample::from(depth)), knob);
+            }
+        }
+    }
+}
@@ -0,0 +1,234 @@
+//! Pseudo random noise sources
+//!
+//! Cheap integer-only generators, suitable for the audio and logic loops.
+
+use crate::decay::decay_step;
+use crate::Sample;
+
+/// White noise from a 32 bit xorshift generator
+#[derive(Clone)]
+pub struct Noise {
+    state: u32,
+}
+
+impl Noise {
+    /// New generator, a `seed` of zero is replaced by a fixed non-zero seed
+    pub const fn new(seed: u32) -> Self {
+        Noise {
+            state: if seed == 0 { 0x2545_f491 } else { seed },
+        }
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        // xorshift32, from Marsaglia's "Xorshift RNGs"
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        self.state
+    }
+
+    /// Next value, evenly spread over the full 12 bit [`Sample`] range
+    pub fn next_sample(&mut self) -> Sample {
+        Sample::new((self.next_u32() >> 20) as i32 + Sample::MIN, false)
+    }
+
+    /// Triangular (TPDF) dither for quantizing to steps of `step` codes
+    ///
+    /// Sum of two uniform values, within `-step..step`. The mean is -1/2 rather
+    /// than zero, which cancels the bias of rounding integers to the nearest
+    /// step (see [`crate::dac::reduce_resolution()`]).
+    pub fn next_tpdf(&mut self, step: i32) -> i32 {
+        let step = step.max(1);
+        let a = (self.next_u32() >> 16) as i32 % step;
+        let b = (self.next_u32() >> 16) as i32 % (step + 1);
+        a + b - step
+    }
+}
+
+/// Slowly wandering random value, for subtle modulation
+///
+/// Picks a new random target every `hold_ticks` calls to
+/// [`SmoothNoise::tick()`] and glides toward it with a one-pole filter
+/// (time constant of `2^smoothing_shift` ticks). The output always stays
+/// within the [`Sample`] range, and moves by at most about `range >>
+/// smoothing_shift` per tick.
+pub struct SmoothNoise {
+    noise: Noise,
+    target: i32,
+    value: i32,
+    hold_ticks: u32,
+    counter: u32,
+    smoothing_shift: u8,
+}
+
+impl SmoothNoise {
+    pub fn new(seed: u32, hold_ticks: u32, smoothing_shift: u8) -> Self {
+        SmoothNoise {
+            noise: Noise::new(seed),
+            target: 0,
+            value: 0,
+            hold_ticks: hold_ticks.max(1),
+            counter: 0,
+            smoothing_shift: smoothing_shift.min(16),
+        }
+    }
+
+    /// Advance one step and return the current value
+    pub fn tick(&mut self) -> Sample {
+        if self.counter == 0 {
+            self.target = self.noise.next_sample().to_clamped() << self.smoothing_shift;
+        }
+        self.counter = (self.counter + 1) % self.hold_ticks;
+        self.value += (self.target - self.value) >> self.smoothing_shift;
+        self.current()
+    }
+
+    pub fn current(&self) -> Sample {
+        Sample::new(self.value >> self.smoothing_shift, false)
+    }
+}
+
+/// Chance of a drop starting on each sample, out of `u32::MAX`
+const DROP_CHANCE: u32 = u32::MAX / 2000;
+/// Drop envelope decay per sample, out of [`crate::decay::DECAY_UNITY`]
+/// (fades within a few milliseconds at 48kHz)
+const DROP_DECAY: u16 = 63_000;
+
+/// Synthesized rain, for when no recordings can be played
+///
+/// A soft low passed noise bed, with brighter bursts of noise for scattered
+/// drops, at about `level`.
+pub struct RainNoise {
+    noise: Noise,
+    /// Low pass filter state, scaled up by `smoothing_shift`
+    bed: i32,
+    /// Current drop's envelope, 0 to [`Sample::MAX`]
+    drop: i32,
+    smoothing_shift: u8,
+    level: Sample,
+}
+
+impl RainNoise {
+    /// `smoothing_shift` sets the bed's low pass, higher is darker
+    pub fn new(seed: u32, smoothing_shift: u8, level: Sample) -> Self {
+        RainNoise {
+            noise: Noise::new(seed),
+            bed: 0,
+            drop: 0,
+            smoothing_shift: smoothing_shift.min(12),
+            level,
+        }
+    }
+
+    pub fn next_sample(&mut self) -> Sample {
+        let white = self.noise.next_sample().to_clamped();
+        self.bed += white - (self.bed >> self.smoothing_shift);
+        // the low pass gains 2^shift, and narrows the noise's spread by
+        // about 2^(shift / 2), so scale back by a bit less than 2^shift
+        let bed = self.bed >> (self.smoothing_shift - self.smoothing_shift / 2);
+
+        if self.noise.next_u32() < DROP_CHANCE {
+            self.drop = Sample::MAX / 2 + (self.noise.next_u32() >> 22) as i32;
+        }
+        let drop = white * self.drop / Sample::MAX;
+        self.drop = decay_step(self.drop, DROP_DECAY);
+
+        Sample::from((bed + drop) / 2).scale(self.level)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Noise, RainNoise, SmoothNoise};
+    use crate::math::rms_level;
+    use crate::Sample;
+
+    #[test]
+    fn test_noise_covers_range() {
+        let mut noise = Noise::new(1);
+        let (mut min, mut max, mut sum) = (0, 0, 0_i64);
+        for _ in 0..10_000 {
+            let value = noise.next_sample().to_clamped();
+            min = min.min(value);
+            max = max.max(value);
+            sum += i64::from(value);
+        }
+        assert!(min < Sample::MIN + 50, "min: {}", min);
+        assert!(max > Sample::MAX - 50, "max: {}", max);
+        // roughly centered
+        assert!((sum / 10_000).abs() < 100, "mean: {}", sum / 10_000);
+    }
+
+    #[test]
+    fn test_noise_tpdf_range_and_mean() {
+        let mut noise = Noise::new(3);
+        let mut sum = 0_i64;
+        for _ in 0..10_000 {
+            let dither = noise.next_tpdf(4);
+            assert!((-4..=3).contains(&dither), "{}", dither);
+            sum += i64::from(dither);
+        }
+        assert!((sum as f64 / 10_000.0 + 0.5).abs() < 0.1);
+        assert!((-1..=0).contains(&noise.next_tpdf(1)));
+    }
+
+    #[test]
+    fn test_noise_zero_seed() {
+        let mut noise = Noise::new(0);
+        assert_ne!(noise.next_u32(), 0);
+    }
+
+    #[test]
+    fn test_smooth_noise_scaled_within_depth() {
+        let mut noise = SmoothNoise::new(7, 200, 6);
+        for depth in [0, 1, 100, 256, Sample::MAX] {
+            let depth = Sample::new(depth, false);
+            for _ in 0..5_000 {
+                let modulation = noise.tick().scale(depth).to_clamped();
+                assert!(modulation.abs() <= depth.to_clamped(), "{}", modulation);
+            }
+        }
+    }
+
+    #[test]
+    fn test_smooth_noise_is_slow() {
+        let mut noise = SmoothNoise::new(42, 2000, 9);
+        let mut previous = noise.tick().to_clamped();
+        let mut direction_changes = 0;
+        let mut previous_step = 0_i32;
+        for _ in 0..20_000 {
+            let value = noise.tick().to_clamped();
+            let step = value - previous;
+            // small steps only, never jumps
+            assert!(step.abs() <= (4096 >> 9) + 1, "step: {}", step);
+            if step != 0 && previous_step != 0 && step.signum() != previous_step.signum() {
+                direction_changes += 1;
+            }
+            if step != 0 {
+                previous_step = step;
+            }
+            previous = value;
+        }
+        // direction only changes when a new target is picked
+        assert!(direction_changes <= 20_000 / 2000, "{}", direction_changes);
+    }
+
+    #[test]
+    fn test_rain_noise_level() {
+        let level = Sample::from(Sample::MAX / 4);
+        let mut rain = RainNoise::new(9, 4, level);
+        let samples: Vec<i32> = (0..48_000)
+            .map(|_| rain.next_sample().to_clamped())
+            .collect();
+        let rms = rms_level(samples.iter().map(|&sample| Sample::from(sample)), 48_000);
+        // audible, but gentle
+        assert!(rms.to_clamped() > 50, "{:?}", rms);
+        assert!(rms.to_clamped() < level.to_clamped(), "{:?}", rms);
+        // never beyond the range, and not stuck at one value
+        assert!(samples
+            .iter()
+            .all(|sample| sample.abs() <= level.to_clamped()));
+        let mean = samples.iter().map(|&sample| i64::from(sample)).sum::<i64>() / 48_000;
+        assert!(mean.abs() < 50, "{}", mean);
+    }
+}
@@ -0,0 +1,159 @@
+//! Sounds played once when triggered, like thunder over the rain
+//!
+//! The rain loops forever, a thunder clap plays through once and then goes
+//! quiet until triggered again. [`OneShot`] counts through a sound's samples,
+//! yielding each index once per trigger and `None` after the last. Triggering
+//! it again, even while playing, restarts it from the beginning.
+//!
+//! [`EdgeTrigger`] turns a polled level, such as a pulse input, into triggers
+//! on its rising edges. Edges within a holdoff of the last trigger are
+//! ignored, so contact bounce or a fast clock doesn't keep restarting the
+//! sound.
+
+/// Position through a sound played once per trigger
+///
+/// Unlike most iterators, it yields again after returning `None` once it's
+/// triggered.
+pub struct OneShot {
+    length: usize,
+    position: usize,
+}
+
+impl OneShot {
+    /// A sound of `length` samples, silent until triggered
+    pub const fn new(length: usize) -> Self {
+        OneShot {
+            length,
+            position: length,
+        }
+    }
+
+    /// Play from the start
+    pub fn trigger(&mut self) {
+        self.position = 0;
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.position < self.length
+    }
+
+    pub fn length(&self) -> usize {
+        self.length
+    }
+}
+
+impl Iterator for OneShot {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if !self.is_playing() {
+            return None;
+        }
+        self.position += 1;
+        Some(self.position - 1)
+    }
+}
+
+/// Rising edge detector for a polled level
+pub struct EdgeTrigger {
+    high: bool,
+    holdoff: u32,
+    since_trigger: u32,
+}
+
+impl EdgeTrigger {
+    /// Edges fewer than `holdoff` updates after the last trigger are ignored
+    ///
+    /// The level starts high, so an input already high at power on has to go
+    /// low before it triggers.
+    pub const fn new(holdoff: u32) -> Self {
+        EdgeTrigger {
+            high: true,
+            holdoff,
+            since_trigger: holdoff,
+        }
+    }
+
+    /// Follow the level, once per poll, returning true on a trigger
+    pub fn update(&mut self, high: bool) -> bool {
+        let rising = high && !self.high;
+        self.high = high;
+        self.since_trigger = self.since_trigger.saturating_add(1);
+        if rising && self.since_trigger >= self.holdoff {
+            self.since_trigger = 0;
+            return true;
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{EdgeTrigger, OneShot};
+
+    #[test]
+    fn test_one_shot_plays_once() {
+        let mut shot = OneShot::new(5);
+        // silent until triggered
+        assert!(!shot.is_playing());
+        assert_eq!(shot.next(), None);
+
+        shot.trigger();
+        assert!(shot.is_playing());
+        let played: Vec<usize> = shot.by_ref().collect();
+        assert_eq!(played, [0, 1, 2, 3, 4]);
+        assert!(!shot.is_playing());
+        assert_eq!(shot.next(), None);
+        assert_eq!(shot.next(), None);
+
+        // triggering part way through restarts it
+        shot.trigger();
+        assert_eq!(shot.next(), Some(0));
+        assert_eq!(shot.next(), Some(1));
+        shot.trigger();
+        assert_eq!(shot.by_ref().count(), shot.length());
+        assert_eq!(shot.next(), None);
+
+        // an empty sound never plays
+        let mut empty = OneShot::new(0);
+        empty.trigger();
+        assert_eq!(empty.next(), None);
+    }
+
+    #[test]
+    fn test_edge_trigger_rising() {
+        let mut edge = EdgeTrigger::new(0);
+        // high at power on doesn't trigger, nor does holding high or low
+        let levels = [true, true, false, true, true, true, false, false, true];
+        let triggers: Vec<bool> = levels.iter().map(|&high| edge.update(high)).collect();
+        assert_eq!(
+            triggers,
+            [false, false, false, true, false, false, false, false, true]
+        );
+    }
+
+    #[test]
+    fn test_edge_trigger_holdoff() {
+        let mut edge = EdgeTrigger::new(10);
+        assert!(!edge.update(false));
+        // the first edge triggers straight away
+        assert!(edge.update(true));
+        // bounces just after it are ignored
+        for _ in 0..3 {
+            assert!(!edge.update(false));
+            assert!(!edge.update(true));
+        }
+        // a held level doesn't trigger once the holdoff has passed
+        for _ in 0..20 {
+            assert!(!edge.update(true));
+        }
+        // a fresh edge after it does
+        assert!(!edge.update(false));
+        assert!(edge.update(true));
+
+        // a clock faster than the holdoff triggers every other pulse
+        let mut edge = EdgeTrigger::new(10);
+        let triggers = (0..120).filter(|n| edge.update(n % 8 >= 4)).count();
+        assert_eq!(triggers, 8);
+    }
+}
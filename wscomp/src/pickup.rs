@@ -0,0 +1,186 @@
+//! Soft takeover for knobs which can be locked
+//!
+//! While locked, a knob's value is held and movements are ignored, so CV can
+//! automate a parameter without accidental bumps overriding it. On unlock the
+//! physical knob usually no longer matches the held value. Rather than jump,
+//! [`Pickup`] keeps the held value until the knob is moved to (or past) it,
+//! then follows the knob again.
+
+use crate::Sample;
+
+/// Where a [`Pickup`] is getting its value from
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PickupState {
+    /// Following the knob
+    Following,
+    /// Holding a value, ignoring the knob
+    Locked,
+    /// Unlocked, holding a value until the knob reaches it
+    Catching,
+}
+
+/// Knob value with lock and soft takeover
+pub struct Pickup {
+    state: PickupState,
+    held: i32,
+    /// Knob position at the previous update, to detect moving past `held`
+    last_knob: i32,
+    threshold: i32,
+}
+
+impl Pickup {
+    /// New pickup following the knob, catching within `threshold` of the
+    /// held value
+    pub fn new(threshold: i32) -> Self {
+        Pickup {
+            state: PickupState::Following,
+            held: 0,
+            last_knob: 0,
+            threshold,
+        }
+    }
+
+    pub fn state(&self) -> PickupState {
+        self.state
+    }
+
+    /// Value being used, the knob's while following
+    pub fn held(&self) -> Sample {
+        Sample::from(self.held)
+    }
+
+    /// Resume holding `held`, as saved before power off
+    ///
+    /// Stays locked if it was locked, otherwise waits for the knob to reach
+    /// `held` as after an unlock, so the knob's position doesn't jump in.
+    pub fn restore(&mut self, held: Sample, locked: bool) {
+        self.held = held.to_clamped();
+        self.state = match locked {
+            true => PickupState::Locked,
+            false => PickupState::Catching,
+        };
+    }
+
+    /// Hold the current value and ignore the knob
+    pub fn lock(&mut self) {
+        self.state = PickupState::Locked;
+    }
+
+    /// Stop ignoring the knob, following it once it reaches the held value
+    pub fn unlock(&mut self) {
+        if self.state == PickupState::Locked {
+            self.state = PickupState::Catching;
+        }
+    }
+
+    pub fn toggle_lock(&mut self) {
+        match self.state {
+            PickupState::Locked => self.unlock(),
+            _ => self.lock(),
+        }
+    }
+
+    /// Update with the knob position, returning the value to use
+    pub fn update(&mut self, knob: Sample) -> Sample {
+        let knob = knob.to_clamped();
+        if self.state == PickupState::Catching {
+            let close = (knob - self.held).abs() <= self.threshold;
+            // a fast turn can jump over the threshold between updates
+            let crossed = (knob - self.held).signum() != (self.last_knob - self.held).signum();
+            if close || crossed {
+                self.state = PickupState::Following;
+            }
+        }
+        self.last_knob = knob;
+        if self.state == PickupState::Following {
+            self.held = knob;
+        }
+        Sample::from(self.held)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Pickup, PickupState};
+    use crate::Sample;
+
+    fn update(pickup: &mut Pickup, knob: i32) -> i32 {
+        pickup.update(Sample::from(knob)).to_clamped()
+    }
+
+    #[test]
+    fn test_pickup_follows_until_locked() {
+        let mut pickup = Pickup::new(20);
+        assert_eq!(update(&mut pickup, 100), 100);
+        assert_eq!(update(&mut pickup, 500), 500);
+        pickup.lock();
+        assert_eq!(pickup.state(), PickupState::Locked);
+        // knob bumps are ignored while CV drives the parameter
+        assert_eq!(update(&mut pickup, -1500), 500);
+        assert_eq!(update(&mut pickup, 2000), 500);
+    }
+
+    #[test]
+    fn test_pickup_soft_takeover_within_threshold() {
+        let mut pickup = Pickup::new(20);
+        update(&mut pickup, 500);
+        pickup.toggle_lock();
+        update(&mut pickup, -1000);
+        pickup.toggle_lock();
+        assert_eq!(pickup.state(), PickupState::Catching);
+        // no jump to the knob position after unlocking
+        assert_eq!(update(&mut pickup, -1000), 500);
+        assert_eq!(update(&mut pickup, 0), 500);
+        assert_eq!(update(&mut pickup, 470), 500);
+        // close enough, take over
+        assert_eq!(update(&mut pickup, 485), 485);
+        assert_eq!(pickup.state(), PickupState::Following);
+        assert_eq!(update(&mut pickup, 300), 300);
+    }
+
+    #[test]
+    fn test_pickup_soft_takeover_when_crossed() {
+        let mut pickup = Pickup::new(20);
+        update(&mut pickup, -200);
+        pickup.lock();
+        update(&mut pickup, 1500);
+        pickup.unlock();
+        assert_eq!(update(&mut pickup, 1000), -200);
+        // quick turn skips over the held value
+        assert_eq!(update(&mut pickup, -600), -600);
+        assert_eq!(pickup.state(), PickupState::Following);
+    }
+
+    #[test]
+    fn test_unlock_only_from_locked() {
+        let mut pickup = Pickup::new(20);
+        pickup.unlock();
+        assert_eq!(pickup.state(), PickupState::Following);
+        assert_eq!(update(&mut pickup, 700), 700);
+    }
+
+    #[test]
+    fn test_pickup_restore() {
+        // knob was at 900 when saved, has been turned to -300 since
+        let mut pickup = Pickup::new(20);
+        update(&mut pickup, -300);
+        pickup.restore(Sample::from(900_i32), false);
+        assert_eq!(pickup.state(), PickupState::Catching);
+        assert_eq!(update(&mut pickup, -300), 900);
+        assert_eq!(pickup.held().to_clamped(), 900);
+        assert_eq!(update(&mut pickup, 890), 890);
+        assert_eq!(pickup.state(), PickupState::Following);
+
+        // restored locked, the knob is ignored until unlocked
+        let mut pickup = Pickup::new(20);
+        update(&mut pickup, 100);
+        pickup.restore(Sample::from(-1200_i32), true);
+        assert_eq!(update(&mut pickup, -1200), -1200);
+        assert_eq!(update(&mut pickup, 1800), -1200);
+        assert_eq!(pickup.state(), PickupState::Locked);
+        pickup.toggle_lock();
+        assert_eq!(update(&mut pickup, 1700), -1200);
+        assert_eq!(update(&mut pickup, -1190), -1190);
+    }
+}
@@ -0,0 +1,53 @@
+//! Normalization probe settings, for detecting patched jacks
+//!
+//! The probe pin puts a voltage on every input jack with nothing plugged in.
+//! Reading each jack with the probe idle and again with it driven shows
+//! which are unplugged: their reading moves with the probe, a patched
+//! cable's doesn't. [`crate::JackSample`] keeps both readings and applies a
+//! [`ProbeConfig`] to decide.
+
+/// Pin level which drives the probe
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProbePolarity {
+    /// Probe driven by setting the pin high, idle low (the Computer)
+    ActiveHigh,
+    /// Probe driven by setting the pin low, idle high
+    ActiveLow,
+}
+
+impl ProbePolarity {
+    /// Whether the pin is high while probing
+    pub const fn probing_high(self) -> bool {
+        matches!(self, ProbePolarity::ActiveHigh)
+    }
+}
+
+/// How the probe is driven and read
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProbeConfig {
+    /// Wait after changing the probe before reading, in microseconds
+    pub settle_micros: u32,
+    pub polarity: ProbePolarity,
+    /// Least change from driving the probe which means nothing is plugged
+    /// in, in [`crate::Sample`] steps
+    pub threshold: i32,
+}
+
+impl ProbeConfig {
+    /// Settings for the Computer, determined through testing
+    pub const fn default() -> Self {
+        ProbeConfig {
+            settle_micros: 200,
+            polarity: ProbePolarity::ActiveHigh,
+            threshold: 300,
+        }
+    }
+}
+
+impl Default for ProbeConfig {
+    fn default() -> Self {
+        Self::default()
+    }
+}
@@ -0,0 +1,153 @@
+//! Composable signal processing stages
+//!
+//! Effects which take one sample and return one implement [`Processor`], so
+//! a signal path can be built once as a [`Chain`] of stages and run per
+//! sample, rather than nesting calls by hand and getting the order wrong.
+//!
+//! Without an allocator, a chain borrows a slice of its stages, which the
+//! caller owns. A chain is itself a [`Processor`], so chains can nest.
+
+use crate::comparator::Comparator;
+use crate::gate::Gate;
+use crate::stereo::Decorrelator;
+use crate::Sample;
+
+/// A stage taking one sample and returning one
+pub trait Processor {
+    fn process(&mut self, sample: Sample) -> Sample;
+}
+
+/// Stages run in order, first to last
+pub struct Chain<'a, 'b> {
+    stages: &'a mut [&'b mut dyn Processor],
+}
+
+impl<'a, 'b> Chain<'a, 'b> {
+    /// Chain of `stages`, empty passes samples through unchanged
+    pub fn new(stages: &'a mut [&'b mut dyn Processor]) -> Self {
+        Chain { stages }
+    }
+
+    pub fn len(&self) -> usize {
+        self.stages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stages.is_empty()
+    }
+}
+
+impl Processor for Chain<'_, '_> {
+    fn process(&mut self, sample: Sample) -> Sample {
+        self.stages
+            .iter_mut()
+            .fold(sample, |sample, stage| stage.process(sample))
+    }
+}
+
+impl<const N: usize> Processor for Decorrelator<N> {
+    fn process(&mut self, sample: Sample) -> Sample {
+        Decorrelator::process(self, sample)
+    }
+}
+
+impl Processor for Gate {
+    fn process(&mut self, sample: Sample) -> Sample {
+        Gate::process(self, sample)
+    }
+}
+
+impl Processor for Comparator {
+    fn process(&mut self, sample: Sample) -> Sample {
+        Comparator::process(self, sample)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Chain, Processor};
+    use crate::gate::Gate;
+    use crate::noise::Noise;
+    use crate::stereo::Decorrelator;
+    use crate::Sample;
+
+    /// Test stages which don't commute, to check the order
+    struct Offset(i32);
+    struct Double;
+
+    impl Processor for Offset {
+        fn process(&mut self, sample: Sample) -> Sample {
+            Sample::from(sample.to_clamped() + self.0)
+        }
+    }
+
+    impl Processor for Double {
+        fn process(&mut self, sample: Sample) -> Sample {
+            Sample::from(sample.to_clamped() * 2)
+        }
+    }
+
+    #[test]
+    fn test_chain_runs_stages_in_order() {
+        let (mut offset, mut double) = (Offset(100), Double);
+        let mut stages: [&mut dyn Processor; 2] = [&mut offset, &mut double];
+        let mut chain = Chain::new(&mut stages);
+        assert_eq!(chain.len(), 2);
+        // (x + 100) * 2, not x * 2 + 100
+        assert_eq!(chain.process(Sample::from(10_i32)).to_clamped(), 220);
+
+        let (mut offset, mut double) = (Offset(100), Double);
+        let mut stages: [&mut dyn Processor; 2] = [&mut double, &mut offset];
+        let mut reversed = Chain::new(&mut stages);
+        assert_eq!(reversed.process(Sample::from(10_i32)).to_clamped(), 120);
+
+        // chains nest
+        let mut inner_offset = Offset(-20);
+        let mut outer_offset = Offset(1);
+        let mut inner_stages: [&mut dyn Processor; 2] = [&mut chain, &mut inner_offset];
+        let mut inner = Chain::new(&mut inner_stages);
+        let mut stages: [&mut dyn Processor; 2] = [&mut inner, &mut outer_offset];
+        let mut nested = Chain::new(&mut stages);
+        assert_eq!(nested.process(Sample::from(10_i32)).to_clamped(), 201);
+    }
+
+    #[test]
+    fn test_chain_matches_stages_by_hand() {
+        let mut noise = Noise::new(0x1234_5678);
+        let mut chained = (
+            Decorrelator::<31>::new(),
+            Decorrelator::<17>::new(),
+            Gate::new(Sample::from(200_i32), Sample::from(100_i32), 10, 4),
+        );
+        let mut by_hand = (
+            Decorrelator::<31>::new(),
+            Decorrelator::<17>::new(),
+            Gate::new(Sample::from(200_i32), Sample::from(100_i32), 10, 4),
+        );
+        let mut stages: [&mut dyn Processor; 3] = [&mut chained.0, &mut chained.1, &mut chained.2];
+        let mut chain = Chain::new(&mut stages);
+        for n in 0..2000 {
+            // bursts of noise, so the gate opens and closes
+            let level = if (n / 300) % 2 == 0 { 1000 } else { 50 };
+            let input = noise.next_sample().scale(Sample::from(level));
+            let expected = by_hand
+                .2
+                .process(by_hand.1.process(by_hand.0.process(input)));
+            assert_eq!(chain.process(input), expected, "{}", n);
+        }
+    }
+
+    #[test]
+    fn test_empty_chain_is_identity() {
+        let mut stages: [&mut dyn Processor; 0] = [];
+        let mut chain = Chain::new(&mut stages);
+        assert!(chain.is_empty());
+        for value in [Sample::MIN, -1, 0, 1, Sample::MAX] {
+            let sample = Sample::from(value);
+            assert_eq!(chain.process(sample), sample);
+        }
+        // including unclamped values
+        let loud = Sample::from(1500_i32) + Sample::from(1500_i32);
+        assert_eq!(chain.process(loud), loud);
+    }
+}
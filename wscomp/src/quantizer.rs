@@ -0,0 +1,202 @@
+//! Snap values to the nearest of a set of allowed steps
+//!
+//! Useful for custom scales (pentatonic, whole tone, etc.) where a bitmask of
+//! semitones ([`Sample::quantize_scale()`]) isn't flexible enough. Steps are
+//! arbitrary [`Sample`] values.
+//!
+//! A noisy value near the half way point between two steps would flip
+//! between them. [`Quantizer::quantize_hysteresis()`] holds the previous
+//! step until the value is clearly past the half way point, like a Schmitt
+//! trigger at each boundary.
+
+use crate::Sample;
+
+/// Maps any input to the nearest allowed step
+#[derive(Clone, Copy)]
+pub struct Quantizer<'a> {
+    steps: &'a [Sample],
+}
+
+impl<'a> Quantizer<'a> {
+    /// New quantizer from steps sorted in ascending order
+    ///
+    /// Returns `None` if `steps` is empty or not sorted. Steps are compared
+    /// by their clamped value.
+    pub fn new(steps: &'a [Sample]) -> Option<Self> {
+        let sorted = steps
+            .windows(2)
+            .all(|pair| pair[0].to_clamped() <= pair[1].to_clamped());
+        if steps.is_empty() || !sorted {
+            return None;
+        }
+        Some(Quantizer { steps })
+    }
+
+    /// Nearest step to `value` and its index in the step list
+    ///
+    /// Values exactly between two steps snap to the lower step. Values beyond
+    /// the first or last step snap to that step.
+    pub fn quantize(&self, value: Sample) -> (Sample, usize) {
+        let value = value.to_clamped();
+        // binary search for the first step at or above value
+        let upper = self.steps.partition_point(|step| step.to_clamped() < value);
+        let index = match upper {
+            0 => 0,
+            upper if upper == self.steps.len() => upper - 1,
+            upper => {
+                let below = value - self.steps[upper - 1].to_clamped();
+                let above = self.steps[upper].to_clamped() - value;
+                if above < below {
+                    upper
+                } else {
+                    upper - 1
+                }
+            }
+        };
+        (self.steps[index], index)
+    }
+
+    /// Like [`Quantizer::quantize()`], but stays on step `previous` until
+    /// `value` is more than `hysteresis` past the half way point to the next
+    /// step
+    ///
+    /// `hysteresis` should be under half the smallest gap between steps. An
+    /// out of range `previous` (such as `usize::MAX` to start) snaps to the
+    /// nearest step.
+    pub fn quantize_hysteresis(
+        &self,
+        value: Sample,
+        previous: usize,
+        hysteresis: i32,
+    ) -> (Sample, usize) {
+        let (nearest, index) = self.quantize(value);
+        let Some(held) = self.steps.get(previous) else {
+            return (nearest, index);
+        };
+        let value = value.to_clamped();
+        let held_value = held.to_clamped();
+        let past_boundary = |neighbour: usize| {
+            let boundary = (held_value + self.steps[neighbour].to_clamped()) / 2;
+            (value - boundary).abs() > hysteresis
+        };
+        let moved = match index {
+            index if index > previous => past_boundary(previous + 1),
+            index if index < previous => past_boundary(previous - 1),
+            _ => false,
+        };
+        if moved {
+            (nearest, index)
+        } else {
+            (*held, previous)
+        }
+    }
+
+    pub fn steps(&self) -> &'a [Sample] {
+        self.steps
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Quantizer;
+    use crate::Sample;
+
+    /// Major pentatonic scale over one octave, 100 per semitone
+    const PENTATONIC: [i32; 6] = [0, 200, 400, 700, 900, 1200];
+
+    fn steps() -> [Sample; 6] {
+        PENTATONIC.map(Sample::from)
+    }
+
+    #[test]
+    fn test_quantizer_snaps_to_nearest() {
+        let steps = steps();
+        let quantizer = Quantizer::new(&steps).unwrap();
+        let check = |value: i32, expected: i32, index: usize| {
+            let (step, step_index) = quantizer.quantize(Sample::from(value));
+            assert_eq!(step.to_clamped(), expected, "input {}", value);
+            assert_eq!(step_index, index, "input {}", value);
+        };
+        check(0, 0, 0);
+        check(99, 0, 0);
+        check(101, 200, 1);
+        check(420, 400, 2);
+        check(600, 700, 3);
+        check(1000, 900, 4);
+        check(1051, 1200, 5);
+        // beyond the ends
+        check(-2048, 0, 0);
+        check(2047, 1200, 5);
+    }
+
+    #[test]
+    fn test_quantizer_ties_snap_down() {
+        let steps = steps();
+        let quantizer = Quantizer::new(&steps).unwrap();
+        assert_eq!(quantizer.quantize(Sample::from(100_i32)).1, 0);
+        assert_eq!(quantizer.quantize(Sample::from(300_i32)).1, 1);
+        assert_eq!(quantizer.quantize(Sample::from(550_i32)).1, 2);
+        assert_eq!(quantizer.quantize(Sample::from(1050_i32)).1, 4);
+    }
+
+    #[test]
+    fn test_quantizer_rejects_bad_steps() {
+        assert!(Quantizer::new(&[]).is_none());
+        let unsorted = [Sample::from(10_i32), Sample::from(5_i32)];
+        assert!(Quantizer::new(&unsorted).is_none());
+        let single = [Sample::from(10_i32)];
+        let quantizer = Quantizer::new(&single).unwrap();
+        assert_eq!(quantizer.quantize(Sample::from(-500_i32)).1, 0);
+    }
+
+    #[test]
+    fn test_quantizer_hysteresis_doesnt_chatter() {
+        // five evenly spaced states, boundaries at -1536, -512, 512, 1535
+        let states = [-2048, -1024, 0, 1024, 2047].map(Sample::from);
+        let quantizer = Quantizer::new(&states).unwrap();
+        let hysteresis = 100;
+        let quantize = |value: i32, previous: usize| {
+            quantizer
+                .quantize_hysteresis(Sample::from(value), previous, hysteresis)
+                .1
+        };
+        // starting snaps to the nearest
+        assert_eq!(quantize(-600, usize::MAX), 1);
+        assert_eq!(quantize(-400, usize::MAX), 2);
+
+        // noise around every boundary stays on the side it came from
+        for (boundary, below) in [(-1536, 0), (-512, 1), (512, 2), (1535, 3)] {
+            let mut index = below;
+            for n in 0..1000 {
+                let noise = (n * 37 % 199) - 99;
+                index = quantize(boundary + noise, index);
+                assert_eq!(index, below, "boundary {} noise {}", boundary, noise);
+            }
+            let mut index = below + 1;
+            for n in 0..1000 {
+                let noise = (n * 37 % 199) - 99;
+                index = quantize(boundary + noise, index);
+                assert_eq!(index, below + 1, "boundary {} noise {}", boundary, noise);
+            }
+            // clearly past, it steps, either direction
+            assert_eq!(quantize(boundary + hysteresis + 1, below), below + 1);
+            assert_eq!(quantize(boundary - hysteresis - 1, below + 1), below);
+        }
+
+        // a full sweep up and down steps once per boundary each way
+        let mut index = 0;
+        let mut changes = 0;
+        let sweep = (-2048..=2047).chain((-2048..=2047).rev());
+        for value in sweep.step_by(7) {
+            let next = quantize(value, index);
+            if next != index {
+                changes += 1;
+            }
+            index = next;
+        }
+        assert_eq!(changes, 8);
+        // big jumps go straight to the nearest state
+        assert_eq!(quantize(2047, 0), 4);
+        assert_eq!(quantize(-2048, 4), 0);
+    }
+}
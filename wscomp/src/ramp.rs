@@ -0,0 +1,88 @@
+//! Linear interpolation between slowly updated values
+//!
+//! A consumer that runs faster than its producer (LEDs refreshed faster than
+//! the control logic, for example) would otherwise hold each value and then
+//! jump. [`Ramp`] glides from the current value to each new target over a
+//! fixed number of ticks instead, usually the ratio of the two rates.
+
+use crate::Sample;
+
+/// Glides linearly to each new target over `steps` ticks
+pub struct Ramp {
+    from: i32,
+    to: i32,
+    steps: u32,
+    step: u32,
+}
+
+impl Ramp {
+    pub fn new(initial: Sample, steps: u32) -> Self {
+        let initial = initial.to_clamped();
+        let steps = steps.max(1);
+        Ramp {
+            from: initial,
+            to: initial,
+            steps,
+            step: steps,
+        }
+    }
+
+    /// Start gliding from the current value toward `target`
+    pub fn set_target(&mut self, target: Sample) {
+        self.from = self.current().to_clamped();
+        self.to = target.to_clamped();
+        self.step = 0;
+    }
+
+    /// Advance one tick and return the new value
+    ///
+    /// The target is reached after `steps` ticks, then held.
+    pub fn tick(&mut self) -> Sample {
+        self.step = (self.step + 1).min(self.steps);
+        self.current()
+    }
+
+    pub fn current(&self) -> Sample {
+        Sample::from(self.from).interpolate_to(Sample::from(self.to), self.step, self.steps)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Ramp;
+    use crate::Sample;
+
+    #[test]
+    fn test_ramp_interpolates_between_updates() {
+        // LEDs at 4x the logic rate
+        let mut ramp = Ramp::new(Sample::from(0_i32), 4);
+        assert_eq!(ramp.current().to_clamped(), 0);
+
+        ramp.set_target(Sample::from(1000_i32));
+        let values: [i32; 5] = core::array::from_fn(|_| ramp.tick().to_clamped());
+        assert_eq!(values, [250, 500, 750, 1000, 1000]);
+
+        ramp.set_target(Sample::from(-1000_i32));
+        let values: [i32; 4] = core::array::from_fn(|_| ramp.tick().to_clamped());
+        assert_eq!(values, [500, 0, -500, -1000]);
+    }
+
+    #[test]
+    fn test_ramp_retargets_from_current_position() {
+        let mut ramp = Ramp::new(Sample::from(0_i32), 4);
+        ramp.set_target(Sample::from(800_i32));
+        ramp.tick();
+        ramp.tick();
+        // a new logic value arrives early, continue from where the LEDs are
+        ramp.set_target(Sample::from(0_i32));
+        assert_eq!(ramp.current().to_clamped(), 400);
+        assert_eq!(ramp.tick().to_clamped(), 300);
+    }
+
+    #[test]
+    fn test_ramp_full_range_single_step() {
+        let mut ramp = Ramp::new(Sample::from(Sample::MIN), 0);
+        ramp.set_target(Sample::from(Sample::MAX));
+        assert_eq!(ramp.tick().to_clamped(), Sample::MAX);
+    }
+}
@@ -0,0 +1,164 @@
+//! Binary reports of the input readings, for plotting them on a host
+//!
+//! Each [`InputReport`] serializes to a fixed [`REPORT_LEN`] byte frame:
+//!
+//! ```text
+//! byte 0-1  : sync, 0xB5 0x52
+//! byte 2    : sequence number, wrapping, so a host can spot dropped frames
+//! byte 3    : flags
+//!               bits 0-1: Z switch, 0 = off, 1 = on, 2 = momentary
+//!               bit 2   : CV 1 connected
+//!               bit 3   : CV 2 connected
+//! byte 4-13 : main knob, X knob, Y knob, CV 1, CV 2, each an i16
+//!             little endian from -2048 to 2047, 0 for unconnected CV
+//! ```
+//!
+//! The sync bytes let a host find the start of a frame in the middle of a
+//! stream.
+
+use crate::error::Error;
+use crate::switch::SwitchPosition;
+use crate::Sample;
+
+/// Bytes in a serialized [`InputReport`]
+pub const REPORT_LEN: usize = 14;
+/// First bytes of every frame
+pub const SYNC: [u8; 2] = [0xb5, 0x52];
+
+const CV1_CONNECTED: u8 = 1 << 2;
+const CV2_CONNECTED: u8 = 1 << 3;
+
+/// Snapshot of the knobs, switch and CV inputs
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InputReport {
+    pub sequence: u8,
+    pub main_knob: Sample,
+    pub x_knob: Sample,
+    pub y_knob: Sample,
+    pub zswitch: SwitchPosition,
+    /// `None` while nothing is plugged in
+    pub cv1: Option<Sample>,
+    pub cv2: Option<Sample>,
+}
+
+impl InputReport {
+    pub fn to_bytes(&self) -> [u8; REPORT_LEN] {
+        let mut flags = self.zswitch as u8;
+        if self.cv1.is_some() {
+            flags |= CV1_CONNECTED;
+        }
+        if self.cv2.is_some() {
+            flags |= CV2_CONNECTED;
+        }
+        let unconnected = Sample::from(0_i32);
+        let values = [
+            self.main_knob,
+            self.x_knob,
+            self.y_knob,
+            self.cv1.unwrap_or(unconnected),
+            self.cv2.unwrap_or(unconnected),
+        ];
+
+        let mut bytes = [0; REPORT_LEN];
+        bytes[..2].copy_from_slice(&SYNC);
+        bytes[2] = self.sequence;
+        bytes[3] = flags;
+        for (chunk, value) in bytes[4..].as_chunks_mut::<2>().0.iter_mut().zip(values) {
+            *chunk = (value.to_clamped() as i16).to_le_bytes();
+        }
+        bytes
+    }
+
+    /// Fails with [`Error::MissingRecord`] without the sync bytes or with an
+    /// unknown switch position
+    pub fn from_bytes(bytes: [u8; REPORT_LEN]) -> Result<Self, Error> {
+        if bytes[..2] != SYNC {
+            return Err(Error::MissingRecord);
+        }
+        let flags = bytes[3];
+        let zswitch = match flags & 0b11 {
+            0 => SwitchPosition::Off,
+            1 => SwitchPosition::On,
+            2 => SwitchPosition::Momentary,
+            _ => return Err(Error::MissingRecord),
+        };
+        let value = |index: usize| {
+            let offset = 4 + 2 * index;
+            Sample::from(i16::from_le_bytes([bytes[offset], bytes[offset + 1]]))
+        };
+        Ok(InputReport {
+            sequence: bytes[2],
+            main_knob: value(0),
+            x_knob: value(1),
+            y_knob: value(2),
+            zswitch,
+            cv1: (flags & CV1_CONNECTED != 0).then(|| value(3)),
+            cv2: (flags & CV2_CONNECTED != 0).then(|| value(4)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{InputReport, REPORT_LEN};
+    use crate::error::Error;
+    use crate::switch::SwitchPosition;
+    use crate::Sample;
+
+    fn report() -> InputReport {
+        InputReport {
+            sequence: 7,
+            main_knob: Sample::from(Sample::MAX),
+            x_knob: Sample::from(Sample::MIN),
+            y_knob: Sample::from(-2_i32),
+            zswitch: SwitchPosition::Momentary,
+            cv1: Some(Sample::from(300_i32)),
+            cv2: None,
+        }
+    }
+
+    #[test]
+    fn test_report_format() {
+        let bytes = report().to_bytes();
+        assert_eq!(
+            bytes,
+            [
+                0xb5, 0x52,   // sync
+                7,      // sequence
+                0b0110, // CV 1 connected, momentary
+                0xff, 0x07, // main 2047
+                0x00, 0xf8, // X -2048
+                0xfe, 0xff, // Y -2
+                0x2c, 0x01, // CV 1 300
+                0x00, 0x00, // CV 2 unconnected
+            ]
+        );
+        assert_eq!(InputReport::from_bytes(bytes), Ok(report()));
+
+        // out of range values are clamped, inverted inputs report the value
+        // the card uses
+        let mut clamped = report();
+        clamped.main_knob = Sample::from(5000_i32);
+        clamped.y_knob = Sample::new(100, true);
+        clamped.zswitch = SwitchPosition::Off;
+        let bytes = clamped.to_bytes();
+        assert_eq!(bytes[3], 0b0100);
+        assert_eq!(bytes[4..6], [0xff, 0x07]);
+        assert_eq!(bytes[8..10], (-100_i16).to_le_bytes());
+    }
+
+    #[test]
+    fn test_report_rejects_bad_frames() {
+        let mut bytes = report().to_bytes();
+        bytes[0] = 0;
+        assert_eq!(InputReport::from_bytes(bytes), Err(Error::MissingRecord));
+        let mut bytes = report().to_bytes();
+        bytes[3] |= 0b11;
+        assert_eq!(InputReport::from_bytes(bytes), Err(Error::MissingRecord));
+        assert_eq!(
+            InputReport::from_bytes([0; REPORT_LEN]),
+            Err(Error::MissingRecord)
+        );
+    }
+}
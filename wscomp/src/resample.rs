@@ -0,0 +1,261 @@
+//! Variable speed playback of sample streams
+//!
+//! [`Resampler`] reads a source at `ratio` source samples per output sample,
+//! linearly interpolating between them. Above unity it skips source samples
+//! (decimates), which folds anything above the new Nyquist frequency back
+//! down as harsh aliasing. So above unity, source samples first go through an
+//! [`AntiAliasFilter`].
+
+use crate::fixed::{self, Fixed, FRAC_BITS};
+
+/// Playback ratio of 1.0, ratios are unsigned [`Fixed`]
+pub const UNITY_RATIO: u32 = fixed::ONE as u32;
+
+/// `speed` corrected for a source recorded at `source_hz` played at
+/// `output_hz`, so it keeps its pitch
+///
+/// Rounded to the nearest step. An `output_hz` of 0 leaves `speed` as is.
+pub const fn speed_for_rate(speed: u32, source_hz: u32, output_hz: u32) -> u32 {
+    if output_hz == 0 {
+        return speed;
+    }
+    let scaled = (speed as u64 * source_hz as u64 + output_hz as u64 / 2) / output_hz as u64;
+    if scaled > u32::MAX as u64 {
+        u32::MAX
+    } else {
+        scaled as u32
+    }
+}
+
+/// One pole low pass for source samples ahead of decimation
+///
+/// The cutoff follows the ratio, sitting a little below the Nyquist
+/// frequency after decimation. At or below unity nothing is removed by
+/// resampling, so the filter is bypassed and samples pass through unchanged.
+pub struct AntiAliasFilter {
+    state: Fixed,
+    /// Fraction of the distance to the input moved each sample, `UNITY_RATIO`
+    /// would be 1.0 (no filtering)
+    coefficient: u32,
+}
+
+impl AntiAliasFilter {
+    pub fn new(ratio: u32) -> Self {
+        let mut filter = AntiAliasFilter {
+            state: 0,
+            coefficient: UNITY_RATIO,
+        };
+        filter.set_ratio(ratio);
+        filter
+    }
+
+    /// Update the cutoff for a new playback ratio
+    pub fn set_ratio(&mut self, ratio: u32) {
+        self.coefficient = match ratio {
+            ratio if ratio <= UNITY_RATIO => UNITY_RATIO,
+            // coefficient 1/ratio, in the same fixed point as the ratio
+            ratio => ((1_u64 << (2 * FRAC_BITS)) / u64::from(ratio)) as u32,
+        };
+    }
+
+    pub fn is_bypassed(&self) -> bool {
+        self.coefficient >= UNITY_RATIO
+    }
+
+    pub fn process(&mut self, input: i16) -> i16 {
+        let input = fixed::from_i16(input);
+        if self.is_bypassed() {
+            self.state = input;
+        } else {
+            // below unity, so the coefficient fits. A full scale swing can
+            // overflow the difference, saturating just slows that one step
+            let difference = input.saturating_sub(self.state);
+            self.state += fixed::mul(difference, self.coefficient as Fixed);
+        }
+        fixed::to_i16(self.state)
+    }
+}
+
+/// Plays a source at a variable `ratio` of its original speed
+pub struct Resampler {
+    ratio: u32,
+    /// Position between `previous` and `current`, unsigned [`Fixed`]
+    phase: u32,
+    previous: i16,
+    current: i16,
+    filter: AntiAliasFilter,
+}
+
+impl Resampler {
+    pub fn new(ratio: u32) -> Self {
+        Resampler {
+            ratio,
+            phase: 0,
+            previous: 0,
+            current: 0,
+            filter: AntiAliasFilter::new(ratio),
+        }
+    }
+
+    pub fn ratio(&self) -> u32 {
+        self.ratio
+    }
+
+    pub fn set_ratio(&mut self, ratio: u32) {
+        self.ratio = ratio;
+        self.filter.set_ratio(ratio);
+    }
+
+    /// Next output sample, reading as many samples from `source` as needed
+    ///
+    /// Output is one source sample behind, so at unity the source passes
+    /// through unchanged, one sample late.
+    pub fn next(&mut self, mut source: impl FnMut() -> i16) -> i16 {
+        self.phase += self.ratio;
+        while self.phase >= UNITY_RATIO {
+            self.phase -= UNITY_RATIO;
+            self.previous = self.current;
+            self.current = self.filter.process(source());
+        }
+        let distance = i32::from(self.current) - i32::from(self.previous);
+        // phase is below unity here, so it fits
+        let offset = fixed::mul(distance, self.phase as Fixed);
+        (i32::from(self.previous) + offset) as i16
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{speed_for_rate, AntiAliasFilter, Resampler, UNITY_RATIO};
+
+    #[test]
+    fn test_speed_for_rate() {
+        // matching rates change nothing
+        assert_eq!(speed_for_rate(UNITY_RATIO, 48_000, 48_000), UNITY_RATIO);
+        assert_eq!(
+            speed_for_rate(UNITY_RATIO + 131, 44_100, 44_100),
+            UNITY_RATIO + 131
+        );
+        // 44.1kHz recordings at 48kHz read 0.91875 source samples each
+        assert_eq!(speed_for_rate(UNITY_RATIO, 44_100, 48_000), 60_211);
+        assert_eq!(speed_for_rate(UNITY_RATIO, 96_000, 48_000), 2 * UNITY_RATIO);
+        assert_eq!(speed_for_rate(UNITY_RATIO, 48_000, 96_000), UNITY_RATIO / 2);
+        // rounded to nearest, saturating, and no rate is no change
+        assert_eq!(speed_for_rate(3, 1, 2), 2);
+        assert_eq!(speed_for_rate(u32::MAX, 2, 1), u32::MAX);
+        assert_eq!(speed_for_rate(UNITY_RATIO, 48_000, 0), UNITY_RATIO);
+    }
+
+    /// Source alternating at the Nyquist frequency
+    fn nyquist() -> impl FnMut() -> i16 {
+        let mut high = false;
+        move || {
+            high = !high;
+            if high {
+                1000
+            } else {
+                -1000
+            }
+        }
+    }
+
+    #[test]
+    fn test_unity_ratio_bypasses_filter() {
+        let filter = AntiAliasFilter::new(UNITY_RATIO);
+        assert!(filter.is_bypassed());
+        assert!(AntiAliasFilter::new(UNITY_RATIO / 2).is_bypassed());
+        assert!(!AntiAliasFilter::new(2 * UNITY_RATIO).is_bypassed());
+
+        let mut resampler = Resampler::new(UNITY_RATIO);
+        let mut source = nyquist();
+        resampler.next(&mut source);
+        for expected in [1000, -1000, 1000, -1000] {
+            assert_eq!(resampler.next(&mut source), expected);
+        }
+    }
+
+    #[test]
+    fn test_high_frequency_attenuated_before_decimation() {
+        // without a filter, taking every other sample of a Nyquist frequency
+        // signal aliases it into a full level constant
+        let mut source = nyquist();
+        let unfiltered: Vec<i16> = (0..100).map(|_| source()).collect();
+        assert!(unfiltered.iter().step_by(2).all(|&s| s == 1000));
+
+        for ratio in [2 * UNITY_RATIO, 3 * UNITY_RATIO, 4 * UNITY_RATIO + 1234] {
+            let mut resampler = Resampler::new(ratio);
+            let mut source = nyquist();
+            // let the filter settle
+            for _ in 0..50 {
+                resampler.next(&mut source);
+            }
+            for _ in 0..100 {
+                let output = resampler.next(&mut source);
+                assert!(output.abs() < 400, "ratio {}: {}", ratio, output);
+            }
+        }
+    }
+
+    #[test]
+    fn test_filter_full_scale_swing() {
+        let mut filter = AntiAliasFilter::new(2 * UNITY_RATIO);
+        let mut output = 0;
+        for input in [i16::MAX, i16::MIN, i16::MAX, i16::MIN] {
+            for _ in 0..100 {
+                output = filter.process(input);
+            }
+            assert!(
+                (i32::from(output) - i32::from(input)).abs() <= 1,
+                "{}",
+                output
+            );
+        }
+    }
+
+    #[test]
+    fn test_filter_passes_low_frequencies() {
+        let mut filter = AntiAliasFilter::new(2 * UNITY_RATIO);
+        let mut output = 0;
+        for _ in 0..100 {
+            output = filter.process(1000);
+        }
+        assert!((output - 1000).abs() <= 1, "{}", output);
+    }
+
+    #[test]
+    fn test_layers_drift_at_different_ratios() {
+        // two layers slightly either side of unity, as in the mixer
+        let ratios = [UNITY_RATIO - 197, UNITY_RATIO + 131];
+        let mut layers = ratios.map(Resampler::new);
+        let mut consumed = [0_u64; 2];
+        let outputs = 48_000 * 60;
+        for _ in 0..outputs {
+            for (layer, count) in layers.iter_mut().zip(consumed.iter_mut()) {
+                layer.next(|| {
+                    *count += 1;
+                    0
+                });
+            }
+        }
+        // each reads exactly its ratio of source samples, no drift from
+        // rounding, and the layers move apart steadily
+        for (ratio, count) in ratios.iter().zip(consumed) {
+            assert_eq!(count, outputs * u64::from(*ratio) / u64::from(UNITY_RATIO));
+        }
+        // after a minute, ~0.18s behind and ~0.12s ahead of unity
+        assert_eq!(consumed, [2_880_000 - 8658, 2_880_000 + 5756]);
+    }
+
+    #[test]
+    fn test_resampler_interpolates() {
+        // half speed ramp, every other output is between two source samples
+        let mut resampler = Resampler::new(UNITY_RATIO / 2);
+        let mut next = 0;
+        let mut source = || {
+            next += 100;
+            next
+        };
+        let outputs: Vec<i16> = (0..8).map(|_| resampler.next(&mut source)).collect();
+        assert_eq!(outputs, [0, 0, 50, 100, 150, 200, 250, 300]);
+    }
+}
@@ -0,0 +1,98 @@
+//! Bounded retry with exponential backoff
+//!
+//! Hardware setup on the Computer (ADC, SPI, flash) is usually infallible in
+//! the HAL, but the first use of a peripheral can still fail. [`Retry`] keeps
+//! the retry/give-up policy separate from the hardware so it can be tested on
+//! the host.
+
+/// What to do after a failed attempt
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum RetryDecision {
+    /// Reinitialize and try again after waiting this many milliseconds
+    RetryAfter(u32),
+    /// All attempts used, report the fault and stop trying
+    GiveUp,
+}
+
+/// Bounded retry policy, doubling the delay after each failure
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone)]
+pub struct Retry {
+    max_attempts: u8,
+    base_delay_ms: u32,
+    max_delay_ms: u32,
+    failures: u8,
+}
+
+impl Retry {
+    /// New policy allowing `max_attempts` total attempts
+    ///
+    /// The first retry waits `base_delay_ms`, each following retry waits twice
+    /// as long, up to `max_delay_ms`.
+    pub fn new(max_attempts: u8, base_delay_ms: u32, max_delay_ms: u32) -> Self {
+        Retry {
+            max_attempts,
+            base_delay_ms,
+            max_delay_ms,
+            failures: 0,
+        }
+    }
+
+    /// Record a failed attempt and decide whether to try again
+    pub fn failed(&mut self) -> RetryDecision {
+        self.failures = self.failures.saturating_add(1);
+        if self.failures >= self.max_attempts {
+            return RetryDecision::GiveUp;
+        }
+        let delay = self
+            .base_delay_ms
+            .saturating_mul(1 << (self.failures - 1).min(31));
+        RetryDecision::RetryAfter(delay.min(self.max_delay_ms))
+    }
+
+    /// Number of failed attempts recorded so far
+    pub fn failures(&self) -> u8 {
+        self.failures
+    }
+
+    /// Start over, after a successful attempt
+    pub fn reset(&mut self) {
+        self.failures = 0;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Retry, RetryDecision};
+
+    #[test]
+    fn test_retry_backoff_then_give_up() {
+        let mut retry = Retry::new(4, 10, 1000);
+        assert_eq!(retry.failed(), RetryDecision::RetryAfter(10));
+        assert_eq!(retry.failed(), RetryDecision::RetryAfter(20));
+        assert_eq!(retry.failed(), RetryDecision::RetryAfter(40));
+        assert_eq!(retry.failed(), RetryDecision::GiveUp);
+        // stays given up
+        assert_eq!(retry.failed(), RetryDecision::GiveUp);
+        assert_eq!(retry.failures(), 5);
+    }
+
+    #[test]
+    fn test_retry_delay_capped() {
+        let mut retry = Retry::new(u8::MAX, 100, 250);
+        assert_eq!(retry.failed(), RetryDecision::RetryAfter(100));
+        assert_eq!(retry.failed(), RetryDecision::RetryAfter(200));
+        for _ in 0..100 {
+            assert_eq!(retry.failed(), RetryDecision::RetryAfter(250));
+        }
+    }
+
+    #[test]
+    fn test_retry_single_attempt_and_reset() {
+        let mut retry = Retry::new(1, 10, 1000);
+        assert_eq!(retry.failed(), RetryDecision::GiveUp);
+        retry.reset();
+        assert_eq!(retry.failures(), 0);
+    }
+}
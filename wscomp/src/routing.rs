@@ -0,0 +1,232 @@
+//! Which signal goes to which physical output
+//!
+//! The card produces more signals than it has outputs. A [`Routing`] maps
+//! each routable output to one [`Source`], and [`Routing::resolve()`] picks
+//! that source's current value.
+//!
+//! The CV outputs are PWM, updated at the LED rate (around 1kHz), so audio
+//! routed to them would be a mess of aliasing. [`Routing::conflicts()`] lists
+//! outputs where that has been configured, so it can be reported rather than
+//! just sounding broken.
+
+use crate::error::Error;
+use crate::Sample;
+
+/// Signals which can be routed to an output
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Source {
+    /// Rain mix, the left channel when stereo
+    Rain,
+    /// Right channel of the stereo rain
+    RainRight,
+    /// Rain intensity, as a slowly changing level
+    Intensity,
+    /// Intensity modulation LFO
+    Lfo,
+    /// 0V
+    Silence,
+}
+
+impl Source {
+    const ALL: [Source; 5] = [
+        Source::Rain,
+        Source::RainRight,
+        Source::Intensity,
+        Source::Lfo,
+        Source::Silence,
+    ];
+
+    /// True for signals with content at audio rates
+    pub fn is_audio(self) -> bool {
+        matches!(self, Source::Rain | Source::RainRight)
+    }
+}
+
+/// Signals for the pulse outputs, which are only ever high or low
+///
+/// Not part of a [`Routing`], the firmware picks one per pulse output.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PulseSource {
+    /// High while the audio loop is busy, for measuring CPU use
+    Busy,
+    /// A trigger each time intensity crosses a threshold, either way
+    ThresholdTriggers,
+    /// High while intensity is above a threshold
+    ThresholdGate,
+}
+
+/// Physical outputs which can be routed
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Destination {
+    Audio1,
+    Audio2,
+    Cv1,
+    Cv2,
+}
+
+impl Destination {
+    pub const ALL: [Destination; 4] = [
+        Destination::Audio1,
+        Destination::Audio2,
+        Destination::Cv1,
+        Destination::Cv2,
+    ];
+
+    /// True for outputs updated too slowly for audio
+    pub fn is_cv_only(self) -> bool {
+        matches!(self, Destination::Cv1 | Destination::Cv2)
+    }
+}
+
+/// Current value of each [`Source`], filled in by the loop producing outputs
+///
+/// A loop without access to a signal leaves it at 0V.
+#[derive(Clone, Copy)]
+pub struct Signals {
+    pub rain: Sample,
+    pub rain_right: Sample,
+    pub intensity: Sample,
+    pub lfo: Sample,
+}
+
+impl Signals {
+    pub fn new() -> Self {
+        let center = Sample::from(Sample::CENTER);
+        Signals {
+            rain: center,
+            rain_right: center,
+            intensity: center,
+            lfo: center,
+        }
+    }
+}
+
+impl Default for Signals {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Source for each [`Destination`], in the order of [`Destination::ALL`]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Routing {
+    sources: [Source; 4],
+}
+
+impl Routing {
+    pub const fn new(sources: [Source; 4]) -> Self {
+        Routing { sources }
+    }
+
+    /// Stereo rain on the audio outputs, intensity and LFO on the CV outputs
+    pub const fn default() -> Self {
+        Routing::new([
+            Source::Rain,
+            Source::RainRight,
+            Source::Intensity,
+            Source::Lfo,
+        ])
+    }
+
+    pub fn source(&self, destination: Destination) -> Source {
+        self.sources[destination as usize]
+    }
+
+    pub fn set(&mut self, destination: Destination, source: Source) {
+        self.sources[destination as usize] = source;
+    }
+
+    /// Value to output on `destination`
+    pub fn resolve(&self, destination: Destination, signals: &Signals) -> Sample {
+        match self.source(destination) {
+            Source::Rain => signals.rain,
+            Source::RainRight => signals.rain_right,
+            Source::Intensity => signals.intensity,
+            Source::Lfo => signals.lfo,
+            Source::Silence => Sample::from(Sample::CENTER),
+        }
+    }
+
+    /// Outputs which can only carry CV, but have an audio source routed
+    pub fn conflicts(&self) -> impl Iterator<Item = Destination> + '_ {
+        Destination::ALL
+            .into_iter()
+            .filter(|&destination| destination.is_cv_only() && self.source(destination).is_audio())
+    }
+
+    pub fn to_bytes(&self) -> [u8; 4] {
+        self.sources.map(|source| source as u8)
+    }
+
+    /// Fails with [`Error::MissingRecord`] for an unknown source
+    pub fn from_bytes(bytes: [u8; 4]) -> Result<Self, Error> {
+        let mut sources = [Source::Silence; 4];
+        for (source, byte) in sources.iter_mut().zip(bytes) {
+            *source = *Source::ALL
+                .get(usize::from(byte))
+                .ok_or(Error::MissingRecord)?;
+        }
+        Ok(Routing::new(sources))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Destination, Routing, Signals, Source};
+    use crate::error::Error;
+    use crate::Sample;
+
+    fn signals() -> Signals {
+        Signals {
+            rain: Sample::from(100_i32),
+            rain_right: Sample::from(200_i32),
+            intensity: Sample::from(300_i32),
+            lfo: Sample::from(400_i32),
+        }
+    }
+
+    #[test]
+    fn test_routing_resolves_sources() {
+        let routing = Routing::default();
+        let outputs = Destination::ALL.map(|d| routing.resolve(d, &signals()).to_clamped());
+        assert_eq!(outputs, [100, 200, 300, 400]);
+
+        // swap the audio outputs, CV intensity on audio 2 as a test level
+        let mut routing = Routing::default();
+        routing.set(Destination::Audio1, Source::RainRight);
+        routing.set(Destination::Audio2, Source::Intensity);
+        routing.set(Destination::Cv2, Source::Silence);
+        let outputs = Destination::ALL.map(|d| routing.resolve(d, &signals()).to_clamped());
+        assert_eq!(outputs, [200, 300, 300, 0]);
+    }
+
+    #[test]
+    fn test_routing_conflicts() {
+        assert_eq!(Routing::default().conflicts().count(), 0);
+        // anything can go to the audio outputs
+        let routing = Routing::new([Source::Lfo, Source::Intensity, Source::Lfo, Source::Silence]);
+        assert_eq!(routing.conflicts().count(), 0);
+
+        let mut routing = Routing::default();
+        routing.set(Destination::Cv2, Source::Rain);
+        assert!(routing.conflicts().eq([Destination::Cv2]));
+        routing.set(Destination::Cv1, Source::RainRight);
+        assert!(routing.conflicts().eq([Destination::Cv1, Destination::Cv2]));
+    }
+
+    #[test]
+    fn test_routing_bytes_round_trip() {
+        let routing = Routing::new([
+            Source::Silence,
+            Source::Rain,
+            Source::Lfo,
+            Source::RainRight,
+        ]);
+        assert_eq!(Routing::from_bytes(routing.to_bytes()), Ok(routing));
+        assert_eq!(Routing::from_bytes([0, 1, 2, 5]), Err(Error::MissingRecord));
+    }
+}
@@ -0,0 +1,175 @@
+//! Automated intensity sequences
+//!
+//! [`StormSequence`] rolls a storm in when started: intensity sweeps from
+//! full light through medium to full heavy, holds, then optionally eases
+//! back down to light. The sweeps follow a [`FadeCurve`], and step once per
+//! tick, so the caller's usual smoothing keeps them click free.
+
+use crate::fade::{fade_between, FadeCurve};
+use crate::Sample;
+
+/// Part of a [`StormSequence`] being played
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SequenceStage {
+    /// Not running, the caller's own intensity applies
+    Idle,
+    /// Sweeping from light to heavy
+    Building,
+    /// At full heavy rain
+    Holding,
+    /// Sweeping back from heavy to light
+    Easing,
+}
+
+/// Light to heavy (and back) intensity sweep, timed in ticks
+pub struct StormSequence {
+    build_ticks: u32,
+    hold_ticks: u32,
+    /// None holds heavy rain until stopped
+    ease_ticks: Option<u32>,
+    curve: FadeCurve,
+    stage: SequenceStage,
+    /// Ticks into the current stage
+    step: u32,
+}
+
+impl StormSequence {
+    /// Sweep up over `build_ticks`, hold for `hold_ticks`, then sweep back
+    /// down over `ease_ticks`, or stay heavy if that's `None`
+    pub const fn new(
+        build_ticks: u32,
+        hold_ticks: u32,
+        ease_ticks: Option<u32>,
+        curve: FadeCurve,
+    ) -> Self {
+        StormSequence {
+            build_ticks,
+            hold_ticks,
+            ease_ticks,
+            curve,
+            stage: SequenceStage::Idle,
+            step: 0,
+        }
+    }
+
+    pub fn stage(&self) -> SequenceStage {
+        self.stage
+    }
+
+    /// Start (or restart) from full light rain
+    pub fn start(&mut self) {
+        self.stage = SequenceStage::Building;
+        self.step = 0;
+    }
+
+    pub fn stop(&mut self) {
+        self.stage = SequenceStage::Idle;
+    }
+
+    /// Advance one tick, returning the intensity while running
+    pub fn tick(&mut self) -> Option<Sample> {
+        let (light, heavy) = (Sample::from(Sample::MIN), Sample::from(Sample::MAX));
+        let intensity = match self.stage {
+            SequenceStage::Idle => return None,
+            SequenceStage::Building => {
+                fade_between(light, heavy, self.step, self.build_ticks, self.curve)
+            }
+            SequenceStage::Holding => heavy,
+            SequenceStage::Easing => {
+                let ease_ticks = self.ease_ticks.unwrap_or(0);
+                fade_between(heavy, light, self.step, ease_ticks, self.curve)
+            }
+        };
+        self.step = self.step.saturating_add(1);
+        self.advance();
+        Some(intensity)
+    }
+
+    /// Move on to the next stage once the current one is over
+    fn advance(&mut self) {
+        let (length, next) = match self.stage {
+            SequenceStage::Idle => return,
+            SequenceStage::Building => (self.build_ticks, SequenceStage::Holding),
+            SequenceStage::Holding => match self.ease_ticks {
+                Some(_) => (self.hold_ticks, SequenceStage::Easing),
+                None => return,
+            },
+            SequenceStage::Easing => (self.ease_ticks.unwrap_or(0), SequenceStage::Idle),
+        };
+        // the last tick of each sweep lands exactly on its end value
+        if self.step > length {
+            self.stage = next;
+            self.step = 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{SequenceStage, StormSequence};
+    use crate::fade::FadeCurve;
+    use crate::Sample;
+
+    /// Intensities for the first `ticks` ticks
+    fn run(sequence: &mut StormSequence, ticks: usize) -> Vec<Option<i32>> {
+        (0..ticks)
+            .map(|_| sequence.tick().map(|intensity| intensity.to_clamped()))
+            .collect()
+    }
+
+    #[test]
+    fn test_storm_sequence_timeline() {
+        let mut sequence = StormSequence::new(4, 2, Some(4), FadeCurve::Linear);
+        assert_eq!(sequence.stage(), SequenceStage::Idle);
+        assert_eq!(sequence.tick(), None);
+
+        sequence.start();
+        let values = run(&mut sequence, 13);
+        let expected = [
+            // light, through medium, to heavy over 4 ticks (rounding down)
+            Sample::MIN,
+            -1025,
+            -1,
+            1023,
+            Sample::MAX,
+            // holding 2 ticks
+            Sample::MAX,
+            Sample::MAX,
+            // and back down
+            1023,
+            -1,
+            -1025,
+            Sample::MIN,
+        ];
+        assert_eq!(values[..11], expected.map(Some));
+        // then done
+        assert_eq!(values[11..], [None, None]);
+        assert_eq!(sequence.stage(), SequenceStage::Idle);
+    }
+
+    #[test]
+    fn test_storm_sequence_holds_without_ease() {
+        let mut sequence = StormSequence::new(100, 10, None, FadeCurve::Exponential);
+        sequence.start();
+        let values = run(&mut sequence, 1000);
+        assert_eq!(values[0], Some(Sample::MIN));
+        // exponential builds slowly, then quickly
+        let quarter = values[25].unwrap() - Sample::MIN;
+        let three_quarters = values[75].unwrap() - Sample::MIN;
+        assert!(three_quarters - quarter > 2 * quarter, "{:?}", values);
+        // never falls on the way up, then stays heavy
+        for pair in values.windows(2) {
+            assert!(pair[1] >= pair[0], "{:?}", pair);
+        }
+        assert_eq!(values[100], Some(Sample::MAX));
+        assert_eq!(values[999], Some(Sample::MAX));
+        assert_eq!(sequence.stage(), SequenceStage::Holding);
+
+        // restarting goes back to light, stopping ends it
+        sequence.start();
+        assert_eq!(sequence.tick(), Some(Sample::from(Sample::MIN)));
+        sequence.stop();
+        assert_eq!(sequence.tick(), None);
+    }
+}
@@ -0,0 +1,54 @@
+//! Detecting ADC mux channels which haven't settled
+//!
+//! After switching the input mux, the ADC input takes a while to move to the
+//! new channel's voltage, longer for high impedance sources. Reading too
+//! early gives a value contaminated by the previous channel. Rather than
+//! always waiting long enough for the worst case, read twice: if the two
+//! agree the channel has settled, otherwise wait longer and read again.
+
+/// Result of comparing two reads of a channel
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Settling {
+    /// The reads agree, use this one
+    Settled(u16),
+    /// The reads disagree, still moving toward the new channel
+    Unsettled,
+}
+
+/// Compare two consecutive reads, `first` then `second`, of a channel
+///
+/// They agree when within `threshold` codes of each other, which should be
+/// a little above the ADC's noise. The later read is used, being closer to
+/// the settled value.
+pub const fn check_settled(first: u16, second: u16, threshold: u16) -> Settling {
+    if first.abs_diff(second) <= threshold {
+        Settling::Settled(second)
+    } else {
+        Settling::Unsettled
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{check_settled, Settling};
+
+    #[test]
+    fn test_stable_reads_settled() {
+        assert_eq!(check_settled(2048, 2048, 16), Settling::Settled(2048));
+        // noise within the threshold, either direction
+        assert_eq!(check_settled(2040, 2056, 16), Settling::Settled(2056));
+        assert_eq!(check_settled(4095, 4080, 16), Settling::Settled(4080));
+        assert_eq!(check_settled(0, 0, 0), Settling::Settled(0));
+    }
+
+    #[test]
+    fn test_unstable_reads_resettle() {
+        // still moving from the previous channel's voltage
+        assert_eq!(check_settled(3000, 2100, 16), Settling::Unsettled);
+        assert_eq!(check_settled(100, 117, 16), Settling::Unsettled);
+        assert_eq!(check_settled(4095, 0, 16), Settling::Unsettled);
+        // any difference without a threshold
+        assert_eq!(check_settled(1000, 1001, 0), Settling::Unsettled);
+    }
+}
@@ -0,0 +1,278 @@
+//! Smoothing with separate rise and fall rates
+//!
+//! [`crate::SampleUpdate`] smooths equally in both directions. Some controls
+//! feel more natural with a fast attack and a slow release: rain intensity
+//! should build quickly when a downpour starts, then trail off gradually.
+//! [`AsymmetricSlew`] is a one pole low pass filter which switches its time
+//! constant depending on whether the input is above or below its output.
+//!
+//! Inputs in different roles also want different smoothing, a CV setting a
+//! level can be smoothed heavily where a knob should stay responsive.
+//! [`ChannelSmoothing`] keeps a time constant for each of several channels.
+//!
+//! [`Slew`] limits the rate of change instead: it moves toward the target
+//! in equal steps, so it glides linearly, like a portamento, and gets there
+//! in a known number of ticks.
+
+use crate::fixed::{self, Fixed};
+use crate::Sample;
+
+/// Largest time constant shift, beyond that the output barely moves
+pub const MAX_SLEW_SHIFT: u8 = 15;
+
+/// One pole smoother with time constants of `2^rise_shift` ticks upward and
+/// `2^fall_shift` ticks downward
+pub struct AsymmetricSlew {
+    value: Fixed,
+    rise_shift: u8,
+    fall_shift: u8,
+}
+
+impl AsymmetricSlew {
+    /// Shifts are limited to [`MAX_SLEW_SHIFT`], 0 follows the input
+    /// immediately
+    pub fn new(initial: Sample, rise_shift: u8, fall_shift: u8) -> Self {
+        AsymmetricSlew {
+            value: fixed::from_sample(initial),
+            rise_shift: rise_shift.min(MAX_SLEW_SHIFT),
+            fall_shift: fall_shift.min(MAX_SLEW_SHIFT),
+        }
+    }
+
+    pub fn set_shifts(&mut self, rise_shift: u8, fall_shift: u8) {
+        self.rise_shift = rise_shift.min(MAX_SLEW_SHIFT);
+        self.fall_shift = fall_shift.min(MAX_SLEW_SHIFT);
+    }
+
+    /// Move toward `target` by one tick, returning the smoothed value
+    pub fn tick(&mut self, target: Sample) -> Sample {
+        let difference = fixed::from_sample(target) - self.value;
+        let shift = if difference > 0 {
+            self.rise_shift
+        } else {
+            self.fall_shift
+        };
+        self.value += difference >> shift;
+        self.current()
+    }
+
+    /// Smoothed value, rounded to the nearest step
+    pub fn current(&self) -> Sample {
+        fixed::to_sample(self.value + fixed::ONE / 2)
+    }
+}
+
+/// Slew limiter, moving at most `rise_per_tick` up and `fall_per_tick` down
+/// each tick
+pub struct Slew {
+    value: i32,
+    rise_per_tick: i32,
+    fall_per_tick: i32,
+}
+
+impl Slew {
+    /// Slew limiter starting at [`Sample::CENTER`]
+    ///
+    /// Rates below 1 are treated as 1, so the target is always reached.
+    pub fn new(rise_per_tick: i32, fall_per_tick: i32) -> Self {
+        Slew {
+            value: Sample::CENTER,
+            rise_per_tick: rise_per_tick.max(1),
+            fall_per_tick: fall_per_tick.max(1),
+        }
+    }
+
+    /// Move toward `target` by one tick, returning the limited value
+    pub fn process(&mut self, target: Sample) -> Sample {
+        let target = target.to_clamped();
+        self.value = target.clamp(
+            self.value - self.fall_per_tick,
+            self.value + self.rise_per_tick,
+        );
+        self.current()
+    }
+
+    pub fn current(&self) -> Sample {
+        Sample::from(self.value)
+    }
+}
+
+/// Smoothing for `N` channels, each with its own time constant
+pub struct ChannelSmoothing<const N: usize> {
+    channels: [AsymmetricSlew; N],
+    shifts: [u8; N],
+}
+
+impl<const N: usize> ChannelSmoothing<N> {
+    /// Channels starting at `initial`, smoothed over `2^shift` ticks each
+    pub fn new(initial: Sample, shifts: [u8; N]) -> Self {
+        ChannelSmoothing {
+            channels: shifts.map(|shift| AsymmetricSlew::new(initial, shift, shift)),
+            shifts,
+        }
+    }
+
+    pub fn set_shifts(&mut self, shifts: [u8; N]) {
+        for (channel, shift) in self.channels.iter_mut().zip(shifts) {
+            channel.set_shifts(shift, shift);
+        }
+        self.shifts = shifts;
+    }
+
+    /// Smooth the next `value` of `channel`
+    ///
+    /// Shift 0 returns `value` itself, so unsmoothed channels keep its
+    /// inversion flag.
+    pub fn tick(&mut self, channel: usize, value: Sample) -> Sample {
+        let smoothed = self.channels[channel].tick(value);
+        match self.shifts[channel] {
+            0 => value,
+            _ => smoothed,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{AsymmetricSlew, ChannelSmoothing, Slew};
+    use crate::Sample;
+
+    /// Ticks for `slew` to get at least 63% (one time constant) of the way
+    /// from its current value to `target`
+    fn ticks_to_time_constant(slew: &mut AsymmetricSlew, target: i32) -> usize {
+        let start = slew.current().to_clamped();
+        let threshold = (target - start) * 63 / 100;
+        (1..10_000)
+            .find(|_| {
+                (slew.tick(Sample::from(target)).to_clamped() - start).abs() >= threshold.abs()
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn test_slew_rise_and_fall_rates() {
+        // fast rise (4 ticks), slow fall (64 ticks)
+        let mut slew = AsymmetricSlew::new(Sample::from(0_i32), 2, 6);
+        assert_eq!(slew.tick(Sample::from(1000_i32)).to_clamped(), 250);
+        assert_eq!(slew.tick(Sample::from(1000_i32)).to_clamped(), 438);
+        // first step down uses the fall rate
+        assert_eq!(slew.tick(Sample::from(-1000_i32)).to_clamped(), 415);
+
+        let mut slew = AsymmetricSlew::new(Sample::from(-1000_i32), 2, 6);
+        let rise = ticks_to_time_constant(&mut slew, 1000);
+        for _ in 0..100 {
+            slew.tick(Sample::from(1000_i32));
+        }
+        assert_eq!(slew.current().to_clamped(), 1000);
+        let fall = ticks_to_time_constant(&mut slew, -1000);
+        assert!((3..=5).contains(&rise), "{}", rise);
+        assert!((60..=68).contains(&fall), "{}", fall);
+
+        // swapped, slow rise and fast fall
+        slew.set_shifts(6, 2);
+        for _ in 0..1000 {
+            slew.tick(Sample::from(-1000_i32));
+        }
+        assert_eq!(ticks_to_time_constant(&mut slew, 1000), fall);
+        assert_eq!(ticks_to_time_constant(&mut slew, -1000), rise);
+    }
+
+    #[test]
+    fn test_slew_settles_on_target() {
+        for (rise, fall) in [(0, 0), (3, 3), (1, 15), (15, 1)] {
+            let mut slew = AsymmetricSlew::new(Sample::from(0_i32), rise, fall);
+            for target in [Sample::MAX, Sample::MIN, 1, -1, 0] {
+                for _ in 0..400_000 {
+                    slew.tick(Sample::from(target));
+                }
+                assert_eq!(slew.current().to_clamped(), target, "{} {}", rise, fall);
+            }
+        }
+        // shift 0 is no smoothing
+        let mut slew = AsymmetricSlew::new(Sample::from(0_i32), 0, 0);
+        assert_eq!(slew.tick(Sample::from(-777_i32)).to_clamped(), -777);
+    }
+
+    #[test]
+    fn test_channel_smoothing_independent() {
+        let mut smoothing = ChannelSmoothing::new(Sample::from(0_i32), [0, 2, 5]);
+        // same step into every channel
+        let step = Sample::from(1024_i32);
+        let first = [0, 1, 2].map(|channel| smoothing.tick(channel, step).to_clamped());
+        assert_eq!(first, [1024, 256, 32]);
+        // each matches a lone smoother with its shift
+        let mut alone =
+            [0, 2, 5].map(|shift| AsymmetricSlew::new(Sample::from(0_i32), shift, shift));
+        for slew in alone.iter_mut() {
+            slew.tick(step);
+        }
+        for n in 0..200 {
+            let value = Sample::from((n * 37 % 400) - 200);
+            for (channel, slew) in alone.iter_mut().enumerate() {
+                assert_eq!(
+                    smoothing.tick(channel, value),
+                    slew.tick(value),
+                    "{} {}",
+                    channel,
+                    n
+                );
+            }
+        }
+
+        // changing one channel leaves the others alone
+        smoothing.set_shifts([0, 2, 0]);
+        let value = Sample::from(-1500_i32);
+        assert_eq!(smoothing.tick(2, value), value);
+        assert_eq!(smoothing.tick(1, value), alone[1].tick(value));
+
+        // unsmoothed channels pass the value itself through
+        let inverted = Sample::new(300, true);
+        assert_eq!(smoothing.tick(0, inverted), inverted);
+    }
+
+    #[test]
+    fn test_slew_limits_rate() {
+        // rises 10 per tick, falls 25
+        let mut slew = Slew::new(10, 25);
+        let up = Sample::from(1000_i32);
+        for tick in 1..=100 {
+            assert_eq!(slew.process(up).to_clamped(), tick * 10);
+        }
+        for tick in 1..=40 {
+            assert_eq!(
+                slew.process(Sample::from(0_i32)).to_clamped(),
+                1000 - tick * 25
+            );
+        }
+        // a step smaller than the rate gets there in one tick
+        assert_eq!(slew.process(Sample::from(-7_i32)).to_clamped(), -7);
+        // negative values fall at the fall rate too
+        assert_eq!(slew.process(Sample::MIN.into()).to_clamped(), -32);
+
+        // no rate is the slowest rate, not stuck
+        let mut slew = Slew::new(0, -5);
+        assert_eq!(slew.process(Sample::from(3_i32)).to_clamped(), 1);
+        assert_eq!(slew.process(Sample::from(-3_i32)).to_clamped(), 0);
+    }
+
+    #[test]
+    fn test_slew_holds_target() {
+        let mut slew = Slew::new(300, 300);
+        for target in [Sample::MAX, Sample::MIN, 5, 0] {
+            let target = Sample::from(target);
+            for _ in 0..20 {
+                slew.process(target);
+            }
+            for _ in 0..100 {
+                assert_eq!(slew.process(target), target);
+                assert_eq!(slew.current(), target);
+            }
+        }
+        // out of range targets stop at the ends
+        let loud = Sample::from(Sample::MAX) + Sample::from(Sample::MAX);
+        for _ in 0..100 {
+            slew.process(loud);
+        }
+        assert_eq!(slew.current().to_clamped(), Sample::MAX);
+    }
+}
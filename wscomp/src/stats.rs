@@ -0,0 +1,318 @@
+//! Loop timing statistics
+//!
+//! Used to check how much of the time budget the audio path uses: how many
+//! iterations run per second, and the longest a single iteration took.
+//! [`RateDrift`] averages the rate over the whole run, to measure how far it
+//! is from nominal.
+//!
+//! A ticker's period is a whole number of timer ticks, so it can only
+//! approximate most rates. [`ticker_period()`] and [`ticker_millihz()`] give
+//! the rate it will really run at. A PWM slice's wrap, with its fractional
+//! divider, gets much closer ([`PwmWrap`]).
+
+/// Counts loop iterations and tracks the longest one over a window
+pub struct LoopStats {
+    count: u32,
+    max_ticks: u32,
+    window: u32,
+}
+
+impl LoopStats {
+    /// New stats, restarting the maximum every `window` iterations
+    pub const fn new(window: u32) -> Self {
+        LoopStats {
+            count: 0,
+            max_ticks: 0,
+            window,
+        }
+    }
+
+    /// Record one iteration which took `ticks`
+    pub fn record(&mut self, ticks: u32) {
+        self.count = self.count.wrapping_add(1);
+        if self.window > 0 && self.count.is_multiple_of(self.window) {
+            // start of a new window
+            self.max_ticks = ticks;
+        } else {
+            self.max_ticks = self.max_ticks.max(ticks);
+        }
+    }
+
+    /// Iterations recorded, wraps around at `u32::MAX`
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// Longest iteration in the current window
+    pub fn max_ticks(&self) -> u32 {
+        self.max_ticks
+    }
+}
+
+/// Iterations per second between two readings of [`LoopStats::count()`]
+/// taken `elapsed_ms` apart, allowing for the count wrapping around
+pub fn rate_per_second(previous: u32, current: u32, elapsed_ms: u32) -> u32 {
+    if elapsed_ms == 0 {
+        return 0;
+    }
+    let count = u64::from(current.wrapping_sub(previous));
+    (count * 1000 / u64::from(elapsed_ms)) as u32
+}
+
+/// Timer ticks per period of a ticker at `rate_hz`, from a `tick_hz` timer
+///
+/// Rounded to the nearest tick like embassy's `Duration::from_hz()`, and at
+/// least one tick. A rate of 0 gets the longest period.
+pub const fn ticker_period(rate_hz: u32, tick_hz: u32) -> u32 {
+    if rate_hz == 0 {
+        return u32::MAX;
+    }
+    let period = (tick_hz as u64 + rate_hz as u64 / 2) / rate_hz as u64;
+    if period == 0 {
+        1
+    } else {
+        period as u32
+    }
+}
+
+/// Rate of a ticker with `period` ticks of a `tick_hz` timer, in
+/// thousandths of a Hz
+pub const fn ticker_millihz(period: u32, tick_hz: u32) -> u64 {
+    if period == 0 {
+        return 0;
+    }
+    tick_hz as u64 * 1000 / period as u64
+}
+
+/// Divider and wrap of a PWM slice counting at a rate
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PwmWrap {
+    /// Clock divider in 16ths, 16 to 4095 like the RP2040's 8.4 divider
+    pub divider: u16,
+    /// Counter wraps after `top`, so each period is `top + 1` counts
+    pub top: u16,
+}
+
+impl PwmWrap {
+    /// Divider and top closest to wrapping at `rate_hz` from `clock_hz`,
+    /// preferring the smallest divider
+    ///
+    /// `None` for rates above the clock, or too slow even for the largest
+    /// divider and top.
+    pub fn for_rate(rate_hz: u32, clock_hz: u32) -> Option<Self> {
+        if rate_hz == 0 || rate_hz > clock_hz {
+            return None;
+        }
+        // period in 16ths of a clock cycle, divider * (top + 1)
+        let (rate, clock) = (u64::from(rate_hz), u64::from(clock_hz) * 16);
+        let mut best: Option<(PwmWrap, u64, u64)> = None;
+        for divider in 16..=4095_u64 {
+            let counts = (clock + rate * divider / 2) / (rate * divider);
+            if counts == 0 || counts > 1 << 16 {
+                continue;
+            }
+            let period = divider * counts;
+            // error of the period, compared cross multiplied to the best
+            let error = (rate * period).abs_diff(clock);
+            let better = match best {
+                Some((_, best_period, best_error)) => {
+                    u128::from(error) * u128::from(best_period)
+                        < u128::from(best_error) * u128::from(period)
+                }
+                None => true,
+            };
+            if better {
+                let wrap = PwmWrap {
+                    divider: divider as u16,
+                    top: (counts - 1) as u16,
+                };
+                best = Some((wrap, period, error));
+                if error == 0 {
+                    break;
+                }
+            }
+        }
+        best.map(|(wrap, _, _)| wrap)
+    }
+
+    /// Rate it wraps at from a `clock_hz` clock, in thousandths of a Hz
+    pub fn millihz(&self, clock_hz: u32) -> u64 {
+        let period = u64::from(self.divider) * (u64::from(self.top) + 1);
+        u64::from(clock_hz) * 16_000 / period
+    }
+}
+
+/// Long run average of a loop's rate, compared to its nominal rate
+pub struct RateDrift {
+    nominal_hz: u32,
+    count: u64,
+    elapsed_micros: u64,
+}
+
+impl RateDrift {
+    pub const fn new(nominal_hz: u32) -> Self {
+        RateDrift {
+            nominal_hz,
+            count: 0,
+            elapsed_micros: 0,
+        }
+    }
+
+    /// Add the iterations between two readings of [`LoopStats::count()`]
+    /// taken `elapsed_micros` apart, allowing for the count wrapping around
+    pub fn record(&mut self, previous: u32, current: u32, elapsed_micros: u64) {
+        self.count += u64::from(current.wrapping_sub(previous));
+        self.elapsed_micros += elapsed_micros;
+    }
+
+    /// Average rate so far, in thousandths of a Hz
+    pub fn average_millihz(&self) -> Option<u64> {
+        if self.elapsed_micros == 0 {
+            return None;
+        }
+        let millihz = u128::from(self.count) * 1_000_000_000 / u128::from(self.elapsed_micros);
+        Some(millihz.min(u128::from(u64::MAX)) as u64)
+    }
+
+    /// Difference of the average rate from nominal, in parts per million,
+    /// negative when slow
+    pub fn drift_ppm(&self) -> Option<i64> {
+        if self.elapsed_micros == 0 || self.nominal_hz == 0 {
+            return None;
+        }
+        // count / (elapsed * nominal) - 1, scaled to ppm
+        let expected = i128::from(self.elapsed_micros) * i128::from(self.nominal_hz);
+        let actual = i128::from(self.count) * 1_000_000;
+        let ppm = (actual - expected) * 1_000_000 / expected;
+        Some(ppm.clamp(i128::from(i64::MIN), i128::from(i64::MAX)) as i64)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{rate_per_second, ticker_millihz, ticker_period, LoopStats, PwmWrap, RateDrift};
+
+    #[test]
+    fn test_loop_stats_max_per_window() {
+        let mut stats = LoopStats::new(4);
+        for ticks in [10, 30, 20] {
+            stats.record(ticks);
+        }
+        assert_eq!(stats.count(), 3);
+        assert_eq!(stats.max_ticks(), 30);
+        // fourth iteration starts a new window
+        stats.record(5);
+        assert_eq!(stats.max_ticks(), 5);
+        stats.record(8);
+        assert_eq!(stats.max_ticks(), 8);
+        assert_eq!(stats.count(), 5);
+    }
+
+    #[test]
+    fn test_rate_per_second() {
+        assert_eq!(rate_per_second(0, 48_000, 1000), 48_000);
+        assert_eq!(rate_per_second(1000, 251_000, 500), 500_000);
+        // count wrapped around since the last reading
+        assert_eq!(rate_per_second(u32::MAX - 99, 400, 1000), 500);
+        assert_eq!(rate_per_second(5, 10, 0), 0);
+    }
+
+    #[test]
+    fn test_loop_stats_count_wraps() {
+        let mut stats = LoopStats::new(0);
+        stats.count = u32::MAX;
+        stats.record(7);
+        assert_eq!(stats.count(), 0);
+        assert_eq!(stats.max_ticks(), 7);
+    }
+
+    #[test]
+    fn test_ticker_period() {
+        // 48kHz from embassy's 1MHz tick is 20.83 ticks, rounded up to 21
+        assert_eq!(ticker_period(48_000, 1_000_000), 21);
+        assert_eq!(ticker_millihz(21, 1_000_000), 47_619_047);
+        // 44.1kHz is 22.68, and exact divisors are exact
+        assert_eq!(ticker_period(44_100, 1_000_000), 23);
+        assert_eq!(ticker_period(50_000, 1_000_000), 20);
+        assert_eq!(ticker_millihz(20, 1_000_000), 50_000_000);
+        // halfway rounds up, just below rounds down
+        assert_eq!(ticker_period(400_000, 1_000_000), 3);
+        assert_eq!(ticker_period(400_001, 1_000_000), 2);
+        // never faster than the timer, nor a zero period
+        assert_eq!(ticker_period(2_000_000, 1_000_000), 1);
+        assert_eq!(ticker_period(0, 1_000_000), u32::MAX);
+        assert_eq!(ticker_millihz(0, 1_000_000), 0);
+        // the achieved rate is always the closest a whole period gets
+        for rate in [8_000, 22_050, 32_000, 44_100, 48_000, 96_000] {
+            let period = ticker_period(rate, 1_000_000);
+            let error =
+                |period: u32| (ticker_millihz(period, 1_000_000) as i64 - rate as i64 * 1000).abs();
+            assert!(error(period) <= error(period - 1), "{}", rate);
+            assert!(error(period) <= error(period + 1), "{}", rate);
+        }
+    }
+
+    #[test]
+    fn test_pwm_wrap_for_rate() {
+        // 48kHz from 125MHz is 2604.17 cycles, 41667 16ths at a 17/16 divider
+        let wrap = PwmWrap::for_rate(48_000, 125_000_000).unwrap();
+        assert_eq!(
+            wrap,
+            PwmWrap {
+                divider: 17,
+                top: 2450
+            }
+        );
+        // 8ppm off, where the 1MHz ticker is nearly 1% off
+        assert_eq!(wrap.millihz(125_000_000), 47_999_616);
+        // exact where the clock divides evenly, with the smallest divider
+        let wrap = PwmWrap::for_rate(50_000, 125_000_000).unwrap();
+        assert_eq!((wrap.divider, wrap.top), (16, 2499));
+        assert_eq!(wrap.millihz(125_000_000), 50_000_000);
+        // slow rates need a bigger divider
+        let wrap = PwmWrap::for_rate(100, 125_000_000).unwrap();
+        assert!(wrap.divider > 16);
+        assert_eq!(wrap.millihz(125_000_000), 100_000);
+        // within half a 16th of a cycle per period
+        for rate in [8_000, 22_050, 44_100, 96_000] {
+            let millihz = PwmWrap::for_rate(rate, 133_000_000)
+                .unwrap()
+                .millihz(133_000_000);
+            let ppm = millihz.abs_diff(u64::from(rate) * 1000) * 1000 / u64::from(rate);
+            assert!(ppm < 25, "{} {}", rate, ppm);
+        }
+        assert_eq!(PwmWrap::for_rate(0, 125_000_000), None);
+        assert_eq!(PwmWrap::for_rate(200_000_000, 125_000_000), None);
+        assert_eq!(PwmWrap::for_rate(1, 125_000_000), None);
+    }
+
+    #[test]
+    fn test_rate_drift_ppm() {
+        let mut drift = RateDrift::new(48_000);
+        assert_eq!(drift.drift_ppm(), None);
+        assert_eq!(drift.average_millihz(), None);
+
+        // exactly nominal
+        drift.record(0, 48_000, 1_000_000);
+        assert_eq!(drift.drift_ppm(), Some(0));
+
+        // the ~47,630Hz measured on the Computer, over an hour of readings
+        let mut drift = RateDrift::new(48_000);
+        let mut count = 0_u32;
+        for _ in 0..3600 {
+            drift.record(count, count.wrapping_add(47_630), 1_000_000);
+            count = count.wrapping_add(47_630);
+        }
+        assert_eq!(drift.average_millihz(), Some(47_630_000));
+        // (47630 - 48000) / 48000
+        assert_eq!(drift.drift_ppm(), Some(-7708));
+
+        // uneven readings average over the total, including wrapping counts
+        let mut drift = RateDrift::new(48_000);
+        drift.record(u32::MAX - 999, 47_000, 1_000_000);
+        drift.record(47_000, 47_000 + 24_024, 500_000);
+        assert_eq!(drift.average_millihz(), Some(48_016_000));
+        assert_eq!(drift.drift_ppm(), Some(333));
+    }
+}
@@ -0,0 +1,113 @@
+//! Stereo from a mono source
+//!
+//! [`Decorrelator`] scrambles the phase of a signal without changing its
+//! frequency balance, so a copy of it sounds like a different (but similar)
+//! recording. [`widen()`] blends between the direct signal and that copy for
+//! the second channel, from mono to fully decorrelated stereo.
+
+use crate::delay::DelayLine;
+use crate::Sample;
+
+/// Schroeder all-pass filter with a delay of `N` samples and a gain of 1/2
+///
+/// Longer delays (several milliseconds) decorrelate more, but start to sound
+/// like an echo on sharp transients. A single stage still passes half of the
+/// signal straight through (inverted), chain two or more stages with
+/// different delays to decorrelate further.
+pub struct Decorrelator<const N: usize> {
+    /// Filter state, within twice the input range so it fits an `i16`
+    delay: DelayLine<N>,
+}
+
+impl<const N: usize> Decorrelator<N> {
+    pub const fn new() -> Self {
+        Decorrelator {
+            delay: DelayLine::new(),
+        }
+    }
+
+    pub fn process(&mut self, input: Sample) -> Sample {
+        if N == 0 {
+            // no delay, nothing to scramble
+            return input;
+        }
+        let delayed = i32::from(self.delay.tap(N - 1));
+        let state = input.to_clamped() + delayed / 2;
+        self.delay.push(state as i16);
+        Sample::from(delayed - state / 2)
+    }
+}
+
+impl<const N: usize> Default for Decorrelator<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Left and right channels for a stereo `width`, from 0 to [`Sample::MAX`]
+///
+/// The left channel is always `direct`. The right channel crossfades from
+/// `direct` at width 0 (mono) to `decorrelated` at full width.
+pub fn widen(direct: Sample, decorrelated: Sample, width: Sample) -> (Sample, Sample) {
+    let width = Sample::from(width.to_clamped().max(0));
+    let right = direct.scale_inverted(width) + decorrelated.scale(width);
+    (direct, right)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{widen, Decorrelator};
+    use crate::noise::Noise;
+    use crate::Sample;
+
+    #[test]
+    fn test_widen_zero_width_is_mono() {
+        let mut noise = Noise::new(3);
+        let mut decorrelator = Decorrelator::<113>::new();
+        for _ in 0..1000 {
+            let direct = noise.next_sample();
+            let (left, right) = widen(direct, decorrelator.process(direct), Sample::from(0_i32));
+            assert_eq!(left.to_clamped(), right.to_clamped());
+            // negative widths are treated as mono too
+            let (left, right) = widen(direct, Sample::from(0_i32), Sample::from(-500_i32));
+            assert_eq!(left.to_clamped(), right.to_clamped());
+        }
+    }
+
+    #[test]
+    fn test_widen_full_width_is_decorrelated() {
+        let mut noise = Noise::new(5);
+        let mut first = Decorrelator::<113>::new();
+        let mut second = Decorrelator::<71>::new();
+        let full = Sample::from(Sample::MAX);
+        let mut correlation = 0_i64;
+        let mut power = 0_i64;
+        for _ in 0..20_000 {
+            let direct = noise.next_sample().scale(Sample::from(1000_i32));
+            let decorrelated = second.process(first.process(direct));
+            let (left, right) = widen(direct, decorrelated, full);
+            assert_eq!(left.to_clamped(), direct.to_clamped());
+            assert_eq!(right.to_clamped(), decorrelated.to_clamped());
+            correlation += i64::from(left.to_clamped()) * i64::from(right.to_clamped());
+            power += i64::from(left.to_clamped()).pow(2);
+        }
+        // channels are mostly unrelated, each stage passes -1/2 of the signal
+        // straight through, so 1/4 correlation remains
+        let ratio = correlation as f64 / power as f64;
+        assert!(ratio.abs() < 0.3, "{}", ratio);
+    }
+
+    #[test]
+    fn test_decorrelator_keeps_level() {
+        // all-pass: an impulse's energy is spread out in time, not lost
+        let mut decorrelator = Decorrelator::<7>::new();
+        let mut energy = 0_i64;
+        for n in 0..200 {
+            let input = if n == 0 { 1024 } else { 0 };
+            let output = decorrelator.process(Sample::from(input)).to_clamped();
+            energy += i64::from(output).pow(2);
+        }
+        let expected = 1024_i64.pow(2);
+        assert!((energy - expected).abs() < expected / 100, "{}", energy);
+    }
+}
@@ -0,0 +1,285 @@
+//! Power-loss tolerant records for flash storage
+//!
+//! Settings are stored in two flash slots, written alternately (ping-pong).
+//! Each record has a sequence number and a CRC, so if power is lost part way
+//! through writing one slot, the other slot still holds the last complete
+//! record and [`newest_slot()`] picks it.
+//!
+//! ```text
+//! magic: [u8; 4], sequence: u32, length: u16, payload: [u8; length], crc: u32
+//! ```
+//!
+//! All integers are little endian. The CRC covers everything before it.
+//!
+//! Flash sectors survive a limited number of erases, around 100,000.
+//! [`SaveThrottle`] limits how often state which changes during play is
+//! saved.
+
+use crate::error::Error;
+
+/// Bytes added around the payload by a record
+pub const RECORD_OVERHEAD: usize = 4 + 4 + 2 + 4;
+
+/// CRC-32 (IEEE 802.3, as used by zip & png), bitwise to avoid a lookup table
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffff_u32;
+    for &byte in bytes {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+/// Write a record into `out`, returning the number of bytes used
+///
+/// Fails with [`Error::BufferTooSmall`] if `out` can't hold the record, or
+/// the payload is too long for the length field.
+pub fn encode_record(
+    magic: [u8; 4],
+    sequence: u32,
+    payload: &[u8],
+    out: &mut [u8],
+) -> Result<usize, Error> {
+    let length = u16::try_from(payload.len()).map_err(|_| Error::BufferTooSmall)?;
+    let total = payload.len() + RECORD_OVERHEAD;
+    if out.len() < total {
+        return Err(Error::BufferTooSmall);
+    }
+    out[0..4].copy_from_slice(&magic);
+    out[4..8].copy_from_slice(&sequence.to_le_bytes());
+    out[8..10].copy_from_slice(&length.to_le_bytes());
+    out[10..10 + payload.len()].copy_from_slice(payload);
+    let crc = crc32(&out[..total - 4]);
+    out[total - 4..total].copy_from_slice(&crc.to_le_bytes());
+    Ok(total)
+}
+
+/// Read a record from the start of `bytes`, returning sequence and payload
+///
+/// Fails with [`Error::MissingRecord`] for a wrong magic or erased flash,
+/// [`Error::BadCrc`] for a torn or corrupt record, and
+/// [`Error::BufferTooSmall`] if the record is longer than `bytes`.
+pub fn decode_record(magic: [u8; 4], bytes: &[u8]) -> Result<(u32, &[u8]), Error> {
+    if bytes.len() < RECORD_OVERHEAD || bytes[0..4] != magic {
+        return Err(Error::MissingRecord);
+    }
+    let sequence = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+    let length = usize::from(u16::from_le_bytes([bytes[8], bytes[9]]));
+    let total = length + RECORD_OVERHEAD;
+    let crc_bytes = bytes.get(total - 4..total).ok_or(Error::BufferTooSmall)?;
+    let crc = u32::from_le_bytes([crc_bytes[0], crc_bytes[1], crc_bytes[2], crc_bytes[3]]);
+    if crc != crc32(&bytes[..total - 4]) {
+        return Err(Error::BadCrc);
+    }
+    Ok((sequence, &bytes[10..10 + length]))
+}
+
+/// Index of the slot holding the newest valid record, given each slot's
+/// sequence number (`None` for slots without a valid record)
+///
+/// Sequence numbers are compared with wrapping, so counting past `u32::MAX`
+/// keeps working.
+pub fn newest_slot(sequences: [Option<u32>; 2]) -> Option<usize> {
+    match sequences {
+        [None, None] => None,
+        [Some(_), None] => Some(0),
+        [None, Some(_)] => Some(1),
+        [Some(a), Some(b)] => {
+            if (b.wrapping_sub(a) as i32) > 0 {
+                Some(1)
+            } else {
+                Some(0)
+            }
+        }
+    }
+}
+
+/// Decides when to save state which changes during play
+///
+/// A save happens once the state has stopped changing for `settle_ticks`,
+/// so a knob turn is saved once rather than at every step, and at most once
+/// every `min_interval_ticks`, including after startup.
+pub struct SaveThrottle {
+    settle_ticks: u32,
+    min_interval_ticks: u32,
+    since_change: u32,
+    since_save: u32,
+    /// State differs from what was last saved
+    pending: bool,
+}
+
+impl SaveThrottle {
+    pub const fn new(settle_ticks: u32, min_interval_ticks: u32) -> Self {
+        SaveThrottle {
+            settle_ticks,
+            min_interval_ticks,
+            since_change: 0,
+            since_save: 0,
+            pending: false,
+        }
+    }
+
+    /// The state no longer matches what was saved
+    pub fn changed(&mut self) {
+        self.pending = true;
+        self.since_change = 0;
+    }
+
+    /// Advance one tick, returning true when the state should be saved now
+    pub fn tick(&mut self) -> bool {
+        self.since_change = self.since_change.saturating_add(1);
+        self.since_save = self.since_save.saturating_add(1);
+        let due = self.pending
+            && self.since_change >= self.settle_ticks
+            && self.since_save >= self.min_interval_ticks;
+        if due {
+            self.pending = false;
+            self.since_save = 0;
+        }
+        due
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{crc32, decode_record, encode_record, newest_slot, SaveThrottle, RECORD_OVERHEAD};
+    use crate::error::Error;
+
+    const MAGIC: [u8; 4] = *b"TEST";
+
+    fn slot_with(sequence: u32, payload: &[u8]) -> [u8; 64] {
+        // unused flash reads as erased (0xff)
+        let mut slot = [0xff_u8; 64];
+        encode_record(MAGIC, sequence, payload, &mut slot).unwrap();
+        slot
+    }
+
+    fn sequence_of(slot: &[u8]) -> Option<u32> {
+        decode_record(MAGIC, slot)
+            .ok()
+            .map(|(sequence, _)| sequence)
+    }
+
+    #[test]
+    fn test_crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn test_record_round_trip() {
+        let slot = slot_with(7, b"settings");
+        assert_eq!(decode_record(MAGIC, &slot), Ok((7, &b"settings"[..])));
+        assert_eq!(decode_record(*b"NOPE", &slot), Err(Error::MissingRecord));
+
+        let mut small = [0_u8; RECORD_OVERHEAD + 2];
+        assert_eq!(
+            encode_record(MAGIC, 1, b"abc", &mut small),
+            Err(Error::BufferTooSmall)
+        );
+        assert_eq!(encode_record(MAGIC, 1, b"ab", &mut small), Ok(small.len()));
+        // length field claims more than was read
+        assert_eq!(
+            decode_record(MAGIC, &slot[..RECORD_OVERHEAD + 7]),
+            Err(Error::BufferTooSmall)
+        );
+    }
+
+    #[test]
+    fn test_record_rejects_erased_and_corrupt() {
+        assert_eq!(decode_record(MAGIC, &[0xff; 64]), Err(Error::MissingRecord));
+        let mut slot = slot_with(7, b"settings");
+        slot[12] ^= 0x01;
+        assert_eq!(decode_record(MAGIC, &slot), Err(Error::BadCrc));
+    }
+
+    #[test]
+    fn test_torn_write_recovers_previous_settings() {
+        let old = slot_with(5, b"old settings");
+        let new = slot_with(6, b"new settings");
+
+        // power lost part way through writing the new record: the rest of
+        // the slot is still erased
+        for written in 0..new.len() {
+            let mut torn = [0xff_u8; 64];
+            torn[..written].copy_from_slice(&new[..written]);
+            if written >= b"new settings".len() + RECORD_OVERHEAD {
+                // whole record made it to flash
+                continue;
+            }
+            let slots = [&old[..], &torn[..]];
+            let newest = newest_slot([sequence_of(slots[0]), sequence_of(slots[1])]).unwrap();
+            assert_eq!(newest, 0, "bytes written: {}", written);
+            assert_eq!(
+                decode_record(MAGIC, slots[newest]).unwrap().1,
+                b"old settings"
+            );
+        }
+
+        // fully written new record wins
+        let newest = newest_slot([sequence_of(&old), sequence_of(&new)]);
+        assert_eq!(newest, Some(1));
+    }
+
+    #[test]
+    fn test_newest_slot() {
+        assert_eq!(newest_slot([None, None]), None);
+        assert_eq!(newest_slot([Some(3), None]), Some(0));
+        assert_eq!(newest_slot([None, Some(3)]), Some(1));
+        assert_eq!(newest_slot([Some(3), Some(4)]), Some(1));
+        assert_eq!(newest_slot([Some(9), Some(4)]), Some(0));
+        // sequence wrapped around
+        assert_eq!(newest_slot([Some(u32::MAX), Some(0)]), Some(1));
+        assert_eq!(newest_slot([Some(1), Some(u32::MAX)]), Some(0));
+    }
+
+    /// Ticks until `throttle` asks for a save, up to `limit`
+    fn ticks_to_save(throttle: &mut SaveThrottle, limit: u32) -> Option<u32> {
+        (1..=limit).find(|_| throttle.tick())
+    }
+
+    #[test]
+    fn test_save_throttle_waits_for_settle() {
+        let mut throttle = SaveThrottle::new(10, 0);
+        // nothing to save
+        assert_eq!(ticks_to_save(&mut throttle, 1000), None);
+        throttle.changed();
+        assert_eq!(ticks_to_save(&mut throttle, 1000), Some(10));
+        // saved, nothing more until the next change
+        assert_eq!(ticks_to_save(&mut throttle, 1000), None);
+
+        // changing every few ticks (a knob being turned) keeps putting it off
+        throttle.changed();
+        for _ in 0..100 {
+            assert_eq!(ticks_to_save(&mut throttle, 5), None);
+            throttle.changed();
+        }
+        assert_eq!(ticks_to_save(&mut throttle, 1000), Some(10));
+    }
+
+    #[test]
+    fn test_save_throttle_limits_flash_wear() {
+        let (settle, interval) = (10, 1000);
+        let mut throttle = SaveThrottle::new(settle, interval);
+        // no save soon after startup, even when settled
+        throttle.changed();
+        assert_eq!(ticks_to_save(&mut throttle, 2000), Some(interval));
+
+        // constant changes, settling just long enough each time: one save
+        // per interval at most
+        let mut saves = 0;
+        for tick in 0..100_000 {
+            if tick % (settle + 1) == 0 {
+                throttle.changed();
+            }
+            if throttle.tick() {
+                saves += 1;
+            }
+        }
+        assert!(saves <= 100_000 / interval);
+        assert!(saves >= 100_000 / interval - 1, "{}", saves);
+    }
+}
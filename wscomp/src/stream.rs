@@ -0,0 +1,464 @@
+//! Buffering for decoded sample streams
+//!
+//! Block based codecs (like IMA ADPCM) decode a whole block of samples at
+//! once. [`SampleQueue`] holds decoded samples ahead of playback, so a block
+//! can be decoded while there are still samples queued, rather than exactly
+//! when the previous block runs out.
+//!
+//! Recordings which weren't made to loop can be looped with a [`TailLoop`],
+//! which crossfades the end of the recording into the start of the next
+//! cycle rather than cutting it off.
+//!
+//! A stream whose recording is missing, or keeps failing to decode, is
+//! reported by its [`StreamHealth`], so the player can fall back to
+//! something else rather than playing silence.
+
+/// Fixed capacity FIFO of decoded samples with a refill watermark
+pub struct SampleQueue<const N: usize> {
+    buffer: [i16; N],
+    read: usize,
+    len: usize,
+    watermark: usize,
+}
+
+impl<const N: usize> SampleQueue<N> {
+    /// New empty queue, asking for a refill at or below `watermark` samples
+    pub const fn new(watermark: usize) -> Self {
+        SampleQueue {
+            buffer: [0; N],
+            read: 0,
+            len: 0,
+            watermark,
+        }
+    }
+
+    /// Number of queued samples
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Space left in the queue
+    pub fn free(&self) -> usize {
+        N - self.len
+    }
+
+    /// True when the queue has dropped to the watermark and should be refilled
+    pub fn below_watermark(&self) -> bool {
+        self.len <= self.watermark
+    }
+
+    /// Queue as many `samples` as fit, returning the number queued
+    pub fn extend(&mut self, samples: &[i16]) -> usize {
+        let count = samples.len().min(self.free());
+        for &sample in &samples[..count] {
+            self.buffer[(self.read + self.len) % N] = sample;
+            self.len += 1;
+        }
+        count
+    }
+
+    /// Oldest queued sample
+    pub fn pop(&mut self) -> Option<i16> {
+        if self.len == 0 {
+            return None;
+        }
+        let sample = self.buffer[self.read];
+        self.read = (self.read + 1) % N;
+        self.len -= 1;
+        Some(sample)
+    }
+
+    /// Drop all queued samples, to restart a stream from its beginning
+    pub fn clear(&mut self) {
+        self.read = 0;
+        self.len = 0;
+    }
+}
+
+/// Choose which of several queues to refill next
+///
+/// Returns the index of the emptiest queue at or below `watermark`, or `None`
+/// if all queues are above it. Refilling one queue per call spreads the
+/// decoding of several streams over separate samples.
+pub fn refill_candidate(levels: &[usize], watermark: usize) -> Option<usize> {
+    levels
+        .iter()
+        .enumerate()
+        .filter(|(_, &level)| level <= watermark)
+        .min_by_key(|(_, &level)| level)
+        .map(|(index, _)| index)
+}
+
+/// Where to start playing a looped stream of `blocks` blocks
+///
+/// Returns the number of whole blocks to skip without decoding, then the
+/// number of samples to skip within the next block, for a start `offset` in
+/// samples. Offsets past the end wrap around the loop.
+pub fn start_position(offset: usize, block_len: usize, blocks: usize) -> (usize, usize) {
+    let loop_len = block_len * blocks;
+    if loop_len == 0 {
+        return (0, 0);
+    }
+    let offset = offset % loop_len;
+    (offset / block_len, offset % block_len)
+}
+
+/// Loop over blocks, with the last `fade_len` samples crossfaded into the head
+///
+/// The tail is rounded up to whole blocks, and each cycle plays the blocks
+/// before it. The first `fade_len` samples of a cycle are mixed with the
+/// tail, which fades out as the head fades in. The tail picks up exactly
+/// where the body of the previous cycle stopped, so the cycle boundary is
+/// seamless, and the tail ends by the time the body does. Tail samples past
+/// the fade, in its last block, are never played.
+#[derive(Clone, Copy)]
+pub struct TailLoop {
+    blocks: usize,
+    block_len: usize,
+    tail_blocks: usize,
+    fade_len: usize,
+}
+
+impl TailLoop {
+    /// Crossfade over `tail_samples`, limited to half of the `blocks` of
+    /// `block_len` samples, so the head and tail don't overlap
+    pub fn new(blocks: usize, block_len: usize, tail_samples: usize) -> Self {
+        let tail_blocks = tail_samples.div_ceil(block_len.max(1)).min(blocks / 2);
+        TailLoop {
+            blocks,
+            block_len,
+            tail_blocks,
+            fade_len: tail_samples.min(tail_blocks * block_len),
+        }
+    }
+
+    /// Blocks played per cycle
+    pub fn cycle_blocks(&self) -> usize {
+        self.blocks - self.tail_blocks
+    }
+
+    /// Tail block to mix into head block `block`, if any
+    pub fn tail_for(&self, block: usize) -> Option<usize> {
+        (block < self.tail_blocks).then(|| self.cycle_blocks() + block)
+    }
+
+    /// Crossfade position and length for `sample` within head `block`
+    ///
+    /// Position 0 is all tail, and positions from the length on are all head,
+    /// see [`crate::Sample::interpolate_to()`] for mixing with these.
+    pub fn fade(&self, block: usize, sample: usize) -> (u32, u32) {
+        (
+            (block * self.block_len + sample) as u32,
+            self.fade_len as u32,
+        )
+    }
+}
+
+/// Whether a stream is decoding, from the results of its recent blocks
+#[derive(Clone, Copy)]
+pub struct StreamHealth {
+    consecutive_failures: u32,
+    limit: u32,
+}
+
+impl StreamHealth {
+    /// Failed once `limit` blocks in a row fail, so a single bad block
+    /// doesn't give up on the recording
+    pub const fn new(limit: u32) -> Self {
+        StreamHealth {
+            consecutive_failures: 0,
+            limit,
+        }
+    }
+
+    /// Record whether a block decoded
+    pub fn record(&mut self, decoded: bool) {
+        self.consecutive_failures = match decoded {
+            true => 0,
+            false => self.consecutive_failures.saturating_add(1),
+        };
+    }
+
+    pub fn is_failed(&self) -> bool {
+        self.consecutive_failures >= self.limit.max(1)
+    }
+}
+
+/// Whether every layer has failed, so the player should fall back
+///
+/// With any layer still decoding, that layer plays on its own instead.
+pub fn all_failed(layers: &[StreamHealth]) -> bool {
+    !layers.is_empty() && layers.iter().all(StreamHealth::is_failed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        all_failed, refill_candidate, start_position, SampleQueue, StreamHealth, TailLoop,
+    };
+    use crate::fade::{fade_between, FadeCurve};
+    use crate::Sample;
+
+    /// Play `cycles` of a looped `recording`, crossfading the tail into the
+    /// head linearly like the firmware does
+    fn play(
+        tail_loop: &TailLoop,
+        block_len: usize,
+        cycles: usize,
+        recording: impl Fn(usize, usize) -> Sample,
+    ) -> Vec<i32> {
+        let mut output = Vec::new();
+        for _cycle in 0..cycles {
+            for block in 0..tail_loop.cycle_blocks() {
+                for sample in 0..block_len {
+                    let head = recording(block, sample);
+                    let value = match tail_loop.tail_for(block) {
+                        Some(tail) => {
+                            let (position, length) = tail_loop.fade(block, sample);
+                            let tail = recording(tail, sample);
+                            fade_between(tail, head, position, length, FadeCurve::Linear)
+                        }
+                        None => head,
+                    };
+                    output.push(value.to_clamped());
+                }
+            }
+        }
+        output
+    }
+
+    #[test]
+    fn test_sample_queue_fifo_wraps() {
+        let mut queue = SampleQueue::<4>::new(1);
+        assert_eq!(queue.extend(&[1, 2, 3]), 3);
+        assert_eq!(queue.pop(), Some(1));
+        assert_eq!(queue.pop(), Some(2));
+        // wraps around the end of the buffer, and stops when full
+        assert_eq!(queue.extend(&[4, 5, 6, 7]), 3);
+        assert_eq!(queue.free(), 0);
+        for expected in [3, 4, 5, 6] {
+            assert_eq!(queue.pop(), Some(expected));
+        }
+        assert_eq!(queue.pop(), None);
+        assert!(queue.is_empty());
+        // cleared, it starts over empty
+        queue.extend(&[8, 9]);
+        queue.clear();
+        assert!(queue.is_empty());
+        assert_eq!(queue.pop(), None);
+        queue.extend(&[10]);
+        assert_eq!(queue.pop(), Some(10));
+    }
+
+    #[test]
+    fn test_sample_queue_watermark() {
+        let mut queue = SampleQueue::<8>::new(2);
+        assert!(queue.below_watermark());
+        queue.extend(&[0; 4]);
+        assert!(!queue.below_watermark());
+        queue.pop();
+        assert!(!queue.below_watermark());
+        queue.pop();
+        // refill requested before the queue runs dry
+        assert!(queue.below_watermark());
+        assert_eq!(queue.len(), 2);
+    }
+
+    #[test]
+    fn test_refill_candidate() {
+        assert_eq!(refill_candidate(&[100, 200, 300], 50), None);
+        assert_eq!(refill_candidate(&[100, 20, 300], 50), Some(1));
+        // emptiest queue wins when several are low
+        assert_eq!(refill_candidate(&[40, 20, 50], 50), Some(1));
+        assert_eq!(refill_candidate(&[0, 20, 50], 50), Some(0));
+        assert_eq!(refill_candidate(&[], 50), None);
+    }
+
+    #[test]
+    fn test_refill_spreads_block_decodes() {
+        // three streams consuming one sample each per tick, refilled with
+        // blocks of 10 samples, never run dry and never decode together
+        const BLOCK: usize = 10;
+        const WATERMARK: usize = 4;
+        let mut queues = [
+            SampleQueue::<{ BLOCK + WATERMARK }>::new(WATERMARK),
+            SampleQueue::<{ BLOCK + WATERMARK }>::new(WATERMARK),
+            SampleQueue::<{ BLOCK + WATERMARK }>::new(WATERMARK),
+        ];
+        for (index, queue) in queues.iter_mut().enumerate() {
+            queue.extend(&[0; BLOCK][..BLOCK - index * 3]);
+        }
+        for _ in 0..1000 {
+            let levels = [queues[0].len(), queues[1].len(), queues[2].len()];
+            if let Some(index) = refill_candidate(&levels, WATERMARK) {
+                assert_eq!(queues[index].extend(&[0; BLOCK]), BLOCK);
+            }
+            for queue in queues.iter_mut() {
+                assert!(queue.pop().is_some(), "queue ran dry");
+            }
+        }
+    }
+
+    #[test]
+    fn test_start_position() {
+        assert_eq!(start_position(0, 2041, 10), (0, 0));
+        assert_eq!(start_position(277, 2041, 10), (0, 277));
+        assert_eq!(start_position(2041, 2041, 10), (1, 0));
+        assert_eq!(start_position(3 * 2041 + 691, 2041, 10), (3, 691));
+        // wraps around the end of the loop
+        assert_eq!(start_position(10 * 2041 + 5, 2041, 10), (0, 5));
+        // nothing to play
+        assert_eq!(start_position(1000, 2041, 0), (0, 0));
+    }
+
+    #[test]
+    fn test_empty_stream_loops_nothing() {
+        // an asset without blocks, played as silence, never indexes a block
+        let tail_loop = TailLoop::new(0, 2041, 4 * 2041);
+        assert_eq!(tail_loop.cycle_blocks(), 0);
+        assert_eq!(tail_loop.tail_for(0), None);
+        assert_eq!(
+            start_position(96_000, 2041, tail_loop.cycle_blocks()),
+            (0, 0)
+        );
+    }
+
+    #[test]
+    fn test_tail_loop_indexes() {
+        let tail_loop = TailLoop::new(10, 4, 12);
+        assert_eq!(tail_loop.cycle_blocks(), 7);
+        let tails: Vec<Option<usize>> = (0..7).map(|block| tail_loop.tail_for(block)).collect();
+        assert_eq!(tails, [Some(7), Some(8), Some(9), None, None, None, None]);
+        assert_eq!(tail_loop.fade(0, 0), (0, 12));
+        assert_eq!(tail_loop.fade(2, 3), (11, 12));
+
+        // partial blocks round the tail up, but fade over the exact length
+        let tail_loop = TailLoop::new(10, 4, 6);
+        assert_eq!(tail_loop.cycle_blocks(), 8);
+        assert_eq!(tail_loop.tail_for(1), Some(9));
+        assert_eq!(tail_loop.tail_for(2), None);
+        assert_eq!(tail_loop.fade(1, 3), (7, 6));
+
+        // tail limited to half, no tail is a plain loop
+        assert_eq!(TailLoop::new(10, 4, 32).cycle_blocks(), 5);
+        assert_eq!(TailLoop::new(10, 4, 32).fade(0, 0), (0, 20));
+        assert_eq!(TailLoop::new(10, 4, 0).cycle_blocks(), 10);
+        assert_eq!(TailLoop::new(10, 4, 0).tail_for(0), None);
+        assert_eq!(TailLoop::new(1, 4, 4).cycle_blocks(), 1);
+    }
+
+    #[test]
+    fn test_tail_loop_is_seamless() {
+        // a recording rising steadily, one step per sample
+        const BLOCK_LEN: usize = 4;
+        const BLOCKS: usize = 10;
+        let recording =
+            |block: usize, sample: usize| Sample::from((block * BLOCK_LEN + sample) as i32);
+        let tail_loop = TailLoop::new(BLOCKS, BLOCK_LEN, 3 * BLOCK_LEN);
+
+        let output = play(&tail_loop, BLOCK_LEN, 3, recording);
+        let cycle_len = (BLOCKS - 3) * BLOCK_LEN;
+        assert_eq!(output.len(), 3 * cycle_len);
+        // the body of the first cycle plays unchanged after the crossfade
+        assert_eq!(output[12..cycle_len], (12..28).collect::<Vec<i32>>()[..]);
+        // the next cycle starts with the tail, continuing on from the body
+        assert_eq!(output[cycle_len - 1], 27);
+        assert_eq!(output[cycle_len], 28);
+        // cycles repeat exactly
+        assert_eq!(output[..cycle_len], output[cycle_len..2 * cycle_len]);
+        // no jumps anywhere in the crossfade
+        for window in output.windows(2) {
+            assert!((window[1] - window[0]).abs() <= 3, "{:?}", window);
+        }
+    }
+
+    #[test]
+    fn test_tail_loop_wrap_is_click_free() {
+        // a recording rising 10 per sample, ending far from where it starts,
+        // which clicks at the wrap when looped plainly
+        const BLOCK_LEN: usize = 8;
+        const BLOCKS: usize = 12;
+        const FADE: usize = 13;
+        let recording =
+            |block: usize, sample: usize| Sample::from(10 * (block * BLOCK_LEN + sample) as i32);
+        let largest_step = |output: &[i32]| {
+            output
+                .windows(2)
+                .map(|window| (window[1] - window[0]).abs())
+                .max()
+                .unwrap()
+        };
+
+        let plain = play(
+            &TailLoop::new(BLOCKS, BLOCK_LEN, 0),
+            BLOCK_LEN,
+            2,
+            recording,
+        );
+        assert_eq!(largest_step(&plain), 950);
+
+        let faded = play(
+            &TailLoop::new(BLOCKS, BLOCK_LEN, FADE),
+            BLOCK_LEN,
+            2,
+            recording,
+        );
+        let cycle_len = (BLOCKS - 2) * BLOCK_LEN;
+        assert_eq!(faded.len(), 2 * cycle_len);
+        // across the wrap the tail carries on from the body
+        assert_eq!(faded[cycle_len - 1], 790);
+        assert_eq!(faded[cycle_len], 800);
+        // the 800 drop back to the start is spread over the fade
+        assert!(largest_step(&faded) <= 800 / FADE as i32 + 10 + 1);
+        // after the fade the head plays unchanged
+        assert_eq!(faded[cycle_len + FADE], 10 * FADE as i32);
+    }
+
+    #[test]
+    fn test_stream_health_needs_repeated_failures() {
+        let mut health = StreamHealth::new(3);
+        assert!(!health.is_failed());
+        health.record(false);
+        health.record(false);
+        // a good block in between resets
+        health.record(true);
+        health.record(false);
+        assert!(!health.is_failed());
+        health.record(false);
+        health.record(false);
+        assert!(health.is_failed());
+        // and recovers
+        health.record(true);
+        assert!(!health.is_failed());
+    }
+
+    #[test]
+    fn test_fallback_on_decode_failure() {
+        let mut layers = [StreamHealth::new(2); 3];
+        assert!(!all_failed(&layers));
+
+        // one layer failing leaves the others playing
+        layers[0].record(false);
+        layers[0].record(false);
+        assert!(layers[0].is_failed());
+        assert!(!all_failed(&layers));
+
+        // all of them failing falls back
+        for layer in &mut layers[1..] {
+            layer.record(false);
+            layer.record(false);
+        }
+        assert!(all_failed(&layers));
+
+        // until any one of them decodes again
+        layers[2].record(true);
+        assert!(!all_failed(&layers));
+
+        // no layers at all isn't a failure to fall back from
+        assert!(!all_failed(&[]));
+    }
+}
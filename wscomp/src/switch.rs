@@ -0,0 +1,412 @@
+//! Decoding the three position Z switch
+//!
+//! The switch is read through the ADC mux as one of three voltages, from a
+//! resistor divider. Units differ, so the thresholds between positions are
+//! configurable and can be calibrated from readings of each position. Near a
+//! threshold, within the deadband, the previous position is kept so noise
+//! can't make the switch flicker between two positions.
+//!
+//! [`ZSwitchDecoder`] also debounces, only changing position after several
+//! readings agree, and times how long the spring loaded momentary position
+//! is held, to tell a tap from a hold.
+
+use crate::error::Error;
+
+/// Position of the Z switch
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SwitchPosition {
+    Off = 0,
+    On = 1,
+    Momentary = 2,
+}
+
+/// ADC levels separating the switch positions
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SwitchThresholds {
+    /// Levels below this are momentary (down)
+    momentary_below: u16,
+    /// Levels above this are on (up)
+    on_above: u16,
+    /// Distance either side of a threshold where the position doesn't change
+    deadband: u16,
+}
+
+impl SwitchThresholds {
+    /// Fails with [`Error::CalibrationOutOfRange`] unless the deadbands
+    /// around the two thresholds are in order and don't overlap
+    pub fn new(momentary_below: u16, on_above: u16, deadband: u16) -> Result<Self, Error> {
+        let momentary_top = u32::from(momentary_below) + u32::from(deadband);
+        let on_bottom = i32::from(on_above) - i32::from(deadband);
+        if momentary_below < deadband || momentary_top >= on_bottom.max(0) as u32 {
+            return Err(Error::CalibrationOutOfRange);
+        }
+        Ok(SwitchThresholds {
+            momentary_below,
+            on_above,
+            deadband,
+        })
+    }
+
+    /// Thresholds matching the Computer's resistor divider
+    pub const fn default() -> Self {
+        SwitchThresholds {
+            momentary_below: 1000,
+            on_above: 3000,
+            deadband: 100,
+        }
+    }
+
+    /// Position for ADC `level`, given the `previous` position
+    pub fn decode(&self, level: u16, previous: SwitchPosition) -> SwitchPosition {
+        let near = |threshold: u16| level.abs_diff(threshold) < self.deadband;
+        match previous {
+            SwitchPosition::Momentary | SwitchPosition::Off if near(self.momentary_below) => {
+                previous
+            }
+            SwitchPosition::On | SwitchPosition::Off if near(self.on_above) => previous,
+            _ if level < self.momentary_below => SwitchPosition::Momentary,
+            _ if level > self.on_above => SwitchPosition::On,
+            _ => SwitchPosition::Off,
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; 6] {
+        let mut bytes = [0; 6];
+        bytes[0..2].copy_from_slice(&self.momentary_below.to_le_bytes());
+        bytes[2..4].copy_from_slice(&self.on_above.to_le_bytes());
+        bytes[4..6].copy_from_slice(&self.deadband.to_le_bytes());
+        bytes
+    }
+
+    /// Fails like [`SwitchThresholds::new()`]
+    pub fn from_bytes(bytes: [u8; 6]) -> Result<Self, Error> {
+        let read = |offset: usize| u16::from_le_bytes([bytes[offset], bytes[offset + 1]]);
+        Self::new(read(0), read(2), read(4))
+    }
+}
+
+impl Default for SwitchThresholds {
+    fn default() -> Self {
+        Self::default()
+    }
+}
+
+/// Collects readings of each switch position, to calibrate thresholds
+pub struct SwitchCalibration {
+    /// Latest level seen in each position, indexed by [`SwitchPosition`]
+    levels: [Option<u16>; 3],
+}
+
+impl SwitchCalibration {
+    pub const fn new() -> Self {
+        SwitchCalibration { levels: [None; 3] }
+    }
+
+    /// Record `level` as a reading of `position`
+    pub fn observe(&mut self, position: SwitchPosition, level: u16) {
+        self.levels[position as usize] = Some(level);
+    }
+
+    /// Thresholds half way between the observed positions
+    ///
+    /// Positions which weren't observed keep the threshold from `current`,
+    /// Off has to be observed. Fails with [`Error::CalibrationOutOfRange`]
+    /// when the positions are too close together for the deadband.
+    pub fn thresholds(&self, current: SwitchThresholds) -> Result<SwitchThresholds, Error> {
+        let [off, on, momentary] = self.levels;
+        let off = off.ok_or(Error::CalibrationOutOfRange)?;
+        let midpoint = |a: u16, b: u16| ((u32::from(a) + u32::from(b)) / 2) as u16;
+        let momentary_below = match momentary {
+            Some(momentary) if momentary < off => midpoint(momentary, off),
+            Some(_) => return Err(Error::CalibrationOutOfRange),
+            None => current.momentary_below,
+        };
+        let on_above = match on {
+            Some(on) if on > off => midpoint(off, on),
+            Some(_) => return Err(Error::CalibrationOutOfRange),
+            None => current.on_above,
+        };
+        SwitchThresholds::new(momentary_below, on_above, current.deadband)
+    }
+}
+
+impl Default for SwitchCalibration {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Debounced Z switch position, with press and hold edges
+pub struct ZSwitchDecoder {
+    thresholds: SwitchThresholds,
+    /// Consistent readings needed to change position
+    debounce: u8,
+    /// Updates in the momentary position which count as a hold
+    hold_ticks: u32,
+    position: SwitchPosition,
+    /// Position readings are moving to, and how many in a row agreed
+    candidate: SwitchPosition,
+    candidate_count: u8,
+    /// Updates the current or last momentary press lasted
+    press_ticks: u32,
+    /// Position before the last update, if it changed
+    changed_from: Option<SwitchPosition>,
+}
+
+impl ZSwitchDecoder {
+    /// Decoder starting off, changing position after `debounce` consistent
+    /// readings and holding after `hold_ticks` updates in momentary
+    ///
+    /// `debounce` 0 is treated as 1, no debouncing.
+    pub const fn new(thresholds: SwitchThresholds, debounce: u8, hold_ticks: u32) -> Self {
+        ZSwitchDecoder {
+            thresholds,
+            debounce: if debounce == 0 { 1 } else { debounce },
+            hold_ticks,
+            position: SwitchPosition::Off,
+            candidate: SwitchPosition::Off,
+            candidate_count: 0,
+            press_ticks: 0,
+            changed_from: None,
+        }
+    }
+
+    pub fn set_thresholds(&mut self, thresholds: SwitchThresholds) {
+        self.thresholds = thresholds;
+    }
+
+    /// Decode the next ADC `level`, returning the debounced position
+    pub fn update(&mut self, level: u16) -> SwitchPosition {
+        let reading = self.thresholds.decode(level, self.position);
+        self.changed_from = None;
+        if reading == self.position {
+            self.candidate_count = 0;
+        } else {
+            if reading != self.candidate {
+                self.candidate = reading;
+                self.candidate_count = 0;
+            }
+            self.candidate_count += 1;
+            if self.candidate_count >= self.debounce {
+                self.changed_from = Some(self.position);
+                self.position = reading;
+                self.candidate_count = 0;
+            }
+        }
+        if self.just_pressed() {
+            self.press_ticks = 1;
+        } else if self.position == SwitchPosition::Momentary {
+            self.press_ticks = self.press_ticks.saturating_add(1);
+        }
+        self.position
+    }
+
+    pub fn position(&self) -> SwitchPosition {
+        self.position
+    }
+
+    /// Whether the last update moved into the momentary position
+    pub fn just_pressed(&self) -> bool {
+        self.changed_from.is_some() && self.position == SwitchPosition::Momentary
+    }
+
+    /// Whether the last update moved out of the momentary position
+    ///
+    /// [`Self::press_ticks()`] tells a tap from a hold.
+    pub fn just_released(&self) -> bool {
+        self.changed_from == Some(SwitchPosition::Momentary)
+    }
+
+    /// Whether the switch is in momentary, and has been for the hold time
+    pub fn is_held(&self) -> bool {
+        self.position == SwitchPosition::Momentary && self.press_ticks >= self.hold_ticks
+    }
+
+    /// Updates the current press has lasted, or the last one did once
+    /// released
+    pub fn press_ticks(&self) -> u32 {
+        self.press_ticks
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{SwitchCalibration, SwitchPosition, SwitchThresholds, ZSwitchDecoder};
+    use crate::error::Error;
+
+    use SwitchPosition::{Momentary, Off, On};
+
+    #[test]
+    fn test_switch_decode() {
+        let thresholds = SwitchThresholds::new(1000, 3000, 0).unwrap();
+        for (level, position) in [(0, Momentary), (999, Momentary), (1000, Off), (2000, Off)] {
+            assert_eq!(thresholds.decode(level, Off), position, "{}", level);
+        }
+        assert_eq!(thresholds.decode(3000, On), Off);
+        assert_eq!(thresholds.decode(3001, Off), On);
+        assert_eq!(thresholds.decode(4095, Momentary), On);
+
+        // other thresholds
+        let thresholds = SwitchThresholds::new(600, 1800, 0).unwrap();
+        assert_eq!(thresholds.decode(700, Momentary), Off);
+        assert_eq!(thresholds.decode(2000, Off), On);
+        assert_eq!(thresholds.decode(500, On), Momentary);
+    }
+
+    #[test]
+    fn test_switch_deadband() {
+        let thresholds = SwitchThresholds::new(1000, 3000, 100).unwrap();
+        // near a threshold the previous position holds
+        assert_eq!(thresholds.decode(1050, Momentary), Momentary);
+        assert_eq!(thresholds.decode(950, Off), Off);
+        assert_eq!(thresholds.decode(2950, On), On);
+        assert_eq!(thresholds.decode(3050, Off), Off);
+        // until it's clearly past
+        assert_eq!(thresholds.decode(1101, Momentary), Off);
+        assert_eq!(thresholds.decode(899, Off), Momentary);
+        assert_eq!(thresholds.decode(3101, Off), On);
+        // the deadband only holds between neighbouring positions
+        assert_eq!(thresholds.decode(950, On), Momentary);
+        assert_eq!(thresholds.decode(3050, Momentary), On);
+
+        // deadbands must fit between the thresholds
+        assert_eq!(
+            SwitchThresholds::new(1000, 1200, 100),
+            Err(Error::CalibrationOutOfRange)
+        );
+        assert_eq!(
+            SwitchThresholds::new(3000, 1000, 0),
+            Err(Error::CalibrationOutOfRange)
+        );
+        assert_eq!(
+            SwitchThresholds::new(50, 3000, 100),
+            Err(Error::CalibrationOutOfRange)
+        );
+        assert!(SwitchThresholds::new(1000, 1201, 100).is_ok());
+    }
+
+    #[test]
+    fn test_switch_calibration() {
+        let current = SwitchThresholds::default();
+        let mut calibration = SwitchCalibration::new();
+        assert_eq!(
+            calibration.thresholds(current),
+            Err(Error::CalibrationOutOfRange)
+        );
+        calibration.observe(Momentary, 300);
+        calibration.observe(Off, 1900);
+        // only the latest reading counts
+        calibration.observe(Off, 1700);
+        calibration.observe(On, 3500);
+        let thresholds = calibration.thresholds(current).unwrap();
+        assert_eq!(thresholds, SwitchThresholds::new(1000, 2600, 100).unwrap());
+        assert_eq!(thresholds.decode(1800, Off), Off);
+        assert_eq!(thresholds.decode(2800, Off), On);
+
+        // unobserved positions keep their thresholds
+        let mut calibration = SwitchCalibration::new();
+        calibration.observe(Off, 2200);
+        calibration.observe(On, 3800);
+        let thresholds = calibration.thresholds(current).unwrap();
+        assert_eq!(thresholds, SwitchThresholds::new(1000, 3000, 100).unwrap());
+
+        // positions in the wrong order
+        calibration.observe(Momentary, 2500);
+        assert_eq!(
+            calibration.thresholds(current),
+            Err(Error::CalibrationOutOfRange)
+        );
+    }
+
+    #[test]
+    fn test_switch_thresholds_bytes() {
+        let thresholds = SwitchThresholds::new(900, 2800, 50).unwrap();
+        assert_eq!(
+            SwitchThresholds::from_bytes(thresholds.to_bytes()),
+            Ok(thresholds)
+        );
+        assert_eq!(
+            SwitchThresholds::from_bytes([0xff; 6]),
+            Err(Error::CalibrationOutOfRange)
+        );
+    }
+
+    /// Feed `levels` through `decoder`, returning each update's position
+    /// and edges, as (position, pressed, released, held)
+    fn run(
+        decoder: &mut ZSwitchDecoder,
+        levels: &[u16],
+    ) -> Vec<(SwitchPosition, bool, bool, bool)> {
+        levels
+            .iter()
+            .map(|&level| {
+                let position = decoder.update(level);
+                (
+                    position,
+                    decoder.just_pressed(),
+                    decoder.just_released(),
+                    decoder.is_held(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_switch_decoder_debounces() {
+        let mut decoder = ZSwitchDecoder::new(SwitchThresholds::default(), 3, 100);
+        // a glitch shorter than the debounce doesn't change position
+        let levels = [2048, 4000, 4000, 2048, 100, 2048, 4000, 4000, 4000, 4000];
+        let positions: Vec<_> = run(&mut decoder, &levels)
+            .iter()
+            .map(|update| update.0)
+            .collect();
+        assert_eq!(positions, [Off, Off, Off, Off, Off, Off, Off, Off, On, On]);
+        // readings flicking between two other positions don't add up
+        let mut decoder = ZSwitchDecoder::new(SwitchThresholds::default(), 2, 100);
+        for level in [4000, 100, 4000, 100, 4000, 100] {
+            assert_eq!(decoder.update(level), Off);
+        }
+
+        // no debouncing
+        let mut decoder = ZSwitchDecoder::new(SwitchThresholds::default(), 0, 100);
+        assert_eq!(decoder.update(4000), On);
+        assert_eq!(decoder.update(100), Momentary);
+        assert!(decoder.just_pressed());
+        // the deadband still applies, from the debounced position
+        assert_eq!(decoder.update(1050), Momentary);
+    }
+
+    #[test]
+    fn test_switch_decoder_edges_fire_once() {
+        let mut decoder = ZSwitchDecoder::new(SwitchThresholds::default(), 2, 6);
+        // a noisy tap: off, bouncing down, held briefly, bouncing back up
+        let tap = [
+            2048, 2048, 100, 2048, 100, 100, 100, 2048, 100, 2048, 2048, 2048,
+        ];
+        let updates = run(&mut decoder, &tap);
+        let pressed: Vec<_> = (0..tap.len()).filter(|&n| updates[n].1).collect();
+        let released: Vec<_> = (0..tap.len()).filter(|&n| updates[n].2).collect();
+        assert_eq!(pressed, [5]);
+        assert_eq!(released, [10]);
+        assert!(updates.iter().all(|update| !update.3));
+        // the tap's length is kept after release
+        assert_eq!(decoder.press_ticks(), 5);
+
+        // a hold, held fires from the hold time until released
+        let hold = [100; 10].iter().chain(&[2048, 2048, 2048]).copied();
+        let updates = run(&mut decoder, &hold.collect::<Vec<_>>());
+        let pressed = updates.iter().filter(|update| update.1).count();
+        let released = updates.iter().filter(|update| update.2).count();
+        let held: Vec<_> = (0..updates.len()).filter(|&n| updates[n].3).collect();
+        assert_eq!((pressed, released), (1, 1));
+        assert_eq!(held, [6, 7, 8, 9, 10]);
+        assert_eq!(decoder.press_ticks(), 10);
+
+        // switching between on and off isn't a press or release
+        let updates = run(&mut decoder, &[4000, 4000, 4000, 2048, 2048]);
+        assert!(updates.iter().all(|update| !update.1 && !update.2));
+        assert_eq!(decoder.position(), Off);
+    }
+}
@@ -0,0 +1,64 @@
+//! RP2040 internal temperature sensor
+//!
+//! The sensor is ADC channel 4. From the RP2040 datasheet (section 4.9.5),
+//! with a 3.3V ADC reference:
+//!
+//! ```text
+//! T = 27 - (ADC_voltage - 0.706) / 0.001721
+//! ```
+//!
+//! The sensor isn't calibrated per chip, so expect readings to be a few
+//! degrees off. Useful for watching trends rather than absolute values.
+
+/// ADC reference voltage on the Computer, in microvolts
+const ADC_VREF_MICROVOLTS: i64 = 3_300_000;
+/// Sensor voltage at 27°C, in microvolts
+const SENSOR_MICROVOLTS_27C: i64 = 706_000;
+/// Sensor slope, in nanovolts per °C (falls as temperature rises)
+const SENSOR_NANOVOLTS_PER_C: i64 = 1_721_000;
+
+/// Die temperature in thousandths of a °C, from a 12 bit ADC reading
+pub fn die_temperature_millicelsius(level: u16) -> i32 {
+    let microvolts = i64::from(level.min(crate::U12_MAX)) * ADC_VREF_MICROVOLTS / 4096;
+    let offset = microvolts - SENSOR_MICROVOLTS_27C;
+    // within a few hundred °C, fits easily into i32
+    27_000 - (offset * 1_000_000 / SENSOR_NANOVOLTS_PER_C) as i32
+}
+
+#[cfg(test)]
+mod test {
+    use super::die_temperature_millicelsius;
+
+    /// The datasheet formula, in floating point
+    fn datasheet(level: u16) -> f64 {
+        let voltage = f64::from(level) * 3.3 / 4096.0;
+        27.0 - (voltage - 0.706) / 0.001721
+    }
+
+    #[test]
+    fn test_die_temperature_matches_datasheet() {
+        for level in (0..4096).step_by(7) {
+            let millicelsius = die_temperature_millicelsius(level);
+            let expected = datasheet(level) * 1000.0;
+            assert!(
+                (f64::from(millicelsius) - expected).abs() < 2.0,
+                "{}: {} vs {}",
+                level,
+                millicelsius,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_die_temperature_known_points() {
+        // 0.706V reads 27°C
+        assert_eq!(die_temperature_millicelsius(876) / 1000, 27);
+        // warmer die, lower voltage
+        assert!(die_temperature_millicelsius(860) > die_temperature_millicelsius(876));
+        assert_eq!(
+            die_temperature_millicelsius(u16::MAX),
+            die_temperature_millicelsius(4095)
+        );
+    }
+}
@@ -0,0 +1,149 @@
+//! Detect shared values which never arrive
+//!
+//! Consumers of a `Watch` see `None` until the producer sends its first value.
+//! [`FirstValueTimeout`] lets a consumer fall back to a default while waiting,
+//! and report once if the producer seems to be stuck.
+//!
+//! [`StallTimeout`] notices a counter which stops advancing while it should be
+//! running, such as the words the DMA has sent to the DAC, so the stream can
+//! be restarted rather than freezing.
+
+/// Counts consumer loop ticks until the first value is received
+#[derive(Clone)]
+pub struct FirstValueTimeout {
+    timeout_ticks: u32,
+    ticks: u32,
+    received: bool,
+    reported: bool,
+}
+
+impl FirstValueTimeout {
+    /// New timeout, expiring after `timeout_ticks` ticks without a value
+    pub const fn new(timeout_ticks: u32) -> Self {
+        FirstValueTimeout {
+            timeout_ticks,
+            ticks: 0,
+            received: false,
+            reported: false,
+        }
+    }
+
+    /// Call once per consumer loop, with whether a value was available
+    ///
+    /// Returns true exactly once, on the tick the timeout expires, so the
+    /// caller can log without flooding.
+    pub fn tick(&mut self, received: bool) -> bool {
+        if received {
+            self.received = true;
+        }
+        if self.received || self.reported {
+            return false;
+        }
+        self.ticks = self.ticks.saturating_add(1);
+        if self.ticks >= self.timeout_ticks {
+            self.reported = true;
+            return true;
+        }
+        false
+    }
+
+    /// True once expired, until a value is received
+    pub fn timed_out(&self) -> bool {
+        self.reported && !self.received
+    }
+}
+
+/// Detects a counter which hasn't changed for a timeout
+///
+/// Times are in any units, such as timer ticks, as long as the timeout uses
+/// the same.
+#[derive(Clone)]
+pub struct StallTimeout {
+    timeout: u64,
+    count: u32,
+    since: u64,
+}
+
+impl StallTimeout {
+    /// New timeout, for a counter at `count` at time `now`
+    pub const fn new(timeout: u64, count: u32, now: u64) -> Self {
+        StallTimeout {
+            timeout,
+            count,
+            since: now,
+        }
+    }
+
+    /// Follow the counter, returning true if it's been stuck at `count` for
+    /// the timeout
+    ///
+    /// After a stall is reported, the timeout starts again from `now`, so a
+    /// counter which stays stuck is reported once per timeout.
+    pub fn check(&mut self, count: u32, now: u64) -> bool {
+        if count != self.count {
+            self.count = count;
+            self.since = now;
+            return false;
+        }
+        if now.wrapping_sub(self.since) >= self.timeout {
+            self.since = now;
+            return true;
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{FirstValueTimeout, StallTimeout};
+
+    #[test]
+    fn test_first_value_timeout_reports_once() {
+        let mut timeout = FirstValueTimeout::new(3);
+        assert!(!timeout.tick(false));
+        assert!(!timeout.tick(false));
+        assert!(timeout.tick(false));
+        assert!(timeout.timed_out());
+        for _ in 0..10 {
+            assert!(!timeout.tick(false));
+        }
+        // late value clears the timed out state
+        assert!(!timeout.tick(true));
+        assert!(!timeout.timed_out());
+    }
+
+    #[test]
+    fn test_first_value_timeout_value_in_time() {
+        let mut timeout = FirstValueTimeout::new(3);
+        assert!(!timeout.tick(false));
+        assert!(!timeout.tick(true));
+        // values going missing later isn't a startup failure
+        for _ in 0..10 {
+            assert!(!timeout.tick(false));
+        }
+        assert!(!timeout.timed_out());
+    }
+
+    #[test]
+    fn test_stall_timeout() {
+        let mut stall = StallTimeout::new(100, 0, 1000);
+        // advancing, however slowly, isn't a stall
+        for (count, now) in [(0, 1050), (1, 1099), (1, 1150), (2, 1198), (3, 1297)] {
+            assert!(!stall.check(count, now));
+        }
+        // stuck for the timeout is
+        assert!(!stall.check(3, 1396));
+        assert!(stall.check(3, 1397));
+        // and again each timeout while it stays stuck
+        assert!(!stall.check(3, 1496));
+        assert!(stall.check(3, 1497));
+        // until it moves again
+        assert!(!stall.check(4, 1600));
+        assert!(!stall.check(4, 1699));
+        // wrapping counters and times still advance
+        let mut stall = StallTimeout::new(100, u32::MAX, u64::MAX - 10);
+        assert!(!stall.check(0, 50));
+        assert!(!stall.check(0, 149));
+        assert!(stall.check(0, 150));
+    }
+}
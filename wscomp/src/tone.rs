@@ -0,0 +1,172 @@
+//! Output volume and muffling, for knobs shaping the rain's sound
+//!
+//! [`knob_volume()`] maps a knob to a gain for [`Sample::scale()`], squared
+//! so it tapers like a volume control rather than bunching the loud end.
+//!
+//! [`Muffle`] is a one pole low pass, for rain heard through a window. A knob
+//! sets its coefficient ([`muffle_coefficient()`]) exponentially, so the
+//! cutoff falls by octaves evenly across the knob's travel, from open to
+//! `depth` octaves below.
+
+use crate::fixed::{self, Fixed};
+use crate::Sample;
+
+/// Largest muffle depth, beyond that the cutoff is below audio
+pub const MAX_MUFFLE_DEPTH: u8 = 12;
+
+/// How far a knob is turned, 0 fully counter-clockwise to [`fixed::ONE`]
+fn knob_fraction(knob: Sample) -> Fixed {
+    let turned = knob.to_clamped() - Sample::MIN;
+    turned * fixed::ONE / (Sample::MAX - Sample::MIN)
+}
+
+/// Gain for a volume knob, silent fully counter-clockwise and unity (at
+/// [`Sample::MAX`]) fully clockwise
+///
+/// Halfway is a quarter of full scale, 12dB down.
+pub fn knob_volume(knob: Sample) -> Sample {
+    let turned = knob.to_clamped() - Sample::MIN;
+    let range = Sample::MAX - Sample::MIN;
+    Sample::from(turned * turned / range * Sample::MAX / range)
+}
+
+/// Low pass coefficient for a muffle knob, [`fixed::ONE`] (open) fully
+/// counter-clockwise down to `2^-depth` fully clockwise
+///
+/// At 48kHz, a depth of 5 brings the cutoff down to about 250Hz. `depth` is
+/// limited to [`MAX_MUFFLE_DEPTH`].
+pub fn muffle_coefficient(knob: Sample, depth: u8) -> Fixed {
+    let depth = Fixed::from(depth.min(MAX_MUFFLE_DEPTH));
+    fixed::exp2(-knob_fraction(knob) * depth)
+}
+
+/// One pole low pass, set from a muffle knob
+pub struct Muffle {
+    state: Fixed,
+    coefficient: Fixed,
+    depth: u8,
+    knob: Sample,
+}
+
+impl Muffle {
+    /// Open, passing samples through unchanged, until a knob is set
+    pub fn new(depth: u8) -> Self {
+        Muffle {
+            state: 0,
+            coefficient: fixed::ONE,
+            depth,
+            knob: Sample::from(Sample::MIN),
+        }
+    }
+
+    /// Follow the muffle knob, only recalculating when it moves
+    pub fn set_knob(&mut self, knob: Sample) {
+        if knob != self.knob {
+            self.knob = knob;
+            self.coefficient = muffle_coefficient(knob, self.depth);
+        }
+    }
+
+    pub fn process(&mut self, sample: Sample) -> Sample {
+        let input = fixed::from_sample(sample);
+        if self.coefficient >= fixed::ONE {
+            self.state = input;
+            return sample;
+        }
+        self.state += fixed::mul(input - self.state, self.coefficient);
+        fixed::to_sample(self.state + fixed::ONE / 2)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{knob_volume, muffle_coefficient, Muffle, MAX_MUFFLE_DEPTH};
+    use crate::fixed::{self, exp2, Fixed};
+    use crate::Sample;
+
+    #[test]
+    fn test_knob_volume() {
+        assert_eq!(knob_volume(Sample::from(Sample::MIN)).to_clamped(), 0);
+        assert_eq!(
+            knob_volume(Sample::from(Sample::MAX)).to_clamped(),
+            Sample::MAX
+        );
+        // halfway is about a quarter, 12dB down
+        let half = knob_volume(Sample::from(0_i32)).to_clamped();
+        assert!((Sample::MAX / 4 - half).abs() <= 2, "{}", half);
+        // full volume leaves the rain unchanged, and it only gets quieter
+        let rain = Sample::from(1500_i32);
+        assert_eq!(rain.scale(knob_volume(Sample::from(Sample::MAX))), rain);
+        let mut previous = 0;
+        for knob in Sample::MIN..=Sample::MAX {
+            let gain = knob_volume(Sample::from(knob)).to_clamped();
+            assert!(gain >= previous, "{}", knob);
+            previous = gain;
+        }
+    }
+
+    #[test]
+    fn test_muffle_coefficient() {
+        let open = Sample::from(Sample::MIN);
+        let closed = Sample::from(Sample::MAX);
+        assert_eq!(muffle_coefficient(open, 5), fixed::ONE);
+        assert_eq!(muffle_coefficient(closed, 5), fixed::ONE >> 5);
+        assert_eq!(muffle_coefficient(closed, 0), fixed::ONE);
+        // halfway is half the octaves
+        let half = muffle_coefficient(Sample::from(0_i32), 4);
+        assert!((half - fixed::ONE / 4).abs() < 100, "{}", half);
+        // deeper than the limit stops at it
+        assert_eq!(
+            muffle_coefficient(closed, 100),
+            exp2(-Fixed::from(MAX_MUFFLE_DEPTH) * fixed::ONE)
+        );
+        // turning the knob only ever closes it further
+        let mut previous = fixed::ONE;
+        for knob in (Sample::MIN..=Sample::MAX).step_by(7) {
+            let coefficient = muffle_coefficient(Sample::from(knob), 5);
+            assert!(coefficient <= previous, "{}", knob);
+            assert!(coefficient > 0);
+            previous = coefficient;
+        }
+    }
+
+    /// Peak output of `muffle` for a square wave of `half_period` samples
+    /// each side, after it has settled
+    fn square_peak(muffle: &mut Muffle, half_period: usize) -> i32 {
+        let mut peak = 0;
+        let samples = (half_period * 200).max(4000);
+        for n in 0..samples {
+            let input = if (n / half_period).is_multiple_of(2) {
+                1000
+            } else {
+                -1000
+            };
+            let output = muffle.process(Sample::from(input)).to_clamped();
+            if n >= samples / 2 {
+                peak = peak.max(output.abs());
+            }
+        }
+        peak
+    }
+
+    #[test]
+    fn test_muffle_filters_highs() {
+        // open, samples pass through unchanged
+        let mut muffle = Muffle::new(5);
+        assert_eq!(square_peak(&mut muffle, 1), 1000);
+
+        muffle.set_knob(Sample::from(Sample::MAX));
+        // highs are cut to a fraction, lows mostly pass
+        assert!(square_peak(&mut muffle, 1) < 50);
+        assert!(square_peak(&mut muffle, 200) > 990);
+        // and a steady level settles exactly
+        for _ in 0..2000 {
+            muffle.process(Sample::from(-700_i32));
+        }
+        assert_eq!(muffle.process(Sample::from(-700_i32)).to_clamped(), -700);
+
+        // turning it back opens it up again
+        muffle.set_knob(Sample::from(Sample::MIN));
+        assert_eq!(muffle.process(Sample::from(321_i32)).to_clamped(), 321);
+    }
+}
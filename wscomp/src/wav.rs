@@ -0,0 +1,352 @@
+//! Reading the WAV files bundled with cards
+//!
+//! Only what's needed to play IMA ADPCM audio: finding the data chunk,
+//! reading the block layout from the format chunk and checking block headers
+//! before handing them to a decoder.
+//!
+//! [`adpcm_format()`] is a `const fn`, so the layout of WAVs bundled with
+//! `include_bytes!` can be read at compile time, for sizing buffers.
+
+use crate::error::Error;
+
+/// Largest step table index in an IMA ADPCM block header
+const ADPCM_MAX_STEP_INDEX: u8 = 88;
+/// WAV format tag of IMA ADPCM
+const FORMAT_IMA_ADPCM: u16 = 0x11;
+
+/// Layout of a mono IMA ADPCM WAV file
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AdpcmFormat {
+    /// Offset of the data chunk's contents from the start of the file
+    pub data_offset: usize,
+    /// Length of the data chunk's contents
+    pub data_len: usize,
+    /// Bytes in each ADPCM block
+    pub block_size: usize,
+    /// Samples decoded from each block
+    pub samples_per_block: usize,
+    pub sample_rate: u32,
+    /// Samples of audio, from the fact chunk if there is one, otherwise
+    /// counted from the data length
+    pub sample_count: usize,
+}
+
+const fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+const fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+/// Offset and length of the contents of the first chunk called `id`
+///
+/// Fails with [`Error::MalformedWav`] if there's no such chunk, or the file
+/// isn't a RIFF WAVE file or is truncated before the chunk ends.
+const fn find_chunk(wav: &[u8], id: &[u8; 4]) -> Result<(usize, usize), Error> {
+    if wav.len() < 12 || u32_at(wav, 0) != u32::from_le_bytes(*b"RIFF") {
+        return Err(Error::MalformedWav);
+    }
+    if u32_at(wav, 8) != u32::from_le_bytes(*b"WAVE") {
+        return Err(Error::MalformedWav);
+    }
+    let mut offset = 12;
+    while offset + 8 <= wav.len() {
+        let length = u32_at(wav, offset + 4) as usize;
+        let start = offset + 8;
+        if u32_at(wav, offset) == u32::from_le_bytes(*id) {
+            if length > wav.len() - start {
+                return Err(Error::MalformedWav);
+            }
+            return Ok((start, length));
+        }
+        // chunks are padded to an even length
+        offset = start.saturating_add(length).saturating_add(length & 1);
+    }
+    Err(Error::MalformedWav)
+}
+
+/// Contents of the data chunk of a RIFF WAVE file
+pub fn data_chunk(wav: &[u8]) -> Result<&[u8], Error> {
+    let (start, length) = find_chunk(wav, b"data")?;
+    Ok(&wav[start..start + length])
+}
+
+/// Layout of a mono IMA ADPCM WAV file, from its fmt, fact and data chunks
+///
+/// Fails with [`Error::UnsupportedWav`] for other formats, or more than one
+/// channel.
+pub const fn adpcm_format(wav: &[u8]) -> Result<AdpcmFormat, Error> {
+    let (fmt, fmt_len) = match find_chunk(wav, b"fmt ") {
+        Ok(chunk) => chunk,
+        Err(e) => return Err(e),
+    };
+    // with the extra samples per block field
+    if fmt_len < 20 {
+        return Err(Error::MalformedWav);
+    }
+    if u16_at(wav, fmt) != FORMAT_IMA_ADPCM || u16_at(wav, fmt + 2) != 1 {
+        return Err(Error::UnsupportedWav);
+    }
+    let sample_rate = u32_at(wav, fmt + 4);
+    let block_size = u16_at(wav, fmt + 12) as usize;
+    let samples_per_block = u16_at(wav, fmt + 18) as usize;
+    // the header's sample, then two per byte after the 4 byte header
+    if block_size <= 4 || samples_per_block != 2 * (block_size - 4) + 1 {
+        return Err(Error::UnsupportedWav);
+    }
+    let (data_offset, data_len) = match find_chunk(wav, b"data") {
+        Ok(chunk) => chunk,
+        Err(e) => return Err(e),
+    };
+    let sample_count = match find_chunk(wav, b"fact") {
+        Ok((fact, fact_len)) if fact_len >= 4 => u32_at(wav, fact) as usize,
+        _ => {
+            let partial = data_len % block_size;
+            let partial_samples = match partial {
+                0..=4 => 0,
+                _ => 2 * (partial - 4) + 1,
+            };
+            data_len / block_size * samples_per_block + partial_samples
+        }
+    };
+    Ok(AdpcmFormat {
+        data_offset,
+        data_len,
+        block_size,
+        samples_per_block,
+        sample_rate,
+        sample_count,
+    })
+}
+
+/// Whole `N` byte blocks of the data chunk
+///
+/// A partial block at the end is ignored. Fails with [`Error::EmptyWav`] if
+/// there isn't a single whole block, so a misconfigured asset is caught
+/// when it's loaded rather than as silence or a stall while playing.
+pub fn adpcm_blocks<const N: usize>(wav: &[u8]) -> Result<&[[u8; N]], Error> {
+    let (blocks, _partial) = data_chunk(wav)?.as_chunks::<N>();
+    if blocks.is_empty() {
+        return Err(Error::EmptyWav);
+    }
+    Ok(blocks)
+}
+
+/// Check the header of a mono IMA ADPCM block
+///
+/// Returns the block's initial predictor and step index.
+pub fn adpcm_block_header(block: &[u8]) -> Result<(i16, u8), Error> {
+    let [predictor_low, predictor_high, step_index, _reserved, ..] = *block else {
+        return Err(Error::BadAdpcmBlock);
+    };
+    if step_index > ADPCM_MAX_STEP_INDEX {
+        return Err(Error::BadAdpcmBlock);
+    }
+    Ok((
+        i16::from_le_bytes([predictor_low, predictor_high]),
+        step_index,
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{adpcm_block_header, adpcm_blocks, adpcm_format, data_chunk, AdpcmFormat};
+    use crate::error::Error;
+
+    /// fmt chunk contents for mono IMA ADPCM at 48kHz, with `block_size`
+    /// byte blocks
+    fn adpcm_fmt(block_size: u16) -> Vec<u8> {
+        let samples_per_block = 2 * (block_size - 4) + 1;
+        let mut fmt = Vec::new();
+        fmt.extend_from_slice(&0x11_u16.to_le_bytes());
+        fmt.extend_from_slice(&1_u16.to_le_bytes());
+        fmt.extend_from_slice(&48_000_u32.to_le_bytes());
+        fmt.extend_from_slice(&24_000_u32.to_le_bytes());
+        fmt.extend_from_slice(&block_size.to_le_bytes());
+        fmt.extend_from_slice(&4_u16.to_le_bytes());
+        fmt.extend_from_slice(&2_u16.to_le_bytes());
+        fmt.extend_from_slice(&samples_per_block.to_le_bytes());
+        fmt
+    }
+
+    fn wav(chunks: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+        let mut bytes = b"RIFF\0\0\0\0WAVE".to_vec();
+        for (id, data) in chunks {
+            bytes.extend_from_slice(*id);
+            bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(data);
+            if data.len() % 2 == 1 {
+                bytes.push(0);
+            }
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_data_chunk_found_after_other_chunks() {
+        let file = wav(&[
+            (b"fmt ", &[1; 20]),
+            (b"LIST", &[2; 3]),
+            (b"data", &[7, 8, 9]),
+        ]);
+        assert_eq!(data_chunk(&file), Ok(&[7_u8, 8, 9][..]));
+    }
+
+    #[test]
+    fn test_data_chunk_malformed_wav() {
+        let file = wav(&[(b"fmt ", &[1; 20]), (b"data", &[7; 16])]);
+        // truncated part way through the data
+        assert_eq!(
+            data_chunk(&file[..file.len() - 1]),
+            Err(Error::MalformedWav)
+        );
+        // no data chunk
+        let no_data = wav(&[(b"fmt ", &[1; 20])]);
+        assert_eq!(data_chunk(&no_data), Err(Error::MalformedWav));
+        // not a WAV file at all
+        assert_eq!(data_chunk(b"ID3 some mp3"), Err(Error::MalformedWav));
+        assert_eq!(data_chunk(&[]), Err(Error::MalformedWav));
+    }
+
+    #[test]
+    fn test_adpcm_blocks_too_small() {
+        let file = wav(&[(b"fmt ", &[1; 20]), (b"data", &[7; 40])]);
+        let blocks = adpcm_blocks::<16>(&file).unwrap();
+        // the partial block at the end is dropped
+        assert_eq!(blocks, [[7; 16]; 2]);
+
+        // less than one block of audio
+        assert_eq!(adpcm_blocks::<64>(&file), Err(Error::EmptyWav));
+        let empty = wav(&[(b"fmt ", &[1; 20]), (b"data", &[])]);
+        assert_eq!(adpcm_blocks::<16>(&empty), Err(Error::EmptyWav));
+        // still malformed when there's no data chunk
+        assert_eq!(adpcm_blocks::<16>(&[]), Err(Error::MalformedWav));
+    }
+
+    #[test]
+    fn test_adpcm_block_header() {
+        assert_eq!(
+            adpcm_block_header(&[0x34, 0x12, 40, 0, 0xab]),
+            Ok((0x1234, 40))
+        );
+        assert_eq!(adpcm_block_header(&[0, 0x80, 88, 0]), Ok((i16::MIN, 88)));
+        assert_eq!(
+            adpcm_block_header(&[0, 0, 89, 0]),
+            Err(Error::BadAdpcmBlock)
+        );
+        assert_eq!(adpcm_block_header(&[0, 0, 0]), Err(Error::BadAdpcmBlock));
+    }
+
+    #[test]
+    fn test_adpcm_format() {
+        let fmt = adpcm_fmt(256);
+        let file = wav(&[
+            (b"fmt ", &fmt),
+            (b"fact", &1000_u32.to_le_bytes()),
+            (b"data", &[0; 600]),
+        ]);
+        assert_eq!(
+            adpcm_format(&file),
+            Ok(AdpcmFormat {
+                data_offset: 12 + 28 + 12 + 8,
+                data_len: 600,
+                block_size: 256,
+                samples_per_block: 505,
+                sample_rate: 48_000,
+                sample_count: 1000,
+            })
+        );
+
+        // without a fact chunk, counted from the data: two whole blocks and
+        // a partial one
+        let file = wav(&[(b"fmt ", &fmt), (b"data", &[0; 600])]);
+        let format = adpcm_format(&file).unwrap();
+        assert_eq!(format.sample_count, 2 * 505 + 2 * (88 - 4) + 1);
+        assert_eq!(format.data_offset, 12 + 28 + 8);
+
+        // usable at compile time
+        const FILE: &[u8] = &[
+            b'R', b'I', b'F', b'F', 0, 0, 0, 0, b'W', b'A', b'V', b'E', //
+            b'f', b'm', b't', b' ', 20, 0, 0, 0, //
+            0x11, 0, 1, 0, 0x80, 0xbb, 0, 0, 0, 0, 0, 0, 0, 4, 4, 0, 2, 0, 0xf9, 7, //
+            b'd', b'a', b't', b'a', 0, 0, 0, 0,
+        ];
+        const BLOCK_SIZE: usize = match adpcm_format(FILE) {
+            Ok(format) => format.block_size,
+            Err(_) => panic!(),
+        };
+        assert_eq!(BLOCK_SIZE, 1024);
+    }
+
+    #[test]
+    fn test_adpcm_format_unsupported() {
+        // PCM
+        let mut pcm = adpcm_fmt(256);
+        pcm[0] = 1;
+        let file = wav(&[(b"fmt ", &pcm), (b"data", &[0; 16])]);
+        assert_eq!(adpcm_format(&file), Err(Error::UnsupportedWav));
+        // stereo
+        let mut stereo = adpcm_fmt(256);
+        stereo[2] = 2;
+        let file = wav(&[(b"fmt ", &stereo), (b"data", &[0; 16])]);
+        assert_eq!(adpcm_format(&file), Err(Error::UnsupportedWav));
+        // samples per block which don't fit the block
+        let mut mismatched = adpcm_fmt(256);
+        mismatched[18] = 0;
+        let file = wav(&[(b"fmt ", &mismatched), (b"data", &[0; 16])]);
+        assert_eq!(adpcm_format(&file), Err(Error::UnsupportedWav));
+
+        // missing or short chunks
+        let file = wav(&[(b"data", &[0; 16])]);
+        assert_eq!(adpcm_format(&file), Err(Error::MalformedWav));
+        let file = wav(&[(b"fmt ", &adpcm_fmt(256)[..16]), (b"data", &[0; 16])]);
+        assert_eq!(adpcm_format(&file), Err(Error::MalformedWav));
+        let file = wav(&[(b"fmt ", &adpcm_fmt(256))]);
+        assert_eq!(adpcm_format(&file), Err(Error::MalformedWav));
+    }
+
+    #[test]
+    fn test_bundled_wavs() {
+        // (file, data chunk header offset) of the WAVs bundled with
+        // backyard_rain. Files with a fact chunk have their data at 136,
+        // files from the editor have a bext chunk instead
+        let bundled = [
+            ("backyard_rain_heavy_loop.wav", 652),
+            ("backyard_rain_heavy_loop_micro.wav", 136),
+            ("backyard_rain_heavy_loop_short.wav", 652),
+            ("backyard_rain_light_loop.wav", 652),
+            ("backyard_rain_light_loop_micro.wav", 136),
+            ("backyard_rain_light_loop_short.wav", 652),
+            ("backyard_rain_medium_loop.wav", 652),
+            ("backyard_rain_medium_loop_micro.wav", 136),
+            ("backyard_rain_medium_loop_short.wav", 652),
+            ("backyard_thunder_01.wav", 136),
+            ("sine_heavy.wav", 136),
+            ("sine_light.wav", 136),
+            ("sine_long.wav", 136),
+            ("sine_medium.wav", 136),
+        ];
+        let data = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("../backyard_rain/data");
+        for (name, header_offset) in bundled {
+            let file = std::fs::read(data.join(name)).unwrap();
+            let format = adpcm_format(&file).unwrap();
+            assert_eq!(format.data_offset, header_offset + 8, "{}", name);
+            assert_eq!(format.block_size, 1024, "{}", name);
+            assert_eq!(format.samples_per_block, 2 * 1024 - 7, "{}", name);
+            assert_eq!(format.sample_rate, 48_000, "{}", name);
+            assert_eq!(
+                data_chunk(&file).unwrap().len(),
+                format.data_len,
+                "{}",
+                name
+            );
+        }
+    }
+}